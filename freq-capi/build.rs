@@ -0,0 +1,17 @@
+use std::env;
+use std::path::Path;
+
+// Generates the header from the exported functions into OUT_DIR, where a test compares it with
+// the committed include/freq.h, so that the header can't drift from their signatures without
+// the build touching the source tree.
+fn main() {
+    let dir = env::var("CARGO_MANIFEST_DIR").unwrap();
+    let out = env::var("OUT_DIR").unwrap();
+    let config = cbindgen::Config::from_file(Path::new(&dir).join("cbindgen.toml"))
+        .expect("cbindgen.toml is valid");
+    cbindgen::generate_with_config(&dir, config)
+        .expect("src/lib.rs can be parsed")
+        .write_to_file(Path::new(&out).join("freq.h"));
+    println!("cargo:rerun-if-changed=src/lib.rs");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+}
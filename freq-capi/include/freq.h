@@ -0,0 +1,74 @@
+/*
+ * C bindings for the freq counting core.
+ *
+ * A counter counts non-overlapping occurrences of a literal needle in a stream of bytes that is
+ * fed to it in arbitrarily sized chunks. Needles that span chunk boundaries are counted.
+ *
+ * Link against libfreq_capi, built by `cargo build -p freq-capi`.
+ */
+
+#ifndef FREQ_H
+#define FREQ_H
+
+/* Generated by cbindgen from freq-capi/src/lib.rs. Do not edit. */
+
+#include <stddef.h>
+#include <stdint.h>
+
+/**
+ * An opaque handle to a counter. Create it with [`freq_counter_new`] and release it with
+ * [`freq_counter_free`].
+ */
+typedef struct FreqCounter FreqCounter;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Creates a counter for the `len`-byte needle at `needle`.
+ *
+ * Returns NULL if the needle is NULL or empty.
+ *
+ * # Safety
+ *
+ * `needle` must be valid for reads of `len` bytes. The needle is copied, so it does not need to
+ * outlive the call.
+ */
+FreqCounter *freq_counter_new(const uint8_t *needle, size_t len);
+
+/**
+ * Feeds the next `len` bytes of the stream to the counter.
+ *
+ * Needles that span consecutive writes are counted.
+ *
+ * # Safety
+ *
+ * `counter` must come from [`freq_counter_new`] and not have been freed. `buf` must be valid for
+ * reads of `len` bytes, and may only be NULL if `len` is 0.
+ */
+void freq_counter_write(FreqCounter *counter, const uint8_t *buf, size_t len);
+
+/**
+ * Returns the number of needles found so far.
+ *
+ * # Safety
+ *
+ * `counter` must come from [`freq_counter_new`] and not have been freed.
+ */
+size_t freq_counter_count(const FreqCounter *counter);
+
+/**
+ * Releases a counter. Passing NULL is a no-op.
+ *
+ * # Safety
+ *
+ * `counter` must be NULL or come from [`freq_counter_new`], and must not be used afterwards.
+ */
+void freq_counter_free(FreqCounter *counter);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* FREQ_H */
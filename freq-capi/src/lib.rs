@@ -0,0 +1,113 @@
+//! C bindings for [`freq::NeedleCounter`].
+//!
+//! The matching declarations in `include/freq.h` are generated from this file by cbindgen when
+//! the crate is built, and a test fails until the committed header matches them, so copy it from
+//! the build's `OUT_DIR` along with any change to a signature here.
+//!
+//! Built for `wasm32-unknown-unknown`, the crate also exports a `Counter` class to JavaScript with
+//! wasm-bindgen, which takes each chunk as a `Uint8Array`.
+
+use freq::NeedleCounter;
+use std::slice;
+
+#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+mod wasm;
+
+/// An opaque handle to a counter. Create it with [`freq_counter_new`] and release it with
+/// [`freq_counter_free`].
+pub struct FreqCounter(NeedleCounter);
+
+/// Creates a counter for the `len`-byte needle at `needle`.
+///
+/// Returns NULL if the needle is NULL or empty.
+///
+/// # Safety
+///
+/// `needle` must be valid for reads of `len` bytes. The needle is copied, so it does not need to
+/// outlive the call.
+#[no_mangle]
+pub unsafe extern "C" fn freq_counter_new(needle: *const u8, len: usize) -> *mut FreqCounter {
+    if needle.is_null() || len == 0 {
+        return std::ptr::null_mut();
+    }
+    let needle = slice::from_raw_parts(needle, len);
+    Box::into_raw(Box::new(FreqCounter(NeedleCounter::new(needle))))
+}
+
+/// Feeds the next `len` bytes of the stream to the counter.
+///
+/// Needles that span consecutive writes are counted.
+///
+/// # Safety
+///
+/// `counter` must come from [`freq_counter_new`] and not have been freed. `buf` must be valid for
+/// reads of `len` bytes, and may only be NULL if `len` is 0.
+#[no_mangle]
+pub unsafe extern "C" fn freq_counter_write(counter: *mut FreqCounter, buf: *const u8, len: usize) {
+    if len == 0 {
+        return;
+    }
+    let counter = &mut *counter;
+    counter.0.write(slice::from_raw_parts(buf, len));
+}
+
+/// Returns the number of needles found so far.
+///
+/// # Safety
+///
+/// `counter` must come from [`freq_counter_new`] and not have been freed.
+#[no_mangle]
+pub unsafe extern "C" fn freq_counter_count(counter: *const FreqCounter) -> usize {
+    (*counter).0.count()
+}
+
+/// Releases a counter. Passing NULL is a no-op.
+///
+/// # Safety
+///
+/// `counter` must be NULL or come from [`freq_counter_new`], and must not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn freq_counter_free(counter: *mut FreqCounter) {
+    if !counter.is_null() {
+        drop(Box::from_raw(counter));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_across_writes() {
+        unsafe {
+            let c = freq_counter_new(b"abc".as_ptr(), 3);
+            assert!(!c.is_null());
+            for chunk in [&b"xxab"[..], b"cab", b"", b"c", b"abcab"] {
+                freq_counter_write(c, chunk.as_ptr(), chunk.len());
+            }
+            freq_counter_write(c, std::ptr::null(), 0);
+            assert_eq!(freq_counter_count(c), 3);
+            freq_counter_free(c);
+        }
+    }
+
+    #[test]
+    fn test_header_is_current() {
+        let generated = include_str!(concat!(env!("OUT_DIR"), "/freq.h"));
+        let committed = include_str!("../include/freq.h");
+        assert!(
+            generated == committed,
+            "include/freq.h is out of date; copy {}/freq.h over it",
+            env!("OUT_DIR")
+        );
+    }
+
+    #[test]
+    fn test_empty_needle() {
+        unsafe {
+            assert!(freq_counter_new(b"".as_ptr(), 0).is_null());
+            assert!(freq_counter_new(std::ptr::null(), 3).is_null());
+            freq_counter_free(std::ptr::null_mut());
+        }
+    }
+}
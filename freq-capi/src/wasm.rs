@@ -0,0 +1,36 @@
+//! The counter as a JavaScript class, exported with wasm-bindgen when built for
+//! `wasm32-unknown-unknown`.
+
+use freq::NeedleCounter;
+use wasm_bindgen::prelude::*;
+
+/// Counts non-overlapping occurrences of a literal needle in a stream of `Uint8Array` chunks.
+///
+/// Needles that span consecutive writes are counted. Call `free()` once done with it.
+#[wasm_bindgen]
+pub struct Counter(NeedleCounter);
+
+#[wasm_bindgen]
+impl Counter {
+    /// Creates a counter for `needle`, such as `new TextEncoder().encode("ERROR")`.
+    ///
+    /// Throws if the needle is empty.
+    #[wasm_bindgen(constructor)]
+    pub fn new(needle: &[u8]) -> Result<Counter, JsError> {
+        if needle.is_empty() {
+            return Err(JsError::new("pattern must be non-empty"));
+        }
+        Ok(Counter(NeedleCounter::new(needle)))
+    }
+
+    /// Feeds the next chunk of the stream to the counter.
+    pub fn write(&mut self, chunk: &[u8]) {
+        self.0.write(chunk);
+    }
+
+    /// The number of needles found so far.
+    #[wasm_bindgen(getter)]
+    pub fn count(&self) -> usize {
+        self.0.count()
+    }
+}
@@ -0,0 +1,10 @@
+"""Python bindings for the freq counting core.
+
+The counting is done by the `freq._native` extension module, which releases the GIL while it
+scans, so counters in different threads run in parallel. Build and install it with
+`maturin develop --release` (or `pip install ./python`).
+"""
+
+from ._native import Counter, count
+
+__all__ = ["Counter", "count"]
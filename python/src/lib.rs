@@ -0,0 +1,143 @@
+//! Python bindings for [`freq::NeedleCounter`], imported as `freq._native` and re-exported by the
+//! `freq` package.
+//!
+//! Scans release the GIL, so counters in different threads run in parallel.
+
+use freq::NeedleCounter;
+use pyo3::buffer::PyBuffer;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyTuple;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::PathBuf;
+use std::slice;
+
+const CHUNK_SIZE: usize = 1 << 20;
+
+// A pattern given as text, which is matched as UTF-8, or as any bytes-like object.
+#[derive(FromPyObject)]
+enum Pattern {
+    Text(String),
+    Bytes(PyBuffer<u8>),
+}
+
+impl Pattern {
+    fn into_needle(self, py: Python<'_>) -> PyResult<Vec<u8>> {
+        let needle = match self {
+            Pattern::Text(text) => text.into_bytes(),
+            Pattern::Bytes(buf) => buf.to_vec(py)?,
+        };
+        if needle.is_empty() {
+            return Err(PyValueError::new_err("pattern must be non-empty"));
+        }
+        Ok(needle)
+    }
+}
+
+// What `count` reads: a bytes-like object, or else a path to a file.
+#[derive(FromPyObject)]
+enum Source {
+    Bytes(PyBuffer<u8>),
+    Path(PathBuf),
+}
+
+// Calls `f` on the bytes of `buf` with the GIL released. The buffer is held for the duration of
+// the call, so it can't be resized or freed under it.
+fn with_bytes<R: Send>(
+    py: Python<'_>,
+    buf: &PyBuffer<u8>,
+    f: impl FnOnce(&[u8]) -> R + Send,
+) -> PyResult<R> {
+    if buf.is_c_contiguous() {
+        // SAFETY: a C-contiguous buffer of u8 is `len_bytes` readable bytes from `buf_ptr`.
+        let bytes = unsafe { slice::from_raw_parts(buf.buf_ptr() as *const u8, buf.len_bytes()) };
+        Ok(py.allow_threads(|| f(bytes)))
+    } else {
+        let bytes = buf.to_vec(py)?;
+        Ok(py.allow_threads(|| f(&bytes)))
+    }
+}
+
+/// Counts non-overlapping occurrences of a literal pattern in a stream of chunks.
+///
+/// Patterns that span two calls to `write` are counted. Using a counter after `close` raises
+/// `ValueError`.
+#[pyclass(module = "freq")]
+struct Counter {
+    // Boxed since Python allocates the object, and doesn't align it for the counter's SIMD state.
+    inner: Option<Box<NeedleCounter>>,
+}
+
+impl Counter {
+    fn open(&mut self) -> PyResult<&mut NeedleCounter> {
+        self.inner
+            .as_deref_mut()
+            .ok_or_else(|| PyValueError::new_err("counter is closed"))
+    }
+}
+
+#[pymethods]
+impl Counter {
+    #[new]
+    fn new(py: Python<'_>, pattern: Pattern) -> PyResult<Self> {
+        let needle = pattern.into_needle(py)?;
+        Ok(Counter {
+            inner: Some(Box::new(NeedleCounter::new(&needle))),
+        })
+    }
+
+    /// Feeds the next chunk of the stream (any bytes-like object) to the counter.
+    fn write(&mut self, py: Python<'_>, chunk: PyBuffer<u8>) -> PyResult<()> {
+        let counter = self.open()?;
+        with_bytes(py, &chunk, |bytes| counter.write(bytes))
+    }
+
+    /// The number of occurrences found so far.
+    #[getter]
+    fn count(&mut self) -> PyResult<usize> {
+        Ok(self.open()?.count())
+    }
+
+    fn close(&mut self) {
+        self.inner = None;
+    }
+
+    fn __enter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    #[pyo3(signature = (*_exc))]
+    fn __exit__(&mut self, _exc: &Bound<'_, PyTuple>) {
+        self.close();
+    }
+}
+
+/// Counts occurrences of `pattern` in a file (given by path) or a bytes-like object.
+#[pyfunction]
+fn count(py: Python<'_>, pattern: Pattern, path_or_bytes: Source) -> PyResult<usize> {
+    let mut counter = NeedleCounter::new(&pattern.into_needle(py)?);
+    match path_or_bytes {
+        Source::Bytes(buf) => with_bytes(py, &buf, |bytes| counter.write(bytes))?,
+        Source::Path(path) => py.allow_threads(|| -> io::Result<()> {
+            let mut file = File::open(path)?;
+            let mut buf = vec![0; CHUNK_SIZE];
+            loop {
+                match file.read(&mut buf) {
+                    Ok(0) => return Ok(()),
+                    Ok(n) => counter.write(&buf[..n]),
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                    Err(e) => return Err(e),
+                }
+            }
+        })?,
+    }
+    Ok(counter.count())
+}
+
+#[pymodule]
+fn _native(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<Counter>()?;
+    m.add_function(wrap_pyfunction!(count, m)?)?;
+    Ok(())
+}
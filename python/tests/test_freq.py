@@ -0,0 +1,72 @@
+import threading
+
+import pytest
+
+import freq
+
+
+def test_count_bytes():
+    assert freq.count(b"abc", b"xxabcabxabc") == 2
+    assert freq.count("ab", bytearray(b"abab")) == 2
+    assert freq.count(b"aa", memoryview(b"aaaaa")) == 2
+
+
+def test_count_text_pattern_is_utf8():
+    assert freq.count("é", "é and é".encode()) == 2
+
+
+def test_count_path(tmp_path):
+    path = tmp_path / "log"
+    path.write_bytes(b"ERROR\nok\nERROR\n" * 1000)
+    assert freq.count("ERROR", path) == 2000
+    assert freq.count("ERROR", str(path)) == 2000
+
+
+def test_count_missing_file(tmp_path):
+    with pytest.raises(FileNotFoundError):
+        freq.count("ERROR", tmp_path / "missing")
+
+
+def test_counter_across_writes():
+    counter = freq.Counter(b"abc")
+    for chunk in [b"xxab", b"cab", b"", b"c", b"abcab"]:
+        counter.write(chunk)
+    assert counter.count == 3
+
+
+def test_counter_non_contiguous_buffer():
+    counter = freq.Counter(b"ab")
+    counter.write(memoryview(b"aXbYaZbW")[::2])
+    assert counter.count == 2
+
+
+def test_empty_pattern():
+    with pytest.raises(ValueError):
+        freq.Counter(b"")
+    with pytest.raises(ValueError):
+        freq.count("", b"abc")
+
+
+def test_closed_counter():
+    with freq.Counter(b"a") as counter:
+        counter.write(b"aaa")
+        assert counter.count == 3
+    with pytest.raises(ValueError):
+        counter.write(b"a")
+    with pytest.raises(ValueError):
+        counter.count
+
+
+def test_threads():
+    data = b"needle in a haystack, " * 100_000
+    counts = [None] * 8
+
+    def scan(i):
+        counts[i] = freq.count(b"needle", data)
+
+    threads = [threading.Thread(target=scan, args=(i,)) for i in range(len(counts))]
+    for thread in threads:
+        thread.start()
+    for thread in threads:
+        thread.join()
+    assert counts == [100_000] * len(counts)
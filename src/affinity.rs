@@ -0,0 +1,104 @@
+use std::io;
+
+// The most CPUs a list can name, as many as a Linux cpu_set_t holds, so that a range such as
+// 0-99999999999 is refused instead of filling memory.
+const MAX_CPUS: usize = 1024;
+
+// The CPUs to run on, from --cpu-list, such as 0-7 or 0,2,4-6, as in taskset.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CpuList(pub Vec<usize>);
+
+impl CpuList {
+    pub fn parse(s: &str) -> Result<CpuList, String> {
+        let parse = |n: &str| {
+            let cpu = n
+                .trim()
+                .parse::<usize>()
+                .map_err(|_| format!("expected a CPU number, not '{}'", n))?;
+            if cpu >= MAX_CPUS {
+                return Err(format!(
+                    "CPU {} is out of range, the most is {}",
+                    cpu,
+                    MAX_CPUS - 1
+                ));
+            }
+            Ok(cpu)
+        };
+        let mut cpus = Vec::new();
+        for part in s.split(',') {
+            match part.split_once('-') {
+                Some((first, last)) => {
+                    let (first, last) = (parse(first)?, parse(last)?);
+                    if first > last {
+                        return Err(format!("the range {} ends before it starts", part));
+                    }
+                    cpus.extend(first..=last);
+                }
+                None => cpus.push(parse(part)?),
+            }
+        }
+        cpus.sort_unstable();
+        cpus.dedup();
+        Ok(CpuList(cpus))
+    }
+
+    // Pins freq to these CPUs. Threads started afterwards, which is all of them when this is
+    // done first, run on them too. Memory is placed on the NUMA node of the CPU that first
+    // touches it, so buffers then come from the nodes of these CPUs as well.
+    #[cfg(target_os = "linux")]
+    pub fn pin(&self) -> io::Result<()> {
+        // SAFETY: cpu_set_t is plain data, for which all zeroes is the empty set, and CPU_SET
+        // is only given CPUs that fit in it.
+        unsafe {
+            let mut set: libc::cpu_set_t = std::mem::zeroed();
+            let max = 8 * std::mem::size_of::<libc::cpu_set_t>();
+            for &cpu in &self.0 {
+                if cpu >= max {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("CPU {} is out of range", cpu),
+                    ));
+                }
+                libc::CPU_SET(cpu, &mut set);
+            }
+            if libc::sched_setaffinity(0, std::mem::size_of_val(&set), &set) != 0 {
+                let e = io::Error::last_os_error();
+                if e.raw_os_error() == Some(libc::EINVAL) {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "none of these CPUs are available",
+                    ));
+                }
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn pin(&self) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "pinning to CPUs is only supported on Linux",
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(CpuList::parse("3"), Ok(CpuList(vec![3])));
+        assert_eq!(
+            CpuList::parse("4-6,0,2,5"),
+            Ok(CpuList(vec![0, 2, 4, 5, 6]))
+        );
+        assert!(CpuList::parse("6-4").is_err());
+        assert!(CpuList::parse("0-").is_err());
+        assert!(CpuList::parse("").is_err());
+        assert_eq!(CpuList::parse("0-1023").map(|c| c.0.len()), Ok(1024));
+        assert!(CpuList::parse("0-99999999999").is_err());
+    }
+}
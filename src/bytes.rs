@@ -0,0 +1,100 @@
+use crate::color;
+use crate::input::{for_each_chunk, open_inputs};
+use clap::Args;
+use std::io::Write;
+use std::path::PathBuf;
+
+#[derive(Args)]
+pub struct BytesArgs {
+    #[arg(help = "The files to read, with - for stdin. If not provided, stdin is used.")]
+    input: Vec<PathBuf>,
+
+    #[arg(long, help = "Label bytes in hex (0x41) rather than decimal (65).")]
+    hex: bool,
+
+    #[arg(long, help = "Only print bytes that occur at least once.")]
+    nonzero: bool,
+}
+
+pub fn run(args: BytesArgs, buffer_size: usize, out: &mut impl Write) {
+    let mut histogram = ByteHistogram::default();
+    for f in open_inputs(&args.input) {
+        for_each_chunk(f, buffer_size, |chunk| histogram.write(chunk));
+    }
+
+    for (b, count) in histogram.counts().into_iter().enumerate() {
+        if args.nonzero && count == 0 {
+            continue;
+        }
+        if args.hex {
+            writeln!(out, "{}\t0x{:02x}", color::count(count), b)
+        } else {
+            writeln!(out, "{}\t{}", color::count(count), b)
+        }
+        .expect("failed to write output");
+    }
+}
+
+// Counts occurrences of each byte value.
+pub struct ByteHistogram {
+    // Consecutive bytes are counted in separate tables, so that runs of the same byte don't wait
+    // on each other's increments.
+    tables: [[u64; 256]; 4],
+}
+
+impl Default for ByteHistogram {
+    fn default() -> Self {
+        ByteHistogram {
+            tables: [[0; 256]; 4],
+        }
+    }
+}
+
+impl ByteHistogram {
+    pub fn write(&mut self, buf: &[u8]) {
+        let [t0, t1, t2, t3] = &mut self.tables;
+        let mut quads = buf.chunks_exact(4);
+        for q in &mut quads {
+            t0[q[0] as usize] += 1;
+            t1[q[1] as usize] += 1;
+            t2[q[2] as usize] += 1;
+            t3[q[3] as usize] += 1;
+        }
+        for &b in quads.remainder() {
+            t0[b as usize] += 1;
+        }
+    }
+
+    pub fn counts(&self) -> [u64; 256] {
+        let mut counts = [0; 256];
+        for table in &self.tables {
+            for (c, t) in counts.iter_mut().zip(table) {
+                *c += t;
+            }
+        }
+        counts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use proptest::collection::vec;
+    use proptest::{prop_assert_eq, proptest};
+
+    proptest! {
+        #[test]
+        fn test_counts(
+            chunk_size in 1..20_usize,
+            data in vec(proptest::num::u8::ANY, 0..200),
+        ) {
+            let mut histogram = ByteHistogram::default();
+            data.chunks(chunk_size).for_each(|chunk| histogram.write(chunk));
+
+            let mut expected = [0; 256];
+            data.iter().for_each(|&b| expected[b as usize] += 1);
+            prop_assert_eq!(histogram.counts(), expected);
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use crate::output;
+use std::collections::hash_map::DefaultHasher;
+use std::fmt::Write as _;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+// Keeps the counts for each file that was searched, so that it needn't be searched again until it
+// changes.
+//
+// Each entry is a file named by the hash of its key, holding the key and then the counts. The key
+// is checked when reading, so a hash collision is only a cache miss.
+pub struct Cache {
+    dir: PathBuf,
+}
+
+impl Cache {
+    pub fn new(dir: PathBuf) -> io::Result<Self> {
+        fs::create_dir_all(&dir)?;
+        Ok(Cache { dir })
+    }
+
+    // Identifies the counts of `patterns` in the file at `path` as it is now, by its size and
+    // modification time. Returns None if the file can't be identified.
+    pub fn key(path: &Path, patterns: &[&[u8]]) -> Option<String> {
+        let path = path.canonicalize().ok()?;
+        let meta = path.metadata().ok()?;
+        // Pipes and devices have no size, and files in /proc and /sys claim to be empty but
+        // aren't, so their size and modification time say nothing about what they hold. Files
+        // that really are empty are no work to count anyway.
+        if !meta.is_file() || meta.len() == 0 {
+            return None;
+        }
+        let mtime = meta.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+
+        let mut key = format!("freq {}\t", env!("CARGO_PKG_VERSION"));
+        write_hex(&mut key, path.as_os_str().as_encoded_bytes());
+        write!(
+            key,
+            "\t{}\t{}.{:09}",
+            meta.len(),
+            mtime.as_secs(),
+            mtime.subsec_nanos()
+        )
+        .unwrap();
+        for pattern in patterns {
+            key.push('\t');
+            write_hex(&mut key, pattern);
+        }
+        Some(key)
+    }
+
+    // Returns the counts stored under `key`, if any.
+    pub fn get(&self, key: &str) -> Option<Vec<usize>> {
+        let entry = fs::read_to_string(self.entry(key)).ok()?;
+        let (stored, counts) = entry.split_once('\n')?;
+        if stored != key {
+            return None;
+        }
+        counts.split_whitespace().map(|c| c.parse().ok()).collect()
+    }
+
+    pub fn put(&self, key: &str, counts: &[usize]) -> io::Result<()> {
+        let mut entry = format!("{}\n", key);
+        for c in counts {
+            write!(entry, "{}\t", c).unwrap();
+        }
+        entry.pop();
+        entry.push('\n');
+        output::replace(&self.entry(key), false, entry.as_bytes())
+    }
+
+    fn entry(&self, key: &str) -> PathBuf {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        self.dir.join(format!("{:016x}", hasher.finish()))
+    }
+}
+
+fn write_hex(s: &mut String, bytes: &[u8]) {
+    for b in bytes {
+        write!(s, "{:02x}", b).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache() {
+        let dir = std::env::temp_dir().join(format!("freq-cache-{}", std::process::id()));
+        let cache = Cache::new(dir.join("cache")).unwrap();
+        let path = dir.join("input.txt");
+        fs::write(&path, "a b a").unwrap();
+
+        let key = Cache::key(&path, &[b"a", b"b"]).unwrap();
+        assert_eq!(cache.get(&key), None);
+        cache.put(&key, &[2, 1]).unwrap();
+        assert_eq!(cache.get(&key), Some(vec![2, 1]));
+
+        // Other patterns, or a changed file, have other keys.
+        assert_ne!(Cache::key(&path, &[b"a"]).unwrap(), key);
+        fs::write(&path, "a b a a").unwrap();
+        assert_ne!(Cache::key(&path, &[b"a", b"b"]).unwrap(), key);
+
+        assert_eq!(Cache::key(&dir.join("missing"), &[b"a"]), None);
+        assert_eq!(Cache::key(&dir, &[b"a"]), None);
+        fs::write(&path, "").unwrap();
+        assert_eq!(Cache::key(&path, &[b"a"]), None);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
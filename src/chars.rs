@@ -0,0 +1,366 @@
+use crate::input::{for_each_chunk, open_inputs};
+use crate::table::{FreqTable, TableArgs};
+use crate::unicode::{category, is_format};
+use clap::{Args, ValueEnum};
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use unicode_segmentation::UnicodeSegmentation;
+
+#[derive(Args)]
+pub struct CharsArgs {
+    #[arg(help = "The files to read, with - for stdin. If not provided, stdin is used.")]
+    input: Vec<PathBuf>,
+
+    #[arg(
+        short,
+        long,
+        help = "Count user-perceived characters (base characters with their combining marks, emoji sequences and flags) instead of code points."
+    )]
+    graphemes: bool,
+
+    #[arg(
+        short,
+        long,
+        help = "Count Unicode general categories (Letter, Number, Punctuation, ...) instead of characters."
+    )]
+    category: bool,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value = "replace",
+        help = "What to do with bytes that are not valid UTF-8."
+    )]
+    invalid: InvalidPolicy,
+
+    #[command(flatten)]
+    table: TableArgs,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum InvalidPolicy {
+    /// Count each invalid sequence as U+FFFD REPLACEMENT CHARACTER.
+    Replace,
+    /// Ignore invalid sequences.
+    Skip,
+    /// Stop with an error.
+    Error,
+}
+
+pub fn run(args: CharsArgs, buffer_size: usize, out: &mut impl Write) {
+    let mut chars = CharCounter::new(args.graphemes, args.invalid);
+    for (i, f) in open_inputs(&args.input).into_iter().enumerate() {
+        for_each_chunk(f, buffer_size, |chunk| chars.write(chunk));
+        chars.finish();
+
+        if let Some(offset) = chars.invalid_at.take() {
+            let name = args.input.get(i).map_or(Path::new("<stdin>"), |p| p);
+            eprintln!(
+                "freq: invalid UTF-8 at byte {} of {}",
+                offset,
+                name.display()
+            );
+            std::process::exit(1);
+        }
+    }
+
+    let mut table = FreqTable::new(&args.table);
+    for (key, count) in chars.entries() {
+        if args.category {
+            let c = key.chars().next().expect("keys are non-empty");
+            table.add_count(category(c).as_bytes(), count);
+        } else {
+            table.add_count(describe(&key).as_bytes(), count);
+        }
+    }
+
+    table
+        .print(&args.table, out)
+        .expect("failed to write output");
+}
+
+// Shows a character or cluster, escaping invisible characters, followed by its code points.
+fn describe(s: &str) -> String {
+    let mut d = String::new();
+    for c in s.chars() {
+        if c.is_control() || is_format(c) || (c.is_whitespace() && c != ' ') {
+            d.extend(c.escape_debug());
+        } else {
+            d.push(c);
+        }
+    }
+    for (i, c) in s.chars().enumerate() {
+        let sep = if i == 0 { '\t' } else { ' ' };
+        write!(d, "{}U+{:04X}", sep, c as u32).unwrap();
+    }
+    d
+}
+
+pub struct CharCounter {
+    graphemes: bool,
+
+    invalid: InvalidPolicy,
+
+    // Counts of ASCII characters, which are most of them, and of everything else.
+    ascii: [usize; 128],
+    other: HashMap<char, usize>,
+
+    // Counts of grapheme clusters, and the last cluster so far, which the next chunk may extend.
+    clusters: HashMap<String, usize>,
+    cluster: String,
+
+    // The start of a character that continues into the next chunk. At most 3 bytes.
+    carry: Vec<u8>,
+
+    // How many bytes of the current input we have decoded.
+    offset: u64,
+
+    // Where the first invalid byte was, when the policy is to stop at it.
+    invalid_at: Option<u64>,
+}
+
+impl CharCounter {
+    pub fn new(graphemes: bool, invalid: InvalidPolicy) -> Self {
+        CharCounter {
+            graphemes,
+            invalid,
+            ascii: [0; 128],
+            other: HashMap::new(),
+            clusters: HashMap::new(),
+            cluster: String::new(),
+            carry: Vec::new(),
+            offset: 0,
+            invalid_at: None,
+        }
+    }
+
+    pub fn write(&mut self, mut buf: &[u8]) {
+        if self.invalid_at.is_some() {
+            return;
+        }
+
+        if !self.carry.is_empty() {
+            // Finish the character that was cut off at the end of the last chunk.
+            let carried = self.carry.len();
+            let take = (4 - carried).min(buf.len());
+            self.carry.extend(&buf[..take]);
+            let joined = std::mem::take(&mut self.carry);
+            let used = match std::str::from_utf8(&joined) {
+                Ok(s) => self.first_char(s),
+                Err(e) if e.valid_up_to() > 0 => {
+                    self.first_char(std::str::from_utf8(&joined[..e.valid_up_to()]).unwrap())
+                }
+                Err(e) => match e.error_len() {
+                    Some(n) => {
+                        self.invalid_sequence();
+                        if self.invalid_at.is_some() {
+                            return;
+                        }
+                        n
+                    }
+                    None => {
+                        // Still incomplete.
+                        self.carry = joined;
+                        return;
+                    }
+                },
+            };
+            self.offset += used as u64;
+            buf = &buf[used - carried..];
+        }
+
+        loop {
+            match std::str::from_utf8(buf) {
+                Ok(s) => {
+                    self.add_str(s);
+                    self.offset += buf.len() as u64;
+                    return;
+                }
+                Err(e) => {
+                    let (valid, rest) = buf.split_at(e.valid_up_to());
+                    self.add_str(std::str::from_utf8(valid).unwrap());
+                    self.offset += valid.len() as u64;
+                    match e.error_len() {
+                        Some(n) => {
+                            self.invalid_sequence();
+                            if self.invalid_at.is_some() {
+                                return;
+                            }
+                            self.offset += n as u64;
+                            buf = &rest[n..];
+                        }
+                        None => {
+                            self.carry.extend(rest);
+                            return;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // Ends the current input.
+    pub fn finish(&mut self) {
+        if !self.carry.is_empty() && self.invalid_at.is_none() {
+            self.invalid_sequence();
+        }
+        self.carry.clear();
+        self.end_cluster();
+        self.offset = 0;
+    }
+
+    // Counts the first character of `s`, returning its length.
+    fn first_char(&mut self, s: &str) -> usize {
+        let c = s.chars().next().unwrap();
+        self.add_char(c);
+        c.len_utf8()
+    }
+
+    fn invalid_sequence(&mut self) {
+        match self.invalid {
+            InvalidPolicy::Replace => self.add_char(char::REPLACEMENT_CHARACTER),
+            InvalidPolicy::Skip => {}
+            InvalidPolicy::Error => self.invalid_at = Some(self.offset),
+        }
+    }
+
+    fn add_str(&mut self, s: &str) {
+        if self.graphemes {
+            // Every cluster but the last is complete, since boundaries only depend on what is
+            // before them and the character after.
+            self.cluster.push_str(s);
+            let mut clusters = self.cluster.graphemes(true);
+            let last = clusters.next_back().map_or(0, str::len);
+            for cluster in clusters {
+                match self.clusters.get_mut(cluster) {
+                    Some(count) => *count += 1,
+                    None => {
+                        self.clusters.insert(cluster.to_string(), 1);
+                    }
+                }
+            }
+            self.cluster.drain(..self.cluster.len() - last);
+            return;
+        }
+        for c in s.chars() {
+            if c.is_ascii() {
+                self.ascii[c as usize] += 1;
+            } else {
+                *self.other.entry(c).or_default() += 1;
+            }
+        }
+    }
+
+    fn add_char(&mut self, c: char) {
+        self.add_str(c.encode_utf8(&mut [0; 4]));
+    }
+
+    fn end_cluster(&mut self) {
+        if self.cluster.is_empty() {
+            return;
+        }
+        match self.clusters.get_mut(&self.cluster) {
+            Some(count) => *count += 1,
+            None => {
+                self.clusters.insert(self.cluster.clone(), 1);
+            }
+        }
+        self.cluster.clear();
+    }
+
+    // The counted characters or clusters, with their counts.
+    pub fn entries(&self) -> Vec<(String, usize)> {
+        if self.graphemes {
+            return self.clusters.iter().map(|(k, &v)| (k.clone(), v)).collect();
+        }
+        let ascii = (0..128u8)
+            .filter(|&b| self.ascii[b as usize] > 0)
+            .map(|b| ((b as char).to_string(), self.ascii[b as usize]));
+        let other = self.other.iter().map(|(c, &v)| (c.to_string(), v));
+        ascii.chain(other).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use proptest::string::bytes_regex;
+    use proptest::{prop_assert_eq, proptest};
+
+    fn counts(counter: &CharCounter) -> Vec<(String, usize)> {
+        let mut entries = counter.entries();
+        entries.sort();
+        entries
+    }
+
+    #[test]
+    fn test_graphemes() {
+        let mut counter = CharCounter::new(true, InvalidPolicy::Replace);
+        counter.write("e\u{301}e🇫🇷🇩🇪👍🏽\u{ff}".as_bytes());
+        counter.finish();
+        assert_eq!(
+            counts(&counter),
+            [
+                ("e".to_string(), 1),
+                ("e\u{301}".to_string(), 1),
+                ("ÿ".to_string(), 1),
+                ("🇩🇪".to_string(), 1),
+                ("🇫🇷".to_string(), 1),
+                ("👍🏽".to_string(), 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid() {
+        let mut counter = CharCounter::new(false, InvalidPolicy::Error);
+        counter.write(b"ab\xc3");
+        counter.write(b"\xa9c\xff");
+        assert_eq!(counter.invalid_at, Some(5));
+
+        // The first invalid sequence is reported even if it was cut off by the end of a chunk.
+        let mut counter = CharCounter::new(false, InvalidPolicy::Error);
+        counter.write(b"ab\xe2\x82");
+        counter.write(b"xc\xff");
+        assert_eq!(counter.invalid_at, Some(2));
+        assert_eq!(
+            counts(&counter),
+            [("a".to_string(), 1), ("b".to_string(), 1)]
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn test_chunked(
+            chunk_size in 1..20_usize,
+            text in bytes_regex("((?s-u:[a\\xc3\\xa9\\xcc\\x81\\xe2\\x82\\xac\\xf0\\x9f\\x91\\x8d\\xff]{0,200}))").unwrap(),
+            graphemes in proptest::bool::ANY,
+        ) {
+            let mut whole = CharCounter::new(graphemes, InvalidPolicy::Replace);
+            whole.write(&text);
+            whole.finish();
+
+            let mut chunked = CharCounter::new(graphemes, InvalidPolicy::Replace);
+            text.chunks(chunk_size).for_each(|chunk| chunked.write(chunk));
+            chunked.finish();
+
+            prop_assert_eq!(counts(&whole), counts(&chunked));
+
+            let mut map = HashMap::new();
+            let text = String::from_utf8_lossy(&text);
+            if graphemes {
+                text.graphemes(true)
+                    .for_each(|g| *map.entry(g.to_string()).or_insert(0) += 1);
+            } else {
+                text.chars()
+                    .for_each(|c| *map.entry(c.to_string()).or_insert(0) += 1);
+            }
+            let mut expected: Vec<(String, usize)> = map.into_iter().collect();
+            expected.sort();
+            prop_assert_eq!(counts(&chunked), expected);
+        }
+    }
+}
@@ -0,0 +1,70 @@
+use std::ops::{Deref, Range};
+use std::sync::Arc;
+
+// A chunk of an input, as read. Clones and slices share the buffer it was read into instead of
+// copying it, so a chunk can be handed to other threads, or part of it kept until the next one
+// arrives. The buffer is only read into again once nothing refers to it anymore.
+#[derive(Clone)]
+pub struct Chunk {
+    buf: Arc<Vec<u8>>,
+    range: Range<usize>,
+}
+
+impl Chunk {
+    // The first `len` bytes of `buf`.
+    pub fn new(buf: Vec<u8>, len: usize) -> Self {
+        assert!(len <= buf.len());
+        Chunk {
+            buf: Arc::new(buf),
+            range: 0..len,
+        }
+    }
+
+    // The part of this chunk in `range`, sharing its buffer.
+    pub fn slice(&self, range: Range<usize>) -> Self {
+        assert!(range.start <= range.end && range.end <= self.len());
+        Chunk {
+            buf: self.buf.clone(),
+            range: self.range.start + range.start..self.range.start + range.end,
+        }
+    }
+
+    // Returns the buffer, to read into again, if nothing else refers to it.
+    pub fn into_buf(self) -> Option<Vec<u8>> {
+        Arc::try_unwrap(self.buf).ok()
+    }
+}
+
+impl From<Vec<u8>> for Chunk {
+    fn from(buf: Vec<u8>) -> Self {
+        let len = buf.len();
+        Chunk::new(buf, len)
+    }
+}
+
+impl Deref for Chunk {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.buf[self.range.clone()]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shared_buffer() {
+        let chunk = Chunk::new(b"abcdef".to_vec(), 5);
+        assert_eq!(&*chunk, b"abcde");
+
+        let tail = chunk.slice(2..5);
+        assert_eq!(&*tail, b"cde");
+        assert_eq!(&*tail.slice(1..2), b"d");
+
+        // The buffer is only given back once the slice is gone too.
+        assert!(chunk.into_buf().is_none());
+        assert_eq!(tail.into_buf().unwrap(), b"abcdef");
+    }
+}
@@ -0,0 +1,103 @@
+use clap::ValueEnum;
+use std::env;
+use std::fmt::{self, Display};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum ColorChoice {
+    /// Color when printing to a terminal, unless NO_COLOR is set.
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    pub fn enabled(self, terminal: bool) -> bool {
+        match self {
+            ColorChoice::Auto => terminal && env::var_os("NO_COLOR").is_none_or(|v| v.is_empty()),
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
+}
+
+const BOLD: &str = "\x1b[1m";
+const DIM: &str = "\x1b[2m";
+const RED: &str = "\x1b[1;31m";
+const RESET: &str = "\x1b[0m";
+
+// Returns the "freq:" that starts messages on stderr, in red if `color` is set.
+pub fn error_prefix(color: bool) -> String {
+    if color {
+        format!("{}freq:{}", RED, RESET)
+    } else {
+        "freq:".to_string()
+    }
+}
+
+// Whether results are printed in color, as decided once from --color.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+// A count, which is bold so that the ones that matter stand out, or dim if it is zero.
+pub fn count<T: Display>(value: T) -> Styled<T> {
+    Styled {
+        value,
+        total: false,
+        enabled: ENABLED.load(Ordering::Relaxed),
+    }
+}
+
+// A field of a total line, such as its label or its counts, which are all bold.
+pub fn total<T: Display>(value: T) -> Styled<T> {
+    Styled {
+        value,
+        total: true,
+        enabled: ENABLED.load(Ordering::Relaxed),
+    }
+}
+
+// A value printed in the style of what it is, if color is enabled.
+pub struct Styled<T> {
+    value: T,
+    total: bool,
+    enabled: bool,
+}
+
+impl<T: Display> Display for Styled<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if !self.enabled {
+            return self.value.fmt(f);
+        }
+        let text = self.value.to_string();
+        let zero = !self.total && text.bytes().all(|b| b == b'0');
+        let style = if zero { DIM } else { BOLD };
+        write!(f, "{}{}{}", style, text, RESET)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_styled() {
+        let styled = |value, total| Styled {
+            value,
+            total,
+            enabled: true,
+        };
+        assert_eq!(styled(12, false).to_string(), "\x1b[1m12\x1b[0m");
+        assert_eq!(styled(0, false).to_string(), "\x1b[2m0\x1b[0m");
+        assert_eq!(styled(0, true).to_string(), "\x1b[1m0\x1b[0m");
+        let plain = Styled {
+            value: 0,
+            total: false,
+            enabled: false,
+        };
+        assert_eq!(plain.to_string(), "0");
+    }
+}
@@ -0,0 +1,131 @@
+use crate::color;
+use crate::engine;
+use crate::input::{for_each_chunk, open_inputs};
+use crate::walk;
+use clap::builder::NonEmptyStringValueParser;
+use clap::Args;
+use freq::{Literals, Matcher};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+#[derive(Args)]
+pub struct CompareArgs {
+    #[arg(help = "The file or directory before. Directories are searched recursively.")]
+    before: PathBuf,
+
+    #[arg(help = "The file or directory after.")]
+    after: PathBuf,
+
+    #[arg(
+        short = 'e',
+        long = "pattern",
+        value_name = "PATTERN",
+        required_unless_present = "sets",
+        value_parser = NonEmptyStringValueParser::new(),
+        help = "A pattern to count. Can be given more than once."
+    )]
+    pub patterns: Vec<String>,
+
+    #[arg(
+        short,
+        long = "set",
+        value_name = "NAME",
+        help = "Count the patterns of a set from the [patterns] table of the config file. Can be given more than once."
+    )]
+    pub sets: Vec<String>,
+}
+
+pub fn run(args: CompareArgs, buffer_size: usize, out: &mut impl Write) {
+    // All patterns are counted in one pass over each side, with one automaton if there are many.
+    let patterns: Vec<&[u8]> = args.patterns.iter().map(|p| p.as_bytes()).collect();
+    let (before, after) = if patterns.len() > engine::FEW_PATTERNS {
+        count_sides(&args, engine::dictionary(&patterns), &patterns, buffer_size)
+    } else {
+        count_sides(&args, Literals::new(&patterns), &patterns, buffer_size)
+    };
+
+    print_table(out, &args, &before, &after).expect("failed to write output");
+}
+
+// Counts the patterns, which `matcher` finds, in all of each side.
+fn count_sides<M: Matcher + Clone>(
+    args: &CompareArgs,
+    matcher: M,
+    patterns: &[&[u8]],
+    buffer_size: usize,
+) -> (Vec<usize>, Vec<usize>) {
+    let prepared = engine::Prepared::new(matcher, patterns);
+    let count = |path: &Path| {
+        let mut totals = vec![0; patterns.len()];
+        for f in open_inputs(&walk::files(&[path.to_path_buf()])) {
+            let mut counter = prepared.counter();
+            for_each_chunk(f, buffer_size, |chunk| counter.write(chunk));
+            counter.finish();
+            for (total, c) in totals.iter_mut().zip(counter.counts()) {
+                *total += c;
+            }
+        }
+        totals
+    };
+    (count(&args.before), count(&args.after))
+}
+
+// Prints a tab-separated table with the count of each pattern before and after, how much it
+// changed, and by what percentage.
+fn print_table(
+    out: &mut impl Write,
+    args: &CompareArgs,
+    before: &[usize],
+    after: &[usize],
+) -> io::Result<()> {
+    writeln!(
+        out,
+        "pattern\t{}\t{}\tchange\tpercent",
+        args.before.display(),
+        args.after.display()
+    )?;
+    for ((pattern, &b), &a) in args.patterns.iter().zip(before).zip(after) {
+        let change = a as i128 - b as i128;
+        write!(
+            out,
+            "{}\t{}\t{}\t{:+}\t",
+            pattern,
+            color::count(b),
+            color::count(a),
+            change
+        )?;
+        if b == 0 {
+            // No percentage of nothing.
+            writeln!(out, "-")?;
+        } else {
+            writeln!(out, "{:+.2}%", 100.0 * change as f64 / b as f64)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_print_table() {
+        let args = CompareArgs {
+            before: PathBuf::from("old.log"),
+            after: PathBuf::from("new"),
+            patterns: vec!["ERROR".to_string(), "WARN".to_string(), "FATAL".to_string()],
+            sets: Vec::new(),
+        };
+        let mut out = Vec::new();
+        print_table(&mut out, &args, &[10, 4, 0], &[15, 1, 2]).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "\
+pattern\told.log\tnew\tchange\tpercent
+ERROR\t10\t15\t+5\t+50.00%
+WARN\t4\t1\t-3\t-75.00%
+FATAL\t0\t2\t+2\t-
+"
+        );
+    }
+}
@@ -0,0 +1,306 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Defaults read from a config file and then the environment, which command line flags override.
+// The file is a small subset of TOML:
+//
+//     buffer_size = 4194304
+//     exclude_dir = ["node_modules", ".*"]
+//     format = "json"
+//     jobs = 8
+//     ordered = true
+//
+//     [patterns]
+//     errors = ["ERROR", "FATAL"]
+//
+//     [profiles]
+//     weblogs = ["--format", "json", "GET /"]
+//
+// Values are strings, integers, booleans, or arrays of strings on one line.
+#[derive(Debug, Default, PartialEq)]
+pub struct Config {
+    // The settings outside of any table, as they would be given on the command line.
+    pub settings: BTreeMap<String, String>,
+
+    // The settings that take an array, one value for each time the flag would be given.
+    pub array_settings: BTreeMap<String, Vec<String>>,
+
+    // Named sets of patterns, from the [patterns] table.
+    pub pattern_sets: BTreeMap<String, Vec<String>>,
+
+    // The arguments that --profile NAME stands for, from the [profiles] table.
+    pub profiles: BTreeMap<String, Vec<String>>,
+}
+
+// The settings that can be given: defaults for --buffer-size, --exclude-dir, --format, --jobs and
+// --ordered.
+const SETTINGS: [&str; 5] = ["buffer_size", "exclude_dir", "format", "jobs", "ordered"];
+
+// The settings for flags that can be given more than once.
+const ARRAY_SETTINGS: [&str; 1] = ["exclude_dir"];
+
+#[derive(Debug, PartialEq)]
+enum Value {
+    String(String),
+    Integer(i64),
+    Bool(bool),
+    Array(Vec<String>),
+}
+
+impl Config {
+    // Reads the config file at `path`. If `path` is None, reads the default config file if it
+    // exists.
+    pub fn load(path: Option<&Path>) -> Result<Config, String> {
+        let (path, required) = match path {
+            Some(path) => (path.to_path_buf(), true),
+            None => match default_path() {
+                Some(path) => (path, false),
+                None => return Ok(Config::default()),
+            },
+        };
+        match fs::read_to_string(&path) {
+            Ok(text) => Config::parse(&text).map_err(|e| format!("{}: {}", path.display(), e)),
+            Err(e) if !required && e.kind() == std::io::ErrorKind::NotFound => {
+                Ok(Config::default())
+            }
+            Err(e) => Err(format!("failed to read {}: {}", path.display(), e)),
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Config, String> {
+        let mut config = Config::default();
+        let mut table = None;
+        for (i, line) in text.lines().enumerate() {
+            let error = |msg: &str| format!("line {}: {}", i + 1, msg);
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[') {
+                let name = name
+                    .strip_suffix(']')
+                    .ok_or_else(|| error("expected ']'"))?;
+                table = match name.trim() {
+                    "patterns" => Some("patterns"),
+                    "profiles" => Some("profiles"),
+                    _ => return Err(error("unknown table; expected [patterns] or [profiles]")),
+                };
+                continue;
+            }
+
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| error("expected 'key = value'"))?;
+            let key = parse_key(key.trim()).ok_or_else(|| error("invalid key"))?;
+            let value = parse_value(value.trim()).map_err(|e| error(&e))?;
+            match (table, value) {
+                (Some("patterns"), Value::Array(patterns)) => {
+                    if patterns.iter().any(|p| p.is_empty()) {
+                        return Err(error("patterns must be non-empty"));
+                    }
+                    config.pattern_sets.insert(key, patterns);
+                }
+                (Some("patterns"), _) => {
+                    return Err(error("a pattern set must be an array of strings"))
+                }
+                (Some(_), Value::Array(args)) => {
+                    if args
+                        .iter()
+                        .any(|a| a == "--profile" || a.starts_with("--profile="))
+                    {
+                        return Err(error("a profile can't use another profile"));
+                    }
+                    config.profiles.insert(key, args);
+                }
+                (Some(_), _) => return Err(error("a profile must be an array of arguments")),
+                (None, value) => {
+                    if !SETTINGS.contains(&key.as_str()) {
+                        return Err(error(&format!("unknown setting '{}'", key)));
+                    }
+                    if ARRAY_SETTINGS.contains(&key.as_str()) {
+                        let Value::Array(values) = value else {
+                            return Err(error("expected an array of strings"));
+                        };
+                        config.array_settings.insert(key, values);
+                        continue;
+                    }
+                    let value = match value {
+                        Value::String(s) => s,
+                        Value::Integer(n) => n.to_string(),
+                        Value::Bool(b) => b.to_string(),
+                        Value::Array(_) => return Err(error("expected a single value")),
+                    };
+                    config.settings.insert(key, value);
+                }
+            }
+        }
+        Ok(config)
+    }
+
+    // Overrides the settings with the FREQ_* environment variables that are set, like
+    // FREQ_BUFFER_SIZE for buffer_size. Array settings are only read from the file.
+    pub fn apply_env(&mut self) {
+        for key in SETTINGS.iter().filter(|key| !ARRAY_SETTINGS.contains(key)) {
+            let var = format!("FREQ_{}", key.to_ascii_uppercase());
+            if let Some(value) = env::var(var).ok().filter(|v| !v.is_empty()) {
+                self.settings.insert(key.to_string(), value);
+            }
+        }
+    }
+}
+
+// The config file in the user's config directory, following the XDG convention.
+fn default_path() -> Option<PathBuf> {
+    let dir = match env::var_os("XDG_CONFIG_HOME").filter(|d| !d.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(env::var_os("HOME")?).join(".config"),
+    };
+    Some(dir.join("freq").join("config.toml"))
+}
+
+// Removes a comment from the end of `line`, unless the '#' is in a string.
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match (quote, c) {
+            (Some('"'), '\\') if !escaped => {
+                escaped = true;
+                continue;
+            }
+            (Some(q), c) if c == q && !escaped => quote = None,
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '#') => return &line[..i],
+            _ => {}
+        }
+        escaped = false;
+    }
+    line
+}
+
+fn parse_key(key: &str) -> Option<String> {
+    if let Ok((s, "")) = parse_string(key) {
+        return Some(s);
+    }
+    let bare = !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    bare.then(|| key.to_string())
+}
+
+fn parse_value(s: &str) -> Result<Value, String> {
+    match s {
+        "true" => return Ok(Value::Bool(true)),
+        "false" => return Ok(Value::Bool(false)),
+        _ => {}
+    }
+    if let Some(mut rest) = s.strip_prefix('[') {
+        let mut items = Vec::new();
+        loop {
+            rest = rest.trim_start();
+            if let Some(after) = rest.strip_prefix(']') {
+                return match after.trim() {
+                    "" => Ok(Value::Array(items)),
+                    _ => Err("unexpected text after the array".to_string()),
+                };
+            }
+            let (item, after) = parse_string(rest)?;
+            items.push(item);
+            rest = after.trim_start();
+            rest = match rest.strip_prefix(',') {
+                Some(after) => after,
+                None if rest.starts_with(']') => rest,
+                None => return Err("expected ',' or ']'".to_string()),
+            };
+        }
+    }
+    if s.starts_with(['"', '\'']) {
+        return match parse_string(s)? {
+            (s, "") => Ok(Value::String(s)),
+            _ => Err("unexpected text after the string".to_string()),
+        };
+    }
+    s.replace('_', "")
+        .parse()
+        .map(Value::Integer)
+        .map_err(|_| "expected a string, integer, boolean or array of strings".to_string())
+}
+
+// Parses the basic ("...") or literal ('...') string at the start of `s`, returning it and the
+// rest of `s`.
+fn parse_string(s: &str) -> Result<(String, &str), String> {
+    if let Some(rest) = s.strip_prefix('\'') {
+        let end = rest.find('\'').ok_or("unterminated string")?;
+        return Ok((rest[..end].to_string(), &rest[end + 1..]));
+    }
+    let rest = s.strip_prefix('"').ok_or("expected a string")?;
+    let mut out = String::new();
+    let mut chars = rest.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Ok((out, &rest[i + 1..])),
+            '\\' => out.push(match chars.next().map(|(_, c)| c) {
+                Some('"') => '"',
+                Some('\\') => '\\',
+                Some('n') => '\n',
+                Some('t') => '\t',
+                Some('r') => '\r',
+                _ => return Err("unknown escape; use \\\", \\\\, \\n, \\t or \\r".to_string()),
+            }),
+            c => out.push(c),
+        }
+    }
+    Err("unterminated string".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let config = Config::parse(
+            r##"
+# Defaults for this machine.
+buffer_size = 4_194_304
+exclude_dir = ["node_modules", '.*']
+format = "json"  # for the dashboards
+ordered = true
+
+[patterns]
+errors = ["ERROR", 'FATAL', "a \"#\" b"]
+"empty set" = []
+
+[profiles]
+weblogs = ["--format", "json", "GET /"]
+"##,
+        )
+        .unwrap();
+        assert_eq!(config.settings["buffer_size"], "4194304");
+        assert_eq!(config.settings["format"], "json");
+        assert_eq!(config.array_settings["exclude_dir"], ["node_modules", ".*"]);
+        assert_eq!(config.settings["ordered"], "true");
+        assert_eq!(
+            config.pattern_sets["errors"],
+            ["ERROR", "FATAL", "a \"#\" b"]
+        );
+        assert!(config.pattern_sets["empty set"].is_empty());
+        assert_eq!(config.profiles["weblogs"], ["--format", "json", "GET /"]);
+
+        assert!(Config::parse("colour = true").is_err());
+        assert!(Config::parse("format = [\"json\"]").is_err());
+        assert!(Config::parse("exclude_dir = \"node_modules\"").is_err());
+        assert!(Config::parse("[patterns]\nerrors = \"ERROR\"").is_err());
+        assert!(Config::parse("[other]").is_err());
+        assert!(Config::parse("[profiles]\na = [\"--profile=b\"]").is_err());
+        assert!(Config::parse("jobs = 4 4").is_err());
+        assert!(Config::parse("format = \"json").is_err());
+        assert_eq!(
+            Config::parse("\n\njobs").unwrap_err(),
+            "line 3: expected 'key = value'"
+        );
+    }
+}
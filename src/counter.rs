@@ -0,0 +1,490 @@
+use crate::matcher::{Literal, Matcher};
+
+/// Counts non-overlapping matches in a stream of bytes that arrives in chunks.
+pub struct Counter<M> {
+    // The searcher we use to find needles.
+    matcher: M,
+
+    // Matching from the start of the stream.
+    run: Run,
+
+    // How many bytes we have been given.
+    len: usize,
+
+    // The first (at most n - 1) bytes of the stream, where n is the longest possible match.
+    head: Vec<u8>,
+
+    // shadows[k - 1] tracks matching that starts at offset k of the stream, for 0 < k < n.
+    // Merging needs these when a match spans the boundary and ends k bytes into the right-hand
+    // counter. Except for highly repetitive input, they agree with `run` soon after the stream
+    // starts and stop costing anything. There are none unless the counter is mergeable.
+    shadows: Vec<Shadow>,
+}
+
+#[derive(Clone, Default)]
+struct Run {
+    // How many matches of each pattern we have found.
+    counts: Vec<usize>,
+
+    // For holding intermediate data.
+    // We keep it around to avoid reallocating it.
+    // It is at most n - 1 bytes long, and starts where the next needle might start.
+    tmp_buf: Vec<u8>,
+}
+
+enum Shadow {
+    // Still different from the main run. The first `skip` bytes of the stream have not been
+    // skipped yet.
+    Live { skip: usize, run: Run },
+
+    // Identical to the main run from now on, which has found `delta` more matches of each
+    // pattern. With several patterns the shadow can be ahead on some of them, so the deltas
+    // wrap around.
+    Synced { delta: Vec<usize> },
+}
+
+/// Counts non-overlapping occurrences of a literal needle.
+pub type NeedleCounter = Counter<Literal>;
+
+impl NeedleCounter {
+    pub fn new(needle: &[u8]) -> Self {
+        Counter::with_matcher(Literal::new(needle))
+    }
+}
+
+impl<M: Matcher> Counter<M> {
+    pub fn with_matcher(matcher: M) -> Self {
+        let patterns = matcher.patterns();
+        Counter {
+            matcher,
+            run: Run::new(patterns),
+            len: 0,
+            head: Vec::new(),
+            shadows: Vec::new(),
+        }
+    }
+
+    /// Like [`Counter::with_matcher`], but the counter can be passed to [`Counter::merge`]. This
+    /// costs up to `max_len() - 1` extra passes over the start of the stream, so only counters
+    /// that are merged should be made this way.
+    pub fn mergeable(matcher: M) -> Self {
+        let mut counter = Counter::with_matcher(matcher);
+        let patterns = counter.matcher.patterns();
+        counter.shadows = (1..counter.matcher.max_len())
+            .map(|skip| Shadow::Live {
+                skip,
+                run: Run::new(patterns),
+            })
+            .collect();
+        counter
+    }
+
+    pub fn matcher(&self) -> &M {
+        &self.matcher
+    }
+
+    /// The number of matches found so far.
+    pub fn count(&self) -> usize {
+        self.run.counts.iter().sum()
+    }
+
+    /// The number of matches of each of the matcher's patterns found so far.
+    pub fn counts(&self) -> &[usize] {
+        &self.run.counts
+    }
+
+    pub fn write(&mut self, buf: &[u8]) {
+        if buf.is_empty() {
+            return;
+        }
+
+        let n = self.matcher.max_len();
+        let head_len = (n - 1 - self.head.len()).min(buf.len());
+        self.head.extend(&buf[..head_len]);
+        self.len += buf.len();
+
+        if !self.has_live_shadows() {
+            self.run.write(&self.matcher, buf);
+            return;
+        }
+
+        // Go in pieces so that shadows are retired as soon as they catch up with the main run.
+        for piece in buf.chunks((8 * n).max(4096)) {
+            self.run.write(&self.matcher, piece);
+            for shadow in &mut self.shadows {
+                if let Shadow::Live { skip, run } = shadow {
+                    let skipped = (*skip).min(piece.len());
+                    *skip -= skipped;
+                    run.write(&self.matcher, &piece[skipped..]);
+                }
+            }
+            self.sync_shadows();
+        }
+    }
+
+    /// Adds the counts from `other`, which must have been given the bytes immediately following
+    /// the ones given to `self`, for the same matcher, and made with [`Counter::mergeable`].
+    ///
+    /// Afterwards `self` is as if it had been given both streams in turn, so matches spanning
+    /// the boundary are counted, and it can keep being written to.
+    pub fn merge(&mut self, other: Counter<M>)
+    where
+        M: PartialEq,
+    {
+        assert!(
+            self.matcher == other.matcher,
+            "cannot merge counters for different matchers"
+        );
+
+        let n = self.matcher.max_len();
+        if other.len < n {
+            // `other` is short enough that we have all of it.
+            self.write(&other.head);
+            return;
+        }
+        assert!(
+            other.shadows.len() == n - 1,
+            "only counters made with Counter::mergeable can be merged"
+        );
+
+        let run = std::mem::take(&mut self.run);
+        self.run = other.continue_run(run);
+        for shadow in &mut self.shadows {
+            if let Shadow::Live { skip, run } = shadow {
+                let run = std::mem::take(run);
+                *shadow = Shadow::Live {
+                    skip: 0,
+                    run: if *skip == 0 {
+                        other.continue_run(run)
+                    } else {
+                        other.run_at(*skip)
+                    },
+                };
+            }
+        }
+
+        let head_len = (n - 1 - self.head.len()).min(other.head.len());
+        self.head.extend(&other.head[..head_len]);
+        self.len += other.len;
+        self.sync_shadows();
+    }
+
+    /// Forgets everything written so far, keeping allocations for reuse.
+    pub fn reset(&mut self) {
+        let patterns = self.matcher.patterns();
+        self.run.clear();
+        self.len = 0;
+        self.head.clear();
+        for (i, shadow) in self.shadows.iter_mut().enumerate() {
+            let mut run = match shadow {
+                Shadow::Live { run, .. } => std::mem::take(run),
+                Shadow::Synced { .. } => Run::new(patterns),
+            };
+            run.clear();
+            *shadow = Shadow::Live { skip: i + 1, run };
+        }
+    }
+
+    fn has_live_shadows(&self) -> bool {
+        self.shadows
+            .iter()
+            .any(|shadow| matches!(shadow, Shadow::Live { .. }))
+    }
+
+    // Retires shadows that are in the same state as the main run.
+    fn sync_shadows(&mut self) {
+        for shadow in &mut self.shadows {
+            if let Shadow::Live { skip: 0, run } = shadow {
+                if run.tmp_buf == self.run.tmp_buf {
+                    let delta = self.run.counts.iter().zip(&run.counts);
+                    *shadow = Shadow::Synced {
+                        delta: delta.map(|(a, b)| a.wrapping_sub(*b)).collect(),
+                    };
+                }
+            }
+        }
+    }
+
+    // The run over our stream starting at offset `k`, for k < n <= self.len.
+    fn run_at(&self, k: usize) -> Run {
+        match k.checked_sub(1).map(|i| &self.shadows[i]) {
+            None => self.run.clone(),
+            Some(Shadow::Live { run, .. }) => run.clone(),
+            Some(Shadow::Synced { delta }) => Run {
+                counts: self
+                    .run
+                    .counts
+                    .iter()
+                    .zip(delta)
+                    .map(|(a, d)| a.wrapping_sub(*d))
+                    .collect(),
+                tmp_buf: self.run.tmp_buf.clone(),
+            },
+        }
+    }
+
+    // Continues `run` over our stream, which must be at least n bytes long.
+    fn continue_run(&self, run: Run) -> Run {
+        let Run {
+            mut counts,
+            tmp_buf: tail,
+        } = run;
+        let mut joined = tail.clone();
+        joined.extend(&self.head);
+
+        // Count the matches that start before the boundary. The run continues in whichever of
+        // our runs starts where the last of them ends.
+        let mut x = 0;
+        self.matcher.find_all(&joined, &mut |m| {
+            if m.start >= tail.len() {
+                return false;
+            }
+            counts[m.pattern] += 1;
+            x = m.end;
+            true
+        });
+
+        let rest = self.run_at(x.saturating_sub(tail.len()));
+        for (c, r) in counts.iter_mut().zip(&rest.counts) {
+            *c += r;
+        }
+        Run {
+            counts,
+            tmp_buf: rest.tmp_buf,
+        }
+    }
+}
+
+impl Run {
+    fn new(patterns: usize) -> Self {
+        Run {
+            counts: vec![0; patterns],
+            tmp_buf: Vec::new(),
+        }
+    }
+
+    fn clear(&mut self) {
+        self.counts.iter_mut().for_each(|c| *c = 0);
+        self.tmp_buf.clear();
+    }
+
+    fn write<M: Matcher>(&mut self, matcher: &M, buf: &[u8]) {
+        if buf.is_empty() {
+            return;
+        }
+
+        // Fast case - if the needle has length 1 we can use a simd loop.
+        if let Some(b) = matcher.single_byte() {
+            self.counts[0] += bytecount::count(buf, b);
+            return;
+        }
+
+        let n = matcher.max_len();
+
+        // The number of bytes in the buffer that we have moved to the tmp buffer.
+        let mut num_buf_bytes = 0;
+
+        if !self.tmp_buf.is_empty() {
+            // Add into the tmp buffer until it is at most 2 * n - 1 bytes long.
+            let y_len = (2 * n - 1)
+                .saturating_sub(self.tmp_buf.len())
+                .min(buf.len());
+            let y = &buf[..y_len];
+            num_buf_bytes = y_len;
+            self.tmp_buf.extend(y);
+
+            // Check for a needle in the tmp buffer.
+            // This will also count the needle if it is there.
+            let cut = find_in(matcher, &self.tmp_buf, &mut self.counts);
+
+            // Remove any bytes that are before the next needle.
+            self.tmp_buf.drain(..cut);
+        }
+
+        if num_buf_bytes == buf.len() {
+            return;
+        }
+
+        num_buf_bytes -= self.tmp_buf.len();
+        self.tmp_buf.clear();
+        // Now we can search the rest of the new buffer for the needle.
+        let next_buffer_cut = find_in(matcher, &buf[num_buf_bytes..], &mut self.counts);
+
+        // Move the rest of the buffer to the temporary buffer.
+        self.tmp_buf.extend(&buf[num_buf_bytes + next_buffer_cut..]);
+    }
+}
+
+// Count matches in the buffer, adding them to `counts`.
+// Returns the largest index `i` such that `buf[..i]` does not contain the start of any match
+// that is not yet complete.
+fn find_in<M: Matcher>(matcher: &M, buf: &[u8], counts: &mut [usize]) -> usize {
+    let n = matcher.max_len();
+    let mut x = 0;
+    matcher.find_all(buf, &mut |m| {
+        counts[m.pattern] += 1;
+        x = m.end;
+        true
+    });
+
+    let l = buf.len().saturating_sub(n - 1).max(x);
+    first_possible_start(matcher, &buf[l..]) + l
+}
+
+fn first_possible_start<M: Matcher>(matcher: &M, buf: &[u8]) -> usize {
+    (0..buf.len())
+        .find(|&i| matcher.could_start(&buf[i..]))
+        .unwrap_or(buf.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Literals;
+
+    use memchr::memmem::find_iter;
+    use proptest::prelude::ProptestConfig;
+    use proptest::string::bytes_regex;
+    use proptest::{prop_assert_eq, proptest};
+
+    proptest! {
+        #![proptest_config(ProptestConfig {
+            cases: 1 << 16,
+            .. ProptestConfig::default()
+        })]
+
+        #[test]
+        fn test_count(
+            chunk_size in 1..100_usize,
+            needle in bytes_regex("((?s-u:.{1,100}))").unwrap(),
+            haystack in bytes_regex("((?s-u:.{0,1000}))").unwrap()
+        ) {
+            let mut counter = NeedleCounter::new(&needle);
+
+            haystack.chunks(chunk_size).for_each(|chunk| {
+                counter.write(chunk);
+            });
+
+
+            let expected = find_iter(&haystack, &needle).count();
+            assert_eq!(counter.count(), expected);
+        }
+
+        #[test]
+        fn test_aba(
+            chunk_size in 1..100_usize,
+            needle in bytes_regex("((?s-u:[ab]{1,10}))").unwrap(),
+            haystack in bytes_regex("((?s-u:[ab]{0,1000}))").unwrap()
+        ) {
+            let mut counter = NeedleCounter::new(&needle);
+
+            haystack.chunks(chunk_size).for_each(|chunk| {
+                counter.write(chunk);
+            });
+
+
+            let expected = find_iter(&haystack, &needle).count();
+            prop_assert_eq!(counter.count(), expected);
+        }
+
+        #[test]
+        fn test_merge(
+            chunk_size in 1..100_usize,
+            needle in bytes_regex("((?s-u:[ab]{1,10}))").unwrap(),
+            haystack in bytes_regex("((?s-u:[ab]{0,1000}))").unwrap(),
+            splits in proptest::collection::vec(0..1000_usize, 0..4),
+            from_left in proptest::bool::ANY,
+        ) {
+            let mut splits: Vec<usize> = splits.into_iter().map(|i| i.min(haystack.len())).collect();
+            splits.push(0);
+            splits.push(haystack.len());
+            splits.sort();
+
+            let mut counters: Vec<NeedleCounter> = splits.windows(2).map(|w| {
+                let mut counter = Counter::mergeable(Literal::new(&needle));
+                haystack[w[0]..w[1]].chunks(chunk_size).for_each(|chunk| {
+                    counter.write(chunk);
+                });
+                counter
+            }).collect();
+
+            let mut counter = if from_left {
+                let mut counters = counters.into_iter();
+                let mut counter = counters.next().unwrap();
+                counters.for_each(|right| counter.merge(right));
+                counter
+            } else {
+                let mut counter = counters.pop().unwrap();
+                while let Some(mut left) = counters.pop() {
+                    left.merge(counter);
+                    counter = left;
+                }
+                counter
+            };
+
+            let expected = find_iter(&haystack, &needle).count();
+            prop_assert_eq!(counter.count(), expected);
+
+            // The merged counter carries on as if it had seen everything.
+            counter.write(&haystack);
+            let expected = find_iter(&[&haystack[..], &haystack[..]].concat(), &needle).count();
+            prop_assert_eq!(counter.count(), expected);
+        }
+
+        #[test]
+        fn test_reset(
+            chunk_size in 1..100_usize,
+            needle in bytes_regex("((?s-u:[ab]{1,10}))").unwrap(),
+            haystack in bytes_regex("((?s-u:[ab]{0,1000}))").unwrap()
+        ) {
+            let mut counter = NeedleCounter::new(&needle);
+            counter.write(&haystack);
+            counter.reset();
+
+            haystack.chunks(chunk_size).for_each(|chunk| {
+                counter.write(chunk);
+            });
+
+            let expected = find_iter(&haystack, &needle).count();
+            prop_assert_eq!(counter.count(), expected);
+        }
+
+        #[test]
+        fn test_literals(
+            chunk_size in 1..100_usize,
+            needles in proptest::collection::vec(bytes_regex("((?s-u:[ab]{1,6}))").unwrap(), 1..4),
+            haystack in bytes_regex("((?s-u:[ab]{0,300}))").unwrap(),
+            split in 0..300_usize,
+        ) {
+            let matcher = Literals::new(&needles);
+            let mut counter = Counter::with_matcher(matcher.clone());
+            haystack.chunks(chunk_size).for_each(|chunk| {
+                counter.write(chunk);
+            });
+
+            // At each point, take the match that ends first, breaking ties by pattern.
+            let mut expected = vec![0; needles.len()];
+            let mut pos = 0;
+            while let Some((end, i)) = needles
+                .iter()
+                .enumerate()
+                .filter_map(|(i, n)| {
+                    find_iter(&haystack[pos..], n).next().map(|s| (pos + s + n.len(), i))
+                })
+                .min()
+            {
+                expected[i] += 1;
+                pos = end;
+            }
+            prop_assert_eq!(counter.counts(), &expected[..]);
+
+            let split = split.min(haystack.len());
+            let mut left = Counter::mergeable(matcher.clone());
+            left.write(&haystack[..split]);
+            let mut right = Counter::mergeable(matcher);
+            right.write(&haystack[split..]);
+            left.merge(right);
+            prop_assert_eq!(left.counts(), &expected[..]);
+        }
+    }
+}
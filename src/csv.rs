@@ -0,0 +1,219 @@
+use crate::linelimit::{self, Limit};
+use memchr::{memchr, memchr2};
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    // At the start of a field, where a quote opens a quoted field.
+    FieldStart,
+    Unquoted,
+    Quoted,
+    // Just after a quote in a quoted field. Another quote makes it a literal quote; anything else
+    // closes the quoted section.
+    QuoteInQuoted,
+}
+
+// Reads one column of CSV data, chunk by chunk. Quoted fields may contain delimiters, doubled
+// quotes and newlines. Records end with "\n" or "\r\n".
+//
+// This is lenient about malformed input: a quote inside an unquoted field is kept as is, as is
+// text after the closing quote of a quoted field, and an unterminated quote runs to the end of
+// the input.
+//
+// Only the field that is read is held in memory, so --max-line-length limits its values rather
+// than whole records.
+pub struct CsvReader {
+    delimiter: u8,
+    quote: u8,
+
+    // The field to read, numbered from 1.
+    field: usize,
+
+    state: State,
+
+    // The number of the current field in the current record.
+    field_index: usize,
+
+    // The contents of the current field so far, if it is the one we are reading.
+    value: Vec<u8>,
+
+    // Whether `value` ends with a '\r' from outside quotes, which is dropped if the record ends
+    // right after it.
+    trailing_cr: bool,
+
+    // Whether we have seen any of the current record.
+    in_record: bool,
+
+    limit: Limit,
+}
+
+impl CsvReader {
+    pub fn new(delimiter: u8, quote: u8, field: usize) -> Self {
+        CsvReader {
+            delimiter,
+            quote,
+            field,
+            state: State::FieldStart,
+            field_index: 1,
+            value: Vec::new(),
+            trailing_cr: false,
+            in_record: false,
+            limit: linelimit::get(),
+        }
+    }
+
+    // Calls `f` on the value of the field in each record that ends in `buf`. Records with fewer
+    // fields are skipped.
+    pub fn write(&mut self, buf: &[u8], f: &mut impl FnMut(&[u8])) {
+        let mut i = 0;
+        while i < buf.len() {
+            self.in_record = true;
+            match self.state {
+                State::FieldStart => {
+                    if buf[i] == self.quote {
+                        self.state = State::Quoted;
+                        i += 1;
+                    } else {
+                        self.state = State::Unquoted;
+                    }
+                }
+                State::Unquoted => {
+                    let rest = &buf[i..];
+                    match memchr2(self.delimiter, b'\n', rest) {
+                        Some(j) => {
+                            self.append(&rest[..j], true);
+                            if rest[j] == b'\n' {
+                                self.end_record(f);
+                            } else {
+                                self.end_field(f);
+                            }
+                            i += j + 1;
+                        }
+                        None => {
+                            self.append(rest, true);
+                            i = buf.len();
+                        }
+                    }
+                }
+                State::Quoted => {
+                    let rest = &buf[i..];
+                    match memchr(self.quote, rest) {
+                        Some(j) => {
+                            self.append(&rest[..j], false);
+                            self.state = State::QuoteInQuoted;
+                            i += j + 1;
+                        }
+                        None => {
+                            self.append(rest, false);
+                            i = buf.len();
+                        }
+                    }
+                }
+                State::QuoteInQuoted => {
+                    if buf[i] == self.quote {
+                        self.append(&[self.quote], false);
+                        self.state = State::Quoted;
+                        i += 1;
+                    } else {
+                        self.state = State::Unquoted;
+                    }
+                }
+            }
+        }
+    }
+
+    // Ends the current input, reading the last record if it has no trailing newline.
+    pub fn finish(&mut self, f: &mut impl FnMut(&[u8])) {
+        if self.in_record {
+            self.end_record(f);
+        }
+    }
+
+    fn append(&mut self, bytes: &[u8], unquoted: bool) {
+        if self.field_index == self.field && !bytes.is_empty() {
+            // The last byte is kept, since it may be a '\r' to drop.
+            self.limit.extend(&mut self.value, bytes, 1);
+            self.trailing_cr = unquoted && bytes.ends_with(b"\r");
+        }
+    }
+
+    fn end_field(&mut self, f: &mut impl FnMut(&[u8])) {
+        if self.field_index == self.field {
+            if let Some(value) = self.limit.apply(&self.value) {
+                f(value);
+            }
+            self.value.clear();
+        }
+        self.field_index += 1;
+        self.trailing_cr = false;
+        self.state = State::FieldStart;
+    }
+
+    fn end_record(&mut self, f: &mut impl FnMut(&[u8])) {
+        if self.trailing_cr {
+            self.value.pop();
+        }
+        self.end_field(f);
+        self.field_index = 1;
+        self.in_record = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use proptest::collection::vec;
+    use proptest::string::string_regex;
+    use proptest::{prop_assert_eq, proptest};
+
+    fn read(text: &[u8], field: usize, chunk_size: usize) -> Vec<String> {
+        let mut reader = CsvReader::new(b',', b'"', field);
+        let mut values = Vec::new();
+        let mut f = |v: &[u8]| values.push(String::from_utf8(v.to_vec()).unwrap());
+        text.chunks(chunk_size)
+            .for_each(|chunk| reader.write(chunk, &mut f));
+        reader.finish(&mut f);
+        values
+    }
+
+    #[test]
+    fn test_quoting() {
+        let text = b"a,\"b,\"\"c\"\"\nd\",e\r\n\"x\"y,\"\",z\nshort\n\"open,";
+        assert_eq!(read(text, 1, 100), ["a", "xy", "short", "open,"]);
+        assert_eq!(read(text, 2, 100), ["b,\"c\"\nd", ""]);
+        assert_eq!(read(text, 3, 100), ["e", "z"]);
+    }
+
+    proptest! {
+        #[test]
+        fn test_chunked(
+            chunk_size in 1..20_usize,
+            field in 1..4_usize,
+            crlf: bool,
+            records in vec(vec(string_regex("[a,\"\r\n]{0,5}").unwrap(), 1..5), 0..20),
+        ) {
+            let mut text = Vec::new();
+            for record in &records {
+                for (i, value) in record.iter().enumerate() {
+                    if i > 0 {
+                        text.push(b',');
+                    }
+                    if value.bytes().any(|b| b",\"\r\n".contains(&b)) {
+                        text.push(b'"');
+                        text.extend(value.replace('"', "\"\"").bytes());
+                        text.push(b'"');
+                    } else {
+                        text.extend(value.bytes());
+                    }
+                }
+                text.extend(if crlf { &b"\r\n"[..] } else { b"\n" });
+            }
+
+            let expected: Vec<_> = records
+                .iter()
+                .filter_map(|record| record.get(field - 1).cloned())
+                .collect();
+            prop_assert_eq!(read(&text, field, chunk_size), expected);
+        }
+    }
+}
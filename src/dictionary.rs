@@ -0,0 +1,199 @@
+use crate::matcher::{Match, Matcher};
+use aho_corasick::{AhoCorasick, AhoCorasickKind};
+
+/// Which automaton a [`Dictionary`] searches with. All of them find the same matches.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Automaton {
+    /// A DFA for up to a hundred or so needles, where it is small, and a compact NFA for more.
+    #[default]
+    Auto,
+    /// An NFA with its states packed together, which takes the least memory, and is the one to
+    /// use for hundreds of thousands of needles.
+    Nfa,
+    /// A DFA, which is the fastest to search with, but can take many times the memory of the NFA
+    /// and much longer to build.
+    Dfa,
+}
+
+/// Matches any of many literal byte strings with a single Aho-Corasick automaton, so that the
+/// haystack is read once however many there are. Matches are the same as those of
+/// [`Literals`](crate::Literals): where two end at the same place, the earlier pattern wins.
+#[derive(Clone)]
+pub struct Dictionary {
+    automaton: AhoCorasick,
+    needles: Vec<Vec<u8>>,
+}
+
+impl Dictionary {
+    pub fn new<N: AsRef<[u8]>>(needles: impl IntoIterator<Item = N>) -> Self {
+        Dictionary::with_automaton(needles, Automaton::Auto, true)
+    }
+
+    /// Builds the dictionary with `automaton`, and with `prefilter`, skips quickly to where a
+    /// needle may start, which helps when matches are rare and there aren't many needles.
+    pub fn with_automaton<N: AsRef<[u8]>>(
+        needles: impl IntoIterator<Item = N>,
+        automaton: Automaton,
+        prefilter: bool,
+    ) -> Self {
+        let needles: Vec<Vec<u8>> = needles
+            .into_iter()
+            .map(|needle| {
+                let needle = needle.as_ref();
+                assert!(!needle.is_empty(), "needles must be non-empty");
+                needle.to_vec()
+            })
+            .collect();
+        assert!(!needles.is_empty(), "there must be at least one needle");
+        let kind = match automaton {
+            Automaton::Auto => None,
+            Automaton::Nfa => Some(AhoCorasickKind::ContiguousNFA),
+            Automaton::Dfa => Some(AhoCorasickKind::DFA),
+        };
+        let automaton = AhoCorasick::builder()
+            .kind(kind)
+            .prefilter(prefilter)
+            .build(&needles)
+            .expect("the automaton is small enough");
+        Dictionary { automaton, needles }
+    }
+
+    pub fn needles(&self) -> impl Iterator<Item = &[u8]> {
+        self.needles.iter().map(|n| &n[..])
+    }
+
+    /// Calls `f` with every match of every needle in `haystack`, including those that overlap,
+    /// in the order of where they end.
+    pub fn find_overlapping(&self, haystack: &[u8], f: &mut dyn FnMut(Match)) {
+        for m in self.automaton.find_overlapping_iter(haystack) {
+            f(Match {
+                start: m.start(),
+                end: m.end(),
+                pattern: m.pattern().as_usize(),
+            });
+        }
+    }
+
+    /// How many bytes the automaton takes.
+    pub fn memory_usage(&self) -> usize {
+        self.automaton.memory_usage()
+    }
+}
+
+impl PartialEq for Dictionary {
+    fn eq(&self, other: &Self) -> bool {
+        self.needles == other.needles
+    }
+}
+
+impl Matcher for Dictionary {
+    fn find_all(&self, haystack: &[u8], f: &mut dyn FnMut(Match) -> bool) {
+        // Overlapping matches come in order of where they end, so the first one to start after
+        // the last match ended is the next match, unless an earlier pattern ends there too.
+        let mut from = 0;
+        let mut best: Option<Match> = None;
+        for m in self.automaton.find_overlapping_iter(haystack) {
+            if best.is_some_and(|b| m.end() > b.end) {
+                let b = best.take().unwrap();
+                if !f(b) {
+                    return;
+                }
+                from = b.end;
+            }
+            if m.start() < from {
+                continue;
+            }
+            let m = Match {
+                start: m.start(),
+                end: m.end(),
+                pattern: m.pattern().as_usize(),
+            };
+            if best.is_none_or(|b| m.pattern < b.pattern) {
+                best = Some(m);
+            }
+        }
+        if let Some(b) = best {
+            f(b);
+        }
+    }
+
+    fn max_len(&self) -> usize {
+        self.needles().map(|n| n.len()).max().unwrap()
+    }
+
+    fn patterns(&self) -> usize {
+        self.needles.len()
+    }
+
+    fn could_start(&self, partial: &[u8]) -> bool {
+        self.needles().any(|n| n.starts_with(partial))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Counter, Literals};
+
+    use proptest::string::bytes_regex;
+    use proptest::{prop_assert_eq, proptest};
+
+    fn find(matcher: &impl Matcher, haystack: &[u8]) -> Vec<Match> {
+        let mut matches = Vec::new();
+        matcher.find_all(haystack, &mut |m| {
+            matches.push(m);
+            true
+        });
+        matches
+    }
+
+    #[test]
+    fn test_dictionary() {
+        let dictionary = Dictionary::new(["abc", "bc", "b", "xyz"]);
+        let ends: Vec<_> = find(&dictionary, b"abcxyzbc")
+            .iter()
+            .map(|m| (m.end, m.pattern))
+            .collect();
+        assert_eq!(ends, [(2, 2), (6, 3), (7, 2)]);
+    }
+
+    proptest! {
+        #[test]
+        fn test_literals(
+            needles in proptest::collection::vec(bytes_regex("((?s-u:[ab]{1,6}))").unwrap(), 1..6),
+            haystack in bytes_regex("((?s-u:[abc]{0,200}))").unwrap(),
+        ) {
+            let literals = find(&Literals::new(&needles), &haystack);
+            for automaton in [Automaton::Auto, Automaton::Nfa, Automaton::Dfa] {
+                let dictionary = Dictionary::with_automaton(&needles, automaton, automaton == Automaton::Nfa);
+                prop_assert_eq!(&find(&dictionary, &haystack), &literals);
+            }
+        }
+
+        #[test]
+        fn test_chunked(
+            chunk_size in 1..20_usize,
+            needles in proptest::collection::vec(bytes_regex("((?s-u:[ab]{1,6}))").unwrap(), 1..6),
+            haystack in bytes_regex("((?s-u:[abc]{0,200}))").unwrap(),
+            split in 0..200_usize,
+        ) {
+            let dictionary = Dictionary::new(&needles);
+            let mut expected = vec![0; needles.len()];
+            for m in find(&dictionary, &haystack) {
+                expected[m.pattern] += 1;
+            }
+
+            let mut counter = Counter::with_matcher(dictionary.clone());
+            haystack.chunks(chunk_size).for_each(|chunk| counter.write(chunk));
+            prop_assert_eq!(counter.counts(), &expected[..]);
+
+            let split = split.min(haystack.len());
+            let mut left = Counter::mergeable(dictionary.clone());
+            left.write(&haystack[..split]);
+            let mut right = Counter::mergeable(dictionary);
+            right.write(&haystack[split..]);
+            left.merge(right);
+            prop_assert_eq!(left.counts(), &expected[..]);
+        }
+    }
+}
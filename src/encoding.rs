@@ -0,0 +1,74 @@
+use clap::ValueEnum;
+use std::ffi::OsStr;
+
+// How a pattern from the command line is turned into the bytes that are searched for.
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum PatternEncoding {
+    /// The bytes of the argument as they were given. On Windows, arguments are UTF-16, and this
+    /// is their WTF-8 form, which is UTF-8 unless they hold unpaired surrogates.
+    Raw,
+    /// UTF-8. The pattern must be valid Unicode.
+    Utf8,
+    /// UTF-16, little-endian, as written by most Windows tools.
+    Utf16le,
+    /// UTF-16, big-endian.
+    Utf16be,
+}
+
+impl PatternEncoding {
+    // Returns the bytes to search for, or None if the pattern has to be valid Unicode but isn't.
+    pub fn encode(self, pattern: &OsStr) -> Option<Vec<u8>> {
+        let units = |s: &str| s.encode_utf16().collect::<Vec<u16>>();
+        match self {
+            PatternEncoding::Raw => Some(pattern.as_encoded_bytes().to_vec()),
+            PatternEncoding::Utf8 => Some(pattern.to_str()?.as_bytes().to_vec()),
+            PatternEncoding::Utf16le => Some(
+                units(pattern.to_str()?)
+                    .into_iter()
+                    .flat_map(u16::to_le_bytes)
+                    .collect(),
+            ),
+            PatternEncoding::Utf16be => Some(
+                units(pattern.to_str()?)
+                    .into_iter()
+                    .flat_map(u16::to_be_bytes)
+                    .collect(),
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode() {
+        let pattern = OsStr::new("né€");
+        assert_eq!(
+            PatternEncoding::Raw.encode(pattern).unwrap(),
+            "né€".as_bytes()
+        );
+        assert_eq!(
+            PatternEncoding::Utf8.encode(pattern).unwrap(),
+            "né€".as_bytes()
+        );
+        assert_eq!(
+            PatternEncoding::Utf16le.encode(pattern).unwrap(),
+            [b'n', 0, 0xe9, 0, 0xac, 0x20]
+        );
+        assert_eq!(
+            PatternEncoding::Utf16be.encode(pattern).unwrap(),
+            [0, b'n', 0, 0xe9, 0x20, 0xac]
+        );
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::ffi::OsStrExt;
+            let invalid = OsStr::from_bytes(b"a\xff");
+            assert_eq!(PatternEncoding::Raw.encode(invalid).unwrap(), b"a\xff");
+            assert_eq!(PatternEncoding::Utf8.encode(invalid), None);
+            assert_eq!(PatternEncoding::Utf16le.encode(invalid), None);
+        }
+    }
+}
@@ -0,0 +1,155 @@
+#[cfg(feature = "hyperscan")]
+use crate::hyperscan;
+use clap::ValueEnum;
+use freq::{Counter, Dictionary, Matcher};
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+#[cfg(feature = "hyperscan")]
+use std::sync::Arc;
+use tracing::{debug_span, info};
+
+// What finds the matches of patterns, from --engine.
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum Engine {
+    /// freq's own matchers, which need nothing else.
+    Builtin,
+    /// Hyperscan or Vectorscan (libhs), for many patterns at once. The patterns are still matched
+    /// literally, not as regular expressions. Needs freq to be built with the hyperscan feature.
+    Hyperscan,
+}
+
+static ENGINE: AtomicU8 = AtomicU8::new(Engine::Builtin as u8);
+
+// The automaton that many patterns are matched with at once, from --automaton.
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum Automaton {
+    /// A DFA for up to a hundred or so patterns, and otherwise the NFA.
+    Auto,
+    /// A compact NFA, which takes the least memory, for hundreds of thousands of patterns.
+    Nfa,
+    /// A DFA, which is fastest, but can take many times the memory of the NFA.
+    Dfa,
+}
+
+static AUTOMATON: AtomicU8 = AtomicU8::new(Automaton::Auto as u8);
+static PREFILTER: AtomicBool = AtomicBool::new(true);
+
+// With more patterns than this, they are matched with one automaton, instead of searching for
+// each of them on its own.
+pub const FEW_PATTERNS: usize = 8;
+
+// Sets the engine, or says why it can't be used.
+pub fn set(engine: Engine) -> Result<(), &'static str> {
+    if engine == Engine::Hyperscan && !cfg!(feature = "hyperscan") {
+        return Err(
+            "freq was built without the hyperscan feature, so it can't use --engine hyperscan",
+        );
+    }
+    ENGINE.store(engine as u8, Ordering::Relaxed);
+    Ok(())
+}
+
+pub fn set_automaton(automaton: Automaton, prefilter: bool) {
+    AUTOMATON.store(automaton as u8, Ordering::Relaxed);
+    PREFILTER.store(prefilter, Ordering::Relaxed);
+}
+
+// Builds a dictionary of `needles` with the automaton asked for.
+pub fn dictionary<N: AsRef<[u8]>>(needles: &[N]) -> Dictionary {
+    let automaton = match AUTOMATON.load(Ordering::Relaxed) {
+        a if a == Automaton::Nfa as u8 => freq::Automaton::Nfa,
+        a if a == Automaton::Dfa as u8 => freq::Automaton::Dfa,
+        _ => freq::Automaton::Auto,
+    };
+    let prefilter = PREFILTER.load(Ordering::Relaxed);
+    let _span = debug_span!("build").entered();
+    let dictionary = Dictionary::with_automaton(needles, automaton, prefilter);
+    info!(
+        "matching {} patterns with an automaton of {} bytes",
+        needles.len(),
+        dictionary.memory_usage()
+    );
+    dictionary
+}
+
+fn engine() -> Engine {
+    match ENGINE.load(Ordering::Relaxed) {
+        e if e == Engine::Hyperscan as u8 => Engine::Hyperscan,
+        _ => Engine::Builtin,
+    }
+}
+
+// Literal patterns made ready to count with the engine, once for all the inputs.
+pub enum Prepared<M> {
+    Builtin(M),
+    #[cfg(feature = "hyperscan")]
+    Hyperscan(Arc<hyperscan::Database>),
+}
+
+impl<M: Matcher + Clone> Prepared<M> {
+    // `matcher` must match `patterns`, which the engine may compile for itself instead.
+    pub fn new(matcher: M, patterns: &[&[u8]]) -> Self {
+        match engine() {
+            #[cfg(feature = "hyperscan")]
+            Engine::Hyperscan => {
+                Prepared::Hyperscan(hyperscan::Database::new(patterns).unwrap_or_else(|e| fail(e)))
+            }
+            _ => {
+                let _ = patterns;
+                Prepared::Builtin(matcher)
+            }
+        }
+    }
+
+    // A counter for the next input.
+    pub fn counter(&self) -> PatternCounter<M> {
+        match self {
+            Prepared::Builtin(matcher) => {
+                PatternCounter::Builtin(Counter::with_matcher(matcher.clone()))
+            }
+            #[cfg(feature = "hyperscan")]
+            Prepared::Hyperscan(db) => PatternCounter::Hyperscan(
+                hyperscan::StreamCounter::new(db.clone()).unwrap_or_else(|e| fail(e)),
+            ),
+        }
+    }
+}
+
+// Counts the matches of each pattern in one input.
+pub enum PatternCounter<M> {
+    Builtin(Counter<M>),
+    #[cfg(feature = "hyperscan")]
+    Hyperscan(hyperscan::StreamCounter),
+}
+
+impl<M: Matcher> PatternCounter<M> {
+    pub fn write(&mut self, chunk: &[u8]) {
+        match self {
+            PatternCounter::Builtin(counter) => counter.write(chunk),
+            #[cfg(feature = "hyperscan")]
+            PatternCounter::Hyperscan(counter) => counter.write(chunk),
+        }
+    }
+
+    // Counts the matches that can only be known at the end of the input.
+    pub fn finish(&mut self) {
+        match self {
+            PatternCounter::Builtin(_) => {}
+            #[cfg(feature = "hyperscan")]
+            PatternCounter::Hyperscan(counter) => counter.finish(),
+        }
+    }
+
+    pub fn counts(&self) -> &[usize] {
+        match self {
+            PatternCounter::Builtin(counter) => counter.counts(),
+            #[cfg(feature = "hyperscan")]
+            PatternCounter::Hyperscan(counter) => counter.counts(),
+        }
+    }
+}
+
+#[cfg(feature = "hyperscan")]
+fn fail(e: String) -> ! {
+    eprintln!("freq: {}", e);
+    std::process::exit(2);
+}
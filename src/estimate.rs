@@ -0,0 +1,205 @@
+use crate::input::{long_path, report, stdin_file};
+use crate::split::ByteRange;
+use freq::Matcher;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+// An estimate of how many matches there are, from the matches in blocks chosen at random.
+#[derive(Debug, Default, PartialEq)]
+pub struct Estimate {
+    pub count: f64,
+    pub variance: f64,
+    pub blocks_read: u64,
+    pub blocks: u64,
+}
+
+impl Estimate {
+    // Reads about `fraction` of the blocks of each file and estimates the total from them. "-" is
+    // stdin, which has to be a file. Files that can't be read, or can't seek, are reported and
+    // skipped.
+    pub fn sample(
+        matcher: &impl Matcher,
+        paths: &[PathBuf],
+        fraction: f64,
+        block_size: usize,
+    ) -> Estimate {
+        let mut rng = fastrand::Rng::new();
+        let mut estimate = Estimate::default();
+        for path in paths {
+            let res = open_file(path).and_then(|(mut f, range)| {
+                estimate.add_file(&mut f, range, matcher, fraction, block_size, &mut rng)
+            });
+            if let Err(e) = res {
+                report(format!("{}: {}", path.display(), e));
+            }
+        }
+        estimate
+    }
+
+    fn add_file(
+        &mut self,
+        f: &mut (impl Read + Seek),
+        range: ByteRange,
+        matcher: &impl Matcher,
+        fraction: f64,
+        block_size: usize,
+        rng: &mut fastrand::Rng,
+    ) -> io::Result<()> {
+        let blocks = range.len().div_ceil(block_size as u64);
+        if blocks == 0 {
+            return Ok(());
+        }
+        let n = ((blocks as f64 * fraction).ceil() as u64).clamp(1, blocks);
+
+        // Floyd's algorithm, for n distinct blocks without listing all of them.
+        let mut chosen = HashSet::new();
+        for j in blocks - n..blocks {
+            let t = rng.u64(0..=j);
+            if !chosen.insert(t) {
+                chosen.insert(j);
+            }
+        }
+        let mut chosen: Vec<u64> = chosen.into_iter().collect();
+        chosen.sort_unstable();
+
+        // A match that starts in a block may end after it.
+        let mut buf = vec![0; block_size + matcher.max_len() - 1];
+        let mut counts = Vec::with_capacity(chosen.len());
+        for block in chosen {
+            let start = range.start + block * block_size as u64;
+            f.seek(SeekFrom::Start(start))?;
+            let len = read_full(f, &mut buf)?;
+            let block_len = len.min(block_size);
+            let mut count = 0;
+            matcher.find_all(&buf[..len], &mut |m| {
+                if m.start >= block_len {
+                    return false;
+                }
+                count += 1;
+                true
+            });
+            counts.push(count as f64);
+        }
+
+        let mean = counts.iter().sum::<f64>() / n as f64;
+        let sample_variance = if n > 1 {
+            counts.iter().map(|c| (c - mean).powi(2)).sum::<f64>() / (n - 1) as f64
+        } else {
+            0.0
+        };
+        let blocks_f = blocks as f64;
+        self.count += blocks_f * mean;
+        // With the finite population correction, so that reading every block is exact.
+        self.variance +=
+            blocks_f * blocks_f * sample_variance / n as f64 * (1.0 - n as f64 / blocks_f);
+        self.blocks_read += n;
+        self.blocks += blocks;
+        Ok(())
+    }
+
+    // Prints the estimate, its 95% confidence interval, and how much was read.
+    pub fn print(&self, out: &mut impl Write, block_size: usize) -> io::Result<()> {
+        let margin = 1.96 * self.variance.sqrt();
+        writeln!(out, "{:.0}\testimated matches", self.count)?;
+        writeln!(
+            out,
+            "{:.0}-{:.0}\t95% confidence interval",
+            (self.count - margin).max(0.0),
+            self.count + margin
+        )?;
+        writeln!(
+            out,
+            "{} of {}\tblocks of {} bytes read",
+            self.blocks_read, self.blocks, block_size
+        )
+    }
+}
+
+// Opens a file to sample, with the part of it to sample: all of it, or the rest of stdin.
+fn open_file(path: &Path) -> io::Result<(File, ByteRange)> {
+    let mut f = if path.as_os_str() == "-" {
+        stdin_file()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "stdin isn't a file"))?
+    } else {
+        File::open(long_path(path))?
+    };
+    let meta = f.metadata()?;
+    if !meta.is_file() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "only files that can seek can be sampled",
+        ));
+    }
+    let start = f.stream_position()?;
+    let end = meta.len().max(start);
+    Ok((f, ByteRange { start, end }))
+}
+
+pub fn parse_fraction(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(f) if f > 0.0 && f <= 1.0 => Ok(f),
+        _ => Err(format!(
+            "expected a fraction greater than 0 and at most 1, not '{}'",
+            s
+        )),
+    }
+}
+
+// Reads until `buf` is full or the input ends, returning how much was read.
+fn read_full(f: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut len = 0;
+    while len < buf.len() {
+        match f.read(&mut buf[len..]) {
+            Ok(0) => break,
+            Ok(n) => len += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use freq::Literal;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_estimate() {
+        // 100 blocks of 10 bytes, with a match spanning each boundary.
+        let text = b"ab......ab".repeat(100);
+        let matcher = Literal::new(b"ba");
+        let estimate = |fraction| {
+            let mut e = Estimate::default();
+            let mut rng = fastrand::Rng::with_seed(7);
+            let mut f = Cursor::new(&text);
+            let range = ByteRange {
+                start: 0,
+                end: text.len() as u64,
+            };
+            e.add_file(&mut f, range, &matcher, fraction, 10, &mut rng)
+                .unwrap();
+            e
+        };
+
+        // Reading every block is exact.
+        let all = estimate(1.0);
+        assert_eq!(all.count, 99.0);
+        assert_eq!(all.variance, 0.0);
+        assert_eq!((all.blocks_read, all.blocks), (100, 100));
+
+        let some = estimate(0.1);
+        assert_eq!((some.blocks_read, some.blocks), (10, 100));
+        assert!((90.0..=100.0).contains(&some.count));
+
+        let mut out = Vec::new();
+        all.print(&mut out, 10).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "99\testimated matches\n99-99\t95% confidence interval\n100 of 100\tblocks of 10 bytes read\n"
+        );
+    }
+}
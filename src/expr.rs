@@ -0,0 +1,233 @@
+// Boolean expressions over patterns, like `("ERROR" | "FATAL") & !"retryable"`, which hold for
+// a record depending on which of the patterns it contains.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Query {
+    pub expr: Expr,
+
+    // The distinct patterns, which the expression refers to by index.
+    pub patterns: Vec<Vec<u8>>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expr {
+    // Holds if the record contains the pattern.
+    Pattern(usize),
+    Not(Box<Expr>),
+    And(Vec<Expr>),
+    Or(Vec<Expr>),
+}
+
+impl Query {
+    // A query that holds for records that contain every one of `required` and none of
+    // `excluded`.
+    pub fn all_but(required: Vec<Vec<u8>>, excluded: Vec<Vec<u8>>) -> Query {
+        let n = required.len();
+        let expr = (0..n)
+            .map(Expr::Pattern)
+            .chain((n..n + excluded.len()).map(|i| Expr::Not(Box::new(Expr::Pattern(i)))));
+        Query {
+            expr: Expr::And(expr.collect()),
+            patterns: required.into_iter().chain(excluded).collect(),
+        }
+    }
+
+    // Parses an expression of double-quoted patterns combined with `&` (and), `|` (or), `!`
+    // (not) and parentheses. `!` binds tightest and `|` loosest. Inside quotes, `\"`, `\\`,
+    // `\n` and `\t` are escapes.
+    pub fn parse(s: &str) -> Result<Query, String> {
+        let mut parser = Parser {
+            s: s.as_bytes(),
+            pos: 0,
+            patterns: Vec::new(),
+        };
+        let expr = parser.or()?;
+        parser.skip_spaces();
+        if parser.pos < parser.s.len() {
+            return Err(parser.error("expected '&', '|' or the end of the expression"));
+        }
+        Ok(Query {
+            expr,
+            patterns: parser.patterns,
+        })
+    }
+}
+
+impl Expr {
+    // Evaluates the expression, given whether a record contains each pattern. `contains` is only
+    // called for patterns the result depends on.
+    pub fn eval(&self, contains: &mut impl FnMut(usize) -> bool) -> bool {
+        match self {
+            Expr::Pattern(i) => contains(*i),
+            Expr::Not(e) => !e.eval(contains),
+            Expr::And(es) => es.iter().all(|e| e.eval(contains)),
+            Expr::Or(es) => es.iter().any(|e| e.eval(contains)),
+        }
+    }
+
+    // Returns a pattern that every record the expression holds for contains, if there is one.
+    pub fn required(&self) -> Option<usize> {
+        match self {
+            Expr::Pattern(i) => Some(*i),
+            Expr::Not(_) => None,
+            Expr::And(es) => es.iter().find_map(Expr::required),
+            Expr::Or(es) => {
+                let first = es.first()?.required()?;
+                es.iter()
+                    .all(|e| e.required() == Some(first))
+                    .then_some(first)
+            }
+        }
+    }
+}
+
+struct Parser<'a> {
+    s: &'a [u8],
+    pos: usize,
+    patterns: Vec<Vec<u8>>,
+}
+
+impl Parser<'_> {
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut es = vec![self.and()?];
+        while self.eat(b'|') {
+            es.push(self.and()?);
+        }
+        Ok(if es.len() == 1 {
+            es.pop().unwrap()
+        } else {
+            Expr::Or(es)
+        })
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut es = vec![self.unary()?];
+        while self.eat(b'&') {
+            es.push(self.unary()?);
+        }
+        Ok(if es.len() == 1 {
+            es.pop().unwrap()
+        } else {
+            Expr::And(es)
+        })
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.eat(b'!') {
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        if self.eat(b'(') {
+            let e = self.or()?;
+            if !self.eat(b')') {
+                return Err(self.error("expected ')'"));
+            }
+            return Ok(e);
+        }
+        if self.eat(b'"') {
+            let pattern = self.string()?;
+            let i = match self.patterns.iter().position(|p| *p == pattern) {
+                Some(i) => i,
+                None => {
+                    self.patterns.push(pattern);
+                    self.patterns.len() - 1
+                }
+            };
+            return Ok(Expr::Pattern(i));
+        }
+        Err(self.error("expected a quoted pattern, '!' or '('"))
+    }
+
+    // Parses the rest of a quoted pattern, after the opening quote.
+    fn string(&mut self) -> Result<Vec<u8>, String> {
+        let start = self.pos - 1;
+        let mut pattern = Vec::new();
+        loop {
+            let Some(&b) = self.s.get(self.pos) else {
+                self.pos = start;
+                return Err(self.error("unterminated pattern"));
+            };
+            self.pos += 1;
+            match b {
+                b'"' => break,
+                b'\\' => {
+                    let escaped = match self.s.get(self.pos) {
+                        Some(b'"') => b'"',
+                        Some(b'\\') => b'\\',
+                        Some(b'n') => b'\n',
+                        Some(b't') => b'\t',
+                        _ => return Err(self.error("unknown escape")),
+                    };
+                    pattern.push(escaped);
+                    self.pos += 1;
+                }
+                b => pattern.push(b),
+            }
+        }
+        if pattern.is_empty() {
+            self.pos = start;
+            return Err(self.error("patterns must be non-empty"));
+        }
+        Ok(pattern)
+    }
+
+    fn skip_spaces(&mut self) {
+        while self.s.get(self.pos).is_some_and(u8::is_ascii_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    // Consumes `b`, after any spaces, if it is next.
+    fn eat(&mut self, b: u8) -> bool {
+        self.skip_spaces();
+        let found = self.s.get(self.pos) == Some(&b);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn error(&self, msg: &str) -> String {
+        format!("{} at offset {}", msg, self.pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use Expr::*;
+
+    #[test]
+    fn test_parse() {
+        let query = Query::parse(r#"("ERROR" | "FATAL") & !"retry\"able" & "ERROR""#).unwrap();
+        assert_eq!(query.patterns, [&b"ERROR"[..], b"FATAL", b"retry\"able"]);
+        assert_eq!(
+            query.expr,
+            And(vec![
+                Or(vec![Pattern(0), Pattern(1)]),
+                Not(Box::new(Pattern(2))),
+                Pattern(0),
+            ])
+        );
+        assert_eq!(query.expr.required(), Some(0));
+
+        assert!(Query::parse(r#""a" &"#).is_err());
+        assert!(Query::parse(r#""a" "b""#).is_err());
+        assert!(Query::parse(r#"("a""#).is_err());
+        assert!(Query::parse(r#""a"#).is_err());
+        assert!(Query::parse(r#""""#).is_err());
+    }
+
+    #[test]
+    fn test_eval() {
+        let query = Query::parse(r#""a" | !("b" & "c")"#).unwrap();
+        let eval = |present: &[usize]| query.expr.eval(&mut |i| present.contains(&i));
+        assert!(eval(&[0]));
+        assert!(eval(&[]));
+        assert!(!eval(&[1, 2]));
+        assert_eq!(query.expr.required(), None);
+
+        let query = Query::all_but(vec![b"x".to_vec()], vec![b"y".to_vec()]);
+        assert_eq!(query.expr, And(vec![Pattern(0), Not(Box::new(Pattern(1)))]));
+        assert_eq!(query.expr.required(), Some(0));
+    }
+}
@@ -0,0 +1,301 @@
+use crate::chunk::Chunk;
+use crate::color;
+use crate::csv::CsvReader;
+use crate::input::{for_each_shared_chunk, open_inputs};
+use crate::linelimit::{self, Limit};
+use crate::normalize::NormalizeArgs;
+use crate::table::{FreqTable, TableArgs};
+use clap::builder::{ArgPredicate, NonEmptyStringValueParser};
+use clap::Args;
+use freq::{Literal, Matcher};
+use memchr::memchr_iter;
+use std::io::Write;
+use std::path::PathBuf;
+
+#[derive(Args)]
+pub struct FieldsArgs {
+    #[arg(help = "The files to read, with - for stdin. If not provided, stdin is used.")]
+    input: Vec<PathBuf>,
+
+    #[arg(
+        short,
+        long,
+        default_value = "\\t",
+        default_value_if("csv", ArgPredicate::IsPresent, Some(",")),
+        value_parser = parse_delimiter,
+        help = "The byte that separates fields. \\t means a tab. Defaults to a tab, or a comma with --csv."
+    )]
+    delimiter: u8,
+
+    #[arg(
+        short,
+        long,
+        value_parser = clap::value_parser!(u32).range(1..),
+        help = "The field to count, numbered from 1. Lines with fewer fields are skipped."
+    )]
+    field: u32,
+
+    #[arg(
+        long,
+        help = "Parse the input as CSV, so that quoted fields may contain the delimiter, quotes and newlines. Backslashes, newlines and tabs in values are printed escaped."
+    )]
+    csv: bool,
+
+    #[arg(
+        long,
+        default_value = "\"",
+        value_parser = parse_quote,
+        requires = "csv",
+        help = "The character that quotes CSV fields."
+    )]
+    quote: u8,
+
+    #[arg(
+        short,
+        long,
+        value_parser = NonEmptyStringValueParser::new(),
+        help = "Count the occurrences of this pattern in the field, rather than how often each value occurs."
+    )]
+    pattern: Option<String>,
+
+    #[command(flatten)]
+    normalize: NormalizeArgs,
+
+    #[command(flatten)]
+    table: TableArgs,
+}
+
+fn parse_delimiter(s: &str) -> Result<u8, String> {
+    match s.as_bytes() {
+        b"\\t" => Ok(b'\t'),
+        &[b] if b.is_ascii() && b != b'\n' => Ok(b),
+        _ => Err("the delimiter must be a single ASCII character other than newline".to_string()),
+    }
+}
+
+fn parse_quote(s: &str) -> Result<u8, String> {
+    match s.as_bytes() {
+        &[b] if b.is_ascii() && b != b'\n' => Ok(b),
+        _ => Err("the quote must be a single ASCII character other than newline".to_string()),
+    }
+}
+
+pub fn run(args: FieldsArgs, buffer_size: usize, out: &mut impl Write) {
+    let field = args.field as usize;
+    let mut reader = if args.csv {
+        FieldReader::Csv(CsvReader::new(args.delimiter, args.quote, field))
+    } else {
+        FieldReader::Delimited(DelimitedReader::new(args.delimiter, field))
+    };
+    let mut values = match &args.pattern {
+        Some(pattern) => Values::Matches(Box::new(Literal::new(pattern.as_bytes())), 0),
+        None => Values::Table(
+            FreqTable::new(&args.table)
+                .normalized(&args.normalize)
+                .escaped(args.csv),
+        ),
+    };
+
+    let mut add = |value: &[u8]| values.add(value);
+    for f in open_inputs(&args.input) {
+        for_each_shared_chunk(f, buffer_size, |chunk| reader.write(chunk, &mut add));
+        reader.finish(&mut add);
+    }
+
+    match values {
+        Values::Table(table) => table.print(&args.table, out),
+        Values::Matches(_, count) => writeln!(out, "{}", color::count(count)),
+    }
+    .expect("failed to write output");
+}
+
+// What we do with the values of the field.
+enum Values {
+    // Count how often each value occurs.
+    Table(FreqTable),
+
+    // Count the matches of a pattern in all the values.
+    Matches(Box<Literal>, usize),
+}
+
+impl Values {
+    fn add(&mut self, value: &[u8]) {
+        match self {
+            Values::Table(table) => table.add(value),
+            Values::Matches(matcher, count) => matcher.find_all(value, &mut |_| {
+                *count += 1;
+                true
+            }),
+        }
+    }
+}
+
+enum FieldReader {
+    Delimited(DelimitedReader),
+    Csv(CsvReader),
+}
+
+impl FieldReader {
+    fn write(&mut self, chunk: &Chunk, f: &mut impl FnMut(&[u8])) {
+        match self {
+            FieldReader::Delimited(reader) => reader.write(chunk, f),
+            FieldReader::Csv(reader) => reader.write(chunk, f),
+        }
+    }
+
+    fn finish(&mut self, f: &mut impl FnMut(&[u8])) {
+        match self {
+            FieldReader::Delimited(reader) => reader.finish(f),
+            FieldReader::Csv(reader) => reader.finish(f),
+        }
+    }
+}
+
+// Reads one field of lines split naively at a delimiter.
+pub struct DelimitedReader {
+    delimiter: u8,
+
+    // The field to read, numbered from 1.
+    field: usize,
+
+    // The start of a line that continues into the next chunk, as a slice of the chunk it's in.
+    tail: Option<Chunk>,
+
+    // A line that ran into another chunk, copied together so far.
+    carry: Vec<u8>,
+
+    limit: Limit,
+}
+
+impl DelimitedReader {
+    pub fn new(delimiter: u8, field: usize) -> Self {
+        DelimitedReader {
+            delimiter,
+            field,
+            tail: None,
+            carry: Vec::new(),
+            limit: linelimit::get(),
+        }
+    }
+
+    // Calls `f` on the value of the field in each line that ends in `chunk`. Lines with fewer
+    // fields are skipped.
+    pub fn write(&mut self, chunk: &Chunk, f: &mut impl FnMut(&[u8])) {
+        let mut start = 0;
+        for end in memchr_iter(b'\n', chunk) {
+            if self.tail.is_none() && self.carry.is_empty() {
+                self.read_line(&chunk[start..end], f);
+            } else {
+                self.join(&chunk[start..end]);
+                self.read_line(&self.carry, f);
+                self.carry.clear();
+            }
+            start = end + 1;
+        }
+        if start == chunk.len() {
+            return;
+        }
+        if self.tail.is_none() && self.carry.is_empty() {
+            self.tail = Some(chunk.slice(start..chunk.len()));
+        } else {
+            self.join(&chunk[start..]);
+        }
+    }
+
+    // Reads the last line of the current input, if it has no trailing newline.
+    pub fn finish(&mut self, f: &mut impl FnMut(&[u8])) {
+        if let Some(tail) = self.tail.take() {
+            self.read_line(&tail, f);
+        } else if !self.carry.is_empty() {
+            self.read_line(&self.carry, f);
+            self.carry.clear();
+        }
+    }
+
+    // Copies the start of the line, and then `rest` of it, into `carry`.
+    fn join(&mut self, rest: &[u8]) {
+        if let Some(tail) = self.tail.take() {
+            self.limit.extend(&mut self.carry, &tail, 0);
+        }
+        self.limit.extend(&mut self.carry, rest, 0);
+    }
+
+    fn read_line(&self, line: &[u8], f: &mut impl FnMut(&[u8])) {
+        let value = self
+            .limit
+            .apply(line)
+            .and_then(|line| nth_field(line, self.delimiter, self.field));
+        if let Some(value) = value {
+            f(value);
+        }
+    }
+}
+
+// Returns the `n`th field of `line`, numbered from 1, if it has that many.
+fn nth_field(line: &[u8], delimiter: u8, n: usize) -> Option<&[u8]> {
+    let mut delimiters = memchr_iter(delimiter, line);
+    let mut start = 0;
+    for _ in 1..n {
+        start = delimiters.next()? + 1;
+    }
+    let end = delimiters.next().unwrap_or(line.len());
+    Some(&line[start..end])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use proptest::string::bytes_regex;
+    use proptest::{prop_assert_eq, proptest};
+
+    #[test]
+    fn test_csv_delimiter() {
+        #[derive(clap::Parser)]
+        struct Cli {
+            #[command(flatten)]
+            fields: FieldsArgs,
+        }
+        let parse = |args: &[&str]| <Cli as clap::Parser>::parse_from(args).fields.delimiter;
+        assert_eq!(parse(&["fields", "-f", "2", "c.tsv"]), b'\t');
+        assert_eq!(parse(&["fields", "--csv", "-f", "2", "c.csv"]), b',');
+        assert_eq!(
+            parse(&["fields", "--csv", "-d", ";", "-f", "2", "c.csv"]),
+            b';'
+        );
+    }
+
+    #[test]
+    fn test_nth_field() {
+        assert_eq!(nth_field(b"a,b,,d", b',', 1), Some(&b"a"[..]));
+        assert_eq!(nth_field(b"a,b,,d", b',', 3), Some(&b""[..]));
+        assert_eq!(nth_field(b"a,b,,d", b',', 4), Some(&b"d"[..]));
+        assert_eq!(nth_field(b"a,b,,d", b',', 5), None);
+        assert_eq!(nth_field(b"", b',', 1), Some(&b""[..]));
+    }
+
+    proptest! {
+        #[test]
+        fn test_chunked(
+            chunk_size in 1..20_usize,
+            field in 1..4_usize,
+            text in bytes_regex("((?s-u:[ab,\\n]{0,200}))").unwrap(),
+        ) {
+            let mut reader = DelimitedReader::new(b',', field);
+            let mut chunked = FreqTable::default();
+            let mut add = |value: &[u8]| chunked.add(value);
+            text.chunks(chunk_size).for_each(|chunk| reader.write(&chunk.to_vec().into(), &mut add));
+            reader.finish(&mut add);
+
+            let mut expected = FreqTable::default();
+            text.strip_suffix(b"\n")
+                .unwrap_or(&text)
+                .split(|&b| b == b'\n')
+                .filter(|_| !text.is_empty())
+                .filter_map(|line| line.split(|&b| b == b',').nth(field - 1))
+                .for_each(|value| expected.add(value));
+
+            prop_assert_eq!(chunked.counts(), expected.counts());
+        }
+    }
+}
@@ -0,0 +1,217 @@
+use crate::matcher::{Match, Matcher};
+
+/// Matches a needle of up to 64 bytes within an edit (Levenshtein) distance: each match is a
+/// run of bytes that the needle turns into with at most `max_errors` substitutions, insertions
+/// and deletions. Matches are found with the bit-parallel algorithm of agrep.
+///
+/// A match is reported as its shortest run of bytes ending where it does, so the longest match
+/// is `max_errors` bytes longer than the needle.
+#[derive(Clone, PartialEq)]
+pub struct Fuzzy {
+    needle: Vec<u8>,
+    max_errors: usize,
+
+    // For each byte, which positions of the needle hold it.
+    masks: Vec<u64>,
+}
+
+impl Fuzzy {
+    pub fn new(needle: &[u8], max_errors: usize) -> Self {
+        assert!(!needle.is_empty(), "needle must be non-empty");
+        assert!(needle.len() <= 64, "needle must be at most 64 bytes");
+        assert!(
+            max_errors < needle.len(),
+            "max_errors must be less than the needle's length"
+        );
+        let mut masks = vec![0; 256];
+        for (i, &b) in needle.iter().enumerate() {
+            masks[b as usize] |= 1 << i;
+        }
+        Fuzzy {
+            needle: needle.to_vec(),
+            max_errors,
+            masks,
+        }
+    }
+
+    pub fn needle(&self) -> &[u8] {
+        &self.needle
+    }
+
+    pub fn max_errors(&self) -> usize {
+        self.max_errors
+    }
+
+    // The length of the shortest match that ends at `end` and starts at or after `from`, given
+    // that there is one.
+    fn shortest_match(&self, haystack: &[u8], from: usize, end: usize) -> usize {
+        let m = self.needle.len();
+        // dist[j] is the distance between the last j bytes of the needle and the bytes before
+        // `end` read so far, going backwards.
+        let mut dist: Vec<usize> = (0..=m).collect();
+        let longest = (end - from).min(m + self.max_errors);
+        for len in 1..=longest {
+            let b = haystack[end - len];
+            let mut diag = dist[0];
+            dist[0] = len;
+            for j in 1..=m {
+                let substituted = diag + usize::from(self.needle[m - j] != b);
+                diag = dist[j];
+                dist[j] = substituted.min(dist[j] + 1).min(dist[j - 1] + 1);
+            }
+            if dist[m] <= self.max_errors {
+                return len;
+            }
+        }
+        longest
+    }
+}
+
+impl Matcher for Fuzzy {
+    fn find_all(&self, haystack: &[u8], f: &mut dyn FnMut(Match) -> bool) {
+        let k = self.max_errors;
+        let found = 1 << (self.needle.len() - 1);
+        // Bit j of states[d] is set if the first j + 1 bytes of the needle are within d errors
+        // of some bytes ending here, that start after the last match. Any prefix can be deleted
+        // outright, which the low bits account for.
+        let start = |states: &mut [u64]| {
+            for (d, s) in states.iter_mut().enumerate() {
+                *s = (1 << d) - 1;
+            }
+        };
+        let mut states = vec![0u64; k + 1];
+        start(&mut states);
+        let mut from = 0;
+        for (i, &b) in haystack.iter().enumerate() {
+            let mask = self.masks[b as usize];
+            let mut before = states[0];
+            states[0] = ((states[0] << 1) | 1) & mask;
+            for d in 1..=k {
+                let old = states[d];
+                states[d] = (((old << 1) | 1) & mask)
+                    // Substituting this byte, or inserting it.
+                    | (before << 1) | 1 | before
+                    // Deleting a byte of the needle.
+                    | (states[d - 1] << 1) | 1;
+                before = old;
+            }
+            if states[k] & found == 0 {
+                continue;
+            }
+            let end = i + 1;
+            let start_at = end - self.shortest_match(haystack, from, end);
+            if !f(Match {
+                start: start_at,
+                end,
+                pattern: 0,
+            }) {
+                return;
+            }
+            from = end;
+            start(&mut states);
+        }
+    }
+
+    fn max_len(&self) -> usize {
+        self.needle.len() + self.max_errors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Counter;
+
+    use proptest::string::bytes_regex;
+    use proptest::{prop_assert_eq, proptest};
+
+    fn distance(a: &[u8], b: &[u8]) -> usize {
+        let mut dist: Vec<usize> = (0..=b.len()).collect();
+        for (i, &x) in a.iter().enumerate() {
+            let mut diag = dist[0];
+            dist[0] = i + 1;
+            for (j, &y) in b.iter().enumerate() {
+                let substituted = diag + usize::from(x != y);
+                diag = dist[j + 1];
+                dist[j + 1] = substituted.min(dist[j + 1] + 1).min(dist[j] + 1);
+            }
+        }
+        dist[b.len()]
+    }
+
+    // Takes the match that ends first, then the next one after it, and so on.
+    fn naive(needle: &[u8], k: usize, haystack: &[u8]) -> Vec<(usize, usize)> {
+        let mut matches = Vec::new();
+        let mut from = 0;
+        for end in 1..=haystack.len() {
+            let starts = (from..end).rev().take(needle.len() + k);
+            if let Some(start) = starts
+                .into_iter()
+                .find(|&s| distance(needle, &haystack[s..end]) <= k)
+            {
+                matches.push((start, end));
+                from = end;
+            }
+        }
+        matches
+    }
+
+    fn find(fuzzy: &Fuzzy, haystack: &[u8]) -> Vec<(usize, usize)> {
+        let mut matches = Vec::new();
+        fuzzy.find_all(haystack, &mut |m| {
+            matches.push((m.start, m.end));
+            true
+        });
+        matches
+    }
+
+    #[test]
+    fn test_fuzzy() {
+        let fuzzy = Fuzzy::new(b"ERROR", 1);
+        // A deletion ends the last one a byte early.
+        assert_eq!(
+            find(&fuzzy, b"an EROR, ERRQR and ERRORS"),
+            [(3, 7), (9, 14), (19, 23)]
+        );
+        assert_eq!(find(&fuzzy, b"ERR OR"), [(0, 6)]);
+        assert_eq!(find(&fuzzy, b"EXXOR"), []);
+        assert_eq!(find(&Fuzzy::new(b"ab", 1), b"xb"), [(1, 2)]);
+    }
+
+    proptest! {
+        #[test]
+        fn test_naive(
+            needle in bytes_regex("((?s-u:[ab]{1,6}))").unwrap(),
+            k in 0..3_usize,
+            haystack in bytes_regex("((?s-u:[abc]{0,60}))").unwrap(),
+        ) {
+            let k = k.min(needle.len() - 1);
+            prop_assert_eq!(find(&Fuzzy::new(&needle, k), &haystack), naive(&needle, k, &haystack));
+        }
+
+        #[test]
+        fn test_chunked(
+            chunk_size in 1..20_usize,
+            needle in bytes_regex("((?s-u:[ab]{1,6}))").unwrap(),
+            k in 0..3_usize,
+            haystack in bytes_regex("((?s-u:[abc]{0,200}))").unwrap(),
+            split in 0..200_usize,
+        ) {
+            let k = k.min(needle.len() - 1);
+            let fuzzy = Fuzzy::new(&needle, k);
+            let expected = find(&fuzzy, &haystack).len();
+
+            let mut counter = Counter::with_matcher(fuzzy.clone());
+            haystack.chunks(chunk_size).for_each(|chunk| counter.write(chunk));
+            prop_assert_eq!(counter.count(), expected);
+
+            let split = split.min(haystack.len());
+            let mut left = Counter::mergeable(fuzzy.clone());
+            left.write(&haystack[..split]);
+            let mut right = Counter::mergeable(fuzzy);
+            right.write(&haystack[split..]);
+            left.merge(right);
+            prop_assert_eq!(left.count(), expected);
+        }
+    }
+}
@@ -0,0 +1,138 @@
+// A shell wildcard pattern for names, as given to --exclude-dir: `*` matches any run of
+// characters, `?` any one, `[abc]` and `[a-z]` one of those, `[!abc]` one not of those, and `\`
+// makes the character after it match only itself.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Glob(Vec<Token>);
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Char(char),
+    Any,
+    Star,
+    Class {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+}
+
+impl Glob {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let mut tokens = Vec::new();
+        let mut chars = s.chars();
+        while let Some(c) = chars.next() {
+            tokens.push(match c {
+                '*' => Token::Star,
+                '?' => Token::Any,
+                '\\' => Token::Char(chars.next().ok_or("a '\\' has nothing after it")?),
+                '[' => parse_class(&mut chars).ok_or_else(|| format!("unclosed '[' in '{}'", s))?,
+                c => Token::Char(c),
+            });
+        }
+        Ok(Glob(tokens))
+    }
+
+    // Whether all of `name` matches.
+    pub fn matches(&self, name: &str) -> bool {
+        let name: Vec<char> = name.chars().collect();
+        let (mut t, mut n) = (0, 0);
+        // Where to go back to when the rest doesn't match: just after the last `*`, with it
+        // taking one more character.
+        let mut star = None;
+        while n < name.len() {
+            match self.0.get(t) {
+                Some(Token::Star) => {
+                    star = Some((t + 1, n));
+                    t += 1;
+                    continue;
+                }
+                Some(token) if token.matches(name[n]) => {
+                    t += 1;
+                    n += 1;
+                    continue;
+                }
+                _ => {}
+            }
+            match star {
+                Some((after, taken)) => {
+                    star = Some((after, taken + 1));
+                    t = after;
+                    n = taken + 1;
+                }
+                None => return false,
+            }
+        }
+        self.0[t..].iter().all(|token| *token == Token::Star)
+    }
+}
+
+impl Token {
+    fn matches(&self, c: char) -> bool {
+        match self {
+            Token::Char(expected) => c == *expected,
+            Token::Any => true,
+            Token::Star => false,
+            Token::Class { negated, ranges } => {
+                ranges.iter().any(|&(lo, hi)| lo <= c && c <= hi) != *negated
+            }
+        }
+    }
+}
+
+// Parses the rest of a `[...]` class, after the `[`. A `]` right at the start is in the class.
+fn parse_class(chars: &mut std::str::Chars) -> Option<Token> {
+    let mut negated = false;
+    let mut ranges = Vec::new();
+    let mut first = true;
+    loop {
+        let c = chars.next()?;
+        match c {
+            '!' | '^' if first && !negated => {
+                negated = true;
+                continue;
+            }
+            ']' if !first => return Some(Token::Class { negated, ranges }),
+            _ => {}
+        }
+        first = false;
+        let lo = if c == '\\' { chars.next()? } else { c };
+        // A '-' just before the ']' is itself.
+        let mut rest = chars.clone();
+        match (rest.next(), rest.next()) {
+            (Some('-'), Some(hi)) if hi != ']' => {
+                *chars = rest;
+                let hi = if hi == '\\' { chars.next()? } else { hi };
+                ranges.push((lo, hi));
+            }
+            _ => ranges.push((lo, lo)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches() {
+        let matches = |glob: &str, name: &str| Glob::parse(glob).unwrap().matches(name);
+        assert!(matches("node_modules", "node_modules"));
+        assert!(!matches("node_modules", "node_modules2"));
+        assert!(matches("*", ""));
+        assert!(matches(".*", ".git"));
+        assert!(!matches(".*", "git"));
+        assert!(matches("*cache*", "__pycache__"));
+        assert!(matches("a*b*c", "abxbc"));
+        assert!(!matches("a*b*c", "abxbd"));
+        assert!(matches("build-?", "build-1"));
+        assert!(!matches("build-?", "build-10"));
+        assert!(matches("[tb]mp", "tmp"));
+        assert!(matches("v[0-9]", "v7"));
+        assert!(!matches("v[!0-9]", "v7"));
+        assert!(matches("[]a]", "]"));
+        assert!(matches("[a-]", "-"));
+        assert!(matches("\\*", "*"));
+        assert!(!matches("\\*", "a"));
+        assert!(Glob::parse("[abc").is_err());
+        assert!(Glob::parse("a\\").is_err());
+    }
+}
@@ -0,0 +1,147 @@
+use crate::color;
+use crate::names;
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::path::{Component, Path, PathBuf};
+
+// What --group-by adds up the counts of inputs by.
+#[derive(Clone, Debug, PartialEq)]
+pub enum GroupBy {
+    // The directory each input is in, or the one this many levels down that it is under.
+    Dir(Option<usize>),
+
+    // The extension of each input's file name.
+    Ext,
+}
+
+// The group of files without an extension.
+const NO_EXT: &[u8] = b"(none)";
+
+impl GroupBy {
+    // Parses "dir", "dir:DEPTH" or "ext".
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s.split_once(':') {
+            None if s == "dir" => Ok(GroupBy::Dir(None)),
+            None if s == "ext" => Ok(GroupBy::Ext),
+            Some(("dir", depth)) => match depth.parse() {
+                Ok(0) | Err(_) => Err(format!(
+                    "the depth must be a positive number, not '{}'",
+                    depth
+                )),
+                Ok(depth) => Ok(GroupBy::Dir(Some(depth))),
+            },
+            _ => Err(format!("expected dir, dir:DEPTH or ext, not '{}'", s)),
+        }
+    }
+
+    // The group of the input at `path`, or of stdin.
+    fn key(&self, path: Option<&Path>) -> Vec<u8> {
+        let Some(path) = path.filter(|p| p.as_os_str() != "-") else {
+            return b"-".to_vec();
+        };
+        let path = names::shown(path);
+        match self {
+            GroupBy::Dir(depth) => {
+                let dir = path.parent().unwrap_or(Path::new(""));
+                let dir = match depth {
+                    Some(depth) => truncate(dir, *depth),
+                    None => dir.to_path_buf(),
+                };
+                if dir.as_os_str().is_empty() {
+                    b".".to_vec()
+                } else {
+                    dir.into_os_string().into_encoded_bytes()
+                }
+            }
+            GroupBy::Ext => match path.extension() {
+                Some(ext) => [b".", ext.as_encoded_bytes()].concat(),
+                None => NO_EXT.to_vec(),
+            },
+        }
+    }
+}
+
+// Returns the first `depth` directories of `dir`, with its root, if it has one.
+fn truncate(dir: &Path, depth: usize) -> PathBuf {
+    let mut kept = 0;
+    dir.components()
+        .take_while(|c| {
+            if matches!(c, Component::Prefix(_) | Component::RootDir) {
+                return true;
+            }
+            kept += 1;
+            kept <= depth
+        })
+        .collect()
+}
+
+// Adds up the count in each input by group, and prints "count<TAB>group" for each group in the
+// order they first appear, then the total.
+pub fn print(
+    out: &mut impl Write,
+    group_by: &GroupBy,
+    paths: &[PathBuf],
+    counts: &[usize],
+) -> io::Result<()> {
+    let mut groups: Vec<(Vec<u8>, usize)> = Vec::new();
+    let mut index = HashMap::new();
+    for (i, &count) in counts.iter().enumerate() {
+        let key = group_by.key(paths.get(i).map(PathBuf::as_path));
+        let i = *index.entry(key.clone()).or_insert_with(|| {
+            groups.push((key, 0));
+            groups.len() - 1
+        });
+        groups[i].1 += count;
+    }
+    for (key, count) in groups {
+        write!(out, "{}", color::count(count))?;
+        names::write_column(out, &key)?;
+        writeln!(out)?;
+    }
+    writeln!(out, "{}", color::total(counts.iter().sum::<usize>()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(GroupBy::parse("dir"), Ok(GroupBy::Dir(None)));
+        assert_eq!(GroupBy::parse("dir:3"), Ok(GroupBy::Dir(Some(3))));
+        assert!(GroupBy::parse("dir:0").is_err());
+        assert!(GroupBy::parse("dir:").is_err());
+        assert_eq!(GroupBy::parse("ext"), Ok(GroupBy::Ext));
+        assert!(GroupBy::parse("file").is_err());
+    }
+
+    #[test]
+    fn test_print() {
+        let paths: Vec<PathBuf> = [
+            "/var/log/nginx/access.log",
+            "/var/log/nginx/old/access.log",
+            "/var/log/app/app.log",
+            "/var/log/app/README",
+            "-",
+            "top.log",
+        ]
+        .iter()
+        .map(PathBuf::from)
+        .collect();
+        let counts = [1, 2, 4, 32, 8, 16];
+        let print = |group_by: GroupBy| {
+            let mut out = Vec::new();
+            print(&mut out, &group_by, &paths, &counts).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(
+            print(GroupBy::Dir(Some(3))),
+            "3\t/var/log/nginx\n36\t/var/log/app\n8\t-\n16\t.\n63\n"
+        );
+        assert_eq!(
+            print(GroupBy::Dir(None)),
+            "1\t/var/log/nginx\n2\t/var/log/nginx/old\n36\t/var/log/app\n8\t-\n16\t.\n63\n"
+        );
+        assert_eq!(print(GroupBy::Ext), "23\t.log\n32\t(none)\n8\t-\n63\n");
+    }
+}
@@ -0,0 +1,152 @@
+use crate::matcher::{Match, Matcher};
+
+const LOW: u64 = 0x7f7f_7f7f_7f7f_7f7f;
+const HIGH: u64 = 0x8080_8080_8080_8080;
+
+/// Matches a needle with at most `max_mismatches` of its bytes substituted, as in barcodes and
+/// primers, whose sequencing errors don't change their length. Every match is as long as the
+/// needle.
+///
+/// Windows of the haystack are compared with the needle eight bytes at a time, counting the bytes
+/// that differ with a popcount.
+#[derive(Clone, PartialEq)]
+pub struct Hamming {
+    needle: Vec<u8>,
+    max_mismatches: usize,
+}
+
+impl Hamming {
+    pub fn new(needle: &[u8], max_mismatches: usize) -> Self {
+        assert!(!needle.is_empty(), "needle must be non-empty");
+        assert!(
+            max_mismatches < needle.len(),
+            "max_mismatches must be less than the needle's length"
+        );
+        Hamming {
+            needle: needle.to_vec(),
+            max_mismatches,
+        }
+    }
+
+    pub fn needle(&self) -> &[u8] {
+        &self.needle
+    }
+
+    pub fn max_mismatches(&self) -> usize {
+        self.max_mismatches
+    }
+
+    // Whether `window` differs from the start of the needle in at most `max_mismatches` bytes.
+    fn close(&self, window: &[u8]) -> bool {
+        let mut mismatches = 0;
+        let mut words = window.chunks_exact(8).zip(self.needle.chunks_exact(8));
+        for (a, b) in words.by_ref() {
+            mismatches += differing_bytes(word(a) ^ word(b));
+            if mismatches > self.max_mismatches {
+                return false;
+            }
+        }
+        let done = window.len() / 8 * 8;
+        let rest = window[done..]
+            .iter()
+            .zip(&self.needle[done..])
+            .filter(|(a, b)| a != b)
+            .count();
+        mismatches + rest <= self.max_mismatches
+    }
+}
+
+fn word(bytes: &[u8]) -> u64 {
+    u64::from_ne_bytes(bytes.try_into().expect("words are 8 bytes"))
+}
+
+// The number of non-zero bytes in `x`.
+fn differing_bytes(x: u64) -> usize {
+    // The high bit of each byte is set if any of its bits are, without carrying into the next.
+    ((((x & LOW) + LOW) | x) & HIGH).count_ones() as usize
+}
+
+impl Matcher for Hamming {
+    fn find_all(&self, haystack: &[u8], f: &mut dyn FnMut(Match) -> bool) {
+        let n = self.needle.len();
+        let mut start = 0;
+        while start + n <= haystack.len() {
+            if !self.close(&haystack[start..start + n]) {
+                start += 1;
+                continue;
+            }
+            let m = Match {
+                start,
+                end: start + n,
+                pattern: 0,
+            };
+            if !f(m) {
+                return;
+            }
+            start += n;
+        }
+    }
+
+    fn max_len(&self) -> usize {
+        self.needle.len()
+    }
+
+    fn could_start(&self, partial: &[u8]) -> bool {
+        self.close(partial)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Counter;
+
+    use proptest::string::bytes_regex;
+    use proptest::{prop_assert_eq, proptest};
+
+    fn find(hamming: &Hamming, haystack: &[u8]) -> Vec<usize> {
+        let mut starts = Vec::new();
+        hamming.find_all(haystack, &mut |m| {
+            starts.push(m.start);
+            true
+        });
+        starts
+    }
+
+    #[test]
+    fn test_hamming() {
+        let hamming = Hamming::new(b"ACGTACGTAC", 2);
+        assert_eq!(find(&hamming, b"ACGTACGTAC ACGTTCGTAA ACGAACGAAA"), [0, 11]);
+        assert_eq!(differing_bytes(0), 0);
+        assert_eq!(differing_bytes(0x8001_0000_ff00_0001), 4);
+    }
+
+    proptest! {
+        #[test]
+        fn test_naive(
+            needle in bytes_regex("((?s-u:[ab]{1,20}))").unwrap(),
+            k in 0..4_usize,
+            haystack in bytes_regex("((?s-u:[abc]{0,200}))").unwrap(),
+            chunk_size in 1..30_usize,
+        ) {
+            let k = k.min(needle.len() - 1);
+            let hamming = Hamming::new(&needle, k);
+            let mut expected = Vec::new();
+            let mut start = 0;
+            while start + needle.len() <= haystack.len() {
+                let window = &haystack[start..start + needle.len()];
+                if window.iter().zip(&needle).filter(|(a, b)| a != b).count() <= k {
+                    expected.push(start);
+                    start += needle.len();
+                } else {
+                    start += 1;
+                }
+            }
+            prop_assert_eq!(find(&hamming, &haystack), expected.clone());
+
+            let mut counter = Counter::with_matcher(hamming);
+            haystack.chunks(chunk_size).for_each(|chunk| counter.write(chunk));
+            prop_assert_eq!(counter.count(), expected.len());
+        }
+    }
+}
@@ -0,0 +1,317 @@
+// Counting with Hyperscan, or Vectorscan, its port to other CPUs, which both install as libhs.
+// They match thousands of patterns at once far faster than anything portable, and their
+// streaming mode carries the state of a match across chunks by itself.
+//
+// freq's patterns are literals, so only literals are compiled: every byte is escaped, and a
+// match starts its pattern's length before where Hyperscan says it ends. Regular expressions
+// would need HS_FLAG_SOM_LEFTMOST to say where each match starts, and a way to ask for them.
+use std::ffi::{c_char, c_int, c_uint, c_ulonglong, c_void, CStr, CString};
+use std::ptr;
+use std::sync::Arc;
+
+#[repr(C)]
+struct HsDatabase {
+    _private: [u8; 0],
+}
+
+#[repr(C)]
+struct HsScratch {
+    _private: [u8; 0],
+}
+
+#[repr(C)]
+struct HsStream {
+    _private: [u8; 0],
+}
+
+#[repr(C)]
+struct HsCompileError {
+    message: *const c_char,
+    expression: c_int,
+}
+
+type MatchHandler = unsafe extern "C" fn(
+    id: c_uint,
+    from: c_ulonglong,
+    to: c_ulonglong,
+    flags: c_uint,
+    context: *mut c_void,
+) -> c_int;
+
+const HS_SUCCESS: c_int = 0;
+const HS_MODE_STREAM: c_uint = 2;
+
+#[link(name = "hs")]
+extern "C" {
+    fn hs_compile_multi(
+        expressions: *const *const c_char,
+        flags: *const c_uint,
+        ids: *const c_uint,
+        elements: c_uint,
+        mode: c_uint,
+        platform: *const c_void,
+        db: *mut *mut HsDatabase,
+        error: *mut *mut HsCompileError,
+    ) -> c_int;
+    fn hs_free_database(db: *mut HsDatabase) -> c_int;
+    fn hs_free_compile_error(error: *mut HsCompileError) -> c_int;
+    fn hs_alloc_scratch(db: *const HsDatabase, scratch: *mut *mut HsScratch) -> c_int;
+    fn hs_free_scratch(scratch: *mut HsScratch) -> c_int;
+    fn hs_open_stream(db: *const HsDatabase, flags: c_uint, stream: *mut *mut HsStream) -> c_int;
+    fn hs_scan_stream(
+        stream: *mut HsStream,
+        data: *const c_char,
+        length: c_uint,
+        flags: c_uint,
+        scratch: *mut HsScratch,
+        on_event: Option<MatchHandler>,
+        context: *mut c_void,
+    ) -> c_int;
+    fn hs_close_stream(
+        stream: *mut HsStream,
+        scratch: *mut HsScratch,
+        on_event: Option<MatchHandler>,
+        context: *mut c_void,
+    ) -> c_int;
+}
+
+// Literal patterns compiled for streaming.
+pub struct Database {
+    db: *mut HsDatabase,
+    lens: Vec<usize>,
+}
+
+// A compiled database is immutable, and can be scanned with from any thread.
+unsafe impl Send for Database {}
+unsafe impl Sync for Database {}
+
+impl Database {
+    pub fn new(patterns: &[&[u8]]) -> Result<Arc<Self>, String> {
+        // Every byte is escaped, so that the patterns are matched literally.
+        let expressions: Vec<CString> = patterns
+            .iter()
+            .map(|p| {
+                let escaped: String = p.iter().map(|b| format!("\\x{:02x}", b)).collect();
+                CString::new(escaped).expect("escaped patterns have no NUL")
+            })
+            .collect();
+        let pointers: Vec<*const c_char> = expressions.iter().map(|e| e.as_ptr()).collect();
+        let flags = vec![0; patterns.len()];
+        let ids: Vec<c_uint> = (0..patterns.len() as c_uint).collect();
+        let mut db = ptr::null_mut();
+        let mut error = ptr::null_mut();
+        // SAFETY: the arrays all have one element per pattern, and outlive the call.
+        let res = unsafe {
+            hs_compile_multi(
+                pointers.as_ptr(),
+                flags.as_ptr(),
+                ids.as_ptr(),
+                patterns.len() as c_uint,
+                HS_MODE_STREAM,
+                ptr::null(),
+                &mut db,
+                &mut error,
+            )
+        };
+        if res != HS_SUCCESS {
+            if error.is_null() {
+                return Err(format!(
+                    "hyperscan failed to compile the patterns ({})",
+                    res
+                ));
+            }
+            // SAFETY: on failure, `error` is set to an error that is ours to free.
+            let message = unsafe {
+                let message = CStr::from_ptr((*error).message)
+                    .to_string_lossy()
+                    .into_owned();
+                hs_free_compile_error(error);
+                message
+            };
+            return Err(format!("hyperscan can't compile the patterns: {}", message));
+        }
+        Ok(Arc::new(Database {
+            db,
+            lens: patterns.iter().map(|p| p.len()).collect(),
+        }))
+    }
+}
+
+impl Drop for Database {
+    fn drop(&mut self) {
+        // SAFETY: the database was compiled by us, and streams hold it alive until they close.
+        unsafe {
+            hs_free_database(self.db);
+        }
+    }
+}
+
+// Counts the non-overlapping matches of a database's patterns in a stream of chunks, choosing
+// them like Matcher::find_all: each is the one that ends first, ties going to the first pattern,
+// among those that start after the previous one ended.
+pub struct StreamCounter {
+    // Kept alive for as long as the scratch space and stream are.
+    _db: Arc<Database>,
+    scratch: *mut HsScratch,
+    stream: *mut HsStream,
+    state: Greedy,
+}
+
+// The scratch space and stream are only used by the thread that owns the counter.
+unsafe impl Send for StreamCounter {}
+
+struct Greedy {
+    lens: Vec<usize>,
+    counts: Vec<usize>,
+    // Where the last match counted ended.
+    last_end: u64,
+    // The first pattern with a match that ends at `.0` and could be counted, once all the
+    // matches that end there have been seen.
+    pending: Option<(u64, usize)>,
+}
+
+impl StreamCounter {
+    pub fn new(db: Arc<Database>) -> Result<Self, String> {
+        let mut scratch = ptr::null_mut();
+        let mut stream = ptr::null_mut();
+        // SAFETY: the database stays alive for as long as the counter holds it.
+        unsafe {
+            if hs_alloc_scratch(db.db, &mut scratch) != HS_SUCCESS {
+                return Err(String::from("hyperscan failed to allocate scratch space"));
+            }
+            if hs_open_stream(db.db, 0, &mut stream) != HS_SUCCESS {
+                hs_free_scratch(scratch);
+                return Err(String::from("hyperscan failed to open a stream"));
+            }
+        }
+        let state = Greedy {
+            counts: vec![0; db.lens.len()],
+            lens: db.lens.clone(),
+            last_end: 0,
+            pending: None,
+        };
+        Ok(StreamCounter {
+            _db: db,
+            scratch,
+            stream,
+            state,
+        })
+    }
+
+    pub fn write(&mut self, buf: &[u8]) {
+        if self.stream.is_null() {
+            return;
+        }
+        for piece in buf.chunks(c_uint::MAX as usize) {
+            // SAFETY: the stream is open, and `state` is what on_match expects as its context.
+            let res = unsafe {
+                hs_scan_stream(
+                    self.stream,
+                    piece.as_ptr().cast(),
+                    piece.len() as c_uint,
+                    0,
+                    self.scratch,
+                    Some(on_match),
+                    (&mut self.state as *mut Greedy).cast(),
+                )
+            };
+            assert_eq!(res, HS_SUCCESS, "hyperscan failed to scan");
+        }
+    }
+
+    // Reports the matches at the very end of the stream, and closes it.
+    pub fn finish(&mut self) {
+        if self.stream.is_null() {
+            return;
+        }
+        // SAFETY: as for write, and the stream isn't used again once closed.
+        unsafe {
+            hs_close_stream(
+                self.stream,
+                self.scratch,
+                Some(on_match),
+                (&mut self.state as *mut Greedy).cast(),
+            );
+        }
+        self.stream = ptr::null_mut();
+        self.state.commit();
+    }
+
+    pub fn counts(&self) -> &[usize] {
+        &self.state.counts
+    }
+}
+
+impl Drop for StreamCounter {
+    fn drop(&mut self) {
+        // SAFETY: both were allocated by us, for `db`, which is still alive.
+        unsafe {
+            if !self.stream.is_null() {
+                hs_close_stream(self.stream, self.scratch, None, ptr::null_mut());
+            }
+            hs_free_scratch(self.scratch);
+        }
+    }
+}
+
+impl Greedy {
+    // Hyperscan reports matches in order of where they end.
+    fn add(&mut self, pattern: usize, end: u64) {
+        if self
+            .pending
+            .is_some_and(|(pending_end, _)| pending_end != end)
+        {
+            self.commit();
+        }
+        let start = end - self.lens[pattern] as u64;
+        if start < self.last_end {
+            return;
+        }
+        self.pending = match self.pending {
+            Some((_, first)) if first < pattern => Some((end, first)),
+            _ => Some((end, pattern)),
+        };
+    }
+
+    fn commit(&mut self) {
+        if let Some((end, pattern)) = self.pending.take() {
+            self.counts[pattern] += 1;
+            self.last_end = end;
+        }
+    }
+}
+
+unsafe extern "C" fn on_match(
+    id: c_uint,
+    _from: c_ulonglong,
+    to: c_ulonglong,
+    _flags: c_uint,
+    context: *mut c_void,
+) -> c_int {
+    // SAFETY: the context is always the counter's state, which is borrowed for the scan.
+    let state = unsafe { &mut *context.cast::<Greedy>() };
+    state.add(id as usize, to);
+    // Carries on scanning.
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_greedy() {
+        // "ab", "b" and "abc" in "abcab", as reported by where they end.
+        let mut state = Greedy {
+            lens: vec![2, 1, 3],
+            counts: vec![0; 3],
+            last_end: 0,
+            pending: None,
+        };
+        for (pattern, end) in [(0, 2), (1, 2), (2, 3), (0, 5), (1, 5)] {
+            state.add(pattern, end);
+        }
+        state.commit();
+        assert_eq!(state.counts, [2, 0, 0]);
+    }
+}
@@ -0,0 +1,260 @@
+use crate::output;
+use crate::split::ByteRange;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tracing::info;
+
+// How much of the start of each file is kept, to tell when it was replaced by another file that
+// has since grown as long, as when a log is copied and truncated.
+const HEAD: usize = 64;
+
+// What --incremental keeps between runs: for each file and patterns counted, how far the file
+// was counted and the counts so far, so that the next run only reads what was appended since.
+//
+// Each line of the state file is an entry: the key, and then the file's inode, the start of the
+// file in hex, how far it was counted and the counts, separated by tabs. Lines that can't be read
+// are dropped, so that their files are counted from the start.
+pub struct State {
+    path: PathBuf,
+    entries: Mutex<BTreeMap<String, Entry>>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Entry {
+    inode: u64,
+    head: Vec<u8>,
+    offset: u64,
+    counts: Vec<usize>,
+}
+
+// Where to pick up counting a file.
+#[derive(Debug)]
+pub struct Resume {
+    key: String,
+    // What to read: from where the last run stopped, or from the start, up to the end of the
+    // last whole line.
+    pub range: ByteRange,
+    // The counts before the range.
+    pub counts: Option<Vec<usize>>,
+    inode: u64,
+    head: Vec<u8>,
+}
+
+impl State {
+    // Reads the state file at `path`, which is empty if there is none yet.
+    pub fn load(path: PathBuf) -> io::Result<Self> {
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e),
+        };
+        let entries = text.lines().filter_map(parse_line).collect();
+        Ok(State {
+            path,
+            entries: Mutex::new(entries),
+        })
+    }
+
+    // Says what to count of the file at `path` for `patterns`, or None if it isn't a file that
+    // can be counted a part at a time, such as stdin or a pipe, or can't be read.
+    pub fn resume(&self, path: &Path, patterns: &[&[u8]]) -> Option<Resume> {
+        let path = path.canonicalize().ok()?;
+        let mut f = File::open(&path).ok()?;
+        let meta = f.metadata().ok()?;
+        if !meta.is_file() {
+            return None;
+        }
+        let mut key = String::new();
+        write_hex(&mut key, path.as_os_str().as_encoded_bytes());
+        for pattern in patterns {
+            key.push('\t');
+            write_hex(&mut key, pattern);
+        }
+
+        let len = meta.len();
+        let mut head = Vec::new();
+        f.by_ref().take(HEAD as u64).read_to_end(&mut head).ok()?;
+        let inode = inode(&meta);
+        let entries = self.entries.lock().unwrap();
+        // The file is the one that was counted, and has only been appended to, if it is as long,
+        // and starts the same.
+        let last = entries.get(&key).filter(|entry| {
+            entry.inode == inode && entry.offset <= len && head.starts_with(&entry.head)
+        });
+        if entries.contains_key(&key) && last.is_none() {
+            info!(
+                "{} was truncated or replaced, so counting it from the start",
+                path.display()
+            );
+        }
+        let start = last.map_or(0, |entry| entry.offset);
+        let counts = last.map(|entry| entry.counts.clone());
+        drop(entries);
+
+        // The last line may still be being written, so it is left for the next run.
+        let end = last_line_end(&mut f, start, len).ok()?;
+        head.truncate(end.min(HEAD as u64) as usize);
+        Some(Resume {
+            key,
+            range: ByteRange { start, end },
+            counts,
+            inode,
+            head,
+        })
+    }
+
+    // Records that the file of `resume` was counted up to the end of its range, with `counts`
+    // in all of it.
+    pub fn put(&self, resume: Resume, counts: &[usize]) {
+        let entry = Entry {
+            inode: resume.inode,
+            head: resume.head,
+            offset: resume.range.end,
+            counts: counts.to_vec(),
+        };
+        self.entries.lock().unwrap().insert(resume.key, entry);
+    }
+
+    // Writes the state file, with the entries of files that weren't counted this time as they
+    // were.
+    pub fn save(&self) -> io::Result<()> {
+        let mut text = String::new();
+        for (key, entry) in self.entries.lock().unwrap().iter() {
+            write!(text, "{}\t{}\t", key, entry.inode).unwrap();
+            write_hex(&mut text, &entry.head);
+            write!(text, "\t{}", entry.offset).unwrap();
+            for c in &entry.counts {
+                write!(text, "\t{}", c).unwrap();
+            }
+            text.push('\n');
+        }
+        output::replace(&self.path, false, text.as_bytes())
+    }
+}
+
+// Parses a line of the state file. The key has the path and each pattern, so the fields after
+// them are found from the count of patterns, which is how many counts there are.
+fn parse_line(line: &str) -> Option<(String, Entry)> {
+    let fields: Vec<&str> = line.split('\t').collect();
+    // The path, the patterns, the inode, the head, the offset and a count for each pattern.
+    let patterns = fields.len().checked_sub(4)? / 2;
+    if fields.len() != 4 + 2 * patterns || patterns == 0 {
+        return None;
+    }
+    let (key, rest) = fields.split_at(1 + patterns);
+    let entry = Entry {
+        inode: rest[0].parse().ok()?,
+        head: parse_hex(rest[1])?,
+        offset: rest[2].parse().ok()?,
+        counts: rest[3..]
+            .iter()
+            .map(|c| c.parse().ok())
+            .collect::<Option<_>>()?,
+    };
+    Some((key.join("\t"), entry))
+}
+
+// Returns where the last line between `start` and `len` in `f` ends, just after its newline, or
+// `start` if there is no newline there.
+fn last_line_end(f: &mut (impl Read + Seek), start: u64, len: u64) -> io::Result<u64> {
+    let mut buf = vec![0; 64 * 1024];
+    let mut end = len;
+    while end > start {
+        let n = (end - start).min(buf.len() as u64) as usize;
+        f.seek(SeekFrom::Start(end - n as u64))?;
+        f.read_exact(&mut buf[..n])?;
+        if let Some(i) = memchr::memrchr(b'\n', &buf[..n]) {
+            return Ok(end - n as u64 + i as u64 + 1);
+        }
+        end -= n as u64;
+    }
+    Ok(start)
+}
+
+// The inode of a file, which stays the same as it is appended to, but not when it is rotated.
+#[cfg(unix)]
+fn inode(meta: &fs::Metadata) -> u64 {
+    std::os::unix::fs::MetadataExt::ino(meta)
+}
+
+// Elsewhere, a rotated file is only told apart by its start.
+#[cfg(not(unix))]
+fn inode(_: &fs::Metadata) -> u64 {
+    0
+}
+
+fn write_hex(s: &mut String, bytes: &[u8]) {
+    for b in bytes {
+        write!(s, "{:02x}", b).unwrap();
+    }
+}
+
+fn parse_hex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resume() {
+        let dir = std::env::temp_dir().join(format!("freq-incremental-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("app.log");
+        let state_path = dir.join("state");
+        let patterns: [&[u8]; 2] = [b"a", b"b\tc"];
+        fs::write(&path, "a\nb a\npart").unwrap();
+
+        let state = State::load(state_path.clone()).unwrap();
+        let resume = state.resume(&path, &patterns).unwrap();
+        assert_eq!(
+            (resume.range, resume.counts.clone()),
+            (ByteRange { start: 0, end: 6 }, None)
+        );
+        state.put(resume, &[2, 0]);
+        state.save().unwrap();
+
+        // Only what was appended is read, from the line that wasn't finished.
+        fs::write(&path, "a\nb a\npartial a\nmore").unwrap();
+        let state = State::load(state_path.clone()).unwrap();
+        let resume = state.resume(&path, &patterns).unwrap();
+        assert_eq!(resume.range, ByteRange { start: 6, end: 16 });
+        assert_eq!(resume.counts, Some(vec![2, 0]));
+        // Other patterns are counted on their own.
+        assert_eq!(state.resume(&path, &[b"a"]).unwrap().counts, None);
+        state.put(resume, &[4, 0]);
+
+        // A file that is shorter, or starts differently, was truncated or replaced.
+        fs::write(&path, "a\n").unwrap();
+        let resume = state.resume(&path, &patterns).unwrap();
+        assert_eq!(
+            (resume.range, resume.counts),
+            (ByteRange { start: 0, end: 2 }, None)
+        );
+        fs::write(&path, "b\nb a\npartial a\nmore and more\n").unwrap();
+        let resume = state.resume(&path, &patterns).unwrap();
+        assert_eq!((resume.range.start, resume.counts), (0, None));
+
+        assert!(state.resume(&dir.join("missing"), &patterns).is_none());
+        assert!(state.resume(&dir, &patterns).is_none());
+        fs::write(&state_path, "garbage\n").unwrap();
+        assert!(State::load(state_path)
+            .unwrap()
+            .entries
+            .lock()
+            .unwrap()
+            .is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
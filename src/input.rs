@@ -0,0 +1,882 @@
+use crate::chunk::Chunk;
+use crate::metrics;
+use crate::split::ByteRange;
+use crate::tune;
+use clap::ValueEnum;
+#[cfg(not(target_os = "wasi"))]
+use crossbeam_channel::{Receiver, RecvTimeoutError, Sender};
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::fmt::Display;
+use std::fs::File;
+use std::io::{self, stdin, IoSliceMut, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering};
+#[cfg(not(target_os = "wasi"))]
+use std::sync::Condvar;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{debug, info, trace};
+
+pub type Input = Box<dyn Read + Send + 'static>;
+
+// Whether to stop at the first input that can't be read.
+static STRICT: AtomicBool = AtomicBool::new(false);
+
+// Whether to read inputs on the thread that counts them, as with --jobs 1.
+static SINGLE_THREADED: AtomicBool = AtomicBool::new(false);
+
+// How inputs are handed from the thread reading them to the one counting them, from --pipeline.
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum Pipeline {
+    /// ping-pong when there is a single input and one thread counting it, where it is fastest,
+    /// and channel otherwise.
+    Auto,
+    /// Chunks read with readv are sent over a channel, and reused once counted.
+    Channel,
+    /// Two buffers are swapped between the reader and the counter, without a channel or
+    /// allocating.
+    PingPong,
+}
+
+static PIPELINE: AtomicU8 = AtomicU8::new(Pipeline::Auto as u8);
+
+// Whether the inputs opened last are a single one, for --pipeline auto.
+static SINGLE_INPUT: AtomicBool = AtomicBool::new(false);
+
+// Whether chunks are handed on to several counting threads, as in freq kmers.
+#[cfg(not(target_os = "wasi"))]
+static PARALLEL_COUNTING: AtomicBool = AtomicBool::new(false);
+
+// How many milliseconds an input may take to read before it's abandoned, from --file-timeout,
+// or 0 for no limit.
+static FILE_TIMEOUT: AtomicU64 = AtomicU64::new(0);
+
+// The file a reader thread is reading, to name it if it times out, since it's opened on that
+// thread. Each reader has its own, so one that was given up on can't clear the next one's.
+type Reading = Arc<Mutex<Option<PathBuf>>>;
+
+thread_local! {
+    // The slot of the reader thread this runs on, if it is one.
+    static READING: RefCell<Option<Reading>> = const { RefCell::new(None) };
+
+    // The error of the last input this thread couldn't read, since inputs can be counted on
+    // several threads at once.
+    static LAST_ERROR: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+fn set_reading(path: Option<&Path>) {
+    READING.with_borrow(|slot| {
+        if let Some(slot) = slot {
+            *slot.lock().unwrap() = path.map(Path::to_path_buf);
+        }
+    });
+}
+
+// Why each input that couldn't be read was skipped, to be summed up at the end.
+static ERRORS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+pub fn set_strict() {
+    STRICT.store(true, Ordering::Relaxed);
+}
+
+pub fn set_single_threaded() {
+    SINGLE_THREADED.store(true, Ordering::Relaxed);
+}
+
+pub fn set_pipeline(pipeline: Pipeline) {
+    PIPELINE.store(pipeline as u8, Ordering::Relaxed);
+}
+
+pub fn set_file_timeout(timeout: Duration) {
+    let millis = timeout.as_millis().try_into().unwrap_or(u64::MAX);
+    FILE_TIMEOUT.store(millis, Ordering::Relaxed);
+}
+
+#[cfg(not(target_os = "wasi"))]
+fn file_timeout() -> Option<Duration> {
+    match FILE_TIMEOUT.load(Ordering::Relaxed) {
+        0 => None,
+        millis => Some(Duration::from_millis(millis)),
+    }
+}
+
+#[cfg(not(target_os = "wasi"))]
+pub fn set_parallel_counting() {
+    PARALLEL_COUNTING.store(true, Ordering::Relaxed);
+}
+
+// The pipeline to read an input with. Only the channel can be given up on while the reader is
+// stuck, so it's the one used with --file-timeout.
+#[cfg(not(target_os = "wasi"))]
+fn pipeline() -> Pipeline {
+    if file_timeout().is_some() {
+        return Pipeline::Channel;
+    }
+    match PIPELINE.load(Ordering::Relaxed) {
+        p if p == Pipeline::Channel as u8 => Pipeline::Channel,
+        p if p == Pipeline::PingPong as u8 => Pipeline::PingPong,
+        _ if SINGLE_INPUT.load(Ordering::Relaxed) && !PARALLEL_COUNTING.load(Ordering::Relaxed) => {
+            Pipeline::PingPong
+        }
+        _ => Pipeline::Channel,
+    }
+}
+
+// Records that an input couldn't be read, and carries on with the rest. In strict mode, exits
+// instead.
+pub fn report(err: impl Display) {
+    if STRICT.load(Ordering::Relaxed) {
+        eprintln!("freq: {}", err);
+        std::process::exit(2);
+    }
+    info!("skipping {}", err);
+    let err = err.to_string();
+    LAST_ERROR.set(Some(err.clone()));
+    ERRORS.lock().unwrap().push(err);
+}
+
+// Returns the error of the last input that couldn't be read on this thread.
+pub fn last_error() -> Option<String> {
+    LAST_ERROR.with_borrow(Clone::clone)
+}
+
+// Returns the errors of the inputs that couldn't be read so far.
+pub fn take_errors() -> Vec<String> {
+    std::mem::take(&mut ERRORS.lock().unwrap())
+}
+
+// Returns the given files as inputs, or stdin if there are none. "-" is stdin too. Each file is
+// only opened when it's first read, and closed once it's read to the end, so that any number of
+// files can be given without running out of file descriptors. A file that can't be opened fails
+// in its turn.
+//
+// Everything is read as a stream, so pipes, FIFOs and character devices like /dev/stdin are read
+// just like files.
+pub fn open_inputs(paths: &[PathBuf]) -> Vec<Input> {
+    open_ranges(paths, None)
+}
+
+// Like open_inputs, but only reads `range` of each input. Files are read from the start of the
+// range, and so is stdin if it is a file. Other stdin is read up to the range and then skipped.
+pub fn open_ranges(paths: &[PathBuf], range: Option<ByteRange>) -> Vec<Input> {
+    let stdin = || -> Input {
+        let Some(range) = range else {
+            return Box::new(stdin());
+        };
+        if let Some(mut f) = stdin_file() {
+            // The range is from where stdin is, as it is for a stream.
+            let seek = f
+                .stream_position()
+                .and_then(|pos| f.seek(SeekFrom::Start(pos + range.start)));
+            if seek.is_ok() {
+                debug!("stdin is a file, so seeking to the range");
+                return Box::new(f.take(range.len()));
+            }
+        }
+        Box::new(StreamRange {
+            inner: stdin(),
+            skip: range.start,
+            left: range.len(),
+        })
+    };
+    SINGLE_INPUT.store(paths.len() <= 1, Ordering::Relaxed);
+    if paths.is_empty() {
+        vec![stdin()]
+    } else {
+        paths
+            .iter()
+            .map(|p| -> Input {
+                if p.as_os_str() == "-" {
+                    return stdin();
+                }
+                Box::new(NamedFile {
+                    path: p.clone(),
+                    range,
+                    state: FileState::Unopened,
+                })
+            })
+            .collect()
+    }
+}
+
+// Returns the file at `path` as an input, to read only `range` of it, as open_ranges does.
+pub fn open_range(path: &Path, range: ByteRange) -> Input {
+    Box::new(NamedFile {
+        path: path.to_path_buf(),
+        range: Some(range),
+        state: FileState::Unopened,
+    })
+}
+
+// Returns stdin as a file if it is one, as with `freq PATTERN < big.log`, so that it can seek
+// like the files given by path. The file is at the same position as stdin.
+#[cfg(any(unix, windows))]
+pub fn stdin_file() -> Option<File> {
+    #[cfg(unix)]
+    let owned = std::os::fd::AsFd::as_fd(&stdin()).try_clone_to_owned();
+    #[cfg(windows)]
+    let owned = std::os::windows::io::AsHandle::as_handle(&stdin()).try_clone_to_owned();
+    let f = File::from(owned.ok()?);
+    f.metadata().ok()?.is_file().then_some(f)
+}
+
+#[cfg(not(any(unix, windows)))]
+pub fn stdin_file() -> Option<File> {
+    None
+}
+
+// A range of a stream that can't seek, read by reading up to it.
+struct StreamRange<R> {
+    inner: R,
+    // How much is left to skip, and then to read.
+    skip: u64,
+    left: u64,
+}
+
+impl<R: Read> Read for StreamRange<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.skip > 0 {
+            let len = buf.len().min(self.skip.try_into().unwrap_or(usize::MAX));
+            let n = self.inner.read(&mut buf[..len])?;
+            if n == 0 {
+                return Ok(0);
+            }
+            self.skip -= n as u64;
+        }
+        let len = buf.len().min(self.left.try_into().unwrap_or(usize::MAX));
+        let n = self.inner.read(&mut buf[..len])?;
+        self.left -= n as u64;
+        Ok(n)
+    }
+}
+
+// A file that is opened lazily, and whose errors say which file it is.
+struct NamedFile {
+    path: PathBuf,
+    // Only this part of the file is read, if given.
+    range: Option<ByteRange>,
+    state: FileState,
+}
+
+enum FileState {
+    Unopened,
+    // With when it was opened, and how much of it has been read.
+    Open(File, Instant, u64),
+    // Read to the end, or failed.
+    Done,
+}
+
+impl NamedFile {
+    fn read_file(&mut self, bufs: &mut [IoSliceMut]) -> io::Result<usize> {
+        if let FileState::Unopened = self.state {
+            // Before opening, which is where a dead mount hangs.
+            set_reading(Some(&self.path));
+            let mut f = File::open(long_path(&self.path))?;
+            if let Some(range) = self.range {
+                f.seek(SeekFrom::Start(range.start))?;
+            }
+            info!("reading {}", self.path.display());
+            self.state = FileState::Open(f, Instant::now(), 0);
+        }
+        match &mut self.state {
+            FileState::Open(f, _, read) => {
+                let n = match self.range {
+                    // Reads into one buffer, which can be cut short at the end of the range.
+                    Some(range) => {
+                        let Some(buf) = bufs.iter_mut().find(|b| !b.is_empty()) else {
+                            return Ok(0);
+                        };
+                        let left = range.len().saturating_sub(*read);
+                        let len = buf.len().min(left.try_into().unwrap_or(usize::MAX));
+                        f.read(&mut buf[..len])?
+                    }
+                    None => f.read_vectored(bufs)?,
+                };
+                *read += n as u64;
+                Ok(n)
+            }
+            _ => Ok(0),
+        }
+    }
+
+    // Closes the file once it's read to the end, and says which file an error is from.
+    // `wanted` is whether there was room to read anything.
+    fn finish_read(&mut self, res: io::Result<usize>, wanted: bool) -> io::Result<usize> {
+        match res {
+            Ok(0) if wanted => {
+                // Closes the file.
+                if let FileState::Open(_, opened, read) = self.state {
+                    debug!(
+                        "read {} bytes of {} in {:.3?}",
+                        read,
+                        self.path.display(),
+                        opened.elapsed()
+                    );
+                }
+                self.state = FileState::Done;
+                set_reading(None);
+                Ok(0)
+            }
+            Ok(n) => Ok(n),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => Err(e),
+            Err(e) => {
+                self.state = FileState::Done;
+                set_reading(None);
+                Err(io::Error::new(
+                    e.kind(),
+                    format!("{}: {}", self.path.display(), e),
+                ))
+            }
+        }
+    }
+}
+
+impl Read for NamedFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let wanted = !buf.is_empty();
+        let res = self.read_file(&mut [IoSliceMut::new(buf)]);
+        self.finish_read(res, wanted)
+    }
+
+    fn read_vectored(&mut self, bufs: &mut [IoSliceMut]) -> io::Result<usize> {
+        let wanted = bufs.iter().any(|b| !b.is_empty());
+        let res = self.read_file(bufs);
+        self.finish_read(res, wanted)
+    }
+}
+
+// Returns the form of `path` to open it by. On Windows, paths longer than MAX_PATH can only be
+// opened in the verbatim \\?\ form, which has to be absolute, and is \\?\UNC\server\share\...
+// for network shares. Messages still show `path` as it was given.
+#[cfg(windows)]
+pub fn long_path(path: &Path) -> Cow<'_, Path> {
+    use std::ffi::OsString;
+    use std::path::{Component, Prefix};
+
+    const MAX_PATH: usize = 260;
+    // This also resolves "." and "..", which verbatim paths don't.
+    let Ok(abs) = std::path::absolute(path) else {
+        return Cow::Borrowed(path);
+    };
+    if abs.as_os_str().len() < MAX_PATH {
+        return Cow::Borrowed(path);
+    }
+    let mut components = abs.components();
+    let Some(Component::Prefix(prefix)) = components.next() else {
+        return Cow::Borrowed(path);
+    };
+    let mut long = OsString::from(r"\\?\");
+    match prefix.kind() {
+        Prefix::Disk(_) => long.push(prefix.as_os_str()),
+        Prefix::UNC(server, share) => {
+            long.push(r"UNC\");
+            long.push(server);
+            long.push(r"\");
+            long.push(share);
+        }
+        // Already verbatim, or a device.
+        _ => return Cow::Owned(abs),
+    }
+    for c in components {
+        if let Component::Normal(c) = c {
+            long.push(r"\");
+            long.push(c);
+        }
+    }
+    Cow::Owned(PathBuf::from(long))
+}
+
+#[cfg(not(windows))]
+pub fn long_path(path: &Path) -> Cow<'_, Path> {
+    Cow::Borrowed(path)
+}
+
+// Makes each input copy everything read from it to stdout. Once stdout is closed, as by
+// `freq --tee ... | head`, the rest is still counted, just not copied.
+pub fn tee_inputs(inputs: Vec<Input>) -> Vec<Input> {
+    inputs
+        .into_iter()
+        .map(|f| Box::new(Tee { inner: f }) as _)
+        .collect()
+}
+
+// Whether stdout was closed by whatever reads it, shared by all the inputs.
+static TEE_CLOSED: AtomicBool = AtomicBool::new(false);
+
+struct Tee<R> {
+    inner: R,
+}
+
+impl<R: Read> Read for Tee<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if TEE_CLOSED.load(Ordering::Relaxed) {
+            return Ok(n);
+        }
+        match io::stdout().write_all(&buf[..n]) {
+            Err(e) if e.kind() == io::ErrorKind::BrokenPipe => {
+                info!("stdout was closed, so the input is no longer copied to it");
+                TEE_CLOSED.store(true, Ordering::Relaxed);
+                Ok(n)
+            }
+            Err(e) => Err(io::Error::new(
+                e.kind(),
+                format!("failed to copy the input to stdout: {}", e),
+            )),
+            Ok(()) => Ok(n),
+        }
+    }
+}
+
+// Reads into `buf`, trying again if interrupted.
+fn read_some(f: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+    loop {
+        match f.read(buf) {
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            res => return res,
+        }
+    }
+}
+
+// Reads into `bufs` in order, in one call where the input supports it, trying again if
+// interrupted.
+#[cfg(not(target_os = "wasi"))]
+fn read_some_vectored(f: &mut impl Read, bufs: &mut [Vec<u8>]) -> io::Result<usize> {
+    let mut slices: Vec<IoSliceMut> = bufs.iter_mut().map(|v| IoSliceMut::new(v)).collect();
+    loop {
+        match f.read_vectored(&mut slices) {
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            res => return res,
+        }
+    }
+}
+
+// The most that one chunk holds, so that it stays in cache while it's counted. A read fills
+// as many chunks as the buffer size makes up, with a single readv where the input supports it,
+// so that a large buffer size still means few system calls.
+#[cfg(not(target_os = "wasi"))]
+const SEGMENT_SIZE: usize = 256 * 1024;
+
+// Reads `f` into chunks on a separate thread. Buffers sent back on the returned sender, once
+// they've been counted, are filled again instead of allocating new ones.
+#[cfg(not(target_os = "wasi"))]
+fn read_chunks<R: Read + Send + 'static>(
+    mut f: R,
+    chunk_size: usize,
+) -> (Receiver<io::Result<Chunk>>, Sender<Vec<u8>>, Reading) {
+    let (s, r) = crossbeam_channel::bounded(0);
+    let (pool_s, pool_r) = crossbeam_channel::unbounded::<Vec<u8>>();
+    let segments = chunk_size.div_ceil(SEGMENT_SIZE).max(1);
+    let segment_size = chunk_size.div_ceil(segments).max(1);
+    let reading = Reading::default();
+    let slot = reading.clone();
+    std::thread::spawn(move || {
+        READING.set(Some(slot));
+        let mut bufs = Vec::with_capacity(segments);
+        loop {
+            // Get buffers, reusing the ones already counted.
+            while bufs.len() < segments {
+                // A zeroed allocation is served by fresh pages from the OS, so this is cheap.
+                bufs.push(pool_r.try_recv().unwrap_or_else(|_| vec![0; segment_size]));
+            }
+
+            // Try to fill the buffers.
+            let timer = metrics::timer();
+            let bytes_read = match read_some_vectored(&mut f, &mut bufs) {
+                Ok(n) => {
+                    metrics::READ.add(timer, n);
+                    n
+                }
+                Err(e) => {
+                    // The error ends the input.
+                    let _ = s.send(Err(e));
+                    break;
+                }
+            };
+
+            // If we read 0 bytes, we are done.
+            if bytes_read == 0 {
+                break;
+            }
+            let filled = bytes_read.div_ceil(segment_size);
+            trace!("read {} bytes into {} chunks", bytes_read, filled);
+
+            // Send the filled buffers, in order. If the receiver is gone, nobody needs the rest.
+            metrics::queued(filled);
+            let mut left = bytes_read;
+            for v in bufs.drain(..filled) {
+                let len = left.min(segment_size);
+                left -= len;
+                let timer = metrics::timer();
+                if s.send(Ok(Chunk::new(v, len))).is_err() {
+                    return;
+                }
+                metrics::READER_WAIT.add(timer, len);
+            }
+        }
+        // Sender drops.
+    });
+    (r, pool_s, reading)
+}
+
+// Calls `each` on everything in `f`, chunk by chunk, reading on a separate thread. Returns
+// whether all of `f` was read; if not, the error has been reported.
+pub fn for_each_chunk<R: Read + Send + 'static>(
+    f: R,
+    chunk_size: usize,
+    mut each: impl FnMut(&[u8]),
+) -> bool {
+    for_each_shared_chunk(f, chunk_size, |chunk| each(chunk))
+}
+
+// Like for_each_chunk, but `each` is given the chunks themselves, so that it can keep them or
+// parts of them without copying.
+pub fn for_each_shared_chunk<R: Read + Send + 'static>(
+    mut f: R,
+    chunk_size: usize,
+    mut each: impl FnMut(&Chunk),
+) -> bool {
+    match auto_tune(&mut f, &mut each) {
+        Ok(true) => {}
+        Ok(false) => return true,
+        Err(e) => {
+            report(e);
+            return false;
+        }
+    }
+    let chunk_size = tune::chosen().unwrap_or(chunk_size);
+    read_with_pipeline(f, chunk_size, each)
+}
+
+// With --auto-tune, reads and counts the start of `f` with each buffer size that is still to be
+// tried, timing them, until all have been. Returns whether there is more of `f` to read.
+fn auto_tune(f: &mut impl Read, each: &mut impl FnMut(&Chunk)) -> io::Result<bool> {
+    while let Some(size) = tune::next_trial() {
+        debug!("auto-tune: trying buffers of {} bytes", size);
+        let mut v = vec![0; size];
+        let mut bytes = 0;
+        let started = Instant::now();
+        while bytes < tune::TRIAL_BYTES {
+            let n = read_some(f, &mut v)?;
+            if n == 0 {
+                tune::record(size, bytes, started.elapsed());
+                return Ok(false);
+            }
+            let chunk = Chunk::new(v, n);
+            count_chunk(&chunk, each);
+            v = chunk.into_buf().unwrap_or_else(|| vec![0; size]);
+            bytes += n;
+        }
+        tune::record(size, bytes, started.elapsed());
+    }
+    Ok(true)
+}
+
+// Reads `f` in chunks of `chunk_size`, on the pipeline asked for.
+#[cfg(not(target_os = "wasi"))]
+fn read_with_pipeline<R: Read + Send + 'static>(
+    f: R,
+    chunk_size: usize,
+    each: impl FnMut(&Chunk),
+) -> bool {
+    if SINGLE_THREADED.load(Ordering::Relaxed) && file_timeout().is_none() {
+        return for_each_chunk_inline(f, chunk_size, each);
+    }
+    match pipeline() {
+        Pipeline::PingPong => for_each_chunk_ping_pong(f, chunk_size, each),
+        _ => for_each_chunk_channel(f, chunk_size, each),
+    }
+}
+
+// Like for_each_shared_chunk, but with chunks sent over a channel by read_chunks.
+#[cfg(not(target_os = "wasi"))]
+fn for_each_chunk_channel<R: Read + Send + 'static>(
+    f: R,
+    chunk_size: usize,
+    mut each: impl FnMut(&Chunk),
+) -> bool {
+    debug!(
+        "reading up to {} bytes at a time, on a separate thread",
+        chunk_size
+    );
+    let deadline = file_timeout().map(|timeout| (Instant::now() + timeout, timeout));
+    let (r, pool, reading) = read_chunks(f, chunk_size);
+    loop {
+        let timer = metrics::timer();
+        let chunk = match deadline {
+            Some((deadline, timeout)) => match r.recv_deadline(deadline) {
+                Ok(chunk) => chunk,
+                Err(RecvTimeoutError::Timeout) => {
+                    // The reader is left behind, and stops once its read returns.
+                    let name = reading.lock().unwrap().take();
+                    let name = name.map_or("stdin".into(), |p| p.display().to_string());
+                    report(format!("{}: gave up after {:?}", name, timeout));
+                    return false;
+                }
+                Err(RecvTimeoutError::Disconnected) => return true,
+            },
+            None => match r.recv() {
+                Ok(chunk) => chunk,
+                Err(_) => return true,
+            },
+        };
+        metrics::COUNTER_WAIT.add(timer, 0);
+        match chunk {
+            Ok(chunk) => {
+                metrics::taken();
+                count_chunk(&chunk, &mut each);
+                if let Some(v) = chunk.into_buf() {
+                    let _ = pool.send(v);
+                }
+            }
+            Err(e) => {
+                report(e);
+                return false;
+            }
+        }
+    }
+}
+
+// Calls `each` on `chunk`, timing it for --stats.
+fn count_chunk(chunk: &Chunk, each: &mut impl FnMut(&Chunk)) {
+    let timer = metrics::timer();
+    each(chunk);
+    metrics::COUNT.add(timer, chunk.len());
+}
+
+// What the reader and the counter of for_each_chunk_ping_pong share.
+#[cfg(not(target_os = "wasi"))]
+struct Exchange {
+    slots: Mutex<Slots>,
+    changed: Condvar,
+}
+
+#[cfg(not(target_os = "wasi"))]
+struct Slots {
+    // A chunk the reader filled, or why reading failed.
+    filled: Option<io::Result<Chunk>>,
+    // A buffer the counter is done with, for the reader to fill.
+    spare: Option<Vec<u8>>,
+    // Whether the reader has stopped.
+    done: bool,
+}
+
+// Like for_each_shared_chunk, but with just two buffers of `chunk_size`: the reader fills one
+// while the other is counted, and then they swap. A buffer that is still shared once counted is
+// replaced by a new one.
+#[cfg(not(target_os = "wasi"))]
+fn for_each_chunk_ping_pong<R: Read + Send>(
+    mut f: R,
+    chunk_size: usize,
+    mut each: impl FnMut(&Chunk),
+) -> bool {
+    debug!(
+        "reading in two buffers of {} bytes, swapped with a separate thread",
+        chunk_size
+    );
+    let exchange = Exchange {
+        slots: Mutex::new(Slots {
+            filled: None,
+            spare: Some(vec![0; chunk_size]),
+            done: false,
+        }),
+        changed: Condvar::new(),
+    };
+    let wait = |cond: fn(&mut Slots) -> bool| {
+        let slots = exchange.slots.lock().unwrap();
+        exchange.changed.wait_while(slots, cond).unwrap()
+    };
+    std::thread::scope(|scope| {
+        scope.spawn(|| {
+            let mut buf = vec![0; chunk_size];
+            loop {
+                let timer = metrics::timer();
+                let res = read_some(&mut f, &mut buf);
+                if let Ok(n) = res {
+                    metrics::READ.add(timer, n);
+                }
+                // Hands the buffer over once the counter has taken the last one.
+                let timer = metrics::timer();
+                let mut slots = wait(|s| s.filled.is_some());
+                match res {
+                    Ok(0) => {}
+                    Ok(n) => {
+                        metrics::queued(1);
+                        slots.filled = Some(Ok(Chunk::new(buf, n)));
+                        exchange.changed.notify_one();
+                        drop(slots);
+                        // Fills the other one, once it's counted.
+                        let mut slots = wait(|s| s.spare.is_none());
+                        buf = slots.spare.take().unwrap();
+                        metrics::READER_WAIT.add(timer, n);
+                        continue;
+                    }
+                    Err(e) => slots.filled = Some(Err(e)),
+                }
+                slots.done = true;
+                exchange.changed.notify_one();
+                return;
+            }
+        });
+
+        loop {
+            let timer = metrics::timer();
+            let mut slots = wait(|s| s.filled.is_none() && !s.done);
+            metrics::COUNTER_WAIT.add(timer, 0);
+            match slots.filled.take() {
+                Some(Ok(chunk)) => {
+                    metrics::taken();
+                    exchange.changed.notify_one();
+                    drop(slots);
+                    count_chunk(&chunk, &mut each);
+                    let buf = chunk.into_buf().unwrap_or_else(|| vec![0; chunk_size]);
+                    exchange.slots.lock().unwrap().spare = Some(buf);
+                    exchange.changed.notify_one();
+                }
+                Some(Err(e)) => {
+                    report(e);
+                    return false;
+                }
+                None => return true,
+            }
+        }
+    })
+}
+
+// WASI has no threads, so reading happens inline.
+#[cfg(target_os = "wasi")]
+fn read_with_pipeline<R: Read>(f: R, chunk_size: usize, each: impl FnMut(&Chunk)) -> bool {
+    for_each_chunk_inline(f, chunk_size, each)
+}
+
+// Like for_each_shared_chunk, but reads on this thread, into one buffer while it isn't shared.
+fn for_each_chunk_inline<R: Read>(
+    mut f: R,
+    chunk_size: usize,
+    mut each: impl FnMut(&Chunk),
+) -> bool {
+    debug!("reading in chunks of up to {} bytes, inline", chunk_size);
+    let mut v = vec![0; chunk_size];
+    loop {
+        let timer = metrics::timer();
+        let bytes_read = match read_some(&mut f, &mut v) {
+            Ok(n) => {
+                metrics::READ.add(timer, n);
+                n
+            }
+            Err(e) => {
+                report(e);
+                return false;
+            }
+        };
+        if bytes_read == 0 {
+            return true;
+        }
+        trace!("read a chunk of {} bytes", bytes_read);
+        let chunk = Chunk::new(v, bytes_read);
+        count_chunk(&chunk, &mut each);
+        v = chunk.into_buf().unwrap_or_else(|| vec![0; chunk_size]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_open_failure() {
+        let dir = std::env::temp_dir().join(format!("freq-input-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let paths = [dir.join("missing"), dir.join("a")];
+
+        // Files are only opened when they're read, so this one can come later.
+        let mut inputs = open_inputs(&paths).into_iter();
+        std::fs::write(dir.join("a"), "abc").unwrap();
+
+        // The missing file fails in its turn, and the next one is still read.
+        let mut read: Vec<u8> = Vec::new();
+        assert!(!for_each_chunk(inputs.next().unwrap(), 2, |c| read.extend(c)));
+        assert!(for_each_chunk(inputs.next().unwrap(), 2, |c| read.extend(c)));
+        assert_eq!(read, b"abc");
+        let errors = take_errors();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].starts_with(&paths[0].display().to_string()));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(not(target_os = "wasi"))]
+    #[test]
+    fn test_segmented_chunks() {
+        let text: Vec<u8> = (0..SEGMENT_SIZE * 5 / 2).map(|i| i as u8).collect();
+        let mut read: Vec<u8> = Vec::new();
+        let mut largest = 0;
+        let f = io::Cursor::new(text.clone());
+        assert!(for_each_chunk_channel(f, SEGMENT_SIZE * 2, |c| {
+            largest = largest.max(c.len());
+            read.extend_from_slice(c);
+        }));
+        assert_eq!(read, text);
+        assert!(largest <= SEGMENT_SIZE);
+    }
+
+    #[cfg(not(target_os = "wasi"))]
+    #[test]
+    fn test_ping_pong() {
+        let text: Vec<u8> = (0..10_000).map(|i| i as u8).collect();
+        let mut read: Vec<u8> = Vec::new();
+        let f = io::Cursor::new(text.clone());
+        assert!(for_each_chunk_ping_pong(f, 333, |c| {
+            assert!(c.len() <= 333);
+            read.extend_from_slice(c);
+        }));
+        assert_eq!(read, text);
+    }
+
+    #[cfg(not(target_os = "wasi"))]
+    #[test]
+    fn test_kept_chunks() {
+        // Chunks that are kept aren't read into again.
+        let text: Vec<u8> = (0..10_000).map(|i| i as u8).collect();
+        let mut kept = Vec::new();
+        let f = io::Cursor::new(text.clone());
+        assert!(for_each_chunk_ping_pong(f, 333, |c| kept.push(c.clone())));
+        assert_eq!(
+            kept.iter()
+                .flat_map(|c| c.iter())
+                .copied()
+                .collect::<Vec<u8>>(),
+            text
+        );
+
+        let mut kept = Vec::new();
+        let f = io::Cursor::new(text.clone());
+        assert!(for_each_chunk_inline(f, 333, |c| kept.push(c.slice(1..c.len()))));
+        let expected: Vec<u8> = text.chunks(333).flat_map(|c| &c[1..]).copied().collect();
+        assert_eq!(
+            kept.iter()
+                .flat_map(|c| c.iter())
+                .copied()
+                .collect::<Vec<u8>>(),
+            expected
+        );
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_long_path() {
+        assert_eq!(
+            long_path(Path::new(r"C:\logs\a.log")),
+            Path::new(r"C:\logs\a.log")
+        );
+
+        let deep = "d".repeat(100);
+        let path = format!(r"C:\logs\{}\x\..\{}\{}\a.log", deep, deep, deep);
+        let long = format!(r"\\?\C:\logs\{}\{}\{}\a.log", deep, deep, deep);
+        assert_eq!(long_path(Path::new(&path)), Path::new(&long));
+
+        let path = format!(r"\\server\share\{}\{}\{}\a.log", deep, deep, deep);
+        let long = format!(r"\\?\UNC\server\share\{}\{}\{}\a.log", deep, deep, deep);
+        assert_eq!(long_path(Path::new(&path)), Path::new(&long));
+    }
+}
@@ -0,0 +1,178 @@
+use crate::color;
+use crate::timecount;
+use freq::Matcher;
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// Counts matches in messages that keep arriving, such as those of --kafka, and prints the count
+// in each interval as it ends, as "count<TAB>start", as --time-bucket prints its buckets. With
+// --ratio, a second pattern is counted too, and each line is "count<TAB>count2<TAB>ratio<TAB>start",
+// where the ratio is taken over the intervals of the last --window. Each message is matched on its
+// own, so no match spans two.
+pub struct Intervals<M> {
+    matcher: M,
+    every: Duration,
+
+    // When the current interval started, in seconds since the Unix epoch, and when it ends.
+    start: i64,
+    deadline: Instant,
+
+    count: usize,
+
+    // With --ratio, the pattern to divide by and its count in the current interval.
+    other: Option<(M, usize)>,
+
+    // How many intervals the ratio is taken over, and the counts of both patterns in the ones
+    // before the current one, oldest first.
+    window: usize,
+    past: VecDeque<(usize, usize)>,
+}
+
+impl<M: Matcher> Intervals<M> {
+    pub fn new(matcher: M, every: Duration) -> Self {
+        Intervals {
+            matcher,
+            every,
+            start: now(),
+            deadline: Instant::now() + every,
+            count: 0,
+            other: None,
+            window: 1,
+            past: VecDeque::new(),
+        }
+    }
+
+    // Also counts `other`, and prints the ratio of the counts over the last `window`, rounded up
+    // to whole intervals.
+    pub fn ratio(mut self, other: M, window: Duration) -> Self {
+        self.other = Some((other, 0));
+        self.window = window
+            .as_secs()
+            .div_ceil(self.every.as_secs().max(1))
+            .max(1) as usize;
+        self
+    }
+
+    pub fn message(&mut self, message: &[u8]) {
+        self.count += count(&self.matcher, message);
+        if let Some((other, count2)) = &mut self.other {
+            *count2 += count(other, message);
+        }
+    }
+
+    // How long until the current interval ends.
+    pub fn until_due(&self) -> Duration {
+        self.deadline.saturating_duration_since(Instant::now())
+    }
+
+    // Prints the count in the current interval, and starts the next one. Intervals keep to
+    // their schedule however long printing takes.
+    pub fn emit(&mut self, out: &mut impl Write) -> io::Result<()> {
+        write!(out, "{}\t", color::count(self.count))?;
+        if let Some((_, count2)) = &mut self.other {
+            write!(out, "{}\t", color::count(*count2))?;
+            let (a, b) = self
+                .past
+                .iter()
+                .fold((self.count, *count2), |(a, b), (x, y)| (a + x, b + y));
+            match b {
+                0 => write!(out, "-\t")?,
+                b => write!(
+                    out,
+                    "{}\t",
+                    color::total(format!("{:.4}", a as f64 / b as f64))
+                )?,
+            }
+            self.past.push_back((self.count, *count2));
+            if self.past.len() == self.window {
+                self.past.pop_front();
+            }
+            *count2 = 0;
+        }
+        writeln!(out, "{}", timecount::timestamp(self.start))?;
+        out.flush()?;
+        self.count = 0;
+        self.start += self.every.as_secs() as i64;
+        self.deadline += self.every;
+        Ok(())
+    }
+}
+
+fn count(matcher: &impl Matcher, message: &[u8]) -> usize {
+    let mut count = 0;
+    matcher.find_all(message, &mut |_| {
+        count += 1;
+        true
+    });
+    count
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use freq::Literal;
+
+    #[test]
+    fn test_intervals() {
+        let mut intervals = Intervals::new(Literal::new(b"ERROR"), Duration::from_secs(60));
+        intervals.start = 0;
+        intervals.message(b"ERROR ERROR");
+        intervals.message(b"ok ERR");
+        intervals.message(b"OR");
+        assert!(intervals.until_due() <= Duration::from_secs(60));
+        let mut out = Vec::new();
+        intervals.emit(&mut out).unwrap();
+        intervals.message(b"ERROR");
+        intervals.emit(&mut out).unwrap();
+        intervals.emit(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "2\t1970-01-01T00:00:00\n1\t1970-01-01T00:01:00\n0\t1970-01-01T00:02:00\n"
+        );
+    }
+
+    #[test]
+    fn test_ratio() {
+        let mut intervals = Intervals::new(Literal::new(b"ERROR"), Duration::from_secs(60))
+            .ratio(Literal::new(b"GET"), Duration::from_secs(60));
+        intervals.start = 0;
+        intervals.message(b"GET / ERROR");
+        intervals.message(b"GET /a");
+        intervals.message(b"GET /b");
+        intervals.message(b"GET /c");
+        let mut out = Vec::new();
+        intervals.emit(&mut out).unwrap();
+        intervals.message(b"ERROR");
+        intervals.emit(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "1\t4\t0.2500\t1970-01-01T00:00:00\n1\t0\t-\t1970-01-01T00:01:00\n"
+        );
+    }
+
+    #[test]
+    fn test_window() {
+        // Two and a half minutes make three intervals.
+        let mut intervals = Intervals::new(Literal::new(b"E"), Duration::from_secs(60))
+            .ratio(Literal::new(b"R"), Duration::from_secs(150));
+        intervals.start = 0;
+        let mut out = Vec::new();
+        for message in ["ER", "R", "R", "RR", "ERR"] {
+            intervals.message(message.as_bytes());
+            intervals.emit(&mut out).unwrap();
+        }
+        let ratios: Vec<String> = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|line| line.split('\t').nth(2).unwrap().to_string())
+            .collect();
+        assert_eq!(ratios, ["1.0000", "0.5000", "0.3333", "0.0000", "0.2000"]);
+    }
+}
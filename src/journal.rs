@@ -0,0 +1,182 @@
+// Reading the messages of the systemd journal with libsystemd, as --journal does, so that they
+// needn't be piped in from journalctl, which copies them and adds its own formatting.
+use crate::timecount;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Parses --since: how long ago, such as "30m" or "2h", or a date and time in UTC, such as
+// "2024-05-01" or "2024-05-01 12:00:00". Returns seconds since the Unix epoch.
+pub fn parse_since(s: &str) -> Result<i64, String> {
+    if let Some(seconds) = timecount::parse_timestamp(s) {
+        return Ok(seconds);
+    }
+    let ago = timecount::parse_duration(s).map_err(|_| {
+        format!(
+            "expected how long ago (30m, 2h, 1d, ...) or a date and time, such as \"2024-05-01 12:00:00\", not '{}'",
+            s
+        )
+    })?;
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64);
+    Ok(now - ago)
+}
+
+// The unit that journalctl --unit means by `name`: a name without a type is a service.
+#[cfg(any(feature = "journal", test))]
+fn unit_name(name: &str) -> String {
+    if name.contains('.') {
+        name.to_string()
+    } else {
+        format!("{}.service", name)
+    }
+}
+
+#[cfg(feature = "journal")]
+pub use reader::Journal;
+
+#[cfg(feature = "journal")]
+mod reader {
+    use std::ffi::{c_char, c_int, c_void, CStr};
+    use std::io::{self, Read};
+    use std::ptr;
+
+    #[repr(C)]
+    struct SdJournal {
+        _private: [u8; 0],
+    }
+
+    const SD_JOURNAL_LOCAL_ONLY: c_int = 1;
+    const ENOENT: c_int = 2;
+    const MESSAGE: &CStr = c"MESSAGE";
+
+    #[link(name = "systemd")]
+    extern "C" {
+        fn sd_journal_open(ret: *mut *mut SdJournal, flags: c_int) -> c_int;
+        fn sd_journal_close(j: *mut SdJournal);
+        fn sd_journal_add_match(j: *mut SdJournal, data: *const c_void, size: usize) -> c_int;
+        fn sd_journal_seek_realtime_usec(j: *mut SdJournal, usec: u64) -> c_int;
+        fn sd_journal_next(j: *mut SdJournal) -> c_int;
+        fn sd_journal_get_realtime_usec(j: *mut SdJournal, ret: *mut u64) -> c_int;
+        fn sd_journal_get_data(
+            j: *mut SdJournal,
+            field: *const c_char,
+            data: *mut *const c_void,
+            length: *mut usize,
+        ) -> c_int;
+    }
+
+    // The messages of the journal on this machine, in order, each on a line of its own, as
+    // `journalctl -o cat` prints them.
+    pub struct Journal {
+        j: *mut SdJournal,
+        // Entries before this time, in microseconds since the Unix epoch, are skipped.
+        since: u64,
+        // Messages read from the journal and not yet from this, from `pos` on.
+        pending: Vec<u8>,
+        pos: usize,
+    }
+
+    // A journal is only read from one thread at a time, which libsystemd allows.
+    unsafe impl Send for Journal {}
+
+    // libsystemd returns a negated errno on failure.
+    fn check(r: c_int) -> io::Result<c_int> {
+        if r < 0 {
+            Err(io::Error::from_raw_os_error(-r))
+        } else {
+            Ok(r)
+        }
+    }
+
+    impl Journal {
+        // Opens the journal, for the messages of `units`, or of all units if there are none,
+        // logged since `since` seconds after the Unix epoch.
+        pub fn open(units: &[String], since: Option<i64>) -> io::Result<Self> {
+            let mut j = ptr::null_mut();
+            check(unsafe { sd_journal_open(&mut j, SD_JOURNAL_LOCAL_ONLY) })?;
+            // Closes it if anything below fails.
+            let mut journal = Journal {
+                j,
+                since: 0,
+                pending: Vec::new(),
+                pos: 0,
+            };
+            // Matches of the same field are alternatives.
+            for unit in units {
+                let field = format!("_SYSTEMD_UNIT={}", super::unit_name(unit));
+                check(unsafe { sd_journal_add_match(j, field.as_ptr().cast(), field.len()) })?;
+            }
+            if let Some(since) = since {
+                journal.since = since.max(0) as u64 * 1_000_000;
+                check(unsafe { sd_journal_seek_realtime_usec(j, journal.since) })?;
+            }
+            Ok(journal)
+        }
+
+        // Adds the message of the next entry to `pending`, returning false at the end.
+        fn next(&mut self) -> io::Result<bool> {
+            loop {
+                if check(unsafe { sd_journal_next(self.j) })? == 0 {
+                    return Ok(false);
+                }
+                let mut usec = 0;
+                check(unsafe { sd_journal_get_realtime_usec(self.j, &mut usec) })?;
+                if usec < self.since {
+                    continue;
+                }
+                let mut data = ptr::null();
+                let mut len = 0;
+                let r =
+                    unsafe { sd_journal_get_data(self.j, MESSAGE.as_ptr(), &mut data, &mut len) };
+                // Some entries, such as those of core dumps, have no message.
+                if r == -ENOENT {
+                    continue;
+                }
+                check(r)?;
+                let field = unsafe { std::slice::from_raw_parts(data.cast::<u8>(), len) };
+                let message = field.strip_prefix(b"MESSAGE=").unwrap_or(field);
+                self.pending.extend_from_slice(message);
+                self.pending.push(b'\n');
+                return Ok(true);
+            }
+        }
+    }
+
+    impl Read for Journal {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.pending.drain(..self.pos);
+            self.pos = 0;
+            // Fills `buf` with as many messages as fit, rather than one each call.
+            while self.pending.len() - self.pos < buf.len() && self.next()? {}
+            let n = buf.len().min(self.pending.len() - self.pos);
+            buf[..n].copy_from_slice(&self.pending[self.pos..self.pos + n]);
+            self.pos += n;
+            Ok(n)
+        }
+    }
+
+    impl Drop for Journal {
+        fn drop(&mut self) {
+            unsafe { sd_journal_close(self.j) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_since() {
+        assert_eq!(parse_since("2024-05-01"), Ok(1714521600));
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
+        let ago = now - parse_since("2h").unwrap();
+        assert!((7200..7210).contains(&ago));
+        assert!(parse_since("yesterday").is_err());
+        assert_eq!(unit_name("nginx"), "nginx.service");
+        assert_eq!(unit_name("backup.timer"), "backup.timer");
+    }
+}
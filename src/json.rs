@@ -0,0 +1,409 @@
+use crate::input::{for_each_chunk, open_inputs};
+use crate::linelimit::{self, Limit};
+use crate::table::{FreqTable, TableArgs};
+use clap::builder::NonEmptyStringValueParser;
+use clap::Args;
+use memchr::memchr_iter;
+use memchr::memmem::Finder;
+use serde_json::Value;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+#[derive(Args)]
+pub struct JsonArgs {
+    #[arg(
+        help = "The newline-delimited JSON files to read, with - for stdin. If not provided, stdin is used."
+    )]
+    input: Vec<PathBuf>,
+
+    #[arg(
+        long,
+        value_parser = parse_path,
+        help = "The value to count in each record, as a jq-style path (.a.b[0], .[\"key\"]) or a JSON pointer (/a/b/0). Records without it are skipped."
+    )]
+    path: Path,
+
+    #[arg(
+        short,
+        long,
+        value_parser = NonEmptyStringValueParser::new(),
+        help = "Count the records whose value contains this pattern, rather than how often each value occurs."
+    )]
+    pattern: Option<String>,
+
+    #[command(flatten)]
+    table: TableArgs,
+}
+
+pub fn run(args: JsonArgs, buffer_size: usize, out: &mut impl Write) {
+    let mut reader = JsonReader::new(args.path);
+    let finder = args.pattern.as_deref().map(Finder::new);
+    let mut table = FreqTable::new(&args.table);
+    let mut matching = 0;
+
+    let mut add = |value: &[u8]| match &finder {
+        Some(finder) => matching += finder.find(value).is_some() as usize,
+        None => table.add(value),
+    };
+    for f in open_inputs(&args.input) {
+        for_each_chunk(f, buffer_size, |chunk| reader.write(chunk, &mut add));
+        reader.finish(&mut add);
+    }
+    MALFORMED.fetch_add(reader.malformed, Ordering::Relaxed);
+
+    match finder {
+        Some(_) => writeln!(out, "{}", matching),
+        None => table.print(&args.table, out),
+    }
+    .expect("failed to write output");
+}
+
+// The number of records that weren't valid JSON, to be reported once the output is written.
+static MALFORMED: AtomicUsize = AtomicUsize::new(0);
+
+pub fn malformed() -> usize {
+    MALFORMED.load(Ordering::Relaxed)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Step {
+    // An object member. In a JSON pointer, this may also be an array index.
+    Key(Vec<u8>),
+    Index(usize),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Path(Vec<Step>);
+
+fn parse_path(s: &str) -> Result<Path, String> {
+    let s = s.as_bytes();
+    let mut steps = Vec::new();
+    match s.first() {
+        // A JSON pointer, where "~1" stands for '/' and "~0" for '~'.
+        Some(b'/') => {
+            for token in s[1..].split(|&b| b == b'/') {
+                let mut key = Vec::with_capacity(token.len());
+                let mut bytes = token.iter();
+                while let Some(&b) = bytes.next() {
+                    if b != b'~' {
+                        key.push(b);
+                        continue;
+                    }
+                    match bytes.next() {
+                        Some(b'0') => key.push(b'~'),
+                        Some(b'1') => key.push(b'/'),
+                        _ => return Err("'~' must be followed by 0 or 1".to_string()),
+                    }
+                }
+                steps.push(Step::Key(key));
+            }
+        }
+        Some(b'.') => {
+            let mut i = 0;
+            while i < s.len() {
+                match s[i] {
+                    b'.' if i + 1 == s.len() || matches!(s[i + 1], b'.' | b'[') => {
+                        if i != 0 {
+                            return Err("expected a key after '.'".to_string());
+                        }
+                        i += 1;
+                    }
+                    b'.' if s[i + 1] == b'"' => {
+                        let (key, len) = parse_quoted(&s[i + 1..])?;
+                        steps.push(Step::Key(key));
+                        i += 1 + len;
+                    }
+                    b'.' => {
+                        let len = s[i + 1..]
+                            .iter()
+                            .position(|&b| b == b'.' || b == b'[')
+                            .unwrap_or(s.len() - i - 1);
+                        steps.push(Step::Key(s[i + 1..i + 1 + len].to_vec()));
+                        i += 1 + len;
+                    }
+                    b'[' => {
+                        let end = match s[i + 1..].first() {
+                            Some(b'"') => {
+                                let (key, len) = parse_quoted(&s[i + 1..])?;
+                                steps.push(Step::Key(key));
+                                i + 1 + len
+                            }
+                            _ => {
+                                let len = s[i + 1..]
+                                    .iter()
+                                    .position(|&b| b == b']')
+                                    .ok_or("missing ']'")?;
+                                let index = std::str::from_utf8(&s[i + 1..i + 1 + len])
+                                    .ok()
+                                    .and_then(|n| n.parse().ok())
+                                    .ok_or("array indices must be non-negative integers")?;
+                                steps.push(Step::Index(index));
+                                i + 1 + len
+                            }
+                        };
+                        if s.get(end) != Some(&b']') {
+                            return Err("missing ']'".to_string());
+                        }
+                        i = end + 1;
+                    }
+                    _ => return Err("expected '.' or '['".to_string()),
+                }
+            }
+        }
+        _ => return Err("paths start with '.' (jq style) or '/' (JSON pointer)".to_string()),
+    }
+    Ok(Path(steps))
+}
+
+// Parses the JSON string at the start of `s`, returning its contents and length.
+fn parse_quoted(s: &[u8]) -> Result<(Vec<u8>, usize), String> {
+    let mut strings = serde_json::Deserializer::from_slice(s).into_iter::<String>();
+    match strings.next() {
+        Some(Ok(key)) => Ok((key.into_bytes(), strings.byte_offset())),
+        _ => Err("unterminated or invalid quoted key".to_string()),
+    }
+}
+
+// Reads the value at a path from each line of newline-delimited JSON, chunk by chunk.
+pub struct JsonReader {
+    path: Path,
+
+    // The start of a line that continues into the next chunk.
+    carry: Vec<u8>,
+
+    limit: Limit,
+
+    // The number of lines that weren't valid JSON.
+    malformed: usize,
+}
+
+impl JsonReader {
+    pub fn new(path: Path) -> Self {
+        JsonReader {
+            path,
+            carry: Vec::new(),
+            limit: linelimit::get(),
+            malformed: 0,
+        }
+    }
+
+    // Calls `f` on the key of the value in each line that ends in `buf`.
+    pub fn write(&mut self, buf: &[u8], f: &mut impl FnMut(&[u8])) {
+        let mut start = 0;
+        for end in memchr_iter(b'\n', buf) {
+            if self.carry.is_empty() {
+                self.read_line(&buf[start..end], f);
+            } else {
+                self.limit.extend(&mut self.carry, &buf[start..end], 0);
+                let line = std::mem::take(&mut self.carry);
+                self.read_line(&line, f);
+                self.carry = line;
+                self.carry.clear();
+            }
+            start = end + 1;
+        }
+        self.limit.extend(&mut self.carry, &buf[start..], 0);
+    }
+
+    // Reads the last line of the current input, if it has no trailing newline.
+    pub fn finish(&mut self, f: &mut impl FnMut(&[u8])) {
+        if !self.carry.is_empty() {
+            let line = std::mem::take(&mut self.carry);
+            self.read_line(&line, f);
+        }
+    }
+
+    fn read_line(&mut self, line: &[u8], f: &mut impl FnMut(&[u8])) {
+        let Some(line) = self.limit.apply(line) else {
+            return;
+        };
+        // Blank lines aren't records.
+        if line.iter().all(u8::is_ascii_whitespace) {
+            return;
+        }
+        match serde_json::from_slice::<Value>(line) {
+            Ok(doc) => {
+                if let Some(value) = lookup(&doc, &self.path) {
+                    f(&key(value));
+                }
+            }
+            Err(_) => self.malformed += 1,
+        }
+    }
+}
+
+// Returns the value at `path` in `doc`.
+fn lookup<'a>(doc: &'a Value, path: &Path) -> Option<&'a Value> {
+    path.0
+        .iter()
+        .try_fold(doc, |value, step| match (step, value) {
+            (Step::Key(key), Value::Object(object)) => object.get(std::str::from_utf8(key).ok()?),
+            (Step::Key(key), Value::Array(array)) => {
+                array.get(std::str::from_utf8(key).ok()?.parse::<usize>().ok()?)
+            }
+            (Step::Index(index), Value::Array(array)) => array.get(*index),
+            _ => None,
+        })
+}
+
+// Returns the key a value is counted under: a string's contents, a number in its shortest form,
+// so that 1 and 1.0 are the same key, or the compact JSON text of anything else.
+fn key(value: &Value) -> Vec<u8> {
+    match value {
+        Value::String(s) => s.as_bytes().to_vec(),
+        Value::Number(n) => match n.as_f64() {
+            // Integers beyond 2^53 can't be told apart as floats, so keep them as written.
+            Some(f) if n.is_f64() && f.fract() == 0.0 && f.abs() < (1u64 << 53) as f64 => {
+                (f as i64).to_string().into_bytes()
+            }
+            _ => n.to_string().into_bytes(),
+        },
+        _ => value.to_string().into_bytes(),
+    }
+}
+
+// Returns the members of the object at `path` in the JSON document `doc`, in document order.
+pub fn members(doc: &[u8], path: &str) -> Option<Vec<(Vec<u8>, Value)>> {
+    let doc = serde_json::from_slice::<Value>(doc).ok()?;
+    match lookup(&doc, &parse_path(path).ok()?)? {
+        Value::Object(object) => Some(
+            object
+                .iter()
+                .map(|(k, v)| (k.as_bytes().to_vec(), v.clone()))
+                .collect(),
+        ),
+        _ => None,
+    }
+}
+
+// Returns the string at `path` in the JSON document `doc`.
+pub fn string(doc: &[u8], path: &str) -> Option<Vec<u8>> {
+    let doc = serde_json::from_slice::<Value>(doc).ok()?;
+    Some(
+        lookup(&doc, &parse_path(path).ok()?)?
+            .as_str()?
+            .as_bytes()
+            .to_vec(),
+    )
+}
+
+// Writes `s` as a JSON string. Invalid UTF-8 is replaced with U+FFFD.
+pub fn write_string(out: &mut impl Write, s: &[u8]) -> io::Result<()> {
+    out.write_all(b"\"")?;
+    for c in String::from_utf8_lossy(s).chars() {
+        match c {
+            '"' => out.write_all(b"\\\"")?,
+            '\\' => out.write_all(b"\\\\")?,
+            '\n' => out.write_all(b"\\n")?,
+            '\r' => out.write_all(b"\\r")?,
+            '\t' => out.write_all(b"\\t")?,
+            c if c.is_control() => write!(out, "\\u{:04x}", c as u32)?,
+            c => write!(out, "{}", c)?,
+        }
+    }
+    out.write_all(b"\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use proptest::{prop_assert_eq, proptest};
+    use serde_json::json;
+
+    fn get(doc: &str, path: &str) -> Option<String> {
+        let doc = serde_json::from_str(doc).unwrap();
+        let path = parse_path(path).unwrap();
+        lookup(&doc, &path).map(|v| String::from_utf8(key(v)).unwrap())
+    }
+
+    #[test]
+    fn test_parse_path() {
+        let key = |k: &str| Step::Key(k.as_bytes().to_vec());
+        assert_eq!(parse_path("."), Ok(Path(vec![])));
+        assert_eq!(
+            parse_path(".a.b[2][\"c.d\"].\"e\""),
+            Ok(Path(vec![
+                key("a"),
+                key("b"),
+                Step::Index(2),
+                key("c.d"),
+                key("e")
+            ]))
+        );
+        assert_eq!(parse_path(".[\"\\u00e9\\\"\"]"), Ok(Path(vec![key("é\"")])));
+        assert_eq!(parse_path(".[0]"), Ok(Path(vec![Step::Index(0)])));
+        assert_eq!(
+            parse_path("/a/~1b~0/0"),
+            Ok(Path(vec![key("a"), key("/b~"), key("0")]))
+        );
+        assert!(parse_path("a").is_err());
+        assert!(parse_path(".a..b").is_err());
+        assert!(parse_path(".a[x]").is_err());
+        assert!(parse_path(".a[0").is_err());
+        assert!(parse_path(".[\"a]").is_err());
+    }
+
+    #[test]
+    fn test_lookup() {
+        let doc =
+            r#" {"a": {"b": [1, {"c": "x\"y"}, [true, {"d": 2}]]}, "key": null, "n": -1.5e3} "#;
+        assert_eq!(get(doc, ".a.b[0]").as_deref(), Some("1"));
+        assert_eq!(get(doc, ".a.b[1].c").as_deref(), Some("x\"y"));
+        assert_eq!(get(doc, "/a/b/2").as_deref(), Some(r#"[true,{"d":2}]"#));
+        assert_eq!(get(doc, ".key").as_deref(), Some("null"));
+        assert_eq!(get(doc, ".n").as_deref(), Some("-1500"));
+        assert_eq!(get(doc, ".a.b[3]"), None);
+        assert_eq!(get(doc, ".a.c"), None);
+        assert_eq!(get(doc, ".n.x"), None);
+    }
+
+    #[test]
+    fn test_number_keys() {
+        let key = |n: &str| String::from_utf8(key(&serde_json::from_str(n).unwrap())).unwrap();
+        assert_eq!(key("1"), key("1.0"));
+        assert_eq!(key("1"), key("1e0"));
+        assert_eq!(key("-0.0"), "0");
+        assert_eq!(key("0.5"), "0.5");
+        assert_eq!(key("18446744073709551615"), "18446744073709551615");
+        assert_ne!(key("9007199254740993"), key("9007199254740992.0"));
+    }
+
+    #[test]
+    fn test_members() {
+        let doc = br#"{"a": 1, "files": {"x.log": 3, "y\"z": [1, 2], "": {}}}"#;
+        assert_eq!(
+            members(doc, ".files"),
+            Some(vec![
+                (b"x.log".to_vec(), json!(3)),
+                (b"y\"z".to_vec(), json!([1, 2])),
+                (b"".to_vec(), json!({})),
+            ])
+        );
+        assert_eq!(members(doc, ".a"), None);
+        assert_eq!(members(doc, ".b"), None);
+        assert_eq!(members(br#"{"files": {"#, ".files"), None);
+
+        let mut out = Vec::new();
+        write_string(&mut out, b"a\"\\\n\x01\xff").unwrap();
+        assert_eq!(
+            serde_json::from_slice::<String>(&out).unwrap(),
+            "a\"\\\n\x01\u{fffd}"
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn test_chunked(chunk_size in 1..20_usize) {
+            let text = b"{\"level\": \"info\"}\n{\"level\":\"warn\"}\nnot json\n\n{}\n{\"level\": 3.0}";
+            let mut reader = JsonReader::new(parse_path(".level").unwrap());
+            let mut values = Vec::new();
+            let mut add = |v: &[u8]| values.push(String::from_utf8(v.to_vec()).unwrap());
+            text.chunks(chunk_size).for_each(|chunk| reader.write(chunk, &mut add));
+            reader.finish(&mut add);
+            prop_assert_eq!(values, ["info", "warn", "3"]);
+            prop_assert_eq!(reader.malformed, 1);
+        }
+    }
+}
@@ -0,0 +1,198 @@
+// Consuming Kafka topics with librdkafka, as --kafka does, so that freq can count matches in a
+// stream of messages as a small metrics job of its own.
+use crate::interval::Intervals;
+use freq::Matcher;
+use std::ffi::{c_char, c_int, c_void, CStr, CString};
+use std::io::Write;
+use std::ptr;
+use tracing::info;
+
+#[repr(C)]
+struct RdKafka {
+    _private: [u8; 0],
+}
+
+#[repr(C)]
+struct RdKafkaConf {
+    _private: [u8; 0],
+}
+
+#[repr(C)]
+struct RdKafkaTopicPartitionList {
+    _private: [u8; 0],
+}
+
+#[repr(C)]
+struct RdKafkaMessage {
+    err: c_int,
+    rkt: *mut c_void,
+    partition: i32,
+    payload: *const c_void,
+    len: usize,
+    key: *const c_void,
+    key_len: usize,
+    offset: i64,
+    _private: *mut c_void,
+}
+
+const RD_KAFKA_CONSUMER: c_int = 1;
+const RD_KAFKA_CONF_OK: c_int = 0;
+const RD_KAFKA_PARTITION_UA: i32 = -1;
+const RD_KAFKA_RESP_ERR_NO_ERROR: c_int = 0;
+// There was nothing to commit, because nothing was consumed since the last commit.
+const RD_KAFKA_RESP_ERR__NO_OFFSET: c_int = -168;
+
+#[link(name = "rdkafka")]
+extern "C" {
+    fn rd_kafka_conf_new() -> *mut RdKafkaConf;
+    fn rd_kafka_conf_destroy(conf: *mut RdKafkaConf);
+    fn rd_kafka_conf_set(
+        conf: *mut RdKafkaConf,
+        name: *const c_char,
+        value: *const c_char,
+        errstr: *mut c_char,
+        errstr_size: usize,
+    ) -> c_int;
+    fn rd_kafka_new(
+        kind: c_int,
+        conf: *mut RdKafkaConf,
+        errstr: *mut c_char,
+        errstr_size: usize,
+    ) -> *mut RdKafka;
+    fn rd_kafka_destroy(rk: *mut RdKafka);
+    fn rd_kafka_poll_set_consumer(rk: *mut RdKafka) -> c_int;
+    fn rd_kafka_topic_partition_list_new(size: c_int) -> *mut RdKafkaTopicPartitionList;
+    fn rd_kafka_topic_partition_list_add(
+        list: *mut RdKafkaTopicPartitionList,
+        topic: *const c_char,
+        partition: i32,
+    ) -> *mut c_void;
+    fn rd_kafka_topic_partition_list_destroy(list: *mut RdKafkaTopicPartitionList);
+    fn rd_kafka_subscribe(rk: *mut RdKafka, topics: *const RdKafkaTopicPartitionList) -> c_int;
+    fn rd_kafka_consumer_poll(rk: *mut RdKafka, timeout_ms: c_int) -> *mut RdKafkaMessage;
+    fn rd_kafka_message_destroy(message: *mut RdKafkaMessage);
+    fn rd_kafka_commit(
+        rk: *mut RdKafka,
+        offsets: *const RdKafkaTopicPartitionList,
+        is_async: c_int,
+    ) -> c_int;
+    fn rd_kafka_consumer_close(rk: *mut RdKafka) -> c_int;
+    fn rd_kafka_err2str(err: c_int) -> *const c_char;
+}
+
+fn err2str(err: c_int) -> String {
+    unsafe { CStr::from_ptr(rd_kafka_err2str(err)) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+fn c_string(s: &str) -> Result<CString, String> {
+    CString::new(s).map_err(|_| format!("'{}' has a NUL byte in it", s.escape_debug()))
+}
+
+// A consumer in a group, subscribed to topics. Offsets are only committed once the counts of the
+// messages before them have been printed, so that none are lost if freq is stopped.
+pub struct Consumer {
+    rk: *mut RdKafka,
+}
+
+impl Consumer {
+    pub fn new(brokers: &str, topics: &[String], group: &str) -> Result<Self, String> {
+        let mut errstr = [0 as c_char; 512];
+        let conf = unsafe { rd_kafka_conf_new() };
+        let settings = [
+            ("bootstrap.servers", brokers),
+            ("group.id", group),
+            ("enable.auto.commit", "false"),
+        ];
+        for (name, value) in settings {
+            let (name, value) = (c_string(name)?, c_string(value)?);
+            let r = unsafe {
+                rd_kafka_conf_set(
+                    conf,
+                    name.as_ptr(),
+                    value.as_ptr(),
+                    errstr.as_mut_ptr(),
+                    errstr.len(),
+                )
+            };
+            if r != RD_KAFKA_CONF_OK {
+                unsafe { rd_kafka_conf_destroy(conf) };
+                return Err(unsafe { CStr::from_ptr(errstr.as_ptr()) }
+                    .to_string_lossy()
+                    .into_owned());
+            }
+        }
+        // The consumer owns the configuration once it is made.
+        let rk =
+            unsafe { rd_kafka_new(RD_KAFKA_CONSUMER, conf, errstr.as_mut_ptr(), errstr.len()) };
+        if rk.is_null() {
+            unsafe { rd_kafka_conf_destroy(conf) };
+            return Err(unsafe { CStr::from_ptr(errstr.as_ptr()) }
+                .to_string_lossy()
+                .into_owned());
+        }
+        let consumer = Consumer { rk };
+        unsafe { rd_kafka_poll_set_consumer(rk) };
+
+        let names = topics
+            .iter()
+            .map(|topic| c_string(topic))
+            .collect::<Result<Vec<_>, _>>()?;
+        let list = unsafe { rd_kafka_topic_partition_list_new(names.len() as c_int) };
+        for name in &names {
+            unsafe {
+                rd_kafka_topic_partition_list_add(list, name.as_ptr(), RD_KAFKA_PARTITION_UA)
+            };
+        }
+        let err = unsafe { rd_kafka_subscribe(rk, list) };
+        unsafe { rd_kafka_topic_partition_list_destroy(list) };
+        if err != RD_KAFKA_RESP_ERR_NO_ERROR {
+            return Err(err2str(err));
+        }
+        info!(
+            "consuming {} as {} from {}",
+            topics.join(", "),
+            group,
+            brokers
+        );
+        Ok(consumer)
+    }
+
+    // Counts matches in the payload of each message, printing the count of each interval and then
+    // committing the offsets of the messages in it, until freq is stopped.
+    pub fn run<M: Matcher>(&self, mut intervals: Intervals<M>, out: &mut impl Write) -> ! {
+        loop {
+            let timeout = intervals.until_due().as_millis().min(1000) as c_int;
+            let message = unsafe { rd_kafka_consumer_poll(self.rk, timeout) };
+            if let Some(m) = unsafe { message.as_ref() } {
+                if m.err != RD_KAFKA_RESP_ERR_NO_ERROR {
+                    // The consumer retries by itself.
+                    eprintln!("freq: kafka: {}", err2str(m.err));
+                } else if !m.payload.is_null() {
+                    let payload = unsafe { std::slice::from_raw_parts(m.payload.cast(), m.len) };
+                    intervals.message(payload);
+                }
+                unsafe { rd_kafka_message_destroy(message) };
+            }
+            if intervals.until_due().is_zero() {
+                intervals.emit(out).expect("failed to write output");
+                let err = unsafe { rd_kafka_commit(self.rk, ptr::null(), 0) };
+                // Such as during a rebalance. The next commit includes these offsets, and until
+                // then, they would only be counted again.
+                if err != RD_KAFKA_RESP_ERR_NO_ERROR && err != RD_KAFKA_RESP_ERR__NO_OFFSET {
+                    eprintln!("freq: kafka: failed to commit offsets: {}", err2str(err));
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Consumer {
+    fn drop(&mut self) {
+        unsafe {
+            rd_kafka_consumer_close(self.rk);
+            rd_kafka_destroy(self.rk);
+        }
+    }
+}
@@ -0,0 +1,503 @@
+use crate::input::{self, for_each_chunk, open_inputs};
+use crate::memory;
+use crate::seq::{Format, SeqParser, SeqSink};
+use crate::sketch::{HeavyHitters, HyperLogLog};
+use crate::table::{print_entries, report_error_bound, TableArgs, TableKey};
+use clap::Args;
+use crossbeam_channel::Sender;
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::hash::{BuildHasherDefault, Hasher};
+use std::io::Write;
+use std::path::PathBuf;
+use std::thread;
+use tracing::{debug, info};
+
+#[derive(Args)]
+pub struct KmersArgs {
+    #[arg(
+        help = "The FASTA, FASTQ or plain sequence files to read, with - for stdin. If not provided, stdin is used."
+    )]
+    input: Vec<PathBuf>,
+
+    #[arg(
+        short,
+        default_value = "21",
+        value_parser = clap::value_parser!(u32).range(1..=32),
+        help = "The length of the k-mers to count."
+    )]
+    k: u32,
+
+    #[arg(
+        long,
+        value_enum,
+        help = "The input format. If not provided, it is detected from the first byte."
+    )]
+    seq_format: Option<Format>,
+
+    #[arg(
+        short,
+        long,
+        help = "The number of threads counting k-mers. Defaults to --jobs, or the number of CPUs."
+    )]
+    pub threads: Option<usize>,
+
+    #[command(flatten)]
+    table: TableArgs,
+}
+
+pub fn run(args: KmersArgs, buffer_size: usize, out: &mut impl Write) {
+    let threads = args
+        .threads
+        .or_else(|| thread::available_parallelism().ok().map(|n| n.get()))
+        .unwrap_or(1)
+        .max(1);
+    // Each thread has its queue of batches, and a batch being counted and one being filled.
+    let queue_bytes = (QUEUE_LEN + 2) * BATCH_SIZE * std::mem::size_of::<u64>();
+    let threads = match memory::io_limit() {
+        Some(limit) if threads * queue_bytes > limit => {
+            let fewer = (limit / queue_bytes).max(1);
+            info!(
+                "counting on {} threads instead of {}, to stay within --max-memory",
+                fewer, threads
+            );
+            fewer
+        }
+        _ => threads,
+    };
+    // Exact tables are turned into sketches if they outgrow their share of --max-memory.
+    let shard_memory = memory::table_limit().map(|limit| limit / threads);
+    let approx_top = args.table.approx_top();
+    let distinct_only = args.table.distinct;
+    let k = args.k as usize;
+
+    // K-mers are sharded between the counting threads by hash, so each has its own table.
+    let (senders, workers): (Vec<_>, Vec<_>) = (0..threads)
+        .map(|_| {
+            let (s, r) = crossbeam_channel::bounded::<Vec<u64>>(QUEUE_LEN);
+            let sketch = args.table.sketch(threads);
+            let distinct = args.table.distinct_sketch();
+            let worker = thread::spawn(move || {
+                let counted = Cell::new(0);
+                let mut batches = r
+                    .iter()
+                    .inspect(|batch| counted.set(counted.get() + batch.len()));
+                let shard = match (sketch, distinct) {
+                    (_, Some(mut distinct)) => {
+                        for batch in batches {
+                            for kmer in batch {
+                                distinct.add(&kmer);
+                            }
+                        }
+                        Shard::Distinct(distinct)
+                    }
+                    (Some(mut sketch), None) => {
+                        for batch in batches {
+                            for kmer in batch {
+                                sketch.add_count(&kmer, 1);
+                            }
+                        }
+                        Shard::Approx(sketch)
+                    }
+                    (None, None) => 'exact: {
+                        let max_entries = shard_memory.map(|memory| memory / KMER_ENTRY_BYTES);
+                        let mut counts = KmerMap::default();
+                        for batch in batches.by_ref() {
+                            for kmer in batch {
+                                *counts.entry(kmer).or_default() += 1;
+                            }
+                            if max_entries.is_some_and(|max| counts.len() > max) {
+                                let memory = shard_memory.unwrap_or_default();
+                                break 'exact approximate(
+                                    counts,
+                                    batches,
+                                    memory,
+                                    approx_top,
+                                    distinct_only,
+                                );
+                            }
+                        }
+                        Shard::Exact(counts)
+                    }
+                };
+                // Turning the shard into entries is done here, alongside the other threads.
+                (Partial::from(shard), counted.get())
+            });
+            (s, worker)
+        })
+        .unzip();
+
+    if threads > 1 {
+        input::set_parallel_counting();
+    }
+    let mut kmers = KmerSink::new(k, senders);
+    let mut parser = SeqParser::new(args.seq_format);
+    for f in open_inputs(&args.input) {
+        for_each_chunk(f, buffer_size, |chunk| parser.write(chunk, &mut kmers));
+        parser.finish(&mut kmers);
+    }
+    drop(kmers);
+
+    let (partials, counted): (Vec<_>, Vec<_>) = workers
+        .into_iter()
+        .map(|worker| worker.join().expect("counting thread panicked"))
+        .unzip();
+    // Uneven shares mean some threads sat idle while others were busy.
+    let all: usize = counted.iter().sum();
+    for (i, n) in counted.iter().enumerate() {
+        debug!(
+            "counting thread {} took {} of {} k-mers ({:.1}%)",
+            i,
+            n,
+            all,
+            100.0 * *n as f64 / all.max(1) as f64
+        );
+    }
+    let Partial {
+        entries,
+        error_bound,
+        total,
+        exact_total,
+        distinct,
+    } = tree_reduce(partials, Partial::merge).expect("no counting threads");
+    if let Some(bound) = error_bound {
+        report_error_bound(bound);
+    }
+
+    // Only some shards may have outgrown --max-memory.
+    let total = total.map(|total| total + exact_total);
+    if let Some(mut distinct) = distinct {
+        for (kmer, _) in &entries {
+            distinct.add(kmer);
+        }
+        writeln!(out, "{}", distinct.estimate()).expect("failed to write output");
+        return;
+    }
+    print_entries(entries, total, &args.table, out, |out, &kmer| {
+        out.write_all(&decode(kmer, k))
+    })
+    .expect("failed to write output");
+}
+
+const BATCH_SIZE: usize = 4096;
+
+// How many batches can wait for each counting thread.
+const QUEUE_LEN: usize = 4;
+
+// Roughly what each entry of an exact table takes, with the hash table's spare room.
+const KMER_ENTRY_BYTES: usize = 24;
+
+// Turns a shard's exact counts, which outgrew `memory`, into a sketch as with --approx, or with
+// --distinct into an estimate, and counts the rest of its k-mers in that.
+fn approximate(
+    counts: KmerMap,
+    r: impl Iterator<Item = Vec<u64>>,
+    memory: usize,
+    top: usize,
+    distinct_only: bool,
+) -> Shard {
+    if distinct_only {
+        memory::warn_table_outgrown("the number of distinct k-mers is estimated");
+        let mut distinct = HyperLogLog::new();
+        for kmer in counts.keys() {
+            distinct.add(kmer);
+        }
+        drop(counts);
+        for batch in r {
+            for kmer in batch {
+                distinct.add(&kmer);
+            }
+        }
+        return Shard::Distinct(distinct);
+    }
+    memory::warn_table_outgrown("only the most frequent k-mers are kept, with estimated counts");
+    // The sketch takes half, leaving room for the k-mers it keeps.
+    let mut sketch = HeavyHitters::new(memory / 2, top);
+    for (kmer, count) in counts {
+        sketch.add_count(&kmer, count);
+    }
+    for batch in r {
+        for kmer in batch {
+            sketch.add_count(&kmer, 1);
+        }
+    }
+    Shard::Approx(sketch)
+}
+
+type KmerMap = HashMap<u64, usize, BuildHasherDefault<KmerHasher>>;
+
+// Encoded k-mers. They are made of bases, so never start with a number.
+impl TableKey for u64 {
+    fn leading_number(&self) -> Option<f64> {
+        None
+    }
+}
+
+// The k-mers counted by one thread.
+enum Shard {
+    Exact(KmerMap),
+
+    // With --approx, only the most frequent k-mers of the shard.
+    Approx(HeavyHitters<u64>),
+
+    // With --distinct, only an estimate of how many k-mers the shard has.
+    Distinct(HyperLogLog),
+}
+
+// What one or more counting threads found, to be merged with the rest. The shards are
+// disjoint, so their entries can just be concatenated. Encoded k-mers sort in the same order as
+// their decoded strings.
+#[derive(Default)]
+struct Partial {
+    entries: Vec<(u64, usize)>,
+
+    // The error bound and total of the shards that were approximated, if any were.
+    error_bound: Option<usize>,
+    total: Option<usize>,
+
+    // The total of the shards that were counted exactly.
+    exact_total: usize,
+
+    distinct: Option<HyperLogLog>,
+}
+
+impl From<Shard> for Partial {
+    fn from(shard: Shard) -> Self {
+        match shard {
+            Shard::Exact(counts) => Partial {
+                exact_total: counts.values().sum(),
+                entries: counts.into_iter().collect(),
+                ..Partial::default()
+            },
+            Shard::Approx(sketch) => Partial {
+                error_bound: Some(sketch.error_bound()),
+                total: Some(sketch.total()),
+                entries: sketch.into_entries().collect(),
+                ..Partial::default()
+            },
+            Shard::Distinct(distinct) => Partial {
+                distinct: Some(distinct),
+                ..Partial::default()
+            },
+        }
+    }
+}
+
+impl Partial {
+    fn merge(mut self, mut other: Partial) -> Partial {
+        // Copies the smaller of the two.
+        if self.entries.len() < other.entries.len() {
+            std::mem::swap(&mut self.entries, &mut other.entries);
+        }
+        self.entries.append(&mut other.entries);
+        self.error_bound = self.error_bound.max(other.error_bound);
+        self.total = match (self.total, other.total) {
+            (Some(a), Some(b)) => Some(a + b),
+            (a, b) => a.or(b),
+        };
+        self.exact_total += other.exact_total;
+        self.distinct = match (self.distinct, other.distinct) {
+            (Some(mut a), Some(b)) => {
+                a.merge(&b);
+                Some(a)
+            }
+            (a, b) => a.or(b),
+        };
+        self
+    }
+}
+
+// Merges `parts` in pairs, each pair on a thread of its own, and then those in pairs, and so on,
+// instead of one at a time on this thread.
+fn tree_reduce<T: Send>(mut parts: Vec<T>, merge: fn(T, T) -> T) -> Option<T> {
+    while parts.len() > 1 {
+        let odd = (parts.len() % 2 == 1).then(|| parts.pop()).flatten();
+        let mut pairs = Vec::with_capacity(parts.len() / 2);
+        let mut parts_iter = parts.into_iter();
+        while let (Some(a), Some(b)) = (parts_iter.next(), parts_iter.next()) {
+            pairs.push((a, b));
+        }
+        parts = thread::scope(|scope| {
+            let merging: Vec<_> = pairs
+                .into_iter()
+                .map(|(a, b)| scope.spawn(move || merge(a, b)))
+                .collect();
+            merging
+                .into_iter()
+                .map(|m| m.join().expect("merging thread panicked"))
+                .collect()
+        });
+        parts.extend(odd);
+    }
+    parts.pop()
+}
+
+// K-mers are already well distributed integers, so a multiply is enough of a hash.
+#[derive(Default)]
+struct KmerHasher(u64);
+
+impl Hasher for KmerHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.write_u64(b as u64);
+        }
+    }
+
+    fn write_u64(&mut self, i: u64) {
+        self.0 = (self.0 ^ i)
+            .wrapping_mul(0x9E37_79B9_7F4A_7C15)
+            .rotate_left(29);
+    }
+}
+
+// Finds canonical k-mers, 2 bits per base, and hands them to the counting threads in batches.
+struct KmerSink {
+    k: usize,
+
+    // The last (up to) k bases, and their reverse complement.
+    forward: u64,
+    reverse: u64,
+
+    // How many bases are in `forward`, up to k. Reset by anything that isn't ACGT.
+    len: usize,
+
+    batches: Vec<Vec<u64>>,
+    senders: Vec<Sender<Vec<u64>>>,
+}
+
+impl KmerSink {
+    fn new(k: usize, senders: Vec<Sender<Vec<u64>>>) -> Self {
+        KmerSink {
+            k,
+            forward: 0,
+            reverse: 0,
+            len: 0,
+            batches: vec![Vec::with_capacity(BATCH_SIZE); senders.len()],
+            senders,
+        }
+    }
+
+    fn emit(&mut self, kmer: u64) {
+        let shard = (kmer.wrapping_mul(0x9E37_79B9_7F4A_7C15) >> 32) as usize % self.senders.len();
+        let batch = &mut self.batches[shard];
+        batch.push(kmer);
+        if batch.len() == BATCH_SIZE {
+            let full = std::mem::replace(batch, Vec::with_capacity(BATCH_SIZE));
+            self.senders[shard]
+                .send(full)
+                .expect("counting thread stopped");
+        }
+    }
+}
+
+impl SeqSink for KmerSink {
+    fn sequence(&mut self, seq: &[u8]) {
+        let mask = if self.k == 32 {
+            u64::MAX
+        } else {
+            (1 << (2 * self.k)) - 1
+        };
+        let top = 2 * (self.k - 1);
+        for &b in seq {
+            let code = match b {
+                b'A' | b'a' => 0,
+                b'C' | b'c' => 1,
+                b'G' | b'g' => 2,
+                b'T' | b't' | b'U' | b'u' => 3,
+                _ => {
+                    self.len = 0;
+                    continue;
+                }
+            };
+            self.forward = ((self.forward << 2) | code) & mask;
+            self.reverse = (self.reverse >> 2) | ((3 - code) << top);
+            self.len += 1;
+            if self.len >= self.k {
+                self.emit(self.forward.min(self.reverse));
+            }
+        }
+    }
+
+    fn end_record(&mut self) {
+        self.len = 0;
+    }
+}
+
+impl Drop for KmerSink {
+    fn drop(&mut self) {
+        for (batch, sender) in self.batches.drain(..).zip(&self.senders) {
+            if !batch.is_empty() {
+                // If the thread is gone, join() reports why.
+                let _ = sender.send(batch);
+            }
+        }
+    }
+}
+
+fn decode(kmer: u64, k: usize) -> Vec<u8> {
+    (0..k)
+        .rev()
+        .map(|i| b"ACGT"[((kmer >> (2 * i)) & 3) as usize])
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn count(seq: &[u8], k: usize) -> Vec<(String, usize)> {
+        let (s, r) = crossbeam_channel::unbounded();
+        let mut sink = KmerSink::new(k, vec![s]);
+        sink.sequence(seq);
+        sink.end_record();
+        drop(sink);
+
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        for kmer in r.into_iter().flatten() {
+            *counts
+                .entry(String::from_utf8(decode(kmer, k)).unwrap())
+                .or_default() += 1;
+        }
+        let mut counts: Vec<_> = counts.into_iter().collect();
+        counts.sort();
+        counts
+    }
+
+    #[test]
+    fn test_canonical() {
+        // CGT is the reverse complement of ACG, so they are the same canonical k-mer.
+        assert_eq!(count(b"ACGTNacg", 3), [("ACG".to_string(), 3)]);
+        assert_eq!(
+            count(b"TTTGA", 2),
+            [
+                ("AA".to_string(), 2),
+                ("CA".to_string(), 1),
+                ("GA".to_string(), 1)
+            ]
+        );
+
+        let long = b"ACGTACGTACGTACGTACGTACGTACGTACGTA";
+        assert_eq!(
+            count(long, 32),
+            [
+                ("ACGTACGTACGTACGTACGTACGTACGTACGT".to_string(), 1),
+                ("CGTACGTACGTACGTACGTACGTACGTACGTA".to_string(), 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tree_reduce() {
+        // Neighbours are merged, so order is kept.
+        let concat = |a: String, b: String| a + &b;
+        for n in 0..8 {
+            let parts: Vec<String> = (0..n).map(|i| i.to_string()).collect();
+            let expected = (n > 0).then(|| parts.concat());
+            assert_eq!(tree_reduce(parts, concat), expected);
+        }
+    }
+}
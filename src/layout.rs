@@ -0,0 +1,196 @@
+use std::io::{self, Write};
+
+// The narrowest a column is cut down to, to fit the table in the terminal.
+const MIN_WIDTH: usize = 12;
+
+// Lays out tab-separated results as a table, for --format table: each column is as wide as its
+// widest cell, with columns of numbers aligned on the right, and with `boxed`, lines are drawn
+// around the cells. If the table is wider than `width`, the widest column that isn't numbers, such
+// as one of paths, is cut down by leaving out the middle of its longest cells, so that the ends of
+// paths, with their file names, still show.
+pub fn write_table(
+    out: &mut impl Write,
+    text: &[u8],
+    boxed: bool,
+    width: Option<usize>,
+) -> io::Result<()> {
+    let text = String::from_utf8_lossy(text);
+    let rows: Vec<Vec<&str>> = text
+        .lines()
+        .map(|line| line.split('\t').collect())
+        .collect();
+    let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+    if columns == 0 {
+        return Ok(());
+    }
+
+    let mut widths = vec![0; columns];
+    let mut numeric = vec![true; columns];
+    for row in &rows {
+        for (i, cell) in row.iter().enumerate() {
+            let visible = strip_escapes(cell);
+            widths[i] = widths[i].max(visible.chars().count());
+            numeric[i] &= visible.is_empty() || is_number(&visible);
+        }
+    }
+
+    // Cut the widest column of text down to fit, if there is one that can give enough.
+    let (gaps, edges) = if boxed { (3, 4) } else { (2, 0) };
+    let total = widths.iter().sum::<usize>() + gaps * (columns - 1) + edges;
+    let widest = (0..columns)
+        .filter(|&i| !numeric[i])
+        .max_by_key(|&i| widths[i]);
+    if let (Some(width), Some(i)) = (width, widest) {
+        if total > width {
+            widths[i] = widths[i]
+                .saturating_sub(total - width)
+                .max(MIN_WIDTH.min(widths[i]));
+        }
+    }
+
+    let rule = |left: &str, middle: &str, right: &str| {
+        let lines: Vec<String> = widths.iter().map(|&w| "─".repeat(w + 2)).collect();
+        format!("{}{}{}", left, lines.join(middle), right)
+    };
+    if boxed {
+        writeln!(out, "{}", rule("┌", "┬", "┐"))?;
+    }
+    for row in &rows {
+        let mut line = String::new();
+        if boxed {
+            line.push_str("│ ");
+        }
+        for (i, &w) in widths.iter().enumerate() {
+            let cell = row.get(i).copied().unwrap_or("");
+            let visible = strip_escapes(cell);
+            let len = visible.chars().count();
+            let (cell, len) = if len > w {
+                (elide(&visible, w), w)
+            } else {
+                (cell.to_string(), len)
+            };
+            let pad = " ".repeat(w - len);
+            if i > 0 {
+                line.push_str(if boxed { " │ " } else { "  " });
+            }
+            if numeric[i] {
+                line.push_str(&pad);
+                line.push_str(&cell);
+            } else {
+                line.push_str(&cell);
+                line.push_str(&pad);
+            }
+        }
+        if boxed {
+            line.push_str(" │");
+        }
+        writeln!(out, "{}", line.trim_end_matches(' '))?;
+    }
+    if boxed {
+        writeln!(out, "{}", rule("└", "┴", "┘"))?;
+    }
+    Ok(())
+}
+
+// Returns `s` without the escape sequences that color it.
+fn strip_escapes(s: &str) -> String {
+    let mut plain = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // Skip to the end of "\x1b[...m".
+            chars.by_ref().find(|&c| c == 'm');
+        } else {
+            plain.push(c);
+        }
+    }
+    plain
+}
+
+// Whether a cell is a count, a rate or a percentage, or "-" for none.
+fn is_number(s: &str) -> bool {
+    s == "-"
+        || (s.bytes().any(|b| b.is_ascii_digit())
+            && s.bytes()
+                .all(|b| b.is_ascii_digit() || matches!(b, b'.' | b'-' | b'+' | b'%')))
+}
+
+// Shortens `s` to `width` characters by replacing the middle with "…", keeping more of the end,
+// where the file name of a path is.
+fn elide(s: &str, width: usize) -> String {
+    if width == 0 {
+        return String::new();
+    }
+    let chars: Vec<char> = s.chars().collect();
+    let head = (width - 1) / 3;
+    let tail = width - 1 - head;
+    let mut elided: String = chars[..head].iter().collect();
+    elided.push('…');
+    elided.extend(&chars[chars.len() - tail..]);
+    elided
+}
+
+// The width of the terminal, from $COLUMNS or else from the terminal itself.
+pub fn terminal_width() -> Option<usize> {
+    if let Some(columns) = std::env::var("COLUMNS").ok().and_then(|c| c.parse().ok()) {
+        return Some(columns);
+    }
+    #[cfg(target_os = "linux")]
+    {
+        // SAFETY: TIOCGWINSZ only writes a winsize to the pointer it is given.
+        let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+        let res = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) };
+        if res == 0 && size.ws_col > 0 {
+            return Some(size.ws_col as usize);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(text: &str, boxed: bool, width: Option<usize>) -> String {
+        let mut out = Vec::new();
+        write_table(&mut out, text.as_bytes(), boxed, width).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_aligned() {
+        let text = "120\t3.20 per MB\tapp.log\n9\t-\tworker.log\n129\t2.24 per MB\n";
+        assert_eq!(
+            table(text, false, None),
+            "\
+120  3.20 per MB  app.log
+  9  -            worker.log
+129  2.24 per MB
+"
+        );
+        assert_eq!(
+            table("3\ta\n12\t\x1b[1mbb\x1b[0m\n", true, None),
+            "\
+┌────┬────┐
+│  3 │ a  │
+│ 12 │ \x1b[1mbb\x1b[0m │
+└────┴────┘
+"
+        );
+        assert_eq!(table("", true, None), "");
+    }
+
+    #[test]
+    fn test_elided() {
+        let text = "1\t/var/log/services/payments/2024-06-01/app.log\n22\tb.log\n";
+        assert_eq!(
+            table(text, false, Some(24)),
+            " 1  /var/l…06-01/app.log\n22  b.log\n"
+        );
+        // Columns aren't cut down past the narrowest they can usefully be.
+        assert_eq!(
+            table(text, false, Some(4)).lines().next(),
+            Some(" 1  /va…/app.log")
+        );
+    }
+}
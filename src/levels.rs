@@ -0,0 +1,88 @@
+use crate::color;
+use crate::input::{for_each_chunk, open_inputs};
+use crate::names;
+use clap::Args;
+use freq::{Counter, Literals};
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+#[derive(Args)]
+pub struct LevelsArgs {
+    #[arg(help = "The log files to read, with - for stdin. If not provided, stdin is used.")]
+    input: Vec<PathBuf>,
+}
+
+// The levels counted, from least to most severe.
+const LEVELS: [&str; 6] = ["TRACE", "DEBUG", "INFO", "WARN", "ERROR", "FATAL"];
+
+pub fn run(args: LevelsArgs, buffer_size: usize, out: &mut impl Write) {
+    // All levels are counted in one pass over each file.
+    let matcher = Literals::new(LEVELS);
+    let mut rows = Vec::new();
+    for f in open_inputs(&args.input) {
+        let mut counter = Counter::with_matcher(matcher.clone());
+        for_each_chunk(f, buffer_size, |chunk| counter.write(chunk));
+        rows.push(counter.counts().to_vec());
+    }
+
+    print_table(out, &args.input, &rows).expect("failed to write output");
+}
+
+// Prints a tab-separated table with a row of counts for each input, and a total row if there
+// is more than one.
+fn print_table(out: &mut impl Write, paths: &[PathBuf], rows: &[Vec<usize>]) -> io::Result<()> {
+    write!(out, "file")?;
+    for level in LEVELS {
+        write!(out, "\t{}", level)?;
+    }
+    writeln!(out)?;
+
+    let mut totals = [0; LEVELS.len()];
+    for (i, row) in rows.iter().enumerate() {
+        for (total, count) in totals.iter_mut().zip(row) {
+            *total += count;
+        }
+        if !names::per_input() {
+            continue;
+        }
+        out.write_all(&names::of(paths.get(i).map(PathBuf::as_path)))?;
+        for count in row {
+            write!(out, "\t{}", color::count(count))?;
+        }
+        writeln!(out)?;
+    }
+
+    if rows.len() > 1 || !names::per_input() {
+        write!(out, "{}", color::total("total"))?;
+        for total in totals {
+            write!(out, "\t{}", color::total(total))?;
+        }
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_levels() {
+        let mut counter = Counter::with_matcher(Literals::new(LEVELS));
+        counter.write(b"INFO start\nWARNING: slow\nERROR x\nINFO done\n");
+        let rows = [counter.counts().to_vec(), vec![0, 0, 0, 0, 1, 1]];
+
+        let mut out = Vec::new();
+        let paths = [PathBuf::from("a.log"), PathBuf::from("b.log")];
+        print_table(&mut out, &paths, &rows).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "\
+file\tTRACE\tDEBUG\tINFO\tWARN\tERROR\tFATAL
+a.log\t0\t0\t2\t1\t1\t0
+b.log\t0\t0\t0\t0\t1\t1
+total\t0\t0\t2\t1\t2\t1
+"
+        );
+    }
+}
@@ -0,0 +1,24 @@
+//! The counting core of `freq`, usable as a library.
+//!
+//! A [`Counter`] counts non-overlapping matches of a [`Matcher`] in a stream of bytes that
+//! arrives in arbitrarily sized chunks, correctly handling matches that span chunk boundaries.
+//! [`NeedleCounter`] is the counter for a [`Literal`] needle, and [`Fuzzy`] matches a needle
+//! within an edit distance, or [`Hamming`] with some of its bytes substituted. [`Near`] matches
+//! one literal followed closely by another. [`Dictionary`] matches many literals with one
+//! Aho-Corasick automaton, and [`Regex`] matches a regular expression with a DFA.
+
+mod counter;
+mod dictionary;
+mod fuzzy;
+mod hamming;
+mod matcher;
+mod near;
+mod regex;
+
+pub use counter::{Counter, NeedleCounter};
+pub use dictionary::{Automaton, Dictionary};
+pub use fuzzy::Fuzzy;
+pub use hamming::Hamming;
+pub use matcher::{Literal, Literals, Match, Matcher};
+pub use near::Near;
+pub use regex::Regex;
@@ -0,0 +1,176 @@
+use crate::color;
+use crate::input::{for_each_chunk, open_inputs};
+use clap::Args;
+use memchr::memchr_iter;
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+#[derive(Args)]
+pub struct LinelensArgs {
+    #[arg(help = "The files to read, with - for stdin. If not provided, stdin is used.")]
+    input: Vec<PathBuf>,
+}
+
+pub fn run(args: LinelensArgs, buffer_size: usize, out: &mut impl Write) {
+    let mut lens = LineLengths::default();
+    for f in open_inputs(&args.input) {
+        for_each_chunk(f, buffer_size, |chunk| lens.write(chunk));
+        lens.finish();
+    }
+
+    lens.print(out).expect("failed to write output");
+}
+
+// The percentiles that are printed, in order.
+const PERCENTILES: [(f64, &str); 4] = [(0.5, "p50"), (0.9, "p90"), (0.99, "p99"), (0.999, "p99.9")];
+
+// How many lines there are of each length, gathered in one pass. Lengths don't include the line
+// ending, \n or \r\n. There are only as many entries as distinct lengths, so percentiles are exact.
+#[derive(Default)]
+pub struct LineLengths {
+    counts: BTreeMap<u64, u64>,
+
+    // The length of the line that continues into the next chunk so far, with its last byte.
+    current: u64,
+    last: Option<u8>,
+}
+
+impl LineLengths {
+    pub fn write(&mut self, buf: &[u8]) {
+        let Some(&last) = buf.last() else {
+            return;
+        };
+        let mut start = 0;
+        for end in memchr_iter(b'\n', buf) {
+            let before = if end == 0 {
+                self.last
+            } else {
+                Some(buf[end - 1])
+            };
+            let len = self.current + (end - start) as u64;
+            self.add(len - u64::from(before == Some(b'\r')));
+            self.current = 0;
+            start = end + 1;
+        }
+        self.current += (buf.len() - start) as u64;
+        self.last = Some(last);
+    }
+
+    // Ends the current input, counting its last line if it has no trailing newline.
+    pub fn finish(&mut self) {
+        if self.current > 0 {
+            self.add(self.current);
+        }
+        self.current = 0;
+        self.last = None;
+    }
+
+    fn add(&mut self, len: u64) {
+        *self.counts.entry(len).or_default() += 1;
+    }
+
+    // The length that at least `p` of the lines are no longer than.
+    fn percentile(&self, p: f64, lines: u64) -> u64 {
+        let rank = ((p * lines as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (&len, &n) in &self.counts {
+            seen += n;
+            if seen >= rank {
+                return len;
+            }
+        }
+        0
+    }
+
+    // Prints "value<TAB>statistic" lines, then how many lines have a length in each power-of-two
+    // range that any do.
+    pub fn print(&self, out: &mut impl Write) -> io::Result<()> {
+        let lines: u64 = self.counts.values().sum();
+        let bytes: u64 = self.counts.iter().map(|(len, n)| len * n).sum();
+        let mean = if lines == 0 {
+            0.0
+        } else {
+            bytes as f64 / lines as f64
+        };
+        let min = self.counts.keys().next().copied().unwrap_or(0);
+        let max = self.counts.keys().next_back().copied().unwrap_or(0);
+
+        writeln!(out, "{}\tlines", color::count(lines))?;
+        writeln!(out, "{}\tmin length", min)?;
+        writeln!(out, "{:.2}\tmean length", mean)?;
+        writeln!(out, "{}\tmax length", max)?;
+        for (p, name) in PERCENTILES {
+            writeln!(out, "{}\t{} length", self.percentile(p, lines), name)?;
+        }
+
+        let mut buckets: BTreeMap<u32, u64> = BTreeMap::new();
+        for (&len, &n) in &self.counts {
+            // 0, then 1, 2-3, 4-7, and so on.
+            *buckets.entry(u64::BITS - len.leading_zeros()).or_default() += n;
+        }
+        for (bucket, n) in buckets {
+            match bucket {
+                0 => writeln!(out, "{}\tlength 0", color::count(n))?,
+                1 => writeln!(out, "{}\tlength 1", color::count(n))?,
+                _ => {
+                    let lo = 1u64 << (bucket - 1);
+                    let hi = lo.checked_mul(2).map_or(u64::MAX, |h| h - 1);
+                    writeln!(out, "{}\tlength {}-{}", color::count(n), lo, hi)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use proptest::string::bytes_regex;
+    use proptest::{prop_assert_eq, proptest};
+
+    fn lengths(inputs: &[&[u8]], chunk_size: usize) -> String {
+        let mut lens = LineLengths::default();
+        for input in inputs {
+            input.chunks(chunk_size).for_each(|c| lens.write(c));
+            lens.finish();
+        }
+        let mut out = Vec::new();
+        lens.print(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_line_lengths() {
+        assert_eq!(
+            lengths(&[b"\nab\r\nabcd\nabcde\n", b"abcdefgh"], 3),
+            "\
+5\tlines
+0\tmin length
+3.80\tmean length
+8\tmax length
+4\tp50 length
+8\tp90 length
+8\tp99 length
+8\tp99.9 length
+1\tlength 0
+1\tlength 2-3
+2\tlength 4-7
+1\tlength 8-15
+"
+        );
+        assert!(lengths(&[b""], 1).starts_with("0\tlines\n0\tmin length\n0.00\tmean length\n"));
+    }
+
+    proptest! {
+        #[test]
+        fn test_chunked(
+            chunk_size in 1..20_usize,
+            text in bytes_regex("((?s-u:[a\\r\\n]{0,200}))").unwrap(),
+        ) {
+            prop_assert_eq!(lengths(&[&text], chunk_size), lengths(&[&text], text.len().max(1)));
+        }
+    }
+}
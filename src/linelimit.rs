@@ -0,0 +1,140 @@
+use clap::ValueEnum;
+use std::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
+
+// What to do with a line, or record, longer than --max-line-length.
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum Policy {
+    /// Leave the line out, as if it wasn't there.
+    Skip,
+    /// Only count the first N bytes of the line.
+    Truncate,
+    /// Stop with an error, without printing results.
+    Error,
+}
+
+// The longest lines that are counted as they are, and what happens to longer ones. Counters copy
+// it from --max-line-length when they are made.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Limit {
+    max: usize,
+    policy: Policy,
+}
+
+impl Default for Limit {
+    fn default() -> Self {
+        Limit {
+            max: usize::MAX,
+            policy: Policy::Skip,
+        }
+    }
+}
+
+static MAX: AtomicUsize = AtomicUsize::new(usize::MAX);
+static POLICY: AtomicU8 = AtomicU8::new(Policy::Skip as u8);
+
+// How many lines were longer than the limit.
+static LONG: AtomicUsize = AtomicUsize::new(0);
+
+pub fn set(max: usize, policy: Policy) {
+    MAX.store(max, Ordering::Relaxed);
+    POLICY.store(policy as u8, Ordering::Relaxed);
+}
+
+// The limit given on the command line, if any.
+pub fn get() -> Limit {
+    let policy = match POLICY.load(Ordering::Relaxed) {
+        p if p == Policy::Truncate as u8 => Policy::Truncate,
+        p if p == Policy::Error as u8 => Policy::Error,
+        _ => Policy::Skip,
+    };
+    Limit::new(MAX.load(Ordering::Relaxed), policy)
+}
+
+// Returns how many lines were skipped or truncated, and the limit they were over.
+pub fn long_lines() -> (usize, Limit) {
+    (LONG.load(Ordering::Relaxed), get())
+}
+
+impl Limit {
+    pub fn new(max: usize, policy: Policy) -> Self {
+        Limit { max, policy }
+    }
+
+    pub fn max(&self) -> usize {
+        self.max
+    }
+
+    pub fn policy(&self) -> Policy {
+        self.policy
+    }
+
+    pub fn is_set(&self) -> bool {
+        self.max != usize::MAX
+    }
+
+    // Returns what of a whole line to count, if anything.
+    pub fn apply<'a>(&self, line: &'a [u8]) -> Option<&'a [u8]> {
+        if line.len() <= self.max {
+            return Some(line);
+        }
+        LONG.fetch_add(1, Ordering::Relaxed);
+        match self.policy {
+            Policy::Skip => None,
+            Policy::Truncate => Some(&line[..self.max]),
+            Policy::Error => self.fail(),
+        }
+    }
+
+    // Appends `more` to `carry`, the start of a line that was cut off at the end of a chunk. Once
+    // the line is too long, only its first bytes past the limit are kept, so that it is known to
+    // be, and its last `keep` bytes, where the separator that ends it may start.
+    pub fn extend(&self, carry: &mut Vec<u8>, more: &[u8], keep: usize) {
+        carry.extend_from_slice(more);
+        let kept = self.max.saturating_add(1);
+        if carry.len() > kept.saturating_add(keep) {
+            if self.policy == Policy::Error {
+                self.fail();
+            }
+            let tail = carry.len() - keep;
+            carry.drain(kept..tail);
+        }
+    }
+
+    fn fail(&self) -> ! {
+        eprintln!(
+            "freq: found a line longer than --max-line-length {}",
+            self.max
+        );
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply() {
+        let skip = Limit::new(3, Policy::Skip);
+        assert_eq!(skip.apply(b"abc"), Some(&b"abc"[..]));
+        assert_eq!(skip.apply(b"abcd"), None);
+        let truncate = Limit::new(3, Policy::Truncate);
+        assert_eq!(truncate.apply(b"abcd"), Some(&b"abc"[..]));
+        assert_eq!(Limit::default().apply(b"abcd"), Some(&b"abcd"[..]));
+    }
+
+    #[test]
+    fn test_extend() {
+        let limit = Limit::new(3, Policy::Truncate);
+        let mut carry = Vec::new();
+        for more in [&b"ab"[..], b"cdef", b"ghij"] {
+            limit.extend(&mut carry, more, 2);
+        }
+        assert_eq!(carry, b"abcdij");
+        assert_eq!(limit.apply(&carry), Some(&b"abc"[..]));
+
+        let mut carry = Vec::new();
+        limit.extend(&mut carry, b"abcdef", 0);
+        assert_eq!(carry, b"abcd");
+    }
+}
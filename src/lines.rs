@@ -0,0 +1,190 @@
+use crate::color;
+use crate::input::{for_each_chunk, open_inputs};
+use crate::linelimit::{self, Limit};
+use crate::normalize::NormalizeArgs;
+use crate::table::{FreqTable, TableArgs};
+use clap::Args;
+use memchr::memchr_iter;
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+#[derive(Args)]
+pub struct LinesArgs {
+    #[arg(help = "The files to read, with - for stdin. If not provided, stdin is used.")]
+    input: Vec<PathBuf>,
+
+    #[command(flatten)]
+    normalize: NormalizeArgs,
+
+    #[command(flatten)]
+    table: TableArgs,
+
+    #[arg(
+        long,
+        requires = "distinct",
+        conflicts_with = "min_count",
+        help = "With --distinct, also print the number of lines and how many times each distinct line occurs on average, such as how much deduplicating would shrink a dataset."
+    )]
+    dup_factor: bool,
+}
+
+pub fn run(args: LinesArgs, buffer_size: usize, out: &mut impl Write) {
+    let mut lines = LineCounter::new(FreqTable::new(&args.table).normalized(&args.normalize));
+    for f in open_inputs(&args.input) {
+        for_each_chunk(f, buffer_size, |chunk| lines.write(chunk));
+        lines.finish();
+    }
+
+    let res = if args.dup_factor {
+        print_dup_factor(out, lines.table.distinct(), lines.lines)
+    } else {
+        lines.table.print(&args.table, out)
+    };
+    res.expect("failed to write output");
+}
+
+// Prints the number of distinct lines, the number of lines, and the ratio of the two.
+fn print_dup_factor(out: &mut impl Write, distinct: usize, lines: usize) -> io::Result<()> {
+    writeln!(out, "{}\tdistinct", color::count(distinct))?;
+    writeln!(out, "{}\tlines", color::count(lines))?;
+    match distinct {
+        0 => writeln!(out, "-\tduplication factor"),
+        _ => writeln!(
+            out,
+            "{:.2}\tduplication factor",
+            lines as f64 / distinct as f64
+        ),
+    }
+}
+
+#[derive(Default)]
+pub struct LineCounter {
+    table: FreqTable,
+
+    // How many lines were counted.
+    lines: usize,
+
+    // The start of a line that continues into the next chunk.
+    carry: Vec<u8>,
+
+    limit: Limit,
+}
+
+impl LineCounter {
+    pub fn new(table: FreqTable) -> Self {
+        LineCounter {
+            table,
+            lines: 0,
+            carry: Vec::new(),
+            limit: linelimit::get(),
+        }
+    }
+
+    pub fn write(&mut self, buf: &[u8]) {
+        let mut start = 0;
+        for end in memchr_iter(b'\n', buf) {
+            if self.carry.is_empty() {
+                self.add(&buf[start..end]);
+            } else {
+                self.limit.extend(&mut self.carry, &buf[start..end], 0);
+                self.add_carry();
+            }
+            start = end + 1;
+        }
+        self.limit.extend(&mut self.carry, &buf[start..], 0);
+    }
+
+    // Counts the last line of the current input, if it has no trailing newline.
+    pub fn finish(&mut self) {
+        if !self.carry.is_empty() {
+            self.add_carry();
+        }
+    }
+
+    fn add(&mut self, line: &[u8]) {
+        if let Some(line) = self.limit.apply(line) {
+            self.table.add(line);
+            self.lines += 1;
+        }
+    }
+
+    // Counts the line in `carry`, and starts the next one.
+    fn add_carry(&mut self) {
+        if let Some(line) = self.limit.apply(&self.carry) {
+            self.table.add(line);
+            self.lines += 1;
+        }
+        self.carry.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::linelimit::Policy;
+
+    use proptest::string::bytes_regex;
+    use proptest::{prop_assert_eq, proptest};
+
+    #[test]
+    fn test_print_dup_factor() {
+        let mut out = Vec::new();
+        print_dup_factor(&mut out, 4, 10).unwrap();
+        print_dup_factor(&mut out, 0, 0).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "4\tdistinct\n10\tlines\n2.50\tduplication factor\n0\tdistinct\n0\tlines\n-\tduplication factor\n"
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn test_chunked(
+            chunk_size in 1..20_usize,
+            text in bytes_regex("((?s-u:[ab\\n]{0,200}))").unwrap(),
+        ) {
+            let mut chunked = LineCounter::default();
+            text.chunks(chunk_size).for_each(|chunk| chunked.write(chunk));
+            chunked.finish();
+
+            let mut expected = FreqTable::default();
+            text.strip_suffix(b"\n")
+                .unwrap_or(&text)
+                .split(|&b| b == b'\n')
+                .filter(|_| !text.is_empty())
+                .for_each(|line| expected.add(line));
+
+            prop_assert_eq!(chunked.table.counts(), expected.counts());
+            prop_assert_eq!(chunked.lines, expected.total());
+        }
+
+        #[test]
+        fn test_limit_chunked(
+            chunk_size in 1..20_usize,
+            max in 0..5_usize,
+            text in bytes_regex("((?s-u:[ab\\n]{0,200}))").unwrap(),
+        ) {
+            let lines = || {
+                text.strip_suffix(b"\n")
+                    .unwrap_or(&text)
+                    .split(|&b| b == b'\n')
+                    .filter(|_| !text.is_empty())
+            };
+            for policy in [Policy::Skip, Policy::Truncate] {
+                let mut chunked = LineCounter {
+                    limit: Limit::new(max, policy),
+                    ..Default::default()
+                };
+                text.chunks(chunk_size).for_each(|chunk| chunked.write(chunk));
+                chunked.finish();
+
+                let mut expected = FreqTable::default();
+                match policy {
+                    Policy::Skip => lines().filter(|line| line.len() <= max).for_each(|line| expected.add(line)),
+                    _ => lines().for_each(|line| expected.add(&line[..line.len().min(max)])),
+                }
+                prop_assert_eq!(chunked.table.counts(), expected.counts());
+            }
+        }
+    }
+}
@@ -0,0 +1,34 @@
+use tracing::level_filters::LevelFilter;
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::EnvFilter;
+
+// Sends what freq says about what it's doing to stderr, at the level chosen with -v, -vv and
+// -vvv:
+//
+//   info: which files are read, cached or skipped, and why
+//   debug: how they're read, such as the matcher and buffer size, and how long each stage took
+//   trace: every chunk read
+//
+// RUST_LOG takes tracing's usual directives, such as RUST_LOG=freq::input=trace, and overrides
+// the level for the modules it names.
+pub fn init(verbose: u8) {
+    let level = match verbose {
+        0 => LevelFilter::OFF,
+        1 => LevelFilter::INFO,
+        2 => LevelFilter::DEBUG,
+        _ => LevelFilter::TRACE,
+    };
+    let filter = EnvFilter::builder()
+        .with_default_directive(level.into())
+        .from_env_lossy();
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr)
+        .with_ansi(false)
+        .with_target(false)
+        // Spans still time themselves, but messages aren't timestamped.
+        .with_timer(())
+        // A stage's span reports how long it took when it closes.
+        .with_span_events(FmtSpan::CLOSE)
+        .init();
+}
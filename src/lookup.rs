@@ -0,0 +1,195 @@
+use crate::color;
+use crate::engine;
+use crate::input::{for_each_chunk, open_inputs};
+use crate::walk;
+use clap::Args;
+use freq::{Dictionary, Match};
+use std::collections::HashSet;
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+#[derive(Args)]
+pub struct LookupArgs {
+    #[arg(help = "The file of patterns to look up, one on each line. Empty lines are skipped.")]
+    needles: PathBuf,
+
+    #[arg(
+        help = "The files to count them in, with - for stdin. Directories are searched recursively. If not provided, stdin is used."
+    )]
+    input: Vec<PathBuf>,
+}
+
+pub fn run(args: LookupArgs, buffer_size: usize, out: &mut impl Write) {
+    let text = std::fs::read(&args.needles).unwrap_or_else(|e| {
+        eprintln!("freq: {}: {}", args.needles.display(), e);
+        std::process::exit(2);
+    });
+    let needles = needles(&text);
+    if needles.is_empty() {
+        eprintln!("freq: {} has no patterns", args.needles.display());
+        std::process::exit(2);
+    }
+    let mut counter = Occurrences::new(&needles);
+    for f in open_inputs(&walk::files(&args.input)) {
+        for_each_chunk(f, buffer_size, |chunk| counter.write(chunk));
+        counter.finish();
+    }
+
+    print(out, &needles, &counter.counts).expect("failed to write output");
+}
+
+// The lines of `text`, without their line endings, each once, in the order they first appear.
+fn needles(text: &[u8]) -> Vec<&[u8]> {
+    let mut seen = HashSet::new();
+    text.split(|&b| b == b'\n')
+        .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+        .filter(|line| !line.is_empty() && seen.insert(*line))
+        .collect()
+}
+
+// Counts the non-overlapping matches of each of many needles on its own, as if it had been
+// counted alone, in one pass with a dictionary automaton: a needle that is part of another,
+// such as "id1" of "id12", is still counted where the other matches.
+pub struct Occurrences {
+    dictionary: Dictionary,
+    max_len: usize,
+    counts: Vec<usize>,
+
+    // Where the last counted match of each needle ended, from the start of the input, so that
+    // its next one isn't counted if it overlaps it.
+    ends: Vec<usize>,
+
+    // The last bytes of the input so far, fewer than the longest needle, where a match that ends
+    // in the next chunk may start.
+    carry: Vec<u8>,
+    // How much of the input came before the current chunk.
+    offset: usize,
+}
+
+impl Occurrences {
+    pub fn new(needles: &[&[u8]]) -> Self {
+        Occurrences {
+            dictionary: engine::dictionary(needles),
+            max_len: needles.iter().map(|n| n.len()).max().unwrap_or(0),
+            counts: vec![0; needles.len()],
+            ends: vec![0; needles.len()],
+            carry: Vec::new(),
+            offset: 0,
+        }
+    }
+
+    pub fn write(&mut self, chunk: &[u8]) {
+        let Occurrences {
+            dictionary,
+            counts,
+            ends,
+            ..
+        } = self;
+        let mut add = |m: Match, offset: usize| {
+            // Matches of a needle come in order, since they all have the same length.
+            if offset + m.start >= ends[m.pattern] {
+                counts[m.pattern] += 1;
+                ends[m.pattern] = offset + m.end;
+            }
+        };
+        // Matches that start in the carry and end in this chunk. Every match of the same needle
+        // that is only in this chunk starts after them.
+        if !self.carry.is_empty() {
+            let mut boundary = self.carry.clone();
+            boundary.extend_from_slice(&chunk[..chunk.len().min(self.max_len - 1)]);
+            let carried = self.carry.len();
+            dictionary.find_overlapping(&boundary, &mut |m| {
+                if m.start < carried && m.end > carried {
+                    add(m, self.offset - carried);
+                }
+            });
+        }
+        dictionary.find_overlapping(chunk, &mut |m| add(m, self.offset));
+
+        let keep = self.max_len - 1;
+        if chunk.len() >= keep {
+            self.carry.clear();
+        } else {
+            let excess = (self.carry.len() + chunk.len()).saturating_sub(keep);
+            self.carry.drain(..excess);
+        }
+        self.carry
+            .extend_from_slice(&chunk[chunk.len().saturating_sub(keep)..]);
+        self.offset += chunk.len();
+    }
+
+    // Ends the current input, so that no match spans it and the next one.
+    pub fn finish(&mut self) {
+        self.carry.clear();
+        self.ends.fill(0);
+        self.offset = 0;
+    }
+}
+
+// Prints "count<TAB>needle" for each needle, in the order of the file, then the total.
+fn print(out: &mut impl Write, needles: &[&[u8]], counts: &[usize]) -> io::Result<()> {
+    for (needle, count) in needles.iter().zip(counts) {
+        write!(out, "{}\t", color::count(count))?;
+        out.write_all(needle)?;
+        writeln!(out)?;
+    }
+    writeln!(out, "{}", color::total(counts.iter().sum::<usize>()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use proptest::string::bytes_regex;
+
+    fn count(needles: &[&[u8]], chunks: &[&[u8]]) -> Vec<usize> {
+        let mut counter = Occurrences::new(needles);
+        chunks.iter().for_each(|chunk| counter.write(chunk));
+        counter.counts
+    }
+
+    #[test]
+    fn test_needles() {
+        assert_eq!(
+            needles(b"id1\r\n\nid12\nid1\nlast"),
+            [&b"id1"[..], b"id12", b"last"]
+        );
+    }
+
+    #[test]
+    fn test_occurrences() {
+        let needles = [&b"id1"[..], b"id12", b"aa"];
+        assert_eq!(count(&needles, &[b"id12 id1 aaaaa"]), [2, 1, 2]);
+        assert_eq!(
+            count(&needles, &[b"i", b"d1", b"2 a", b"aa", b"aa"]),
+            [1, 1, 2]
+        );
+
+        // Matches don't span inputs.
+        let mut counter = Occurrences::new(&needles);
+        counter.write(b"xi");
+        counter.finish();
+        counter.write(b"d1");
+        assert_eq!(counter.counts, [0, 0, 0]);
+    }
+
+    proptest! {
+        #[test]
+        fn test_chunked(
+            chunk_size in 1..20_usize,
+            text in bytes_regex("((?s-u:[ab\\n]{0,200}))").unwrap(),
+        ) {
+            let needles = [&b"ab"[..], b"aba", b"b\na", b"bb"];
+            let chunks: Vec<_> = text.chunks(chunk_size).collect();
+            let alone: Vec<_> = needles
+                .iter()
+                .map(|&needle| {
+                    let mut counter = freq::NeedleCounter::new(needle);
+                    counter.write(&text);
+                    counter.count()
+                })
+                .collect();
+            prop_assert_eq!(count(&needles, &chunks), alone);
+        }
+    }
+}
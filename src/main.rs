@@ -1,253 +1,2303 @@
 extern crate core;
 
+mod affinity;
+mod bytes;
+mod cache;
+mod chars;
+mod chunk;
+mod color;
+mod compare;
+mod config;
+mod csv;
+mod encoding;
+mod engine;
+mod estimate;
+mod expr;
+mod fields;
+mod glob;
+mod group;
+#[cfg(feature = "hyperscan")]
+mod hyperscan;
+mod incremental;
+mod input;
+#[cfg(any(feature = "kafka", not(target_os = "wasi")))]
+mod interval;
+mod journal;
+mod json;
+#[cfg(feature = "kafka")]
+mod kafka;
+#[cfg(not(target_os = "wasi"))]
+mod kmers;
+mod layout;
+mod levels;
+mod linelens;
+mod linelimit;
+mod lines;
+mod log;
+mod lookup;
+mod man;
+mod matrix;
+mod memory;
+mod merge;
+mod metrics;
+mod msgpack;
+mod names;
+mod normalize;
+mod output;
+mod rate;
+mod records;
+mod report;
+mod sample;
+mod sections;
+mod seq;
+mod seqcount;
+mod sketch;
+mod split;
+mod stats;
+#[cfg(not(target_os = "wasi"))]
+mod syslog;
+mod table;
+mod timecount;
+mod tune;
+mod unicode;
+mod walk;
+mod wc;
+mod wholeline;
+mod words;
+
 use clap::error::ErrorKind;
-use clap::{CommandFactory, Parser};
-use crossbeam_channel::Receiver;
-use memchr::memmem::Finder;
+use clap::parser::ValueSource;
+use clap::{ArgGroup, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use freq::{Fuzzy, Hamming, Literal, Literals, Matcher, Near, NeedleCounter};
+use input::{for_each_chunk, open_ranges, tee_inputs, Input};
+use output::Output;
+use sample::Sampler;
+#[cfg(not(target_os = "wasi"))]
+use std::collections::BTreeMap;
+use std::collections::HashSet;
 use std::ffi::OsString;
-use std::fs::File;
-use std::io::{stdin, Read};
+use std::io::{self, IsTerminal, Write};
 use std::path::PathBuf;
+#[cfg(not(target_os = "wasi"))]
+use std::sync::Mutex;
+use std::time::Duration;
+use tracing::{debug, debug_span, info};
 
 #[derive(Parser)]
-#[command(version, about = "freq - count the occurrences of a literal pattern")]
+#[command(
+    version,
+    about = "freq - count the occurrences of a literal pattern",
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true,
+    // The modes that always find matches with freq's own matchers.
+    group = ArgGroup::new("builtin_only").multiple(true).conflicts_with("engine"),
+    // The matchers other than a literal, of which one can be used at a time.
+    group = ArgGroup::new("mode").conflicts_with_all([
+        "cache", "revcomp", "estimate", "and_patterns", "not_patterns", "record_sep", "null_data",
+        "record_size", "per_record_stats", "columns", "expr",
+    ]),
+    // What is counted besides matches, as wc does, in the same pass.
+    group = ArgGroup::new("wc").multiple(true).conflicts_with_all([
+        "line_regexp", "ratio", "revcomp", "cache", "baseline", "estimate", "rate",
+        "seq_format", "time_bucket", "and_patterns", "not_patterns", "record_sep", "null_data",
+        "record_size", "per_record_stats", "percent_lines", "columns", "expr",
+    ]),
+    // The messages that keep arriving, which are counted in --emit-every intervals.
+    group = ArgGroup::new("streams").args(["kafka", "syslog_listen"])
+)]
 struct Args {
-    #[arg(required = true, help = "The pattern to search for.")]
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[arg(required_unless_present_any = ["expr", "near"], help = "The pattern to search for.")]
     /// The pattern to search for.
-    pattern: OsString,
+    pattern: Option<OsString>,
 
-    #[arg(help = "The files to search in. If not provided, stdin is used.")]
+    #[arg(
+        help = "The files to search in, with - for stdin. Directories are searched recursively. If not provided, stdin is used."
+    )]
     input: Vec<PathBuf>,
 
     #[clap(
         short,
         long,
+        global = true,
         default_value = "1048576",
         help = "The size of the buffer used to read the file. Larger buffers use more memory, but might be faster."
     )]
     buffer_size: usize,
+
+    #[clap(
+        long,
+        global = true,
+        help = "Try several buffer sizes on the first few hundred MB read, and read the rest with whichever was fastest, instead of --buffer-size. The best size differs widely between local disks, network filesystems and pipes."
+    )]
+    auto_tune: bool,
+
+    #[clap(
+        long,
+        global = true,
+        value_name = "SIZE",
+        value_parser = table::parse_size,
+        help = "Keep to about this much memory, such as 512M, by reading in smaller buffers, using fewer threads, and turning frequency tables that outgrow it into estimates, as with --approx. Accepts K, M and G suffixes."
+    )]
+    max_memory: Option<usize>,
+
+    #[clap(
+        short,
+        long,
+        global = true,
+        value_name = "N",
+        value_parser = clap::value_parser!(u32).range(1..),
+        help = "The most threads to count on, such as in freq kmers, or inputs to count at once. With 1, inputs are read on the counting thread too, instead of on one of their own. Defaults to the number of CPUs freq can run on."
+    )]
+    jobs: Option<u32>,
+
+    #[clap(
+        long,
+        global = true,
+        value_name = "LIST",
+        value_parser = affinity::CpuList::parse,
+        help = "Only run on these CPUs, such as 0-7 or 0,2,4-6, to keep away from other work on a shared host. Buffers are then allocated on the NUMA nodes of these CPUs. Linux only."
+    )]
+    cpu_list: Option<affinity::CpuList>,
+
+    #[clap(
+        long,
+        global = true,
+        value_enum,
+        default_value = "auto",
+        help = "How inputs are handed from the thread reading them to the one counting them."
+    )]
+    pipeline: input::Pipeline,
+
+    #[clap(
+        long,
+        global = true,
+        value_enum,
+        default_value = "builtin",
+        help = "What finds the matches of the pattern, or of the patterns of freq compare. Patterns are literal text with every engine."
+    )]
+    engine: engine::Engine,
+
+    #[clap(
+        long,
+        global = true,
+        value_enum,
+        default_value = "auto",
+        help = "The automaton that matches many patterns at once, such as the lines of freq lookup."
+    )]
+    automaton: engine::Automaton,
+
+    #[clap(
+        long,
+        global = true,
+        help = "Don't skip ahead to where one of many patterns may start before running the automaton, which is faster when there are so many patterns that most places may start one."
+    )]
+    no_prefilter: bool,
+
+    #[clap(
+        long,
+        global = true,
+        help = "Say on stderr how long reading and counting took, how long each waited for the other, and how many chunks were queued, to tell whether freq is I/O-bound or CPU-bound."
+    )]
+    stats: bool,
+
+    #[clap(
+        long,
+        global = true,
+        value_name = "FILE",
+        help = "Read defaults from this config file instead of ~/.config/freq/config.toml. Flags override them."
+    )]
+    config: Option<PathBuf>,
+
+    #[clap(
+        long,
+        global = true,
+        value_enum,
+        value_name = "WHEN",
+        default_value = "auto",
+        help = "Whether to highlight counts, dim zero counts, and make totals and errors stand out."
+    )]
+    color: color::ColorChoice,
+
+    #[clap(
+        short,
+        long,
+        global = true,
+        action = clap::ArgAction::Count,
+        help = "Say which files are read or skipped and why on stderr. -vv also says how they're read and how long each stage took, and -vvv reports every chunk read. RUST_LOG, such as RUST_LOG=freq::input=trace, overrides the level for the modules it names."
+    )]
+    verbose: u8,
+
+    #[clap(
+        long,
+        global = true,
+        help = "Stop at the first input that can't be read, without printing results, instead of skipping it and listing it at the end."
+    )]
+    strict: bool,
+
+    #[clap(
+        long,
+        global = true,
+        value_name = "DURATION",
+        value_parser = timecount::parse_timeout,
+        conflicts_with = "auto_tune",
+        help = "Give up on an input that takes longer than this (500ms, 30s, 5m, ...) to read, such as one on a hung network mount, and skip it as unreadable. Inputs are then always read on a thread of their own."
+    )]
+    file_timeout: Option<Duration>,
+
+    #[clap(
+        long,
+        global = true,
+        value_name = "N",
+        value_parser = table::parse_size,
+        help = "Deal with lines longer than N bytes as --long-lines says, and say how many there were, so that one huge line, such as a 10GB JSON blob, is never held in memory whole. Applies to freq lines, fields and json and to the record modes, where it limits records. Accepts K, M and G suffixes."
+    )]
+    max_line_length: Option<usize>,
+
+    #[clap(
+        long,
+        global = true,
+        value_enum,
+        value_name = "POLICY",
+        default_value = "skip",
+        requires = "max_line_length",
+        help = "What to do with lines longer than --max-line-length."
+    )]
+    long_lines: linelimit::Policy,
+
+    #[clap(
+        long,
+        global = true,
+        value_name = "NAME",
+        help = "Use the arguments of this profile from the [profiles] table of the config file, as if they were given in its place. Options also given on the command line override the profile's."
+    )]
+    profile: Option<String>,
+
+    #[clap(
+        short,
+        long,
+        global = true,
+        value_name = "FILE",
+        help = "Write the results to FILE instead of stdout. The file is only replaced once the results are complete, so it is never seen half-written."
+    )]
+    output: Option<PathBuf>,
+
+    #[clap(
+        long,
+        global = true,
+        requires = "output",
+        help = "Add the results to the end of the output file instead of replacing it."
+    )]
+    append: bool,
+
+    #[clap(
+        long,
+        global = true,
+        value_enum,
+        value_name = "STYLE",
+        help = "How to show the paths of inputs in results. If not provided, they are shown as they were given."
+    )]
+    path_style: Option<names::PathStyle>,
+
+    #[clap(
+        long,
+        global = true,
+        value_name = "GLOB",
+        value_parser = glob::Glob::parse,
+        help = "Don't search directories with names that match GLOB, such as node_modules or '.*', when searching directories given as inputs. They are skipped without reading anything in them. Can be given more than once."
+    )]
+    exclude_dir: Vec<glob::Glob>,
+
+    #[clap(
+        long,
+        global = true,
+        help = "Leave out the results for each input, even with -H, --rate or --percent-lines, and the rows for each file of freq levels and freq matrix, and only print the totals."
+    )]
+    summary_only: bool,
+
+    #[clap(
+        long,
+        global = true,
+        help = "Don't search directories on other file systems than the directory given as an input they are in, such as /proc or network mounts under /, as find -xdev does."
+    )]
+    one_file_system: bool,
+
+    #[arg(
+        long,
+        value_enum,
+        group = "builtin_only",
+        help = "Treat the input as sequence data and only count matches in sequences, not in headers or qualities. Matches may span the line breaks of multi-line FASTA sequences."
+    )]
+    seq_format: Option<seq::Format>,
+
+    #[arg(
+        long,
+        requires = "seq_format",
+        help = "Print the count for each record, named by the first word of its header, before the total."
+    )]
+    per_record: bool,
+
+    #[arg(
+        long,
+        value_name = "Q",
+        requires = "seq_format",
+        help = "Don't count matches that overlap a base with a Phred quality score below Q. Needs --seq-format fastq."
+    )]
+    min_qual: Option<u8>,
+
+    #[arg(
+        long,
+        value_name = "START-END",
+        value_parser = split::ByteRange::parse,
+        conflicts_with_all = ["cache", "seq_format", "record_sep", "null_data", "record_size", "mode"],
+        help = "Only count the bytes from START up to END of each input, as in a plan from freq split. Offsets start from 0. Ranges start lines, so matches that can span lines, such as of records or multi-line FASTA, can't be counted this way."
+    )]
+    range: Option<split::ByteRange>,
+
+    #[arg(
+        long,
+        value_enum,
+        value_name = "ENCODING",
+        default_value = "raw",
+        conflicts_with = "expr",
+        help = "How to encode the pattern, and the --and and --not patterns, into the bytes that are searched for, such as utf16le for files written by Windows tools."
+    )]
+    pattern_encoding: encoding::PatternEncoding,
+
+    #[arg(
+        long,
+        help = "Also count the reverse complement of the (DNA) pattern, in the same pass. Prints the count of each, then their sum. A palindromic pattern is only counted as itself."
+    )]
+    revcomp: bool,
+
+    #[arg(
+        long,
+        value_name = "K",
+        group = "mode",
+        group = "builtin_only",
+        help = "Also count runs of bytes that the pattern turns into with at most K substitutions, insertions and deletions, such as misspellings in OCR'd text or errors in sequencing reads. The pattern must be at most 64 bytes, and longer than K."
+    )]
+    max_errors: Option<usize>,
+
+    #[arg(
+        long,
+        value_name = "K",
+        group = "mode",
+        group = "builtin_only",
+        help = "Also count runs of bytes as long as the pattern that differ from it in at most K bytes, such as barcodes and primers with sequencing errors. Faster than --max-errors, which also allows insertions and deletions. K must be less than the pattern's length."
+    )]
+    max_mismatches: Option<usize>,
+
+    #[arg(
+        long,
+        value_name = "A..B/N",
+        value_parser = NearSpec::parse,
+        group = "mode",
+        group = "builtin_only",
+        help = "Instead of a pattern, count the places where A is followed by B starting at most N bytes after it ends, across lines too, such as 'req=42..ERROR/200' for a request that ends in an error."
+    )]
+    near: Option<NearSpec>,
+
+    #[arg(
+        short = 'x',
+        long,
+        conflicts_with_all = ["mode", "ratio", "revcomp", "cache", "baseline", "estimate", "rate", "sample", "show_matches", "seq_format", "time_bucket", "and_patterns", "not_patterns", "record_sep", "null_data", "record_size", "per_record_stats", "percent_lines", "columns", "expr"],
+        group = "builtin_only",
+        help = "Count the lines that are exactly the pattern, as with grep -x, instead of its matches, such as to check names against an allowlist. A \\r before the newline is part of the line."
+    )]
+    line_regexp: bool,
+
+    #[arg(
+        long,
+        value_name = "PATTERN2",
+        conflicts_with_all = ["mode", "revcomp", "cache", "baseline", "estimate", "rate", "sample", "show_matches", "seq_format", "time_bucket", "and_patterns", "not_patterns", "record_sep", "null_data", "record_size", "per_record_stats", "percent_lines", "columns", "expr"],
+        group = "builtin_only",
+        help = "Also count PATTERN2 in the same pass, and print both counts and then the pattern's count divided by PATTERN2's, such as errors per request or cache hits per lookup. The two are counted on their own, so their matches may overlap. With --kafka or --syslog-listen, each interval's line has both counts and the ratio."
+    )]
+    ratio: Option<OsString>,
+
+    #[arg(
+        long,
+        value_name = "N",
+        conflicts_with = "seq_format",
+        help = "Also print N matches chosen at random, with their file, byte offset and surrounding bytes, to check what is being counted."
+    )]
+    sample: Option<usize>,
+
+    #[arg(
+        long,
+        value_name = "K",
+        conflicts_with_all = ["sample", "seq_format", "and_patterns", "not_patterns", "record_sep", "null_data", "record_size", "per_record_stats", "columns", "expr", "time_bucket"],
+        help = "Also print the first K matches, with their file, byte offset and surrounding bytes, to check what is being counted."
+    )]
+    show_matches: Option<usize>,
+
+    #[arg(
+        long,
+        alias = "sample-context",
+        value_name = "N",
+        default_value = "20",
+        value_parser = sample::Context::parse,
+        help = "How much to show on each side of a sampled or shown match: N bytes, or N lines besides the match's own, like 2l. Context that is cut short is marked with '…'."
+    )]
+    context: sample::Context,
+
+    #[arg(
+        long,
+        help = "Copy the input to stdout unchanged while counting, and print the results to stderr, to count what passes through a pipeline."
+    )]
+    tee: bool,
+
+    #[arg(
+        long,
+        value_name = "DIR",
+        conflicts_with_all = ["sample", "show_matches", "tee", "seq_format", "time_bucket", "and_patterns", "not_patterns", "record_sep", "null_data", "record_size", "per_record_stats", "columns", "expr"],
+        help = "Keep the counts for each file in DIR, and reuse them while the file's size and modification time are unchanged, so that only new or changed files are searched."
+    )]
+    cache: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "STATE_FILE",
+        conflicts_with_all = ["cache", "range", "rate", "wc", "mode", "ratio", "sample", "show_matches", "tee", "seq_format", "time_bucket", "and_patterns", "not_patterns", "record_sep", "null_data", "record_size", "per_record_stats", "percent_lines", "columns", "expr"],
+        help = "Keep how far each file was counted and its counts so far in STATE_FILE, and only read what was appended to it since on the next run, such as for counting busy logs from cron. Each file is counted up to its last newline, and a file that was truncated or replaced is counted from the start again. The counts printed are for all of each file."
+    )]
+    incremental: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "NAMES",
+        value_delimiter = ',',
+        requires = "input",
+        conflicts_with_all = ["cache", "incremental", "range", "journal", "estimate", "mode", "ratio", "tee", "seq_format", "time_bucket", "and_patterns", "not_patterns", "record_sep", "null_data", "record_size", "per_record_stats", "percent_lines", "columns", "expr"],
+        help = "Only count matches within these sections of each file, which must be an ELF or PE executable or library, such as .rodata,.data for its strings and data rather than its code. Each section is counted on its own, so no match spans two."
+    )]
+    sections: Vec<String>,
+
+    #[arg(
+        long,
+        conflicts_with_all = ["input", "cache", "incremental", "range", "estimate", "baseline"],
+        help = "Count matches in the messages of the systemd journal on this machine, each as a line of its own, instead of in files, without piping them in from journalctl. Needs freq built with the journal feature."
+    )]
+    journal: bool,
+
+    #[arg(
+        long,
+        value_name = "UNIT",
+        requires = "journal",
+        help = "With --journal, only count the messages logged by this systemd unit, such as nginx.service, or nginx for short. Can be given more than once."
+    )]
+    unit: Vec<String>,
+
+    #[arg(
+        long,
+        value_name = "TIME",
+        requires = "journal",
+        value_parser = journal::parse_since,
+        help = "With --journal, only count the messages logged since TIME: how long ago (30m, 2h, 1d, ...), or a date and time in UTC, such as 2024-05-01 or \"2024-05-01 12:00:00\"."
+    )]
+    since: Option<i64>,
+
+    #[arg(
+        long,
+        value_name = "BROKERS",
+        requires = "topics",
+        conflicts_with_all = ["input", "journal", "cache", "incremental", "range", "estimate", "baseline", "tee", "rate", "sample", "show_matches", "wc", "time_bucket", "seq_format", "line_regexp", "and_patterns", "not_patterns", "record_sep", "null_data", "record_size", "per_record_stats", "percent_lines", "columns", "expr"],
+        help = "Instead of reading files, consume messages from these Kafka brokers (host:port, separated by commas), and keep printing the count in the messages of each --emit-every interval. Offsets are committed once the counts of their messages are printed. Needs freq built with the kafka feature."
+    )]
+    kafka: Option<String>,
+
+    #[arg(
+        long = "topic",
+        value_name = "TOPIC",
+        requires = "kafka",
+        help = "With --kafka, a topic to consume. Can be given more than once."
+    )]
+    topics: Vec<String>,
+
+    #[arg(
+        long,
+        value_name = "GROUP",
+        requires = "kafka",
+        help = "With --kafka, the consumer group to consume in, whose committed offsets the next run starts from. Defaults to freq."
+    )]
+    group: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "ADDR",
+        conflicts_with_all = ["input", "journal", "kafka", "cache", "incremental", "range", "estimate", "baseline", "tee", "rate", "sample", "show_matches", "wc", "time_bucket", "seq_format", "line_regexp", "and_patterns", "not_patterns", "record_sep", "null_data", "record_size", "per_record_stats", "percent_lines", "columns", "expr"],
+        help = "Instead of reading files, listen for syslog messages on this address, such as 0.0.0.0:5514, over both UDP and TCP, and keep printing the count in the messages of each --emit-every interval. Over TCP, each message either starts with its length and a space, or ends with a newline."
+    )]
+    syslog_listen: Option<std::net::SocketAddr>,
+
+    #[arg(
+        long,
+        value_name = "DURATION",
+        value_parser = timecount::parse_duration,
+        default_value = "1m",
+        help = "With --kafka or --syslog-listen, how often to print the count in the messages since the last one (30s, 5m, 1h, ...)."
+    )]
+    emit_every: i64,
+
+    #[arg(
+        long,
+        value_name = "DURATION",
+        value_parser = timecount::parse_duration,
+        requires = "ratio",
+        requires = "streams",
+        help = "With --ratio and --kafka or --syslog-listen, print the ratio over the last DURATION (5m, 1h, ...) each interval, rather than over the interval alone, as a rolling error rate. It is rounded up to whole --emit-every intervals. The counts are still those of each interval."
+    )]
+    window: Option<i64>,
+
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        default_value = "text",
+        help = "How to print the results. json prints an object with the count in each file and the total, which can be saved for --baseline. jsonl prints an object as each file starts and finishes or fails, then the total, so that a long run can be followed as it goes. msgpack is the same as json, in MessagePack. table lines up the columns of text, such as those of frequency tables and of results for each file, and cuts long paths down to fit the terminal."
+    )]
+    format: report::Format,
+
+    #[arg(
+        long,
+        global = true,
+        help = "With --format table, draw lines around the table and between its columns."
+    )]
+    boxed: bool,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = ["format", "revcomp", "sample", "show_matches", "seq_format", "time_bucket", "and_patterns", "not_patterns", "record_sep", "null_data", "record_size", "per_record_stats", "columns", "expr"],
+        help = "Compare the count in each file with a report saved from --format json, printing the old and new count of each file that changed and then the totals. Exits with status 1 if any count went up."
+    )]
+    baseline: Option<PathBuf>,
+
+    #[arg(
+        long,
+        conflicts_with_all = ["cache", "range", "tee", "format", "baseline", "revcomp", "sample", "show_matches", "seq_format", "time_bucket", "and_patterns", "not_patterns", "record_sep", "null_data", "record_size", "per_record_stats", "columns", "expr"],
+        group = "builtin_only",
+        help = "Instead of reading every block of the input files, read a random sample of them and print an estimate of the total count with a 95% confidence interval, for a quick look at a very large dataset. Only files that can seek can be sampled."
+    )]
+    estimate: bool,
+
+    #[arg(
+        long,
+        value_name = "FRACTION",
+        default_value = "0.01",
+        value_parser = estimate::parse_fraction,
+        requires = "estimate",
+        help = "The fraction of the blocks of each file to read with --estimate, greater than 0 and at most 1. Blocks are the size of the buffer."
+    )]
+    sample_fraction: f64,
+
+    #[arg(
+        long = "and",
+        value_name = "PATTERN",
+        conflicts_with_all = ["seq_format", "sample", "revcomp", "time_bucket"],
+        group = "builtin_only",
+        help = "Instead of counting matches, count the lines that contain both the pattern and this one. Can be given more than once, to require every pattern."
+    )]
+    and_patterns: Vec<OsString>,
+
+    #[arg(
+        long = "not",
+        value_name = "PATTERN",
+        conflicts_with_all = ["seq_format", "sample", "revcomp", "time_bucket"],
+        group = "builtin_only",
+        help = "Instead of counting matches, count the lines that contain the pattern but not this one. Can be given more than once, to exclude lines with any of them."
+    )]
+    not_patterns: Vec<OsString>,
+
+    #[arg(
+        long,
+        value_name = "SEP",
+        value_parser = records::RecordSep::parse,
+        conflicts_with_all = ["seq_format", "sample", "revcomp", "time_bucket"],
+        group = "builtin_only",
+        help = "Split the input into records ending with SEP instead of lines, and count the records that contain the pattern. SEP can be several bytes, such as '\\n\\n' for paragraphs, and accepts escapes like \\0, \\t and \\x1e. Applies to --and, --not and --expr too."
+    )]
+    record_sep: Option<records::RecordSep>,
+
+    #[arg(
+        short = 'z',
+        long,
+        conflicts_with_all = ["record_sep", "seq_format", "sample", "revcomp", "time_bucket"],
+        group = "builtin_only",
+        help = "Like --record-sep '\\0', for NUL-separated data such as the output of find -print0."
+    )]
+    null_data: bool,
+
+    #[arg(
+        long,
+        value_name = "BYTES",
+        value_parser = clap::value_parser!(u32).range(1..),
+        conflicts_with_all = ["record_sep", "null_data", "seq_format", "sample", "revcomp", "time_bucket"],
+        group = "builtin_only",
+        help = "Split the input into records of this many bytes instead of lines, for headerless binary formats, and count the records that contain the pattern. The last record may be shorter."
+    )]
+    record_size: Option<u32>,
+
+    #[arg(
+        long,
+        requires = "record_size",
+        conflicts_with = "expr",
+        help = "With --record-size, also print how many matches start at each offset within the counted records."
+    )]
+    offsets: bool,
+
+    #[arg(
+        long,
+        conflicts_with_all = ["expr", "seq_format", "sample", "revcomp", "time_bucket"],
+        group = "builtin_only",
+        help = "Count records, as with --and, --not, --record-sep, -z or --record-size, and print how many there are, how many contain the pattern, the matches in them and the most matches in any one record."
+    )]
+    per_record_stats: bool,
+
+    #[arg(
+        long,
+        conflicts_with_all = ["expr", "offsets", "per_record_stats", "mode", "cache", "baseline", "estimate", "rate", "seq_format", "sample", "show_matches", "revcomp", "time_bucket"],
+        group = "builtin_only",
+        help = "Print how many lines contain the pattern, out of how many there are, and what percentage that is, for each input and then over all of them, such as the share of requests that hit an endpoint. With --and, --not or records, counts those instead."
+    )]
+    percent_lines: bool,
+
+    #[arg(
+        short = 'H',
+        long,
+        conflicts_with_all = ["line_regexp", "ratio", "revcomp", "baseline", "estimate", "rate", "seq_format", "time_bucket", "and_patterns", "not_patterns", "record_sep", "null_data", "record_size", "per_record_stats", "percent_lines", "columns", "expr"],
+        help = "Print the count in each input followed by its name, as grep -cH does, and then the total, even if there is only one input."
+    )]
+    with_filename: bool,
+
+    #[arg(
+        long,
+        help = "Print what is printed for each input, such as with -H or --format jsonl, in the order the inputs were given or found in, instead of as each is counted. Inputs are counted --jobs at a time, so without it, those that finish first come first."
+    )]
+    ordered: bool,
+
+    #[arg(
+        long,
+        conflicts_with_all = ["line_regexp", "ratio", "revcomp", "baseline", "estimate", "seq_format", "time_bucket", "and_patterns", "not_patterns", "record_sep", "null_data", "record_size", "per_record_stats", "percent_lines", "columns", "expr"],
+        help = "After the total, print how many inputs have matches, how many have none, and how many couldn't be read, without listing them."
+    )]
+    count_files: bool,
+
+    #[arg(
+        long,
+        conflicts_with = "with_filename",
+        help = "Leave the names of inputs out of results for each input, such as those of --rate and --percent-lines, as grep -h does."
+    )]
+    no_filename: bool,
+
+    #[arg(
+        long,
+        value_name = "GROUP",
+        value_parser = group::GroupBy::parse,
+        conflicts_with_all = ["with_filename", "line_regexp", "ratio", "revcomp", "baseline", "estimate", "rate", "seq_format", "time_bucket", "and_patterns", "not_patterns", "record_sep", "null_data", "record_size", "per_record_stats", "percent_lines", "columns", "expr"],
+        help = "Add up the counts of the inputs by the directory each is in, and print the count of each directory instead of a total alone. dir:N adds them up by the directory N levels down that each is under instead, such as dir:3 for /var/log/SERVICE, and ext by the extension of each file name, such as .c, with (none) for files without one."
+    )]
+    group_by: Option<group::GroupBy>,
+
+    #[arg(
+        long,
+        value_name = "RANGE",
+        value_parser = records::Columns::parse,
+        conflicts_with_all = ["expr", "seq_format", "sample", "revcomp", "time_bucket"],
+        group = "builtin_only",
+        help = "Only count matches that start within this range of byte columns of their line, or record, such as 20-80, 20- or -80. Columns are numbered from 1, as with cut."
+    )]
+    columns: Option<records::Columns>,
+
+    #[arg(
+        long,
+        value_name = "EXPR",
+        value_parser = expr::Query::parse,
+        conflicts_with_all = ["and_patterns", "not_patterns", "seq_format", "sample", "revcomp", "time_bucket"],
+        group = "builtin_only",
+        help = "Instead of a pattern, count the lines for which a boolean expression over quoted patterns holds, such as '(\"ERROR\" | \"FATAL\") & !\"retryable\"'."
+    )]
+    expr: Option<expr::Query>,
+
+    #[arg(
+        long,
+        value_enum,
+        value_name = "UNIT",
+        conflicts_with_all = ["cache", "baseline", "estimate", "revcomp", "seq_format", "time_bucket", "and_patterns", "not_patterns", "record_sep", "null_data", "record_size", "per_record_stats", "columns", "expr"],
+        help = "Also print the count in each input divided by how much of it was read, then the total, so that inputs of very different sizes can be compared. MB and GB are 2^20 and 2^30 bytes."
+    )]
+    rate: Option<rate::Rate>,
+
+    #[arg(
+        long,
+        value_name = "DURATION",
+        value_parser = timecount::parse_duration,
+        conflicts_with_all = ["seq_format", "sample"],
+        group = "builtin_only",
+        help = "Count matches per time bucket (30s, 5m, 1h, 1d, ...), by the timestamp on each line, before the total. Lines without a timestamp belong to the one before them."
+    )]
+    time_bucket: Option<i64>,
+
+    #[arg(
+        long,
+        value_name = "FORMAT",
+        value_parser = timecount::TimeFormat::parse,
+        requires = "time_bucket",
+        help = "The format of the timestamps, such as '%Y-%m-%dT%H:%M:%S'. Supports %Y, %m, %b, %d, %e, %H, %M and %S. If not provided, common log formats are detected."
+    )]
+    time_format: Option<timecount::TimeFormat>,
+
+    #[arg(
+        long,
+        group = "wc",
+        help = "Also print how many lines the inputs have, counted in the same pass as the matches, such as for matches per line without a second pass with wc -l. As with wc, a last line without a newline isn't counted."
+    )]
+    lines: bool,
+
+    #[arg(
+        long,
+        group = "wc",
+        help = "Also print how many words the inputs have, counted in the same pass as the matches. As with wc -w, words are separated by ASCII whitespace."
+    )]
+    words: bool,
+
+    #[arg(
+        long,
+        group = "wc",
+        help = "Also print how many bytes were read, counted in the same pass as the matches."
+    )]
+    bytes: bool,
 }
 
-struct NeedleCounter {
-    // The needle we are looking for.
-    needle: Vec<u8>,
+#[derive(Subcommand)]
+enum Command {
+    /// Count how often each word occurs.
+    Words(words::WordsArgs),
+
+    /// Count how often each distinct line occurs, like `sort | uniq -c | sort -rn`.
+    Lines(lines::LinesArgs),
 
-    // How many needles we have found.
-    count: usize,
+    /// Count how often each byte value occurs.
+    Bytes(bytes::BytesArgs),
 
-    // For holding intermediate data.
-    // We keep it around to avoid reallocating it.
-    // It is at most n - 1 bytes long.
-    tmp_buf: Vec<u8>,
+    /// Count how often each Unicode character occurs.
+    Chars(chars::CharsArgs),
 
-    // The searcher we use to find needles.
-    finder: Finder<'static>,
+    /// Count how often each value of a delimited field occurs, like `cut -f | sort | uniq -c`.
+    Fields(fields::FieldsArgs),
+
+    /// Count how often each value at a path occurs in newline-delimited JSON.
+    Json(json::JsonArgs),
+
+    /// Count canonical k-mers in DNA sequences.
+    #[cfg(not(target_os = "wasi"))]
+    Kmers(kmers::KmersArgs),
+
+    /// Count log levels (TRACE, DEBUG, INFO, WARN, ERROR and FATAL) in each file.
+    Levels(levels::LevelsArgs),
+
+    /// Print statistics about the input: sizes, words, byte entropy and line endings.
+    Stats(stats::StatsArgs),
+
+    /// Print the distribution of line lengths: the shortest, mean and longest, percentiles, and a histogram, such as to spot malformed records or a truncated dump.
+    Linelens(linelens::LinelensArgs),
+
+    /// Compare the counts of patterns in two files or directories, such as logs from before and after a change.
+    Compare(compare::CompareArgs),
+
+    /// Count patterns in each file and print a table with a row for each file and a column for each pattern, or a CSV file with --csv.
+    Matrix(matrix::MatrixArgs),
+
+    /// Count how many times each line of a file appears in the inputs, such as which of thousands of identifiers are still in a data dump, in one pass.
+    Lookup(lookup::LookupArgs),
+
+    /// Print a man page for freq in roff, generated from its options.
+    Man(man::ManArgs),
+
+    /// Split a file into ranges of about the same size that start lines, for counting each with --range, such as on separate machines.
+    Split(split::SplitArgs),
+
+    /// Add up the reports from --format json of parts of the same inputs, such as the ranges from freq split.
+    Merge(merge::MergeArgs),
 }
 
-impl NeedleCounter {
-    pub fn new(needle: &[u8]) -> Self {
-        NeedleCounter {
-            needle: needle.to_vec(),
-            count: 0,
-            tmp_buf: Vec::new(),
-            finder: Finder::new(needle).into_owned(),
+fn main() {
+    let started = std::time::Instant::now();
+    let (mut args, config) = parse_args();
+    log::init(args.verbose);
+    // Before any threads are started, so that they all inherit it.
+    if let Some(cpus) = &args.cpu_list {
+        if let Err(e) = cpus.pin() {
+            eprintln!("freq: can't pin to --cpu-list: {}", e);
         }
     }
-
-    pub fn count(&self) -> usize {
-        self.count
+    if args.jobs == Some(1) {
+        input::set_single_threaded();
     }
-
-    fn write(&mut self, buf: &[u8]) {
-        if buf.is_empty() {
-            return;
+    input::set_pipeline(args.pipeline);
+    engine::set_automaton(args.automaton, !args.no_prefilter);
+    if let Err(e) = engine::set(args.engine) {
+        let mut cmd = Args::command();
+        cmd.error(ErrorKind::InvalidValue, e).exit();
+    }
+    if args.journal && !cfg!(feature = "journal") {
+        let mut cmd = Args::command();
+        cmd.error(
+            ErrorKind::InvalidValue,
+            "freq was built without the journal feature, so it can't read --journal",
+        )
+        .exit();
+    }
+    if args.kafka.is_some() && !cfg!(feature = "kafka") {
+        let mut cmd = Args::command();
+        cmd.error(
+            ErrorKind::InvalidValue,
+            "freq was built without the kafka feature, so it can't read --kafka",
+        )
+        .exit();
+    }
+    if args.syslog_listen.is_some() && cfg!(target_os = "wasi") {
+        let mut cmd = Args::command();
+        cmd.error(
+            ErrorKind::InvalidValue,
+            "WASI has no threads to receive messages on, so freq can't --syslog-listen there",
+        )
+        .exit();
+    }
+    if args.stats {
+        metrics::enable();
+    }
+    if let Some(max_memory) = args.max_memory {
+        memory::set_limit(max_memory);
+        args.buffer_size = memory::buffer_size(args.buffer_size);
+    }
+    // After --max-memory, which caps the sizes tried.
+    if args.auto_tune {
+        tune::enable();
+    }
+    let mut out = args.output();
+    // MessagePack is binary, and color would corrupt it.
+    if args.color.enabled(out.is_terminal()) && args.format != report::Format::Msgpack {
+        color::enable();
+    }
+    if args.strict {
+        input::set_strict();
+    }
+    if let Some(timeout) = args.file_timeout {
+        input::set_file_timeout(timeout);
+    }
+    if let Some(style) = args.path_style {
+        names::set_style(style);
+    }
+    if args.no_filename {
+        names::hide();
+    }
+    if args.summary_only {
+        names::summarize();
+    }
+    if let Some(max) = args.max_line_length {
+        linelimit::set(max, args.long_lines);
+    }
+    walk::exclude_dirs(args.exclude_dir.clone());
+    if args.one_file_system {
+        walk::set_one_file_system();
+    }
+    // There is no pattern argument, so the first argument is an input.
+    if args.near.is_some() || args.expr.is_some() {
+        if let Some(path) = args.pattern.take() {
+            args.input.insert(0, PathBuf::from(path));
         }
+    }
+    if args.command.is_none() {
+        let _span = debug_span!("walk").entered();
+        args.input = walk::files(&args.input);
+    }
 
-        let n = self.needle.len();
+    let mut failed = false;
+    let span = debug_span!("count").entered();
+    match args.command {
+        Some(Command::Words(words_args)) => words::run(words_args, args.buffer_size, &mut out),
+        Some(Command::Lines(lines_args)) => lines::run(lines_args, args.buffer_size, &mut out),
+        Some(Command::Bytes(bytes_args)) => bytes::run(bytes_args, args.buffer_size, &mut out),
+        Some(Command::Chars(chars_args)) => chars::run(chars_args, args.buffer_size, &mut out),
+        Some(Command::Fields(fields_args)) => fields::run(fields_args, args.buffer_size, &mut out),
+        Some(Command::Json(json_args)) => json::run(json_args, args.buffer_size, &mut out),
+        #[cfg(not(target_os = "wasi"))]
+        Some(Command::Kmers(mut kmers_args)) => {
+            kmers_args.threads = kmers_args.threads.or(args.jobs.map(|n| n as usize));
+            kmers::run(kmers_args, args.buffer_size, &mut out)
+        }
+        Some(Command::Levels(levels_args)) => levels::run(levels_args, args.buffer_size, &mut out),
+        Some(Command::Stats(stats_args)) => stats::run(stats_args, args.buffer_size, &mut out),
+        Some(Command::Linelens(linelens_args)) => {
+            linelens::run(linelens_args, args.buffer_size, &mut out)
+        }
+        Some(Command::Compare(mut compare_args)) => {
+            add_pattern_sets(&config, &compare_args.sets, &mut compare_args.patterns);
+            compare::run(compare_args, args.buffer_size, &mut out)
+        }
+        Some(Command::Matrix(mut matrix_args)) => {
+            add_pattern_sets(&config, &matrix_args.sets, &mut matrix_args.patterns);
+            matrix::run(matrix_args, args.buffer_size, &mut out)
+        }
+        Some(Command::Lookup(lookup_args)) => lookup::run(lookup_args, args.buffer_size, &mut out),
+        Some(Command::Man(man_args)) => man::run(man_args, Args::command(), &mut out),
+        Some(Command::Split(split_args)) => split::run(split_args, &mut out),
+        Some(Command::Merge(merge_args)) => merge::run(merge_args, &mut out),
+        None => failed = count_pattern(&args, &mut out),
+    }
+    span.exit();
+    debug_span!("write").in_scope(|| out.commit().expect("failed to write output"));
+    debug!("finished in {:.3?}", started.elapsed());
+    if args.stats {
+        metrics::print(&mut io::stderr()).expect("failed to write stats");
+    }
+    match linelimit::long_lines() {
+        (0, _) => {}
+        (n, limit) if limit.policy() == linelimit::Policy::Truncate => {
+            eprintln!("freq: truncated {} lines to {} bytes", n, limit.max())
+        }
+        (n, limit) => eprintln!(
+            "freq: skipped {} lines longer than {} bytes",
+            n,
+            limit.max()
+        ),
+    }
+    let malformed = json::malformed();
+    if malformed > 0 {
+        eprintln!("freq: skipped {} malformed JSON records", malformed);
+    }
+    let errors = input::take_errors();
+    if !errors.is_empty() {
+        let color = args.color.enabled(io::stderr().is_terminal());
+        eprintln!(
+            "{} skipped {} unreadable inputs:",
+            color::error_prefix(color),
+            errors.len()
+        );
+        for e in errors {
+            eprintln!("  {}", e);
+        }
+        std::process::exit(2);
+    }
+    if malformed > 0 {
+        std::process::exit(2);
+    }
+    if failed {
+        std::process::exit(1);
+    }
+}
 
-        // Fast case - if the needle has length 1 we can use a simd loop.
-        if n == 1 {
-            let b = self.needle[0];
-            self.count += bytecount::count(&buf, b);
-            return;
+// Parses the command line, with defaults from the config file and the environment.
+fn parse_args() -> (Args, config::Config) {
+    // The config file has to be read before parsing, since it changes the defaults.
+    let argv: Vec<OsString> = std::env::args_os().collect();
+    let mut path = None;
+    let mut rest = argv.iter().skip(1);
+    while let Some(arg) = rest.next() {
+        if arg == "--" {
+            break;
+        } else if arg == "--config" {
+            path = rest.next().map(PathBuf::from);
+        } else if let Some(p) = arg.as_encoded_bytes().strip_prefix(b"--config=") {
+            path = Some(PathBuf::from(String::from_utf8_lossy(p).into_owned()));
         }
+    }
+    let mut config = config::Config::load(path.as_deref()).unwrap_or_else(|e| {
+        let mut cmd = Args::command();
+        cmd.error(ErrorKind::Io, e).exit();
+    });
+    config.apply_env();
 
-        // The number of bytes in the buffer that we have moved to the tmp buffer.
-        let mut num_buf_bytes = 0;
+    let mut cmd = Args::command();
+    for (key, value) in &config.settings {
+        // Defaults have to live as long as the command.
+        let value: &'static str = Box::leak(value.clone().into_boxed_str());
+        cmd = match key.as_str() {
+            "buffer_size" => cmd.mut_arg("buffer_size", |a| a.default_value(value)),
+            "format" => cmd.mut_arg("format", |a| a.default_value(value)),
+            "jobs" => cmd.mut_arg("jobs", |a| a.default_value(value)),
+            "ordered" => cmd.mut_arg("ordered", |a| a.default_value(value)),
+            _ => unreachable!("unknown settings are rejected when parsing"),
+        };
+    }
+    for (key, values) in &config.array_settings {
+        let values: Vec<&'static str> = values
+            .iter()
+            .map(|v| &*Box::leak(v.clone().into_boxed_str()))
+            .collect();
+        cmd = match key.as_str() {
+            "exclude_dir" => cmd.mut_arg("exclude_dir", |a| a.default_values(values)),
+            _ => unreachable!("unknown settings are rejected when parsing"),
+        };
+    }
+    let argv = expand_profiles(argv, &config);
+    let matches = cmd.get_matches_from(argv);
+    let args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    check_format(
+        &args,
+        matches.value_source("format") == Some(ValueSource::CommandLine),
+    );
+    (args, config)
+}
 
-        if !self.tmp_buf.is_empty() {
-            // Add into the tmp buffer until it is at most 2 * n - 1 bytes long.
-            let y_len = (2 * n - 1)
-                .saturating_sub(self.tmp_buf.len())
-                .min(buf.len());
-            let y = &buf[..y_len];
-            num_buf_bytes = y_len;
-            self.tmp_buf.extend(y);
+// Exits if the results can't be printed in the format that was asked for. Reports only have the
+// count in each file, and subcommands and some modes print more than that. A default format
+// from the config file is left to the modes that can print it.
+fn check_format(args: &Args, explicit: bool) {
+    let mut cmd = Args::command();
+    if args.boxed && args.format != report::Format::Table {
+        cmd.error(ErrorKind::ArgumentConflict, "--boxed needs --format table")
+            .exit();
+    }
+    if !explicit || matches!(args.format, report::Format::Text | report::Format::Table) {
+        return;
+    }
+    let text_only = [
+        (args.command.is_some(), "a subcommand"),
+        (args.revcomp, "--revcomp"),
+        (args.sample.is_some(), "--sample"),
+        (args.show_matches.is_some(), "--show-matches"),
+        (args.seq_format.is_some(), "--seq-format"),
+        (args.time_bucket.is_some(), "--time-bucket"),
+        (!args.and_patterns.is_empty(), "--and"),
+        (!args.not_patterns.is_empty(), "--not"),
+        (args.record_bounds().is_some(), "records"),
+        (args.per_record_stats, "--per-record-stats"),
+        (args.columns.is_some(), "--columns"),
+        (args.expr.is_some(), "--expr"),
+        (args.line_regexp, "--line-regexp"),
+        (args.ratio.is_some(), "--ratio"),
+        (args.percent_lines, "--percent-lines"),
+        (args.rate.is_some(), "--rate"),
+        (args.with_filename, "--with-filename"),
+        (args.no_filename, "--no-filename"),
+        (args.group_by.is_some(), "--group-by"),
+        (args.count_files, "--count-files"),
+        (
+            args.lines || args.words || args.bytes,
+            "--lines, --words and --bytes",
+        ),
+    ];
+    if let Some((_, mode)) = text_only.iter().find(|(used, _)| *used) {
+        let format = args.format.to_possible_value().expect("formats have names");
+        let msg = format!("--format {} can't be used with {}", format.get_name(), mode);
+        cmd.error(ErrorKind::ArgumentConflict, msg).exit();
+    }
+}
 
-            // Check for a needle in the tmp buffer.
-            // This will also count the needle if it is there.
-            let (cut, c) = self.find_in(&self.tmp_buf);
-            self.count += c;
+// Replaces each --profile NAME with the arguments of that profile in the config file. Options
+// given on the command line override the same options in the profile, which are left out.
+fn expand_profiles(argv: Vec<OsString>, config: &config::Config) -> Vec<OsString> {
+    let cmd = Args::command();
+    let mut given = HashSet::new();
+    let mut user_args = argv.iter().skip(1).map(|arg| arg.to_string_lossy());
+    while let Some(arg) = user_args.next() {
+        if arg == "--" {
+            break;
+        }
+        let (ids, takes_next) = options(&cmd, &arg);
+        given.extend(ids);
+        if takes_next {
+            user_args.next();
+        }
+    }
 
-            // Remove any bytes that are before the next needle.
-            self.tmp_buf.drain(..cut);
+    let mut expanded = Vec::with_capacity(argv.len());
+    let mut argv = argv.into_iter();
+    while let Some(arg) = argv.next() {
+        let name = if arg == "--" {
+            expanded.push(arg);
+            break;
+        } else if arg == "--profile" {
+            argv.next()
+        } else if let Some(name) = arg.as_encoded_bytes().strip_prefix(b"--profile=") {
+            Some(OsString::from(String::from_utf8_lossy(name).into_owned()))
+        } else {
+            expanded.push(arg);
+            continue;
+        };
+        // A missing name is left for clap to report.
+        let Some(name) = name else {
+            expanded.push(arg);
+            break;
+        };
+        let Some(profile) = name.to_str().and_then(|n| config.profiles.get(n)) else {
+            let mut cmd = Args::command();
+            let msg = format!(
+                "no profile named '{}' in the config file",
+                name.to_string_lossy()
+            );
+            cmd.error(ErrorKind::ValueValidation, msg).exit();
+        };
+        let mut profile = profile.iter();
+        while let Some(arg) = profile.next() {
+            let (ids, takes_next) = options(&cmd, arg);
+            if ids.iter().any(|id| given.contains(id)) {
+                if takes_next {
+                    profile.next();
+                }
+                continue;
+            }
+            expanded.push(OsString::from(arg));
+            if takes_next {
+                expanded.extend(profile.next().map(OsString::from));
+            }
         }
+    }
+    expanded.extend(argv);
+    expanded
+}
 
-        if num_buf_bytes == buf.len() {
-            return;
+// Returns the ids of the options that `arg` gives, as a long option or a cluster of short ones,
+// and whether the argument after it is the value of the last.
+fn options(cmd: &clap::Command, arg: &str) -> (Vec<String>, bool) {
+    if let Some(long) = arg.strip_prefix("--") {
+        let (name, inline_value) = match long.split_once('=') {
+            Some((name, _)) => (name, true),
+            None => (long, false),
+        };
+        let Some(option) = cmd.get_arguments().find(|a| {
+            a.get_long() == Some(name) || a.get_all_aliases().is_some_and(|v| v.contains(&name))
+        }) else {
+            return (Vec::new(), false);
+        };
+        let takes_next = !inline_value && option.get_action().takes_values();
+        return (vec![option.get_id().to_string()], takes_next);
+    }
+    let mut ids = Vec::new();
+    let Some(shorts) = arg.strip_prefix('-') else {
+        return (ids, false);
+    };
+    for (i, c) in shorts.char_indices() {
+        let Some(option) = cmd.get_arguments().find(|a| a.get_short() == Some(c)) else {
+            break;
+        };
+        ids.push(option.get_id().to_string());
+        // The rest of the cluster, if there is any, is the value.
+        if option.get_action().takes_values() {
+            return (ids, i + c.len_utf8() == shorts.len());
         }
+    }
+    (ids, false)
+}
+
+// Counts the pattern, or the records that a query holds for, as the options say. Returns whether
+// freq should fail.
+fn count_pattern(args: &Args, out: &mut Output) -> bool {
+    if let Some(query) = &args.expr {
+        let bounds = args
+            .record_bounds()
+            .unwrap_or(records::Bounds::Separator(b"\n".to_vec()));
+        let tally = records::count(
+            query,
+            &bounds,
+            None,
+            records::Tally::default(),
+            args.open_inputs(&args.input),
+            args.buffer_size,
+        );
+        tally.print(out).expect("failed to write output");
+        return false;
+    }
+
+    if let Some(near) = &args.near {
+        let first = encode_pattern(args, &near.first);
+        let second = encode_pattern(args, &near.second);
+        let matcher = Near::new(&first, &second, near.gap);
+        return count_needle(args, matcher, near.spec.as_bytes(), out)
+            .expect("failed to write output");
+    }
+
+    let pattern = args.pattern.as_ref().expect("pattern is required");
+    let needle = encode_pattern(args, pattern);
+    let needle = &needle[..];
+    if needle.is_empty() {
+        let mut cmd = Args::command();
+        cmd.error(ErrorKind::ValueValidation, "Pattern must be non-empty")
+            .exit();
+    }
+
+    let other = args.ratio.as_ref().map(|other| encode_pattern(args, other));
+    if other.as_ref().is_some_and(|other| other.is_empty()) {
+        let mut cmd = Args::command();
+        cmd.error(
+            ErrorKind::ValueValidation,
+            "--ratio's pattern must be non-empty",
+        )
+        .exit();
+    }
+
+    // A plan from freq split only keeps lines whole.
+    let newline = |pattern: &[u8]| pattern.contains(&b'\n');
+    if args.range.is_some() && (newline(needle) || other.as_deref().is_some_and(newline)) {
+        let mut cmd = Args::command();
+        cmd.error(
+            ErrorKind::ArgumentConflict,
+            "--range can't count a pattern with a newline, whose matches can span ranges",
+        )
+        .exit();
+    }
+    // So does --incremental, which stops at the end of a line.
+    if args.incremental.is_some() && newline(needle) {
+        let mut cmd = Args::command();
+        cmd.error(
+            ErrorKind::ArgumentConflict,
+            "--incremental can't count a pattern with a newline, whose matches can span runs",
+        )
+        .exit();
+    }
 
-        num_buf_bytes -= self.tmp_buf.len();
-        self.tmp_buf.clear();
-        // Now we can search the rest of the new buffer for the needle.
-        let (mut next_buffer_cut, c) = self.find_in(&buf[num_buf_bytes..]);
-        self.count += c;
-        next_buffer_cut += num_buf_bytes;
+    if args.min_qual.is_some() && args.seq_format != Some(seq::Format::Fastq) {
+        let mut cmd = Args::command();
+        cmd.error(
+            ErrorKind::ArgumentConflict,
+            "--min-qual needs --seq-format fastq",
+        )
+        .exit();
+    }
 
-        // Move the rest of the buffer to the temporary buffer.
-        self.tmp_buf.extend(&buf[next_buffer_cut..]);
+    if args.estimate {
+        let stdin = [PathBuf::from("-")];
+        let paths = if args.input.is_empty() {
+            &stdin[..]
+        } else {
+            &args.input[..]
+        };
+        if paths.iter().any(|path| path.as_os_str() == "-") && input::stdin_file().is_none() {
+            let mut cmd = Args::command();
+            cmd.error(
+                ErrorKind::ArgumentConflict,
+                "--estimate needs files to seek in, and stdin isn't one",
+            )
+            .exit();
+        }
+        let matcher = Literal::new(needle);
+        let estimate =
+            estimate::Estimate::sample(&matcher, paths, args.sample_fraction, args.buffer_size);
+        estimate
+            .print(out, args.buffer_size)
+            .expect("failed to write output");
+        return false;
     }
 
-    // Count needles in the buffer.
-    // Returns (i, c) where `i` is the largest index such that `buf[..i]` does not contain any
-    // needles, and `c` is the number of needles found.
-    fn find_in(&self, buf: &[u8]) -> (usize, usize) {
-        let n = self.needle.len();
-        let mut x = 0;
+    let record_bounds = args.record_bounds();
+    let res = if !args.and_patterns.is_empty()
+        || !args.not_patterns.is_empty()
+        || record_bounds.is_some()
+        || args.per_record_stats
+        || args.percent_lines
+    {
+        let mut required = vec![needle.to_vec()];
+        required.extend(line_patterns(args, &args.and_patterns, "--and"));
+        let excluded = line_patterns(args, &args.not_patterns, "--not");
+        let query = expr::Query::all_but(required, excluded);
+        let bounds = record_bounds.unwrap_or(records::Bounds::Separator(b"\n".to_vec()));
+        let inputs = args.open_inputs(&args.input);
+        if args.percent_lines {
+            // Each input is counted on its own, for its percentage.
+            let tallies: Vec<_> = inputs
+                .into_iter()
+                .map(|input| {
+                    let tally = records::Tally::new(false, true);
+                    records::count(
+                        &query,
+                        &bounds,
+                        args.columns,
+                        tally,
+                        vec![input],
+                        args.buffer_size,
+                    )
+                })
+                .collect();
+            let names: Vec<_> = (0..tallies.len())
+                .map(|i| input_name(&args.input, i))
+                .collect();
+            records::print_percent(out, &names, &tallies).expect("failed to write output");
+            return false;
+        }
+        let tally = records::Tally::new(args.offsets, args.per_record_stats);
+        records::count(
+            &query,
+            &bounds,
+            args.columns,
+            tally,
+            inputs,
+            args.buffer_size,
+        )
+        .print(out)
+    } else if args.columns.is_some() {
+        // Count matches, within lines so that their columns are known.
+        let query = expr::Query::all_but(vec![needle.to_vec()], Vec::new());
+        let bounds = records::Bounds::Separator(b"\n".to_vec());
+        let tally = records::Tally {
+            matches: Some(0),
+            ..Default::default()
+        };
+        let inputs = args.open_inputs(&args.input);
+        let tally = records::count(
+            &query,
+            &bounds,
+            args.columns,
+            tally,
+            inputs,
+            args.buffer_size,
+        );
+        writeln!(out, "{}", color::count(tally.matches.unwrap_or(0)))
+    } else if args.line_regexp {
         let mut count = 0;
-        while let Some(i) = self.finder.find(&buf[x..]) {
-            count += 1;
-            x += i + n;
+        for f in args.open_inputs(&args.input) {
+            let mut lines = wholeline::WholeLineCounter::new(needle);
+            for_each_chunk(f, args.buffer_size, |chunk| lines.write(chunk));
+            lines.finish();
+            count += lines.count();
         }
+        writeln!(out, "{}", color::count(count))
+    } else if let Some(other) = &other {
+        count_ratio(args, needle, other, out)
+    } else if args.revcomp {
+        let Some(rc) = seq::reverse_complement(needle) else {
+            let mut cmd = Args::command();
+            cmd.error(
+                ErrorKind::ValueValidation,
+                "--revcomp needs a DNA or RNA pattern",
+            )
+            .exit();
+        };
+        let matcher = Literals::new([needle, &rc]);
+        let mut sampler = new_sampler(args, &matcher);
+        let counts = count(
+            args,
+            matcher,
+            &[needle, &rc],
+            sampler.as_mut(),
+            None,
+            None,
+            out,
+        );
+        let totals = totals(&counts);
+        print_pattern_counts(out, &[needle, &rc], &totals)
+            .and_then(|_| print_samples(out, args, sampler))
+    } else if let Some(max_errors) = args.max_errors {
+        if needle.len() > 64 {
+            let mut cmd = Args::command();
+            cmd.error(
+                ErrorKind::ValueValidation,
+                "--max-errors needs a pattern of at most 64 bytes",
+            )
+            .exit();
+        }
+        check_errors(needle, max_errors, "--max-errors");
+        return count_needle(args, Fuzzy::new(needle, max_errors), needle, out)
+            .expect("failed to write output");
+    } else if let Some(max_mismatches) = args.max_mismatches {
+        check_errors(needle, max_mismatches, "--max-mismatches");
+        return count_needle(args, Hamming::new(needle, max_mismatches), needle, out)
+            .expect("failed to write output");
+    } else {
+        return count_needle(args, Literal::new(needle), needle, out)
+            .expect("failed to write output");
+    };
+    res.expect("failed to write output");
+    false
+}
 
-        let l = buf.len().saturating_sub(n - 1).max(x);
-        let i = first_possible_prefix(&self.needle, &buf[l..]) + l;
-        (i, count)
+// Counts `needle` and `other` in one pass, and prints both counts and then their ratio, or "-" if
+// `other` wasn't found.
+fn count_ratio(args: &Args, needle: &[u8], other: &[u8], out: &mut Output) -> io::Result<()> {
+    #[cfg(any(feature = "kafka", not(target_os = "wasi")))]
+    if args.streaming() {
+        let every = Duration::from_secs(args.emit_every as u64);
+        let window = Duration::from_secs(args.window.unwrap_or(args.emit_every) as u64);
+        let intervals = interval::Intervals::new(Literal::new(needle), every);
+        stream(args, intervals.ratio(Literal::new(other), window), out);
+    }
+    let mut counts = [0, 0];
+    for f in args.open_inputs(&args.input) {
+        // Matches don't continue from one input into the next.
+        let mut a = NeedleCounter::new(needle);
+        let mut b = NeedleCounter::new(other);
+        for_each_chunk(f, args.buffer_size, |chunk| {
+            a.write(chunk);
+            b.write(chunk);
+        });
+        counts[0] += a.count();
+        counts[1] += b.count();
+    }
+    for (pattern, count) in [needle, other].iter().zip(counts) {
+        write!(out, "{}\t", color::count(count))?;
+        out.write_all(pattern)?;
+        writeln!(out)?;
+    }
+    match counts {
+        [_, 0] => writeln!(out, "-"),
+        [a, b] => writeln!(
+            out,
+            "{}",
+            color::total(format!("{:.4}", a as f64 / b as f64))
+        ),
     }
 }
 
-pub fn first_possible_prefix(needle: &[u8], buf: &[u8]) -> usize {
-    (0..buf.len())
-        .filter(|&i| needle.starts_with(&buf[i..]))
-        .next()
-        .unwrap_or(buf.len())
+// Counts the messages of --kafka or --syslog-listen in intervals, printing each as it ends, until
+// freq is stopped.
+#[cfg(any(feature = "kafka", not(target_os = "wasi")))]
+fn stream<M: Matcher>(args: &Args, intervals: interval::Intervals<M>, out: &mut impl Write) -> ! {
+    #[cfg(feature = "kafka")]
+    if let Some(brokers) = &args.kafka {
+        let group = args.group.as_deref().unwrap_or("freq");
+        let consumer = kafka::Consumer::new(brokers, &args.topics, group).unwrap_or_else(|e| {
+            eprintln!("freq: kafka: {}", e);
+            std::process::exit(2);
+        });
+        consumer.run(intervals, out);
+    }
+    #[cfg(not(target_os = "wasi"))]
+    if let Some(addr) = args.syslog_listen {
+        let messages = syslog::listen(addr).unwrap_or_else(|e| {
+            eprintln!("freq: failed to listen on {}: {}", addr, e);
+            std::process::exit(2);
+        });
+        syslog::run(messages, intervals, out);
+    }
+    unreachable!("only called with --kafka or --syslog-listen");
 }
 
-fn get_uninit_vec<T>(len: usize) -> Vec<T> {
-    let mut v = Vec::with_capacity(len);
-    unsafe {
-        v.set_len(len);
+// Exits if `flag` can't allow `errors` in matches of `needle`, which would match anything if
+// they were as many as its bytes.
+fn check_errors(needle: &[u8], errors: usize, flag: &str) {
+    if errors >= needle.len() {
+        let mut cmd = Args::command();
+        cmd.error(
+            ErrorKind::ValueValidation,
+            format!("{} must be less than the pattern's length", flag),
+        )
+        .exit();
     }
-    v
 }
 
-fn read_chunks<R: Read + Send + 'static>(mut f: R, chunk_size: usize) -> Receiver<Vec<u8>> {
-    let (s, r) = crossbeam_channel::bounded(0);
-    std::thread::spawn(move || {
-        loop {
-            // Get a buffer.
-            let mut v = get_uninit_vec(chunk_size);
+// The two patterns of --near and how far apart they can be.
+#[derive(Clone)]
+struct NearSpec {
+    spec: String,
+    first: OsString,
+    second: OsString,
+    gap: usize,
+}
 
-            // Try to fill the buffer.
-            let bytes_read = f.read(&mut v).expect("failed to read");
+impl NearSpec {
+    // Parses "A..B/N". A can't contain "..", but B can, and either can contain '/'.
+    fn parse(s: &str) -> Result<Self, String> {
+        let err = || format!("expected A..B/N, such as 'req=42..ERROR/200', not '{}'", s);
+        let (patterns, gap) = s.rsplit_once('/').ok_or_else(err)?;
+        let (first, second) = patterns.split_once("..").ok_or_else(err)?;
+        let gap = gap.parse().map_err(|_| err())?;
+        if first.is_empty() || second.is_empty() {
+            return Err("both patterns of --near must be non-empty".to_string());
+        }
+        Ok(NearSpec {
+            spec: s.to_string(),
+            first: first.into(),
+            second: second.into(),
+            gap,
+        })
+    }
+}
 
-            // If we read 0 bytes, we are done.
-            if bytes_read == 0 {
-                break;
+// Adds the patterns of each of the sets named `sets` in the config file to `patterns`.
+fn add_pattern_sets(config: &config::Config, sets: &[String], patterns: &mut Vec<String>) {
+    for name in sets {
+        let Some(set) = config.pattern_sets.get(name) else {
+            let mut cmd = Args::command();
+            let msg = format!("no pattern set named '{}' in the config file", name);
+            cmd.error(ErrorKind::ValueValidation, msg).exit();
+        };
+        patterns.extend(set.iter().cloned());
+    }
+}
+
+// Counts the matches of `matcher`, which finds `needle`, and prints them as asked. Returns
+// whether a count went up since the baseline.
+fn count_needle<M: Matcher + Clone + Send + Sync>(
+    args: &Args,
+    matcher: M,
+    needle: &[u8],
+    out: &mut Output,
+) -> io::Result<bool> {
+    let mut sampler = new_sampler(args, &matcher);
+    // Read the baseline first, so that a bad one doesn't waste a scan.
+    let baseline = args.baseline.as_ref().map(|path| {
+        report::Report::read(path).unwrap_or_else(|e| {
+            let mut cmd = Args::command();
+            cmd.error(ErrorKind::Io, e).exit();
+        })
+    });
+    let mut scanned = Vec::new();
+    let mut wc = (args.lines || args.words || args.bytes)
+        .then(|| wc::Counts::new(args.lines, args.words, args.bytes));
+    let counted = count(
+        args,
+        matcher,
+        &[needle],
+        sampler.as_mut(),
+        args.rate.is_some().then_some(&mut scanned),
+        wc.as_mut(),
+        out,
+    );
+    if args.format == report::Format::Jsonl {
+        let count = totals(&counted)[0];
+        return report::Event::Total {
+            pattern: needle,
+            count,
+        }
+        .write(out)
+        .map(|_| false);
+    }
+    if matches!(args.format, report::Format::Json | report::Format::Msgpack) || baseline.is_some() {
+        let report = report::Report {
+            files: counted
+                .iter()
+                .enumerate()
+                .map(|(i, c)| (input_name(&args.input, i), c.counts[0]))
+                .collect(),
+        };
+        // A count that went up since the baseline is a failure.
+        return match baseline {
+            Some(baseline) => report.print_diff(out, &baseline),
+            None if args.format == report::Format::Msgpack => {
+                report.write_msgpack(out, needle).map(|_| false)
             }
+            None => report.write_json(out, needle).map(|_| false),
+        };
+    }
+    let counts: Vec<_> = counted.iter().map(|c| c.counts[0]).collect();
+    if let Some(rate) = args.rate {
+        let names: Vec<_> = (0..counts.len())
+            .map(|i| input_name(&args.input, i))
+            .collect();
+        rate::print(out, rate, &names, &counts, &scanned)?;
+    } else if let Some(group_by) = &args.group_by {
+        group::print(out, group_by, &args.input, &counts)?;
+    } else if args.with_filename {
+        // The count in each input was printed as it was counted.
+        writeln!(out, "{}", color::total(totals(&counted)[0]))?;
+    } else {
+        writeln!(out, "{}", color::count(totals(&counted)[0]))?;
+    }
+    if args.count_files {
+        print_file_summary(out, &counted)?;
+    }
+    if let Some(wc) = &wc {
+        wc.print(out)?;
+    }
+    print_samples(out, args, sampler)?;
+    Ok(false)
+}
+
+// The name of the `i`th input in results: its path in the --path-style, or "-" for stdin.
+fn input_name(paths: &[PathBuf], i: usize) -> Vec<u8> {
+    names::of(paths.get(i).map(PathBuf::as_path))
+}
+
+impl Args {
+    // Whether the input is messages that keep arriving, from --kafka or --syslog-listen.
+    #[cfg(any(feature = "kafka", not(target_os = "wasi")))]
+    fn streaming(&self) -> bool {
+        self.kafka.is_some() || self.syslog_listen.is_some()
+    }
 
-            // Send the buffer.
-            v.truncate(bytes_read);
-            s.send(v).expect("failed to send");
+    // Opens the inputs, copying them to stdout with --tee.
+    fn open_inputs(&self, paths: &[PathBuf]) -> Vec<Input> {
+        #[cfg(feature = "journal")]
+        if self.journal {
+            let journal = journal::Journal::open(&self.unit, self.since).unwrap_or_else(|e| {
+                eprintln!("freq: failed to open the journal: {}", e);
+                std::process::exit(2);
+            });
+            return vec![Box::new(journal)];
         }
-        // Sender drops.
-    });
-    r
+        let inputs = open_ranges(paths, self.range);
+        if self.tee {
+            tee_inputs(inputs)
+        } else {
+            inputs
+        }
+    }
+
+    // Where to print results: the output file if there is one, or stderr with --tee, since
+    // stdout is taken by the input.
+    fn output(&self) -> Output {
+        let out = if let Some(path) = &self.output {
+            Output::file(path.clone(), self.append)
+        } else if self.tee {
+            Output::stderr()
+        } else {
+            Output::stdout()
+        };
+        if self.format == report::Format::Table {
+            Output::table(out, self.boxed)
+        } else {
+            out
+        }
+    }
+
+    // How to split the input into records, if a record separator or size was given.
+    fn record_bounds(&self) -> Option<records::Bounds> {
+        if self.null_data {
+            Some(records::Bounds::Separator(b"\0".to_vec()))
+        } else if let Some(size) = self.record_size {
+            Some(records::Bounds::Size(size as usize))
+        } else {
+            self.record_sep
+                .clone()
+                .map(|sep| records::Bounds::Separator(sep.0))
+        }
+    }
 }
 
-fn main() {
-    let args = Args::parse();
+// Returns the bytes to search for, in the encoding given by --pattern-encoding.
+fn encode_pattern(args: &Args, pattern: &OsString) -> Vec<u8> {
+    args.pattern_encoding.encode(pattern).unwrap_or_else(|| {
+        let mut cmd = Args::command();
+        let msg = format!(
+            "{:?} is not valid Unicode, so it can't be encoded as {}",
+            pattern,
+            args.pattern_encoding
+                .to_possible_value()
+                .expect("no encoding is skipped")
+                .get_name()
+        );
+        cmd.error(ErrorKind::InvalidUtf8, msg).exit();
+    })
+}
 
-    let needle = args.pattern.as_encoded_bytes();
-    if needle.is_empty() {
+// Returns the patterns given to `flag`, exiting if any is empty.
+fn line_patterns(args: &Args, patterns: &[OsString], flag: &str) -> Vec<Vec<u8>> {
+    if patterns.iter().any(|p| p.is_empty()) {
         let mut cmd = Args::command();
-        cmd.error(ErrorKind::ValueValidation, "Pattern must be non-empty")
-            .exit();
+        cmd.error(
+            ErrorKind::ValueValidation,
+            format!("{} patterns must be non-empty", flag),
+        )
+        .exit();
     }
+    patterns.iter().map(|p| encode_pattern(args, p)).collect()
+}
 
-    let v: Vec<Box<dyn Read + Send + 'static>> = if args.input.is_empty() {
-        vec![Box::new(stdin())]
-    } else {
-        args
-            .input
-            .iter()
-            .map(|p| {
-                File::open(p.clone()).expect(format!("failed to open {}", p.display()).as_str())
-            })
-            .map(|f| Box::new(f) as _)
-            .collect()
+// Counts matches in all inputs, returning the total for each pattern. Matches are also offered
+// to `sampler`, if given, and how much of each input was read is added to `scanned`.
+fn count<M: Matcher + Clone + Send + Sync, W: Write>(
+    args: &Args,
+    matcher: M,
+    patterns: &[&[u8]],
+    mut sampler: Option<&mut Sampler<M>>,
+    mut scanned: Option<&mut Vec<rate::Scanned>>,
+    mut wc: Option<&mut wc::Counts>,
+    out: &mut W,
+) -> Vec<Counted> {
+    let inputs = args.open_inputs(&args.input);
+    debug!(
+        "counting {} patterns with {}",
+        patterns.len(),
+        std::any::type_name::<M>()
+    );
+    // Streams of messages that don't end.
+    #[cfg(any(feature = "kafka", not(target_os = "wasi")))]
+    if args.streaming() {
+        let every = Duration::from_secs(args.emit_every as u64);
+        stream(args, interval::Intervals::new(matcher, every), out);
+    }
+    // These only count the inputs together.
+    if let Some(bucket) = args.time_bucket {
+        let format = args.time_format.clone();
+        let totals = timecount::count(matcher, inputs, args.buffer_size, bucket, format, out)
+            .expect("failed to write output");
+        return vec![Counted::total(totals)];
+    }
+    if let Some(format) = args.seq_format {
+        let per_record = args.per_record.then_some(out as &mut dyn Write);
+        let totals = seqcount::count(
+            matcher,
+            inputs,
+            format,
+            args.buffer_size,
+            args.min_qual,
+            per_record,
+        );
+        return vec![Counted::total(totals)];
+    }
+
+    let cache = args
+        .cache
+        .clone()
+        .and_then(|dir| match cache::Cache::new(dir) {
+            Ok(cache) => Some(cache),
+            Err(e) => {
+                eprintln!("freq: failed to create the cache directory: {}", e);
+                None
+            }
+        });
+
+    let incremental = args.incremental.clone().map(|path| {
+        incremental::State::load(path.clone()).unwrap_or_else(|e| {
+            eprintln!("freq: {}: {}", path.display(), e);
+            std::process::exit(2);
+        })
+    });
+    let saved = |counts: Vec<Counted>| {
+        if let Some(state) = &incremental {
+            if let Err(e) = state.save() {
+                eprintln!("freq: failed to write the --incremental state: {}", e);
+            }
+        }
+        counts
     };
+    let stored = Stored {
+        cache: cache.as_ref(),
+        incremental: incremental.as_ref(),
+    };
+
+    let prepared = engine::Prepared::new(matcher, patterns);
+    // WASI has no threads, so inputs are counted one at a time there.
+    #[cfg(not(target_os = "wasi"))]
+    {
+        let threads = count_threads(args, inputs.len());
+        if threads > 1 && sampler.is_none() && scanned.is_none() && wc.is_none() {
+            return saved(count_parallel(
+                args, &prepared, stored, patterns, inputs, threads, out,
+            ));
+        }
+    }
 
     // Counting happens in this thread.
-    let mut total_count = 0;
-    for f in v {
-        let r = read_chunks(f, args.buffer_size);
-        let mut counter = NeedleCounter::new(needle);
-        while let Ok(v) = r.recv() {
-            counter.write(&v);
+    let mut counts = Vec::new();
+    for (i, f) in inputs.into_iter().enumerate() {
+        print_started(args, out, i).expect("failed to write output");
+        let mut read = rate::Scanned::new();
+        let counted = count_input(args, &prepared, stored, patterns, i, f, |chunk| {
+            if let Some(sampler) = &mut sampler {
+                sampler.write(chunk);
+            }
+            if let Some(rate) = args.rate {
+                read.write(chunk, rate);
+            }
+            if let Some(wc) = &mut wc {
+                wc.write(chunk);
+            }
+        });
+        if !counted.cached {
+            if let Some(wc) = &mut wc {
+                wc.finish();
+            }
+            if let Some(scanned) = &mut scanned {
+                scanned.push(read);
+            }
+            if let Some(sampler) = &mut sampler {
+                sampler.finish();
+            }
+        }
+        print_counted(args, out, i, &counted).expect("failed to write output");
+        counts.push(counted);
+    }
+    saved(counts)
+}
+
+// How many threads to count `inputs` inputs on at once: --jobs, or one for each CPU, but no more
+// than there are inputs, or than there is memory to read them with under --max-memory. Inputs
+// copied to stdout with --tee, or read to tune the buffer size, are counted one at a time.
+#[cfg(not(target_os = "wasi"))]
+fn count_threads(args: &Args, inputs: usize) -> usize {
+    if args.tee || args.auto_tune {
+        return 1;
+    }
+    let threads = args
+        .jobs
+        .map(|n| n as usize)
+        .or_else(|| std::thread::available_parallelism().ok().map(|n| n.get()))
+        .unwrap_or(1)
+        .min(inputs);
+    // Each input is read with up to three buffers at once.
+    match memory::io_limit() {
+        Some(limit) => threads.min(limit / (3 * args.buffer_size)).max(1),
+        None => threads.max(1),
+    }
+}
+
+// The counts of an input, and how they were come by.
+struct Counted {
+    counts: Vec<usize>,
+    // Why the input couldn't be read to the end, if it couldn't.
+    error: Option<String>,
+    // Whether the counts were taken from the --cache instead.
+    cached: bool,
+}
+
+impl Counted {
+    // The counts of all the inputs together, in modes that only count them that way.
+    fn total(counts: Vec<usize>) -> Self {
+        Counted {
+            counts,
+            error: None,
+            cached: false,
         }
-        total_count += counter.count();
     }
-    println!("{}", total_count);
+}
+
+// What is kept of the counts between runs: the --cache, and the --incremental state.
+#[derive(Clone, Copy)]
+struct Stored<'a> {
+    cache: Option<&'a cache::Cache>,
+    incremental: Option<&'a incremental::State>,
+}
+
+// Counts the `i`th input, `f`, or takes its counts from the cache, handing what is read of it to
+// `each` too. With --incremental, only what was appended since the last run is read.
+fn count_input<M: Matcher + Clone>(
+    args: &Args,
+    prepared: &engine::Prepared<M>,
+    stored: Stored,
+    patterns: &[&[u8]],
+    i: usize,
+    mut f: Input,
+    mut each: impl FnMut(&[u8]),
+) -> Counted {
+    let cache = stored.cache;
+    let key = cache.and_then(|_| cache::Cache::key(args.input.get(i)?, patterns));
+    let cached = cache.zip(key.as_ref());
+    if let Some(counts) = cached.and_then(|(cache, key)| cache.get(key)) {
+        info!("using the cached counts of {}", args.input[i].display());
+        return Counted {
+            counts,
+            error: None,
+            cached: true,
+        };
+    }
+
+    let resume = stored
+        .incremental
+        .zip(args.input.get(i))
+        .and_then(|(state, path)| Some((state, state.resume(path, patterns)?)));
+    if let Some((_, resume)) = &resume {
+        f = input::open_range(&args.input[i], resume.range);
+    }
+
+    let mut complete = true;
+    let parts = if args.sections.is_empty() {
+        vec![f]
+    } else {
+        let path = &args.input[i];
+        match sections::ranges(path, &args.sections) {
+            Ok(ranges) => {
+                debug!("counting {} sections of {}", ranges.len(), path.display());
+                ranges
+                    .into_iter()
+                    .map(|range| input::open_range(path, range))
+                    .collect()
+            }
+            Err(e) => {
+                input::report(format!("{}: {}", path.display(), e));
+                complete = false;
+                Vec::new()
+            }
+        }
+    };
+
+    // Each of the --sections is counted with a counter of its own, so no match spans two.
+    let mut counts = vec![0; patterns.len()];
+    let block = fused_block(args);
+    for part in parts {
+        let mut counter = prepared.counter();
+        complete &= for_each_chunk(part, args.buffer_size, |chunk| {
+            for block in chunk.chunks(block) {
+                counter.write(block);
+                each(block);
+            }
+        });
+        counter.finish();
+        for (count, n) in counts.iter_mut().zip(counter.counts()) {
+            *count += n;
+        }
+    }
+    // The counts of a file that couldn't be read to the end are only of part of it.
+    if let Some((cache, key)) = cached.filter(|_| complete) {
+        if let Err(e) = cache.put(key, &counts) {
+            eprintln!("freq: failed to write to the cache: {}", e);
+        }
+    }
+    if let Some((state, resume)) = resume {
+        for (count, before) in counts.iter_mut().zip(resume.counts.iter().flatten()) {
+            *count += before;
+        }
+        if complete {
+            state.put(resume, &counts);
+        }
+    }
+    Counted {
+        counts,
+        error: (!complete).then(|| input::last_error().unwrap_or_default()),
+        cached: false,
+    }
+}
+
+// How much of each chunk to count matches in before handing it to what else reads it, which with
+// lines or words to count reads every byte again: a block small enough to still be in the cache,
+// so that the chunk is only read from memory once. Otherwise the chunk is handed over whole.
+fn fused_block(args: &Args) -> usize {
+    if args.rate == Some(rate::Rate::Line) || args.lines || args.words {
+        FUSED_BLOCK
+    } else {
+        usize::MAX
+    }
+}
+
+const FUSED_BLOCK: usize = 32 << 10;
+
+// Counts `inputs` on `threads` threads at once, printing what is printed for each as it is
+// counted, in the order they finish in, or with --ordered, in the order they were given in.
+#[cfg(not(target_os = "wasi"))]
+fn count_parallel<M: Matcher + Clone + Send + Sync, W: Write>(
+    args: &Args,
+    prepared: &engine::Prepared<M>,
+    stored: Stored,
+    patterns: &[&[u8]],
+    inputs: Vec<Input>,
+    threads: usize,
+    out: &mut W,
+) -> Vec<Counted> {
+    // What a thread says about the input it is counting.
+    enum Progress {
+        Started(usize),
+        Finished(usize, Counted),
+    }
+
+    debug!("counting {} inputs on {} threads", inputs.len(), threads);
+    let mut counts: Vec<Option<Counted>> = (0..inputs.len()).map(|_| None).collect();
+    let queue = Mutex::new(inputs.into_iter().enumerate());
+    let (s, r) = std::sync::mpsc::channel();
+    std::thread::scope(|scope| {
+        for _ in 0..threads {
+            let s = s.clone();
+            let queue = &queue;
+            scope.spawn(move || {
+                let next = || queue.lock().unwrap().next();
+                while let Some((i, f)) = next() {
+                    let _ = s.send(Progress::Started(i));
+                    let counted = count_input(args, prepared, stored, patterns, i, f, |_| {});
+                    let _ = s.send(Progress::Finished(i, counted));
+                }
+            });
+        }
+        drop(s);
+
+        // With --ordered, the inputs that were counted before those given ahead of them.
+        let mut waiting = BTreeMap::new();
+        let mut next = 0;
+        for progress in r {
+            match progress {
+                Progress::Started(i) if !args.ordered => {
+                    print_started(args, out, i).expect("failed to write output");
+                }
+                Progress::Started(_) => {}
+                Progress::Finished(i, counted) if !args.ordered => {
+                    print_counted(args, out, i, &counted).expect("failed to write output");
+                    counts[i] = Some(counted);
+                }
+                Progress::Finished(i, counted) => {
+                    waiting.insert(i, counted);
+                    while let Some(counted) = waiting.remove(&next) {
+                        print_started(args, out, next).expect("failed to write output");
+                        print_counted(args, out, next, &counted).expect("failed to write output");
+                        counts[next] = Some(counted);
+                        next += 1;
+                    }
+                }
+            }
+        }
+    });
+    counts.into_iter().flatten().collect()
+}
+
+// Prints the event for the start of the `i`th input with --format jsonl.
+fn print_started(args: &Args, out: &mut impl Write, i: usize) -> io::Result<()> {
+    if args.format != report::Format::Jsonl || !names::per_input() {
+        return Ok(());
+    }
+    let event = report::Event::Start {
+        file: &input_name(&args.input, i),
+    };
+    event.write(out)
+}
+
+// Prints what is printed for the `i`th input as soon as it is counted: its event with --format
+// jsonl, or its count and name with -H.
+fn print_counted(args: &Args, out: &mut impl Write, i: usize, counted: &Counted) -> io::Result<()> {
+    if !names::per_input() {
+        return Ok(());
+    }
+    let name = input_name(&args.input, i);
+    if args.format == report::Format::Jsonl {
+        let count = counted.counts[0];
+        return match &counted.error {
+            None => report::Event::Finish { file: &name, count }.write(out),
+            Some(message) => report::Event::Error {
+                file: &name,
+                message,
+            }
+            .write(out),
+        };
+    }
+    if args.with_filename {
+        write!(out, "{}", color::count(counted.counts[0]))?;
+        names::write_column(out, &name)?;
+        writeln!(out)?;
+        // So that it's seen before slower inputs are counted.
+        out.flush()?;
+    }
+    Ok(())
+}
+
+// Adds up the counts of each pattern in each input.
+fn totals(counted: &[Counted]) -> Vec<usize> {
+    let mut totals = vec![0; counted.first().map_or(0, |c| c.counts.len())];
+    for c in counted {
+        for (total, c) in totals.iter_mut().zip(&c.counts) {
+            *total += c;
+        }
+    }
+    totals
+}
+
+// Prints how many inputs had matches, how many had none, and how many couldn't be read to the
+// end, for --count-files.
+fn print_file_summary(out: &mut impl Write, counted: &[Counted]) -> io::Result<()> {
+    let failed = counted.iter().filter(|c| c.error.is_some()).count();
+    let matched = counted
+        .iter()
+        .filter(|c| c.error.is_none() && c.counts[0] > 0)
+        .count();
+    let unmatched = counted.len() - failed - matched;
+    writeln!(out, "{}\tfiles with matches", color::count(matched))?;
+    writeln!(out, "{}\tfiles without", color::count(unmatched))?;
+    writeln!(out, "{}\tfiles that couldn't be read", color::count(failed))
+}
+
+// Prints "count<TAB>pattern" for each pattern, then the total.
+fn print_pattern_counts(
+    out: &mut impl Write,
+    patterns: &[&[u8]],
+    totals: &[usize],
+) -> io::Result<()> {
+    for (pattern, count) in patterns.iter().zip(totals) {
+        write!(out, "{}\t", color::count(count))?;
+        out.write_all(pattern)?;
+        writeln!(out)?;
+    }
+    writeln!(out, "{}", color::total(totals.iter().sum::<usize>()))
+}
+
+fn new_sampler<M: Matcher + Clone>(args: &Args, matcher: &M) -> Option<Sampler<M>> {
+    let context = args.context.bytes();
+    if let Some(size) = args.show_matches {
+        return Some(Sampler::new(matcher.clone(), size, context).keep_first());
+    }
+    args.sample
+        .map(|size| Sampler::new(matcher.clone(), size, context))
+}
+
+// Prints "file:offset<TAB>before<TAB>match<TAB>after" for each sampled or shown match.
+fn print_samples<M: Matcher>(
+    out: &mut impl Write,
+    args: &Args,
+    sampler: Option<Sampler<M>>,
+) -> io::Result<()> {
+    let Some(sampler) = sampler else {
+        return Ok(());
+    };
+    for sample in sampler.into_samples() {
+        match args.input.get(sample.input) {
+            Some(path) => write!(out, "{}:{}", path.display(), sample.offset)?,
+            None => write!(out, "-:{}", sample.offset)?,
+        }
+        out.write_all(b"\t")?;
+        sample.write(out, args.context.lines())?;
+        writeln!(out)?;
+    }
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    use memchr::memmem::find_iter;
-    use proptest::prelude::ProptestConfig;
-    use proptest::string::bytes_regex;
-    use proptest::{prop_assert_eq, proptest};
-
-    proptest! {
-        #![proptest_config(ProptestConfig {
-            cases: 1 << 16,
-            .. ProptestConfig::default()
-        })]
-
-        #[test]
-        fn test_count(
-            chunk_size in 1..100_usize,
-            needle in bytes_regex("((?s-u:.{1,100}))").unwrap(),
-            haystack in bytes_regex("((?s-u:.{0,1000}))").unwrap()
-        ) {
-            let mut counter = NeedleCounter::new(&needle);
-
-            haystack.chunks(chunk_size).for_each(|chunk| {
-                counter.write(chunk);
-            });
+    #[test]
+    fn test_expand_profiles() {
+        let config = config::Config::parse(
+            "[profiles]\nweb = [\"--format\", \"json\", \"-v\", \"--jobs=2\", \"GET /\"]",
+        )
+        .unwrap();
+        let expand = |argv: &[&str]| {
+            let argv = argv.iter().map(OsString::from).collect();
+            expand_profiles(argv, &config)
+        };
+        assert_eq!(
+            expand(&["freq", "--profile", "web", "x.log"]),
+            ["freq", "--format", "json", "-v", "--jobs=2", "GET /", "x.log"]
+        );
+        // Options given on the command line win, wherever they are.
+        assert_eq!(
+            expand(&["freq", "--profile=web", "--format", "text", "x.log"]),
+            ["freq", "-v", "--jobs=2", "GET /", "--format", "text", "x.log"]
+        );
+        assert_eq!(
+            expand(&["freq", "-j3", "--profile", "web", "x.log"]),
+            ["freq", "-j3", "--format", "json", "-v", "GET /", "x.log"]
+        );
+        assert_eq!(
+            expand(&[
+                "freq",
+                "--format=text",
+                "-v",
+                "--profile",
+                "web",
+                "--",
+                "--jobs=1"
+            ]),
+            [
+                "freq",
+                "--format=text",
+                "-v",
+                "--jobs=2",
+                "GET /",
+                "--",
+                "--jobs=1"
+            ]
+        );
+
+        let args = Args::try_parse_from(expand(&[
+            "freq",
+            "--profile",
+            "web",
+            "--format",
+            "text",
+            "x.log",
+        ]))
+        .unwrap();
+        assert!(args.format == report::Format::Text);
+    }
 
+    #[test]
+    fn test_record_sep() {
+        let args =
+            Args::try_parse_from(["freq", "--record-sep", "\\n\\n", "ERROR", "l.txt"]).unwrap();
+        assert_eq!(
+            args.record_bounds(),
+            Some(records::Bounds::Separator(b"\n\n".to_vec()))
+        );
+        assert!(Args::try_parse_from(["freq", "--record-sep", "", "ERROR"]).is_err());
+    }
 
-            let expected = find_iter(&haystack, &needle).count();
-            assert_eq!(counter.count(), expected);
+    #[test]
+    fn test_engine_conflicts() {
+        for mode in [
+            &["--time-bucket", "1m"][..],
+            &["--seq-format", "fasta"],
+            &["-z"],
+        ] {
+            let argv = ["freq", "--engine", "builtin"]
+                .iter()
+                .chain(mode)
+                .chain(&["ERROR"]);
+            let err = Args::try_parse_from(argv).err().unwrap();
+            assert_eq!(err.kind(), ErrorKind::ArgumentConflict);
         }
+        assert!(Args::try_parse_from(["freq", "--engine", "builtin", "ERROR"]).is_ok());
+        assert!(Args::try_parse_from(["freq", "--time-bucket", "1m", "ERROR"]).is_ok());
+    }
 
-        #[test]
-        fn test_aba(
-            chunk_size in 1..100_usize,
-            needle in bytes_regex("((?s-u:[ab]{1,10}))").unwrap(),
-            haystack in bytes_regex("((?s-u:[ab]{0,1000}))").unwrap()
-        ) {
-            let mut counter = NeedleCounter::new(&needle);
+    #[test]
+    fn test_count_ordered() {
+        let dir = std::env::temp_dir().join(format!("freq-ordered-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut argv: Vec<OsString> = ["freq", "-H", "--ordered", "--jobs", "4", "x"]
+            .iter()
+            .map(OsString::from)
+            .collect();
+        for (name, text) in [("a", "x\n"), ("b", ""), ("c", "xx\nx\n")] {
+            std::fs::write(dir.join(name), text).unwrap();
+            argv.push(dir.join(name).into_os_string());
+        }
+        let args = Args::try_parse_from(argv).unwrap();
+        let mut out = Vec::new();
+        let counted = count(
+            &args,
+            Literal::new(b"x"),
+            &[b"x"],
+            None,
+            None,
+            None,
+            &mut out,
+        );
+        let counts: Vec<_> = counted.iter().map(|c| c.counts[0]).collect();
+        assert_eq!(counts, [1, 0, 3]);
+        let names: Vec<_> = (0..3).map(|i| dir.join(["a", "b", "c"][i])).collect();
+        let expected = format!(
+            "1\t{}\n0\t{}\n3\t{}\n",
+            names[0].display(),
+            names[1].display(),
+            names[2].display()
+        );
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 
-            haystack.chunks(chunk_size).for_each(|chunk| {
-                counter.write(chunk);
-            });
+    #[test]
+    fn test_file_summary() {
+        let mut counted: Vec<_> = [3, 0, 1].map(|n| Counted::total(vec![n])).into();
+        counted[2].error = Some("gone".to_string());
+        let mut out = Vec::new();
+        print_file_summary(&mut out, &counted).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "1\tfiles with matches\n1\tfiles without\n1\tfiles that couldn't be read\n"
+        );
+    }
 
+    #[test]
+    fn test_mode_group() {
+        let parse = |argv: &[&str]| Args::try_parse_from(["freq"].iter().chain(argv));
+        assert!(parse(&["--max-errors", "1", "--max-mismatches", "1", "ERROR"]).is_err());
+        assert!(parse(&["--near", "a..b/1", "--expr", "\"a\""]).is_err());
+        assert!(parse(&["--max-mismatches", "1", "-z", "ERROR"]).is_err());
+        assert!(parse(&["--max-mismatches", "1", "ERROR"]).is_ok());
+    }
 
-            let expected = find_iter(&haystack, &needle).count();
-            prop_assert_eq!(counter.count(), expected);
-        }
+    #[test]
+    fn test_wc_group() {
+        let parse = |argv: &[&str]| Args::try_parse_from(["freq"].iter().chain(argv));
+        assert!(parse(&[
+            "--lines",
+            "--words",
+            "--bytes",
+            "--max-errors",
+            "1",
+            "ERROR"
+        ])
+        .is_ok());
+        assert!(parse(&["--lines", "--rate", "per-line", "ERROR"]).is_err());
+        assert!(parse(&["--bytes", "-z", "ERROR"]).is_err());
+    }
+
+    #[test]
+    fn test_fused_block() {
+        let block = |argv: &[&str]| {
+            fused_block(&Args::try_parse_from(["freq"].iter().chain(argv)).unwrap())
+        };
+        assert_eq!(block(&["--rate", "per-line", "ERROR"]), FUSED_BLOCK);
+        assert_eq!(block(&["--words", "ERROR"]), FUSED_BLOCK);
+        assert_eq!(block(&["--bytes", "ERROR"]), usize::MAX);
+        assert_eq!(block(&["--rate", "per-mb", "ERROR"]), usize::MAX);
     }
 }
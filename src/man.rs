@@ -0,0 +1,63 @@
+use clap::{Args, Command};
+use std::io::Write;
+
+#[derive(Args)]
+pub struct ManArgs {
+    #[arg(
+        value_name = "SUBCOMMAND",
+        help = "Print the page for this subcommand, as freq-SUBCOMMAND, instead of the one for freq."
+    )]
+    subcommand: Option<String>,
+}
+
+// Writes the man page for `cmd`, or one of its subcommands, in roff. clap_mangen builds it from
+// the same definitions as --help, so the two can't disagree.
+pub fn run(args: ManArgs, mut cmd: Command, out: &mut impl Write) {
+    if let Some(name) = args.subcommand {
+        // Building propagates the global options to the subcommands.
+        cmd.build();
+        let Some(sub) = cmd.find_subcommand(&name) else {
+            cmd.error(
+                clap::error::ErrorKind::InvalidSubcommand,
+                format!("no such subcommand '{}'", name),
+            )
+            .exit();
+        };
+        let version = cmd.get_version().unwrap_or_default().to_string();
+        cmd = sub
+            .clone()
+            .name(format!("{}-{}", cmd.get_name(), name))
+            .version(version);
+    }
+    clap_mangen::Man::new(cmd)
+        .render(out)
+        .expect("failed to write output");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Args;
+    use clap::CommandFactory;
+
+    fn render(subcommand: Option<&str>) -> String {
+        let args = ManArgs {
+            subcommand: subcommand.map(str::to_string),
+        };
+        let mut out = Vec::new();
+        run(args, Args::command(), &mut out);
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_run() {
+        let page = render(None);
+        assert!(page.contains(".TH freq 1"));
+        assert!(page.contains("\\-\\-file\\-timeout"));
+        assert!(page.contains(".SH SUBCOMMANDS"));
+
+        let page = render(Some("split"));
+        assert!(page.contains(".TH freq-split 1"));
+        assert!(page.contains("\\-\\-parts"));
+    }
+}
@@ -0,0 +1,186 @@
+use memchr::memmem::Finder;
+
+/// A match found by a [`Matcher`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Match {
+    pub start: usize,
+    pub end: usize,
+
+    /// Which of the matcher's patterns matched.
+    pub pattern: usize,
+}
+
+/// Finds matches in a buffer, for use by a [`Counter`](crate::Counter).
+///
+/// The counter takes care of matches that span chunk boundaries by holding back the last
+/// `max_len() - 1` bytes of each chunk, or fewer if no match can start in them.
+pub trait Matcher {
+    /// Calls `f` on each match in `haystack`, in order, until it returns false.
+    ///
+    /// Matches are non-empty and chosen greedily by where they end: each is the one that ends
+    /// first among those starting after the previous one ended. For a single literal these are
+    /// the usual leftmost non-overlapping matches. Choosing by end means matches never change if
+    /// `haystack` were extended, and it finds as many matches as possible.
+    fn find_all(&self, haystack: &[u8], f: &mut dyn FnMut(Match) -> bool);
+
+    /// The length of the longest possible match. This bounds how far back the counter has to
+    /// look when a new chunk arrives.
+    fn max_len(&self) -> usize;
+
+    /// The number of patterns, which are numbered from 0.
+    fn patterns(&self) -> usize {
+        1
+    }
+
+    /// Returns whether a match could start at the start of `partial` once more bytes arrive.
+    /// `partial` is always shorter than `max_len()`.
+    fn could_start(&self, partial: &[u8]) -> bool {
+        let _ = partial;
+        true
+    }
+
+    /// If every match is the same single byte, returns it, so that the counter can use a
+    /// faster loop.
+    fn single_byte(&self) -> Option<u8> {
+        None
+    }
+}
+
+/// Matches a literal byte string.
+#[derive(Clone)]
+pub struct Literal {
+    finder: Finder<'static>,
+}
+
+impl Literal {
+    pub fn new(needle: &[u8]) -> Self {
+        assert!(!needle.is_empty(), "needle must be non-empty");
+        Literal {
+            finder: Finder::new(needle).into_owned(),
+        }
+    }
+
+    pub fn needle(&self) -> &[u8] {
+        self.finder.needle()
+    }
+}
+
+impl PartialEq for Literal {
+    fn eq(&self, other: &Self) -> bool {
+        self.needle() == other.needle()
+    }
+}
+
+impl Matcher for Literal {
+    fn find_all(&self, haystack: &[u8], f: &mut dyn FnMut(Match) -> bool) {
+        let n = self.needle().len();
+        for start in self.finder.find_iter(haystack) {
+            let m = Match {
+                start,
+                end: start + n,
+                pattern: 0,
+            };
+            if !f(m) {
+                break;
+            }
+        }
+    }
+
+    fn max_len(&self) -> usize {
+        self.needle().len()
+    }
+
+    fn could_start(&self, partial: &[u8]) -> bool {
+        self.needle().starts_with(partial)
+    }
+
+    fn single_byte(&self) -> Option<u8> {
+        match self.needle() {
+            &[b] => Some(b),
+            _ => None,
+        }
+    }
+}
+
+/// Matches any of a few literal byte strings, searching for each separately. Where two matches
+/// end at the same place, the earlier pattern wins.
+#[derive(Clone)]
+pub struct Literals {
+    finders: Vec<Finder<'static>>,
+}
+
+impl Literals {
+    pub fn new<N: AsRef<[u8]>>(needles: impl IntoIterator<Item = N>) -> Self {
+        let finders: Vec<_> = needles
+            .into_iter()
+            .map(|needle| {
+                let needle = needle.as_ref();
+                assert!(!needle.is_empty(), "needles must be non-empty");
+                Finder::new(needle).into_owned()
+            })
+            .collect();
+        assert!(!finders.is_empty(), "there must be at least one needle");
+        Literals { finders }
+    }
+
+    pub fn needles(&self) -> impl Iterator<Item = &[u8]> {
+        self.finders.iter().map(|f| f.needle())
+    }
+}
+
+impl PartialEq for Literals {
+    fn eq(&self, other: &Self) -> bool {
+        self.needles().eq(other.needles())
+    }
+}
+
+impl Matcher for Literals {
+    fn find_all(&self, haystack: &[u8], f: &mut dyn FnMut(Match) -> bool) {
+        // The next match of each needle after the last match we reported. Each finder only ever
+        // moves forward, so this is linear in the haystack.
+        let mut next: Vec<Option<usize>> = self.finders.iter().map(|f| f.find(haystack)).collect();
+        loop {
+            let best = next
+                .iter()
+                .enumerate()
+                .filter_map(|(i, s)| s.map(|s| (s + self.finders[i].needle().len(), i)))
+                .min();
+            let Some((end, pattern)) = best else {
+                return;
+            };
+            let start = end - self.finders[pattern].needle().len();
+            if !f(Match {
+                start,
+                end,
+                pattern,
+            }) {
+                return;
+            }
+
+            for (finder, s) in self.finders.iter().zip(&mut next) {
+                if s.is_some_and(|s| s < end) {
+                    *s = finder.find(&haystack[end..]).map(|i| i + end);
+                }
+            }
+        }
+    }
+
+    fn max_len(&self) -> usize {
+        self.needles().map(|n| n.len()).max().unwrap()
+    }
+
+    fn patterns(&self) -> usize {
+        self.finders.len()
+    }
+
+    fn could_start(&self, partial: &[u8]) -> bool {
+        self.needles().any(|n| n.starts_with(partial))
+    }
+
+    fn single_byte(&self) -> Option<u8> {
+        match (self.finders.len(), self.finders[0].needle()) {
+            (1, &[b]) => Some(b),
+            _ => None,
+        }
+    }
+}
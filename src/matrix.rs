@@ -0,0 +1,214 @@
+use crate::color;
+use crate::engine;
+use crate::input::{for_each_chunk, open_inputs};
+use crate::names;
+use crate::walk;
+use clap::builder::NonEmptyStringValueParser;
+use clap::Args;
+use freq::{Literals, Matcher};
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+#[derive(Args)]
+pub struct MatrixArgs {
+    #[arg(
+        help = "The files to count in, with - for stdin. Directories are searched recursively. If not provided, stdin is used."
+    )]
+    input: Vec<PathBuf>,
+
+    #[arg(
+        short = 'e',
+        long = "pattern",
+        value_name = "PATTERN",
+        required_unless_present = "sets",
+        value_parser = NonEmptyStringValueParser::new(),
+        help = "A pattern to count. Can be given more than once."
+    )]
+    pub patterns: Vec<String>,
+
+    #[arg(
+        short,
+        long = "set",
+        value_name = "NAME",
+        help = "Count the patterns of a set from the [patterns] table of the config file. Can be given more than once."
+    )]
+    pub sets: Vec<String>,
+
+    #[arg(
+        long,
+        help = "Print the table as CSV, with quotes where a pattern or file name needs them, instead of separated by tabs."
+    )]
+    csv: bool,
+
+    #[arg(
+        long,
+        help = "Only list the files that contain every pattern, such as configs that set both of two options, and only add those up in the totals."
+    )]
+    all_patterns: bool,
+}
+
+pub fn run(args: MatrixArgs, buffer_size: usize, out: &mut impl Write) {
+    // All patterns are counted in one pass over each file, with one automaton if there are many.
+    let patterns: Vec<&[u8]> = args.patterns.iter().map(|p| p.as_bytes()).collect();
+    let paths = walk::files(&args.input);
+    let rows = if patterns.len() > engine::FEW_PATTERNS {
+        count_rows(
+            engine::dictionary(&patterns),
+            &patterns,
+            &paths,
+            buffer_size,
+        )
+    } else {
+        count_rows(Literals::new(&patterns), &patterns, &paths, buffer_size)
+    };
+
+    print_table(out, &args, &paths, &rows).expect("failed to write output");
+}
+
+// Counts the patterns, which `matcher` finds, in each file.
+fn count_rows<M: Matcher + Clone>(
+    matcher: M,
+    patterns: &[&[u8]],
+    paths: &[PathBuf],
+    buffer_size: usize,
+) -> Vec<Vec<usize>> {
+    let prepared = engine::Prepared::new(matcher, patterns);
+    let mut rows = Vec::new();
+    for f in open_inputs(paths) {
+        let mut counter = prepared.counter();
+        for_each_chunk(f, buffer_size, |chunk| counter.write(chunk));
+        counter.finish();
+        rows.push(counter.counts().to_vec());
+    }
+    rows
+}
+
+// Prints a row for each file with the count of each pattern in it, under a row of the patterns,
+// and with more than one file, a row of totals. With --all-patterns, files that lack any of the
+// patterns are left out.
+fn print_table(
+    out: &mut impl Write,
+    args: &MatrixArgs,
+    paths: &[PathBuf],
+    rows: &[Vec<usize>],
+) -> io::Result<()> {
+    let sep = if args.csv { "," } else { "\t" };
+    // Color would end up in the cells of a CSV file.
+    let count = |n: usize| {
+        if args.csv {
+            n.to_string()
+        } else {
+            color::count(n).to_string()
+        }
+    };
+    let total = |s: String| {
+        if args.csv {
+            s
+        } else {
+            color::total(s).to_string()
+        }
+    };
+
+    write!(out, "file")?;
+    for pattern in &args.patterns {
+        write!(out, "{}", sep)?;
+        write_cell(out, args.csv, pattern.as_bytes())?;
+    }
+    writeln!(out)?;
+
+    let mut totals = vec![0; args.patterns.len()];
+    let mut listed = 0;
+    for (i, row) in rows.iter().enumerate() {
+        if args.all_patterns && row.contains(&0) {
+            continue;
+        }
+        listed += 1;
+        for (t, &n) in totals.iter_mut().zip(row) {
+            *t += n;
+        }
+        if !names::per_input() {
+            continue;
+        }
+        let name = names::of(paths.get(i).map(PathBuf::as_path));
+        write_cell(out, args.csv, &name)?;
+        for &n in row {
+            write!(out, "{}{}", sep, count(n))?;
+        }
+        writeln!(out)?;
+    }
+
+    if listed > 1 || !names::per_input() {
+        write!(out, "{}", total("total".to_string()))?;
+        for t in totals {
+            write!(out, "{}{}", sep, total(t.to_string()))?;
+        }
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+// Writes a pattern or file name, quoted for CSV if it has a comma, quote or line break in it.
+fn write_cell(out: &mut impl Write, csv: bool, cell: &[u8]) -> io::Result<()> {
+    if !csv
+        || !cell
+            .iter()
+            .any(|b| matches!(b, b',' | b'"' | b'\n' | b'\r'))
+    {
+        return out.write_all(cell);
+    }
+    out.write_all(b"\"")?;
+    for (i, part) in cell.split(|&b| b == b'"').enumerate() {
+        if i > 0 {
+            out.write_all(b"\"\"")?;
+        }
+        out.write_all(part)?;
+    }
+    out.write_all(b"\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_print_table() {
+        let mut args = MatrixArgs {
+            input: Vec::new(),
+            patterns: vec!["ERROR".to_string(), "took 1,5\"s".to_string()],
+            sets: Vec::new(),
+            csv: false,
+            all_patterns: false,
+        };
+        let paths = [PathBuf::from("a.log"), PathBuf::from("b,c.log")];
+        let rows = [vec![3, 0], vec![1, 2]];
+        let table = |args: &MatrixArgs| {
+            let mut out = Vec::new();
+            print_table(&mut out, args, &paths, &rows).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(
+            table(&args),
+            "\
+file\tERROR\ttook 1,5\"s
+a.log\t3\t0
+b,c.log\t1\t2
+total\t4\t2
+"
+        );
+        args.csv = true;
+        assert_eq!(
+            table(&args),
+            "\
+file,ERROR,\"took 1,5\"\"s\"
+a.log,3,0
+\"b,c.log\",1,2
+total,4,2
+"
+        );
+        args.all_patterns = true;
+        assert_eq!(
+            table(&args),
+            "file,ERROR,\"took 1,5\"\"s\"\n\"b,c.log\",1,2\n"
+        );
+    }
+}
@@ -0,0 +1,101 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Once;
+use tracing::info;
+
+// The most memory freq should use, from --max-memory, or 0 for no limit. It is shared out:
+//
+//   - an eighth to reading, which holds up to three buffers at once (one being filled, one
+//     waiting to be handed over and one being counted), and to the queues between threads
+//   - the rest to frequency tables, which turn into sketches, as with --approx, if they outgrow it
+static LIMIT: AtomicUsize = AtomicUsize::new(0);
+
+// The smallest buffer worth reading with, even if the limit is tiny.
+const MIN_BUFFER_SIZE: usize = 4096;
+
+pub fn set_limit(bytes: usize) {
+    LIMIT.store(bytes, Ordering::Relaxed);
+}
+
+pub fn limit() -> Option<usize> {
+    match LIMIT.load(Ordering::Relaxed) {
+        0 => None,
+        limit => Some(limit),
+    }
+}
+
+// How much reading and queues can take.
+#[cfg(not(target_os = "wasi"))]
+pub fn io_limit() -> Option<usize> {
+    limit().map(io_share)
+}
+
+// How much frequency tables can take, between them.
+pub fn table_limit() -> Option<usize> {
+    limit().map(table_share)
+}
+
+fn io_share(limit: usize) -> usize {
+    limit / 8
+}
+
+fn table_share(limit: usize) -> usize {
+    limit - io_share(limit)
+}
+
+// Returns the buffer size to read with: `requested`, unless three of them are more than reading
+// can take.
+pub fn buffer_size(requested: usize) -> usize {
+    fit_buffer(requested, max_buffer_size())
+}
+
+fn fit_buffer(requested: usize, max: Option<usize>) -> usize {
+    let Some(max) = max else {
+        return requested;
+    };
+    if requested <= max {
+        return requested;
+    }
+    info!(
+        "reading in buffers of {} bytes instead of {}, to stay within --max-memory",
+        max, requested
+    );
+    max
+}
+
+// The largest buffer size that reading can take, if there is a limit.
+pub fn max_buffer_size() -> Option<usize> {
+    limit().map(buffer_share)
+}
+
+fn buffer_share(limit: usize) -> usize {
+    (io_share(limit) / 3).max(MIN_BUFFER_SIZE)
+}
+
+// Says on stderr, once, that a table outgrew its share of the limit and is now estimated.
+pub fn warn_table_outgrown(what: &str) {
+    static WARNED: Once = Once::new();
+    WARNED.call_once(|| {
+        eprintln!(
+            "freq: the table outgrew --max-memory, so {} from here on",
+            what
+        );
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The shares are worked out from a given limit, since setting the global one would change
+    // it for the other tests running meanwhile.
+    #[test]
+    fn test_limits() {
+        let limit = 64 << 20;
+        assert_eq!(table_share(limit), 56 << 20);
+        let max = Some(buffer_share(limit));
+        assert_eq!(fit_buffer(1 << 20, max), 1 << 20);
+        assert_eq!(fit_buffer(16 << 20, max), (8 << 20) / 3);
+        assert_eq!(fit_buffer(16 << 20, None), 16 << 20);
+        assert_eq!(buffer_share(1024), MIN_BUFFER_SIZE);
+    }
+}
@@ -0,0 +1,36 @@
+use crate::report::Report;
+use clap::Args;
+use std::io::Write;
+use std::path::PathBuf;
+
+#[derive(Args)]
+pub struct MergeArgs {
+    #[arg(
+        required = true,
+        help = "The reports to merge, from --format json, such as one for each range of freq split."
+    )]
+    reports: Vec<PathBuf>,
+}
+
+pub fn run(args: MergeArgs, out: &mut impl Write) {
+    let mut merged = Report::default();
+    let mut merged_pattern = None;
+    for path in &args.reports {
+        let (report, pattern) = Report::read_with_pattern(path).unwrap_or_else(|e| {
+            eprintln!("freq: {}", e);
+            std::process::exit(2);
+        });
+        // Counts of different patterns don't add up to anything.
+        if merged_pattern.get_or_insert_with(|| pattern.clone()) != &pattern {
+            eprintln!(
+                "freq: {} counts another pattern than the reports before it",
+                path.display()
+            );
+            std::process::exit(2);
+        }
+        merged.merge(&report);
+    }
+    merged
+        .write_json(out, &merged_pattern.unwrap_or_default())
+        .expect("failed to write output");
+}
@@ -0,0 +1,164 @@
+use std::io::{self, Write};
+#[cfg(not(target_os = "wasi"))]
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+// Whether to measure how reading and counting spend their time, for --stats.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+// The time spent in a stage of reading, and the bytes it handled.
+pub struct Stage {
+    nanos: AtomicU64,
+    bytes: AtomicU64,
+}
+
+// Reading from the input.
+pub static READ: Stage = Stage::new();
+// Counting what was read.
+pub static COUNT: Stage = Stage::new();
+// The counter waiting for the reader to hand it a chunk.
+pub static COUNTER_WAIT: Stage = Stage::new();
+// The reader waiting for the counter to take a chunk, or give a buffer back.
+pub static READER_WAIT: Stage = Stage::new();
+
+// The chunks that have been read but not taken by the counter yet.
+#[cfg(not(target_os = "wasi"))]
+static QUEUED: AtomicUsize = AtomicUsize::new(0);
+
+// How often the counter left each number of chunks behind in the queue when it took one. The
+// last bucket is for that many or more.
+static OCCUPANCY: [AtomicU64; 9] = [const { AtomicU64::new(0) }; 9];
+
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+// Starts timing a stage, if measuring.
+pub fn timer() -> Option<Instant> {
+    enabled().then(Instant::now)
+}
+
+impl Stage {
+    const fn new() -> Self {
+        Stage {
+            nanos: AtomicU64::new(0),
+            bytes: AtomicU64::new(0),
+        }
+    }
+
+    // Adds the time since `timer` was started, and `bytes` handled in it.
+    pub fn add(&self, timer: Option<Instant>, bytes: usize) {
+        if let Some(started) = timer {
+            let nanos = started.elapsed().as_nanos().try_into().unwrap_or(u64::MAX);
+            self.nanos.fetch_add(nanos, Ordering::Relaxed);
+            self.bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+        }
+    }
+
+    fn time(&self) -> Duration {
+        Duration::from_nanos(self.nanos.load(Ordering::Relaxed))
+    }
+
+    fn bytes(&self) -> u64 {
+        self.bytes.load(Ordering::Relaxed)
+    }
+}
+
+// Records that the reader queued `n` more chunks.
+#[cfg(not(target_os = "wasi"))]
+pub fn queued(n: usize) {
+    if enabled() {
+        QUEUED.fetch_add(n, Ordering::Relaxed);
+    }
+}
+
+// Records that the counter took a chunk from the queue.
+#[cfg(not(target_os = "wasi"))]
+pub fn taken() {
+    if enabled() {
+        let left = QUEUED.fetch_sub(1, Ordering::Relaxed).saturating_sub(1);
+        OCCUPANCY[left.min(OCCUPANCY.len() - 1)].fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+// Prints what was measured, and whether freq was waiting on its inputs or on counting them.
+pub fn print(w: &mut impl Write) -> io::Result<()> {
+    for (name, stage) in [("read", &READ), ("count", &COUNT)] {
+        let secs = stage.time().as_secs_f64();
+        let rate = if secs > 0.0 {
+            format!("{:.1} MB/s", stage.bytes() as f64 / secs / 1e6)
+        } else {
+            String::from("-")
+        };
+        writeln!(
+            w,
+            "freq: {:<5} {} bytes in {:.3?} ({})",
+            name,
+            stage.bytes(),
+            stage.time(),
+            rate
+        )?;
+    }
+    let counter_wait = COUNTER_WAIT.time();
+    let reader_wait = READER_WAIT.time();
+    writeln!(
+        w,
+        "freq: the counter waited {:.3?} for data, and the reader {:.3?} to hand it over",
+        counter_wait, reader_wait
+    )?;
+
+    let occupancy: Vec<u64> = OCCUPANCY
+        .iter()
+        .map(|n| n.load(Ordering::Relaxed))
+        .collect();
+    if occupancy.iter().any(|&n| n > 0) {
+        let last = occupancy.len() - 1;
+        let buckets: Vec<String> = occupancy
+            .iter()
+            .enumerate()
+            .filter(|(_, &n)| n > 0)
+            .map(|(i, n)| match i {
+                i if i == last => format!("{}+: {}", i, n),
+                i => format!("{}: {}", i, n),
+            })
+            .collect();
+        writeln!(
+            w,
+            "freq: chunks left queued when one was taken: {}",
+            buckets.join(", ")
+        )?;
+    }
+
+    // Reading inline doesn't wait, so then it's down to which took longer.
+    let (io, cpu) = if counter_wait.is_zero() && reader_wait.is_zero() {
+        (READ.time(), COUNT.time())
+    } else {
+        (counter_wait, reader_wait)
+    };
+    let verdict = if io > cpu {
+        "I/O-bound, so faster storage or larger buffers may help"
+    } else {
+        "CPU-bound, so a faster matcher or more threads may help"
+    };
+    writeln!(w, "freq: {}", verdict)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stage() {
+        let stage = Stage::new();
+        stage.add(None, 10);
+        assert_eq!(stage.bytes(), 0);
+        stage.add(Some(Instant::now()), 10);
+        stage.add(Some(Instant::now()), 5);
+        assert_eq!(stage.bytes(), 15);
+    }
+}
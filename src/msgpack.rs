@@ -0,0 +1,88 @@
+use std::io::{self, Write};
+
+// Just enough of MessagePack (https://msgpack.org) to write reports.
+
+pub fn write_map_len(out: &mut impl Write, len: usize) -> io::Result<()> {
+    match len {
+        0..=15 => out.write_all(&[0x80 | len as u8]),
+        _ => write_long_len(out, len, 0xde, 0xdf),
+    }
+}
+
+pub fn write_uint(out: &mut impl Write, n: u64) -> io::Result<()> {
+    if n < 0x80 {
+        out.write_all(&[n as u8])
+    } else if n <= u8::MAX as u64 {
+        out.write_all(&[0xcc, n as u8])
+    } else if n <= u16::MAX as u64 {
+        out.write_all(&[0xcd])?;
+        out.write_all(&(n as u16).to_be_bytes())
+    } else if n <= u32::MAX as u64 {
+        out.write_all(&[0xce])?;
+        out.write_all(&(n as u32).to_be_bytes())
+    } else {
+        out.write_all(&[0xcf])?;
+        out.write_all(&n.to_be_bytes())
+    }
+}
+
+// Writes `s` as a string, or as binary if it isn't UTF-8, as can happen with paths.
+pub fn write_str(out: &mut impl Write, s: &[u8]) -> io::Result<()> {
+    let len = s.len();
+    if std::str::from_utf8(s).is_ok() {
+        match len {
+            0..=31 => out.write_all(&[0xa0 | len as u8])?,
+            32..=255 => out.write_all(&[0xd9, len as u8])?,
+            _ => write_long_len(out, len, 0xda, 0xdb)?,
+        }
+    } else {
+        match len {
+            0..=255 => out.write_all(&[0xc4, len as u8])?,
+            _ => write_long_len(out, len, 0xc5, 0xc6)?,
+        }
+    }
+    out.write_all(s)
+}
+
+// Writes a length that needs 16 or 32 bits, after the marker for that size.
+fn write_long_len(out: &mut impl Write, len: usize, marker16: u8, marker32: u8) -> io::Result<()> {
+    if let Ok(len) = u16::try_from(len) {
+        out.write_all(&[marker16])?;
+        out.write_all(&len.to_be_bytes())
+    } else if let Ok(len) = u32::try_from(len) {
+        out.write_all(&[marker32])?;
+        out.write_all(&len.to_be_bytes())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "too long for MessagePack",
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write() {
+        let mut out = Vec::new();
+        write_map_len(&mut out, 2).unwrap();
+        write_str(&mut out, b"a").unwrap();
+        write_uint(&mut out, 5).unwrap();
+        write_str(&mut out, b"\xff").unwrap();
+        write_uint(&mut out, 300).unwrap();
+        assert_eq!(
+            out,
+            [0x82, 0xa1, b'a', 0x05, 0xc4, 0x01, 0xff, 0xcd, 0x01, 0x2c]
+        );
+
+        let mut out = Vec::new();
+        write_str(&mut out, &[b'x'; 40]).unwrap();
+        assert_eq!(out[..2], [0xd9, 40]);
+        let mut out = Vec::new();
+        write_map_len(&mut out, 16).unwrap();
+        write_uint(&mut out, 1 << 40).unwrap();
+        assert_eq!(out, [0xde, 0, 16, 0xcf, 0, 0, 1, 0, 0, 0, 0, 0]);
+    }
+}
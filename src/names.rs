@@ -0,0 +1,122 @@
+use clap::ValueEnum;
+use std::io::{self, Write};
+use std::path::{self, Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+
+// How the paths of inputs are shown in results, as given to --path-style.
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum PathStyle {
+    /// Relative to the current directory, where it is inside it.
+    Relative,
+    /// From the root of the file system.
+    Absolute,
+    /// Just the file name.
+    Basename,
+}
+
+// The style of paths, with 0 for as they were given.
+static STYLE: AtomicU8 = AtomicU8::new(0);
+
+// Whether results leave out the names of inputs, with --no-filename.
+static HIDDEN: AtomicBool = AtomicBool::new(false);
+
+// Whether results leave out the inputs altogether, and only have totals, with --summary-only.
+static SUMMARY_ONLY: AtomicBool = AtomicBool::new(false);
+
+pub fn set_style(style: PathStyle) {
+    STYLE.store(style as u8 + 1, Ordering::Relaxed);
+}
+
+pub fn hide() {
+    HIDDEN.store(true, Ordering::Relaxed);
+}
+
+pub fn summarize() {
+    SUMMARY_ONLY.store(true, Ordering::Relaxed);
+}
+
+// Whether to print results for each input, and not only totals.
+pub fn per_input() -> bool {
+    !SUMMARY_ONLY.load(Ordering::Relaxed)
+}
+
+fn style() -> Option<PathStyle> {
+    match STYLE.load(Ordering::Relaxed) {
+        s if s == PathStyle::Relative as u8 + 1 => Some(PathStyle::Relative),
+        s if s == PathStyle::Absolute as u8 + 1 => Some(PathStyle::Absolute),
+        s if s == PathStyle::Basename as u8 + 1 => Some(PathStyle::Basename),
+        _ => None,
+    }
+}
+
+// The name of an input as it is shown: its path in the --path-style, or "-" for stdin.
+pub fn of(path: Option<&Path>) -> Vec<u8> {
+    match path {
+        Some(path) if path.as_os_str() != "-" => shown(path).into_os_string().into_encoded_bytes(),
+        _ => b"-".to_vec(),
+    }
+}
+
+// The path of an input file in the --path-style.
+pub fn shown(path: &Path) -> PathBuf {
+    style().map_or_else(|| path.to_path_buf(), |style| styled(path, style))
+}
+
+fn styled(path: &Path, style: PathStyle) -> PathBuf {
+    match style {
+        PathStyle::Relative => {
+            let Ok(dir) = std::env::current_dir() else {
+                return path.to_path_buf();
+            };
+            relative_to(path, &dir)
+        }
+        PathStyle::Absolute => path::absolute(path).unwrap_or_else(|_| path.to_path_buf()),
+        PathStyle::Basename => path
+            .file_name()
+            .map_or_else(|| path.to_path_buf(), PathBuf::from),
+    }
+}
+
+// Returns `path` relative to `dir` if it is inside it, or else as it is.
+fn relative_to(path: &Path, dir: &Path) -> PathBuf {
+    match path.strip_prefix(dir) {
+        Ok(rest) if rest.as_os_str().is_empty() => PathBuf::from("."),
+        Ok(rest) => rest.to_path_buf(),
+        Err(_) => path.to_path_buf(),
+    }
+}
+
+// Writes "<TAB>name" after the results for an input, unless --no-filename was given.
+pub fn write_column(out: &mut impl Write, name: &[u8]) -> io::Result<()> {
+    if HIDDEN.load(Ordering::Relaxed) {
+        return Ok(());
+    }
+    out.write_all(b"\t")?;
+    out.write_all(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_styled() {
+        let dir = Path::new("/srv/app");
+        assert_eq!(
+            relative_to(Path::new("/srv/app/logs/a.log"), dir),
+            Path::new("logs/a.log")
+        );
+        assert_eq!(relative_to(Path::new("/srv/app"), dir), Path::new("."));
+        assert_eq!(
+            relative_to(Path::new("/var/log/a.log"), dir),
+            Path::new("/var/log/a.log")
+        );
+        assert_eq!(
+            styled(Path::new("logs/a.log"), PathStyle::Basename),
+            Path::new("a.log")
+        );
+        assert!(styled(Path::new("logs/a.log"), PathStyle::Absolute).is_absolute());
+        assert_eq!(of(None), b"-");
+        assert_eq!(of(Some(Path::new("-"))), b"-");
+    }
+}
@@ -0,0 +1,190 @@
+use crate::matcher::{Match, Matcher};
+use memchr::memmem::{Finder, FinderRev};
+
+/// Matches a literal `first` followed by a literal `second` that starts at most `gap` bytes after
+/// it ends, such as a request id followed by an error. Whatever is between them, newlines
+/// included, is part of the match.
+///
+/// A match is reported from the last `first` before its `second`, so the longest match is
+/// `gap` bytes longer than the two literals.
+#[derive(Clone)]
+pub struct Near {
+    first: FinderRev<'static>,
+    second: Finder<'static>,
+    gap: usize,
+}
+
+impl Near {
+    pub fn new(first: &[u8], second: &[u8], gap: usize) -> Self {
+        assert!(
+            !first.is_empty() && !second.is_empty(),
+            "literals must be non-empty"
+        );
+        Near {
+            first: FinderRev::new(first).into_owned(),
+            second: Finder::new(second).into_owned(),
+            gap,
+        }
+    }
+
+    pub fn first(&self) -> &[u8] {
+        self.first.needle()
+    }
+
+    pub fn second(&self) -> &[u8] {
+        self.second.needle()
+    }
+
+    pub fn gap(&self) -> usize {
+        self.gap
+    }
+}
+
+impl PartialEq for Near {
+    fn eq(&self, other: &Self) -> bool {
+        self.first() == other.first() && self.second() == other.second() && self.gap == other.gap
+    }
+}
+
+impl Matcher for Near {
+    fn find_all(&self, haystack: &[u8], f: &mut dyn FnMut(Match) -> bool) {
+        let first_len = self.first().len();
+        let second_len = self.second().len();
+        // Where the previous match ended, and where to look for the next `second`. Occurrences
+        // of `second` can overlap, so each one that has no `first` before it is only skipped by
+        // a byte.
+        let mut from = 0;
+        let mut at = first_len;
+        while at + second_len <= haystack.len() {
+            let Some(found) = self.second.find(&haystack[at..]) else {
+                return;
+            };
+            let end_first = at + found;
+            let lo = from.max(end_first.saturating_sub(self.gap + first_len));
+            let Some(start) = self.first.rfind(&haystack[lo..end_first]) else {
+                at = end_first + 1;
+                continue;
+            };
+            let end = end_first + second_len;
+            let m = Match {
+                start: lo + start,
+                end,
+                pattern: 0,
+            };
+            if !f(m) {
+                return;
+            }
+            from = end;
+            at = end + first_len;
+        }
+    }
+
+    fn max_len(&self) -> usize {
+        self.first().len() + self.gap + self.second().len()
+    }
+
+    fn could_start(&self, partial: &[u8]) -> bool {
+        let first = self.first();
+        if partial.len() < first.len() {
+            first.starts_with(partial)
+        } else {
+            partial.starts_with(first)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Counter;
+
+    use proptest::string::bytes_regex;
+    use proptest::{prop_assert_eq, proptest};
+
+    // Takes the match that ends first, then the next one after it, and so on.
+    fn naive(first: &[u8], second: &[u8], gap: usize, haystack: &[u8]) -> Vec<usize> {
+        let mut ends = Vec::new();
+        let mut from = 0;
+        for end in 1..=haystack.len() {
+            let Some(start_second) = end.checked_sub(second.len()) else {
+                continue;
+            };
+            if !haystack[..end].ends_with(second) {
+                continue;
+            }
+            let found = (from..=start_second).any(|start| {
+                let end_first = start + first.len();
+                end_first <= start_second
+                    && start_second - end_first <= gap
+                    && haystack[start..].starts_with(first)
+            });
+            if found {
+                ends.push(end);
+                from = end;
+            }
+        }
+        ends
+    }
+
+    fn find(near: &Near, haystack: &[u8]) -> Vec<usize> {
+        let mut ends = Vec::new();
+        near.find_all(haystack, &mut |m| {
+            ends.push(m.end);
+            true
+        });
+        ends
+    }
+
+    #[test]
+    fn test_near() {
+        let near = Near::new(b"req=7", b"ERROR", 10);
+        let haystack = b"req=7 ok\nreq=7 then\nERROR\nERROR req=7 .............. ERROR";
+        let mut matches = Vec::new();
+        near.find_all(haystack, &mut |m| {
+            matches.push((m.start, m.end));
+            true
+        });
+        // The second ERROR has no req=7 before it since the first match, and the last one is
+        // too far from its req=7.
+        assert_eq!(matches, [(9, 25)]);
+        assert_eq!(find(&Near::new(b"ab", b"ba", 0), b"aba abba"), [8]);
+    }
+
+    proptest! {
+        #[test]
+        fn test_naive(
+            first in bytes_regex("((?s-u:[ab]{1,3}))").unwrap(),
+            second in bytes_regex("((?s-u:[ab]{1,3}))").unwrap(),
+            gap in 0..6_usize,
+            haystack in bytes_regex("((?s-u:[abc]{0,80}))").unwrap(),
+        ) {
+            let near = Near::new(&first, &second, gap);
+            prop_assert_eq!(find(&near, &haystack), naive(&first, &second, gap, &haystack));
+        }
+
+        #[test]
+        fn test_chunked(
+            chunk_size in 1..20_usize,
+            first in bytes_regex("((?s-u:[ab]{1,3}))").unwrap(),
+            second in bytes_regex("((?s-u:[ab]{1,3}))").unwrap(),
+            gap in 0..6_usize,
+            haystack in bytes_regex("((?s-u:[abc]{0,200}))").unwrap(),
+            split in 0..200_usize,
+        ) {
+            let near = Near::new(&first, &second, gap);
+            let expected = find(&near, &haystack).len();
+
+            let mut counter = Counter::with_matcher(near.clone());
+            haystack.chunks(chunk_size).for_each(|chunk| counter.write(chunk));
+            prop_assert_eq!(counter.count(), expected);
+
+            let split = split.min(haystack.len());
+            let mut left = Counter::mergeable(near.clone());
+            left.write(&haystack[..split]);
+            let mut right = Counter::mergeable(near);
+            right.write(&haystack[split..]);
+            left.merge(right);
+            prop_assert_eq!(left.count(), expected);
+        }
+    }
+}
@@ -0,0 +1,140 @@
+use caseless::Caseless;
+use clap::{Args, ValueEnum};
+use unicode_normalization::UnicodeNormalization;
+
+#[derive(Args)]
+pub struct NormalizeArgs {
+    #[arg(
+        long,
+        help = "Count keys that differ only in case as one, such as \"Error\" and \"ERROR\". Keys are printed in lowercase."
+    )]
+    pub fold_case: bool,
+
+    #[arg(
+        long,
+        value_enum,
+        help = "Normalize the Unicode in keys, so that text with accents counts as one key however the accents are encoded."
+    )]
+    pub normalize: Option<Normalization>,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum Normalization {
+    /// Canonical composition, which prefers precomposed characters such as "é" over "e" followed
+    /// by a combining accent.
+    Nfc,
+}
+
+// Rewrites keys to a normal form. Invalid UTF-8 is left as it is.
+pub struct Normalizer {
+    fold_case: bool,
+    nfc: bool,
+
+    // Kept to avoid reallocating it.
+    out: Vec<u8>,
+}
+
+impl Normalizer {
+    // Returns None if the arguments don't ask for any normalization.
+    pub fn new(args: &NormalizeArgs) -> Option<Self> {
+        let nfc = args.normalize == Some(Normalization::Nfc);
+        (args.fold_case || nfc).then(|| Normalizer {
+            fold_case: args.fold_case,
+            nfc,
+            out: Vec::new(),
+        })
+    }
+
+    // Returns the normal form of `key`, which may be `key` itself.
+    pub fn apply<'a>(&'a mut self, key: &'a [u8]) -> &'a [u8] {
+        // ASCII is already in NFC.
+        if key.is_ascii() {
+            if !self.fold_case || !key.iter().any(u8::is_ascii_uppercase) {
+                return key;
+            }
+            self.out.clear();
+            self.out.extend(key.iter().map(u8::to_ascii_lowercase));
+            return &self.out;
+        }
+
+        self.out.clear();
+        for chunk in key.utf8_chunks() {
+            let s = chunk.valid();
+            match (self.fold_case, self.nfc) {
+                // Folding decomposed text catches marks that fold to letters, as in "ᾳ".
+                (true, true) => extend(&mut self.out, s.chars().nfd().default_case_fold().nfc()),
+                (true, false) => extend(&mut self.out, s.chars().default_case_fold()),
+                (false, true) => extend(&mut self.out, s.nfc()),
+                (false, false) => self.out.extend(s.as_bytes()),
+            }
+            self.out.extend(chunk.invalid());
+        }
+        &self.out
+    }
+}
+
+fn extend(out: &mut Vec<u8>, chars: impl Iterator<Item = char>) {
+    let mut buf = [0; 4];
+    for c in chars {
+        out.extend(c.encode_utf8(&mut buf).as_bytes());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn normalize(key: &[u8], fold_case: bool, normalize: bool) -> Vec<u8> {
+        let args = NormalizeArgs {
+            fold_case,
+            normalize: normalize.then_some(Normalization::Nfc),
+        };
+        Normalizer::new(&args).unwrap().apply(key).to_vec()
+    }
+
+    fn nfc(s: &str) -> String {
+        String::from_utf8(normalize(s.as_bytes(), false, true)).unwrap()
+    }
+
+    #[test]
+    fn test_nfc() {
+        assert_eq!(nfc("cafe\u{301}"), "caf\u{e9}");
+        assert_eq!(nfc("caf\u{e9}"), "caf\u{e9}");
+        // Marks are reordered before composing: dot below (class 220) comes before dot above
+        // (class 230), and composes with the d.
+        assert_eq!(nfc("d\u{307}\u{323}"), "\u{1E0D}\u{307}");
+        assert_eq!(nfc("\u{1E0B}\u{323}"), "\u{1E0D}\u{307}");
+        // Hangul, in jamo and as a syllable.
+        assert_eq!(nfc("\u{1100}\u{1161}\u{11A8}"), "\u{AC01}");
+        assert_eq!(nfc("\u{AC01}"), "\u{AC01}");
+        // Singletons and composition exclusions are not recomposed.
+        assert_eq!(nfc("\u{F900}\u{212B}"), "\u{8C48}\u{C5}");
+        assert_eq!(nfc("\u{915}\u{93C}"), "\u{915}\u{93C}");
+        // A mark with nothing to attach to.
+        assert_eq!(nfc("\u{301}e"), "\u{301}e");
+    }
+
+    #[test]
+    fn test_fold_case() {
+        assert_eq!(normalize(b"ERROR", true, false), b"error");
+        assert_eq!(normalize("Stra\u{DF}E".as_bytes(), true, false), b"strasse");
+        assert_eq!(
+            normalize("STRA\u{1E9E}E".as_bytes(), true, false),
+            b"strasse"
+        );
+        // The iota subscript folds to a letter after the marks it is canonically ordered with.
+        assert_eq!(
+            normalize("\u{1F8F}\u{324}".as_bytes(), true, true),
+            "\u{1F07}\u{324}\u{3B9}".as_bytes()
+        );
+        assert_eq!(
+            normalize(&["\u{C9}t\u{c9}".as_bytes(), b"\xff"].concat(), true, false),
+            ["\u{e9}t\u{e9}".as_bytes(), b"\xff"].concat()
+        );
+        assert_eq!(
+            normalize("E\u{301}T\u{e9}".as_bytes(), true, true),
+            "\u{e9}t\u{e9}".as_bytes()
+        );
+        assert_eq!(normalize(b"ok\xff", true, true), b"ok\xff");
+    }
+}
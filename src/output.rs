@@ -0,0 +1,176 @@
+use crate::layout;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, IsTerminal, StderrLock, StdoutLock, Write};
+use std::path::{Path, PathBuf};
+
+// Where results are printed.
+pub enum Output {
+    Stdout(BufWriter<StdoutLock<'static>>),
+    Stderr(StderrLock<'static>),
+
+    // The results are kept until they are complete, and then written to a temporary file that
+    // replaces the file, so that nothing ever sees it half-written.
+    File {
+        path: PathBuf,
+        append: bool,
+        results: Vec<u8>,
+    },
+
+    // The results are kept until they are complete, and then laid out as a table, for
+    // --format table.
+    Table {
+        out: Box<Output>,
+        boxed: bool,
+        results: Vec<u8>,
+    },
+}
+
+impl Output {
+    pub fn stdout() -> Self {
+        Output::Stdout(BufWriter::new(io::stdout().lock()))
+    }
+
+    pub fn stderr() -> Self {
+        Output::Stderr(io::stderr().lock())
+    }
+
+    // Writes to `path` once the results are complete, after what it already contains if
+    // `append` is set.
+    pub fn file(path: PathBuf, append: bool) -> Self {
+        Output::File {
+            path,
+            append,
+            results: Vec::new(),
+        }
+    }
+
+    // Lays out the results printed to `out` as a table, in a box if `boxed` is set.
+    pub fn table(out: Output, boxed: bool) -> Self {
+        Output::Table {
+            out: Box::new(out),
+            boxed,
+            results: Vec::new(),
+        }
+    }
+
+    // Whether the results are shown on a terminal.
+    pub fn is_terminal(&self) -> bool {
+        match self {
+            Output::Stdout(_) => io::stdout().is_terminal(),
+            Output::Stderr(_) => io::stderr().is_terminal(),
+            Output::File { .. } => false,
+            Output::Table { out, .. } => out.is_terminal(),
+        }
+    }
+
+    // Finishes printing the results.
+    pub fn commit(self) -> io::Result<()> {
+        match self {
+            Output::Stdout(mut out) => out.flush(),
+            Output::Stderr(mut out) => out.flush(),
+            Output::File {
+                path,
+                append,
+                results,
+            } => replace(&path, append, &results),
+            Output::Table {
+                mut out,
+                boxed,
+                results,
+            } => {
+                // Long cells are only cut down to fit a terminal.
+                let width = out.is_terminal().then(layout::terminal_width).flatten();
+                layout::write_table(&mut out, &results, boxed, width)?;
+                out.commit()
+            }
+        }
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Output::Stdout(out) => out.write(buf),
+            Output::Stderr(out) => out.write(buf),
+            Output::File { results, .. } => results.write(buf),
+            Output::Table { results, .. } => results.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Output::Stdout(out) => out.flush(),
+            Output::Stderr(out) => out.flush(),
+            Output::File { .. } | Output::Table { .. } => Ok(()),
+        }
+    }
+}
+
+// Replaces the file at `path` with `contents`, or appends them, by writing a temporary file next
+// to it and renaming that over it.
+pub fn replace(path: &Path, append: bool, contents: &[u8]) -> io::Result<()> {
+    let Some(name) = path.file_name() else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} is not a file name", path.display()),
+        ));
+    };
+    // Renaming is only atomic within a file system, so the temporary file is in the same
+    // directory.
+    let dir = path.parent().unwrap_or(Path::new(""));
+    let temp = dir.join(format!(
+        ".{}.{}.tmp",
+        name.to_string_lossy(),
+        std::process::id()
+    ));
+
+    let res = (|| {
+        let mut f = File::create(&temp)?;
+        if append {
+            match File::open(path) {
+                Ok(mut old) => {
+                    io::copy(&mut old, &mut f)?;
+                }
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e),
+            }
+        }
+        f.write_all(contents)?;
+        f.sync_all()?;
+        fs::rename(&temp, path)
+    })();
+    if res.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file() {
+        let dir = std::env::temp_dir().join(format!("freq-output-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("results.tsv");
+
+        let write = |text: &str, append: bool| {
+            let mut out = Output::file(path.clone(), append);
+            out.write_all(text.as_bytes()).unwrap();
+            // Nothing is written until the results are complete.
+            assert!(!path.exists() || fs::read_to_string(&path).unwrap() != text);
+            out.commit().unwrap();
+        };
+        write("1\ta\n", false);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "1\ta\n");
+        write("2\tb\n", true);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "1\ta\n2\tb\n");
+        write("3\tc\n", false);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "3\tc\n");
+
+        // Only the results are left.
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,146 @@
+use crate::color;
+use crate::names;
+use clap::ValueEnum;
+use std::io::{self, Write};
+
+// What --rate divides counts by.
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum Rate {
+    /// Matches per MiB read.
+    #[value(name = "per-mb")]
+    Mb,
+    /// Matches per GiB read.
+    #[value(name = "per-gb")]
+    Gb,
+    /// Matches per line read. A last line without a newline still counts.
+    #[value(name = "per-line")]
+    Line,
+}
+
+impl Rate {
+    fn unit(self) -> &'static str {
+        match self {
+            Rate::Mb => "per MB",
+            Rate::Gb => "per GB",
+            Rate::Line => "per line",
+        }
+    }
+}
+
+// How much of an input was read.
+#[derive(Clone, Copy, Default)]
+pub struct Scanned {
+    bytes: u64,
+    newlines: u64,
+    // Whether the last byte read was a newline, or nothing was read.
+    ended_line: bool,
+}
+
+impl Scanned {
+    pub fn new() -> Self {
+        Scanned {
+            ended_line: true,
+            ..Default::default()
+        }
+    }
+
+    pub fn write(&mut self, chunk: &[u8], rate: Rate) {
+        let Some(&last) = chunk.last() else {
+            return;
+        };
+        self.bytes += chunk.len() as u64;
+        if rate == Rate::Line {
+            self.newlines += bytecount::count(chunk, b'\n') as u64;
+            self.ended_line = last == b'\n';
+        }
+    }
+
+    fn add(&mut self, other: &Scanned) {
+        self.bytes += other.bytes;
+        self.newlines += other.newlines + u64::from(!other.ended_line);
+    }
+
+    fn per(&self, rate: Rate) -> f64 {
+        match rate {
+            Rate::Mb => self.bytes as f64 / (1u64 << 20) as f64,
+            Rate::Gb => self.bytes as f64 / (1u64 << 30) as f64,
+            Rate::Line => (self.newlines + u64::from(!self.ended_line)) as f64,
+        }
+    }
+}
+
+// Prints "count<TAB>rate<TAB>name" for each input, then the total count and rate over all of
+// them. Inputs that were empty have no rate.
+pub fn print(
+    out: &mut impl Write,
+    rate: Rate,
+    names: &[Vec<u8>],
+    counts: &[usize],
+    scanned: &[Scanned],
+) -> io::Result<()> {
+    let mut all = Scanned::new();
+    for ((name, &count), s) in names.iter().zip(counts).zip(scanned) {
+        all.add(s);
+        if !names::per_input() {
+            continue;
+        }
+        write_rate(out, count, s, rate)?;
+        names::write_column(out, name)?;
+        writeln!(out)?;
+    }
+    write_total(out, counts.iter().sum(), &all, rate)?;
+    writeln!(out)
+}
+
+fn write_rate(out: &mut impl Write, count: usize, scanned: &Scanned, rate: Rate) -> io::Result<()> {
+    write!(out, "{}\t", color::count(count))?;
+    match scanned.per(rate) {
+        0.0 => write!(out, "-"),
+        per => write!(out, "{:.2} {}", count as f64 / per, rate.unit()),
+    }
+}
+
+fn write_total(
+    out: &mut impl Write,
+    count: usize,
+    scanned: &Scanned,
+    rate: Rate,
+) -> io::Result<()> {
+    write!(out, "{}\t", color::total(count))?;
+    match scanned.per(rate) {
+        0.0 => write!(out, "-"),
+        per => write!(
+            out,
+            "{}",
+            color::total(format!("{:.2} {}", count as f64 / per, rate.unit()))
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_print() {
+        let mut a = Scanned::new();
+        a.write(b"x\ny\n", Rate::Line);
+        a.write(b"z", Rate::Line);
+        let mut b = Scanned::new();
+        b.write(b"w\n", Rate::Line);
+        let names = [b"a".to_vec(), b"b".to_vec(), b"c".to_vec()];
+        let mut out = Vec::new();
+        print(
+            &mut out,
+            Rate::Line,
+            &names,
+            &[3, 1, 0],
+            &[a, b, Scanned::new()],
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "3\t1.00 per line\ta\n1\t1.00 per line\tb\n0\t-\tc\n4\t1.00 per line\n"
+        );
+    }
+}
@@ -0,0 +1,756 @@
+use crate::color;
+use crate::expr::{Expr, Query};
+use crate::input::{for_each_chunk, Input};
+use crate::linelimit::{self, Limit};
+use crate::names;
+use freq::{Literal, Matcher};
+use memchr::memmem::{Finder, FinderRev};
+use std::io::{self, Write};
+
+// How the input is split into records.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Bounds {
+    // Each record ends with the separator, except maybe the last.
+    Separator(Vec<u8>),
+    // Each record is this many bytes long, except maybe the last.
+    Size(usize),
+}
+
+// Counts the records that a query holds for into `tally`, along with whatever else it was made to
+// count.
+pub fn count(
+    query: &Query,
+    bounds: &Bounds,
+    columns: Option<Columns>,
+    tally: Tally,
+    inputs: Vec<Input>,
+    buffer_size: usize,
+) -> Tally {
+    let mut records = RecordCounter::new(query, bounds, columns, tally);
+    for f in inputs {
+        for_each_chunk(f, buffer_size, |chunk| records.write(chunk));
+        records.finish();
+    }
+    records.tally
+}
+
+// A record separator as given to --record-sep. Clap would take a bare Vec<u8> for a list of
+// bytes, one per value.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecordSep(pub Vec<u8>);
+
+impl RecordSep {
+    pub fn parse(s: &str) -> Result<RecordSep, String> {
+        parse_separator(s).map(RecordSep)
+    }
+}
+
+// Parses a record separator of one or more bytes, which may include the escapes \0, \t, \n, \r,
+// \\ and \xNN.
+pub fn parse_separator(s: &str) -> Result<Vec<u8>, String> {
+    let mut sep = Vec::new();
+    let mut bytes = s.bytes();
+    while let Some(b) = bytes.next() {
+        if b != b'\\' {
+            sep.push(b);
+            continue;
+        }
+        sep.push(match bytes.next() {
+            Some(b'0') => 0,
+            Some(b't') => b'\t',
+            Some(b'n') => b'\n',
+            Some(b'r') => b'\r',
+            Some(b'\\') => b'\\',
+            Some(b'x') => {
+                let hex = [bytes.next(), bytes.next()];
+                match hex {
+                    [Some(a), Some(b)] if a.is_ascii_hexdigit() && b.is_ascii_hexdigit() => {
+                        let hex = [a, b];
+                        let hex = std::str::from_utf8(&hex).expect("hex digits are ASCII");
+                        u8::from_str_radix(hex, 16).expect("these are hex digits")
+                    }
+                    _ => return Err("\\x must be followed by two hex digits".to_string()),
+                }
+            }
+            _ => return Err("unknown escape; use \\0, \\t, \\n, \\r, \\\\ or \\xNN".to_string()),
+        });
+    }
+    if sep.is_empty() {
+        return Err("the separator must be non-empty".to_string());
+    }
+    Ok(sep)
+}
+
+// A range of byte offsets within each record where matches of the first pattern of a query
+// count. Others are ignored, as if the record didn't contain them.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Columns {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Columns {
+    // Parses a range of byte columns like cut's: "20-80", "20-" or "-80", numbered from 1 and
+    // inclusive, or a single column.
+    pub fn parse(s: &str) -> Result<Columns, String> {
+        let parse = |n: &str| match n.parse::<usize>() {
+            Ok(0) => Err("columns are numbered from 1".to_string()),
+            Ok(n) => Ok(n),
+            Err(e) => Err(format!("invalid column {:?}: {}", n, e)),
+        };
+        let (first, last) = match s.split_once('-') {
+            Some(("", "")) => return Err("expected a range like 20-80".to_string()),
+            Some(("", last)) => (1, parse(last)?),
+            Some((first, "")) => (parse(first)?, usize::MAX),
+            Some((first, last)) => (parse(first)?, parse(last)?),
+            None => (parse(s)?, parse(s)?),
+        };
+        if first > last {
+            return Err("the range must not end before it starts".to_string());
+        }
+        Ok(Columns {
+            start: first - 1,
+            end: last,
+        })
+    }
+
+    fn contains(&self, offset: usize) -> bool {
+        (self.start..self.end).contains(&offset)
+    }
+}
+
+// The records that a query holds for.
+#[derive(Debug, Default, PartialEq)]
+pub struct Tally {
+    pub count: usize,
+
+    // The matches of the first pattern in the counted records, if they are being counted.
+    pub matches: Option<usize>,
+
+    // How many matches of the first pattern start at each offset within the counted records,
+    // if they are being counted.
+    pub offsets: Option<Vec<usize>>,
+
+    // Statistics over all records, if they are being gathered.
+    pub per_record: Option<PerRecord>,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct PerRecord {
+    pub records: usize,
+
+    // The most matches of the first pattern in any counted record.
+    pub max_matches: usize,
+}
+
+impl Tally {
+    pub fn new(offsets: bool, per_record: bool) -> Self {
+        Tally {
+            count: 0,
+            matches: per_record.then_some(0),
+            offsets: offsets.then(Vec::new),
+            per_record: per_record.then(PerRecord::default),
+        }
+    }
+
+    // Whether every record has to be passed to `add`, not just those that may match.
+    fn needs_all(&self) -> bool {
+        self.per_record.is_some()
+    }
+
+    fn add(&mut self, filter: &Filter, record: &[u8]) {
+        let Some(record) = filter.limit.apply(record) else {
+            return;
+        };
+        if let Some(stats) = &mut self.per_record {
+            stats.records += 1;
+        }
+        if !filter.matches(record) {
+            return;
+        }
+        self.count += 1;
+        if self.matches.is_none() && self.offsets.is_none() {
+            return;
+        }
+
+        let mut matches = 0;
+        filter.find_first(record, &mut |start| {
+            matches += 1;
+            if let Some(offsets) = &mut self.offsets {
+                if start >= offsets.len() {
+                    offsets.resize(start + 1, 0);
+                }
+                offsets[start] += 1;
+            }
+            true
+        });
+        if let Some(total) = &mut self.matches {
+            *total += matches;
+        }
+        if let Some(stats) = &mut self.per_record {
+            stats.max_matches = stats.max_matches.max(matches);
+        }
+    }
+
+    // Prints the offsets that had matches in order, if they were counted, and then the count, or
+    // the statistics if they were gathered.
+    pub fn print(&self, out: &mut impl Write) -> io::Result<()> {
+        for (offset, &n) in self.offsets.iter().flatten().enumerate() {
+            if n > 0 {
+                writeln!(out, "{}\t{}", color::count(n), offset)?;
+            }
+        }
+        let Some(stats) = &self.per_record else {
+            return writeln!(out, "{}", color::count(self.count));
+        };
+        writeln!(out, "{}\trecords", color::count(stats.records))?;
+        writeln!(out, "{}\trecords with a match", color::count(self.count))?;
+        writeln!(
+            out,
+            "{:.2}%\tof records with a match",
+            percent(self.count, stats.records)
+        )?;
+        writeln!(out, "{}\tmatches", color::count(self.matches.unwrap_or(0)))?;
+        writeln!(
+            out,
+            "{}\tmost matches in a record",
+            color::count(stats.max_matches)
+        )
+    }
+}
+
+// Prints "count<TAB>records<TAB>percent<TAB>name" for each input, from tallies that gathered
+// statistics, then the same over all of them without a name.
+pub fn print_percent(out: &mut impl Write, names: &[Vec<u8>], tallies: &[Tally]) -> io::Result<()> {
+    let records = |tally: &Tally| tally.per_record.as_ref().map_or(0, |stats| stats.records);
+    let per_input = if names::per_input() { tallies } else { &[] };
+    for (name, tally) in names.iter().zip(per_input) {
+        let n = records(tally);
+        write!(
+            out,
+            "{}\t{}\t{:.2}%",
+            color::count(tally.count),
+            n,
+            percent(tally.count, n)
+        )?;
+        names::write_column(out, name)?;
+        writeln!(out)?;
+    }
+    let count = tallies.iter().map(|tally| tally.count).sum();
+    let n = tallies.iter().map(records).sum();
+    writeln!(
+        out,
+        "{}\t{}\t{}",
+        color::total(count),
+        color::total(n),
+        color::total(format!("{:.2}%", percent(count, n)))
+    )
+}
+
+// What percentage of `records` the `count` is, or 0 if there are none.
+fn percent(count: usize, records: usize) -> f64 {
+    if records == 0 {
+        0.0
+    } else {
+        100.0 * count as f64 / records as f64
+    }
+}
+
+pub struct RecordCounter {
+    filter: Filter,
+    split: Split,
+    tally: Tally,
+
+    // The start of a record that continues into the next chunk. It never contains a whole
+    // separator, or a whole fixed-size record.
+    carry: Vec<u8>,
+}
+
+// Decides whether a query holds for a record.
+struct Filter {
+    patterns: Vec<Literal>,
+    expr: Expr,
+
+    // Where matches of the first pattern count, if not everywhere.
+    columns: Option<Columns>,
+
+    // A pattern that every counted record contains, if there is one.
+    required: Option<usize>,
+
+    // What to do with records longer than --max-line-length.
+    limit: Limit,
+}
+
+enum Split {
+    Separator(Box<Separator>),
+    Size(usize),
+}
+
+struct Separator {
+    finder: Finder<'static>,
+    finder_rev: FinderRev<'static>,
+
+    // Whether two occurrences of the separator can overlap, as in "\n\n\n". If not, searching
+    // for separators backwards finds the same ones as searching forwards.
+    overlaps: bool,
+}
+
+impl RecordCounter {
+    pub fn new(query: &Query, bounds: &Bounds, columns: Option<Columns>, tally: Tally) -> Self {
+        let split = match bounds {
+            Bounds::Separator(sep) => {
+                assert!(!sep.is_empty(), "the separator must be non-empty");
+                Split::Separator(Box::new(Separator {
+                    finder: Finder::new(sep).into_owned(),
+                    finder_rev: FinderRev::new(sep).into_owned(),
+                    overlaps: (1..sep.len()).any(|i| sep[i..] == sep[..sep.len() - i]),
+                }))
+            }
+            Bounds::Size(size) => {
+                assert!(*size > 0, "the record size must be positive");
+                Split::Size(*size)
+            }
+        };
+        RecordCounter {
+            filter: Filter {
+                patterns: query.patterns.iter().map(|p| Literal::new(p)).collect(),
+                expr: query.expr.clone(),
+                columns,
+                required: query.expr.required(),
+                limit: linelimit::get(),
+            },
+            split,
+            tally,
+            carry: Vec::new(),
+        }
+    }
+
+    pub fn write(&mut self, mut buf: &[u8]) {
+        let RecordCounter {
+            filter,
+            split,
+            tally,
+            carry,
+        } = self;
+        if !carry.is_empty() {
+            // Finish the record that was cut off at the end of the last chunk.
+            let used = match split {
+                Split::Separator(sep) => {
+                    let Some(end) = sep.find_end(carry, buf) else {
+                        filter.limit.extend(carry, buf, sep.len() - 1);
+                        return;
+                    };
+                    let used = end + sep.len() - carry.len();
+                    carry.extend(&buf[..used]);
+                    carry.truncate(end);
+                    used
+                }
+                Split::Size(size) => {
+                    let used = buf.len().min(*size - carry.len());
+                    carry.extend(&buf[..used]);
+                    if carry.len() < *size {
+                        return;
+                    }
+                    used
+                }
+            };
+            tally.add(filter, carry);
+            carry.clear();
+            buf = &buf[used..];
+        }
+
+        // Records without the required pattern are only skipped if none need to be seen, such as
+        // to tell whether they are too long.
+        let required = filter
+            .required
+            .filter(|_| !tally.needs_all() && !filter.limit.is_set())
+            .map(|r| &filter.patterns[r]);
+        let mut visit = |record: &[u8]| tally.add(filter, record);
+        match split {
+            Split::Separator(sep) => {
+                let end = sep.records(buf, required, &mut visit);
+                filter.limit.extend(carry, &buf[end..], sep.len() - 1);
+            }
+            Split::Size(size) => {
+                let end = fixed_records(*size, buf, required, &mut visit);
+                carry.extend(&buf[end..]);
+            }
+        }
+    }
+
+    // Counts the last record of the current input, if it has no trailing separator or is short.
+    pub fn finish(&mut self) {
+        if !self.carry.is_empty() {
+            self.tally.add(&self.filter, &self.carry);
+            self.carry.clear();
+        }
+    }
+}
+
+impl Filter {
+    fn matches(&self, record: &[u8]) -> bool {
+        self.expr.eval(&mut |i| match self.columns {
+            Some(_) if i == 0 => {
+                let mut found = false;
+                self.find_first(record, &mut |_| {
+                    found = true;
+                    false
+                });
+                found
+            }
+            _ => find(&self.patterns[i], record).is_some(),
+        })
+    }
+
+    // Calls `f` with where each match of the first pattern in `record` that counts starts,
+    // until it returns false.
+    fn find_first(&self, record: &[u8], f: &mut impl FnMut(usize) -> bool) {
+        let columns = self.columns;
+        self.patterns[0].find_all(record, &mut |m| match columns {
+            Some(c) if m.start >= c.end => false,
+            Some(c) if !c.contains(m.start) => true,
+            _ => f(m.start),
+        });
+    }
+}
+
+impl Separator {
+    fn len(&self) -> usize {
+        self.finder.needle().len()
+    }
+
+    // Returns where the separator that ends `carry` is, given the chunk after it. The separator
+    // may be split between the two, so look for one that starts in the carry first.
+    fn find_end(&self, carry: &[u8], buf: &[u8]) -> Option<usize> {
+        let n = self.len();
+        let tail = carry.len().saturating_sub(n - 1);
+        let mut joint = carry[tail..].to_vec();
+        joint.extend(&buf[..buf.len().min(n - 1)]);
+        match self.finder.find(&joint) {
+            Some(i) => Some(tail + i),
+            None => self.finder.find(buf).map(|i| carry.len() + i),
+        }
+    }
+
+    // Calls `visit` with the records in `text` that end with a separator, skipping some that
+    // don't contain the `required` pattern. Returns where the last of them ends.
+    fn records(
+        &self,
+        text: &[u8],
+        required: Option<&Literal>,
+        visit: &mut impl FnMut(&[u8]),
+    ) -> usize {
+        if let Some(r) = required.filter(|_| !self.overlaps) {
+            return self.records_with(r, text, visit);
+        }
+
+        let n = self.len();
+        let mut start = 0;
+
+        // Only the records with a match of the required pattern need checking, so keep track of
+        // where the next one is.
+        let next_required = |from: usize| find(required?, &text[from..]).map(|i| from + i);
+        let mut next = next_required(0);
+        for end in self.finder.find_iter(text) {
+            if required.is_none() || next.is_some_and(|i| i < end) {
+                visit(&text[start..end]);
+                next = next_required(end + n);
+            }
+            start = end + n;
+        }
+        start
+    }
+
+    // Like `records`, but only looks at the records around matches of the required pattern,
+    // finding their bounds by searching backwards and forwards from the match.
+    fn records_with(
+        &self,
+        required: &Literal,
+        text: &[u8],
+        visit: &mut impl FnMut(&[u8]),
+    ) -> usize {
+        let n = self.len();
+        let mut from = 0;
+        while let Some(i) = find(required, &text[from..]).map(|i| from + i) {
+            // Look for the separator before the match, or one the match starts inside of.
+            let before = &text[from..(i + n - 1).min(text.len())];
+            let start = self.finder_rev.rfind(before).map_or(from, |j| from + j + n);
+            if start > i {
+                from = start;
+                continue;
+            }
+            let Some(end) = self.finder.find(&text[i..]).map(|j| i + j) else {
+                // The record continues into the next chunk.
+                return start;
+            };
+            visit(&text[start..end]);
+            from = end + n;
+        }
+        self.finder_rev
+            .rfind(&text[from..])
+            .map_or(from, |j| from + j + n)
+    }
+}
+
+// Calls `visit` with the whole records of `size` bytes in `text`, skipping those that don't
+// contain the `required` pattern. Returns where the last of them ends.
+fn fixed_records(
+    size: usize,
+    text: &[u8],
+    required: Option<&Literal>,
+    visit: &mut impl FnMut(&[u8]),
+) -> usize {
+    let end = text.len() / size * size;
+    let Some(required) = required else {
+        text[..end].chunks_exact(size).for_each(visit);
+        return end;
+    };
+    let mut from = 0;
+    while let Some(i) = find(required, &text[from..end]).map(|i| from + i) {
+        // The match may run into the next record, in which case the record is still checked but
+        // won't be counted for it.
+        let start = i / size * size;
+        visit(&text[start..start + size]);
+        from = start + size;
+    }
+    end
+}
+
+// Returns where the first match of `pattern` in `text` starts.
+fn find(pattern: &Literal, text: &[u8]) -> Option<usize> {
+    let mut start = None;
+    pattern.find_all(text, &mut |m| {
+        start = Some(m.start);
+        false
+    });
+    start
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use proptest::string::bytes_regex;
+    use proptest::{prop_assert_eq, proptest};
+
+    fn tally_chunked(
+        query: &str,
+        bounds: Bounds,
+        tally: Tally,
+        text: &[u8],
+        chunk_size: usize,
+    ) -> Tally {
+        let query = Query::parse(query).unwrap();
+        let mut records = RecordCounter::new(&query, &bounds, None, tally);
+        text.chunks(chunk_size)
+            .for_each(|chunk| records.write(chunk));
+        records.finish();
+        records.tally
+    }
+
+    fn count_chunked(query: &str, sep: &[u8], text: &[u8], chunk_size: usize) -> usize {
+        let bounds = Bounds::Separator(sep.to_vec());
+        tally_chunked(query, bounds, Tally::default(), text, chunk_size).count
+    }
+
+    // Splits `text` into records the simple way.
+    fn split<'a>(text: &'a [u8], sep: &[u8]) -> Vec<&'a [u8]> {
+        let mut records = Vec::new();
+        let mut rest = text;
+        while !rest.is_empty() {
+            match rest.windows(sep.len()).position(|w| w == sep) {
+                Some(i) => {
+                    records.push(&rest[..i]);
+                    rest = &rest[i + sep.len()..];
+                }
+                None => {
+                    records.push(rest);
+                    break;
+                }
+            }
+        }
+        records
+    }
+
+    #[test]
+    fn test_records() {
+        let text = b"ab c\nab\nc\ncab ab\nc ab\n\nx";
+        assert_eq!(count_chunked(r#""ab" & "c""#, b"\n", text, 100), 3);
+        assert_eq!(count_chunked(r#""ab" & !"c""#, b"\n", text, 100), 1);
+        assert_eq!(count_chunked(r#"!"ab""#, b"\n", text, 100), 3);
+        assert_eq!(count_chunked(r#""a\nb""#, b"\0", b"a\nb\0a\0a\nb", 100), 2);
+
+        // Paragraphs.
+        let text = b"ERROR x\n  at y\n\nINFO\n\n\nERROR z\n";
+        assert_eq!(count_chunked(r#""ERROR""#, b"\n\n", text, 3), 2);
+        assert_eq!(count_chunked(r#""ERROR" & "at""#, b"\n\n", text, 3), 1);
+    }
+
+    #[test]
+    fn test_fixed_size() {
+        let text = b"ab..|.ab.|...a|b...|.ab";
+        let tally = tally_chunked(r#""ab""#, Bounds::Size(5), Tally::new(true, false), text, 2);
+        assert_eq!(tally.count, 3);
+        assert_eq!(tally.offsets, Some(vec![1, 2]));
+
+        let mut out = Vec::new();
+        tally.print(&mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "1\t0\n2\t1\n3\n");
+
+        let tally = tally_chunked(r#"!"ab""#, Bounds::Size(5), Tally::default(), text, 100);
+        assert_eq!(tally.count, 2);
+    }
+
+    #[test]
+    fn test_per_record() {
+        let text = b"ab ab\nab\nc\nab ab ab c\n";
+        let query = r#""ab" & !"c""#;
+        let bounds = Bounds::Separator(b"\n".to_vec());
+        let tally = tally_chunked(query, bounds, Tally::new(false, true), text, 3);
+        assert_eq!(tally.count, 2);
+        assert_eq!(tally.matches, Some(3));
+        assert_eq!(
+            tally.per_record,
+            Some(PerRecord {
+                records: 4,
+                max_matches: 2,
+            })
+        );
+
+        let mut out = Vec::new();
+        tally.print(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "\
+4\trecords
+2\trecords with a match
+50.00%\tof records with a match
+3\tmatches
+2\tmost matches in a record
+"
+        );
+    }
+
+    #[test]
+    fn test_print_percent() {
+        let bounds = Bounds::Separator(b"\n".to_vec());
+        let tally = |text: &[u8]| {
+            tally_chunked(r#""ab""#, bounds.clone(), Tally::new(false, true), text, 3)
+        };
+        let tallies = [tally(b"ab\nc\nab ab\nd"), tally(b"")];
+        let names = [b"a.log".to_vec(), b"b.log".to_vec()];
+        let mut out = Vec::new();
+        print_percent(&mut out, &names, &tallies).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "2\t4\t50.00%\ta.log\n0\t0\t0.00%\tb.log\n2\t4\t50.00%\n"
+        );
+    }
+
+    #[test]
+    fn test_columns() {
+        assert_eq!(Columns::parse("20-80"), Ok(Columns { start: 19, end: 80 }));
+        assert_eq!(Columns::parse("-3"), Ok(Columns { start: 0, end: 3 }));
+        assert_eq!(
+            Columns::parse("5-"),
+            Ok(Columns {
+                start: 4,
+                end: usize::MAX
+            })
+        );
+        assert_eq!(Columns::parse("7"), Ok(Columns { start: 6, end: 7 }));
+        assert!(Columns::parse("0-3").is_err());
+        assert!(Columns::parse("4-3").is_err());
+        assert!(Columns::parse("-").is_err());
+        assert!(Columns::parse("a-3").is_err());
+
+        // Columns 3 to 5 of each line.
+        let text = b"xyab ab\nab   ab\nxxxxab\nxyzzzab\nabababab\n";
+        let columns = Columns::parse("3-5").unwrap();
+        let query = Query::all_but(vec![b"ab".to_vec()], Vec::new());
+        let bounds = Bounds::Separator(b"\n".to_vec());
+        let tally = Tally::new(false, true);
+        let mut records = RecordCounter::new(&query, &bounds, Some(columns), tally);
+        text.chunks(4).for_each(|chunk| records.write(chunk));
+        records.finish();
+        assert_eq!(records.tally.count, 3);
+        assert_eq!(records.tally.matches, Some(4));
+    }
+
+    #[test]
+    fn test_parse_separator() {
+        assert_eq!(parse_separator(";"), Ok(b";".to_vec()));
+        assert_eq!(parse_separator("\\0"), Ok(b"\0".to_vec()));
+        assert_eq!(parse_separator("\\n\\n"), Ok(b"\n\n".to_vec()));
+        assert_eq!(parse_separator("--\\x1e"), Ok(b"--\x1e".to_vec()));
+        assert!(parse_separator("\\xzz").is_err());
+        assert!(parse_separator("\\q").is_err());
+        assert!(parse_separator("").is_err());
+    }
+
+    proptest! {
+        #[test]
+        fn test_chunked(
+            chunk_size in 1..20_usize,
+            text in bytes_regex("((?s-u:[abc\\n]{0,200}))").unwrap(),
+            sep in proptest::sample::select(vec![&b"\n"[..], b"\n\n", b"ca", b"aaa"]),
+        ) {
+            let has_ab = |record: &[u8]| record.windows(2).any(|w| w == b"ab");
+
+            let expected = split(&text, sep)
+                .into_iter()
+                .filter(|r| has_ab(r) && r.contains(&b'c'))
+                .count();
+            prop_assert_eq!(count_chunked(r#""ab" & "c""#, sep, &text, chunk_size), expected);
+
+            let expected = split(&text, sep)
+                .into_iter()
+                .filter(|r| !has_ab(r) || !r.contains(&b'c'))
+                .count();
+            prop_assert_eq!(count_chunked(r#"!"ab" | !"c""#, sep, &text, chunk_size), expected);
+
+            let bounds = Bounds::Separator(sep.to_vec());
+            let tally = tally_chunked(r#""ab""#, bounds, Tally::new(false, true), &text, chunk_size);
+            let stats = tally.per_record.unwrap();
+            prop_assert_eq!(stats.records, split(&text, sep).len());
+            prop_assert_eq!(tally.count, split(&text, sep).into_iter().filter(|r| has_ab(r)).count());
+        }
+
+        #[test]
+        fn test_limit_chunked(
+            chunk_size in 1..20_usize,
+            max in 0..10_usize,
+            text in bytes_regex("((?s-u:[abc\\n]{0,200}))").unwrap(),
+            sep in proptest::sample::select(vec![&b"\n"[..], b"\n\n", b"ca", b"aaa"]),
+        ) {
+            let query = Query::parse(r#""ab""#).unwrap();
+            let bounds = Bounds::Separator(sep.to_vec());
+            let mut records = RecordCounter::new(&query, &bounds, None, Tally::new(false, true));
+            records.filter.limit = Limit::new(max, linelimit::Policy::Truncate);
+            text.chunks(chunk_size).for_each(|chunk| records.write(chunk));
+            records.finish();
+
+            let truncated: Vec<_> = split(&text, sep).into_iter().map(|r| &r[..r.len().min(max)]).collect();
+            let has_ab = |record: &[u8]| record.windows(2).any(|w| w == b"ab");
+            prop_assert_eq!(records.tally.count, truncated.iter().filter(|r| has_ab(r)).count());
+            prop_assert_eq!(records.tally.per_record.unwrap().records, truncated.len());
+        }
+
+        #[test]
+        fn test_fixed_size_chunked(
+            chunk_size in 1..20_usize,
+            size in 1..8_usize,
+            text in bytes_regex("((?s-u:[abc]{0,200}))").unwrap(),
+        ) {
+            let tally = tally_chunked(r#""ab""#, Bounds::Size(size), Tally::default(), &text, chunk_size);
+            let records = text.chunks(size).filter(|r| r.windows(2).any(|w| w == b"ab"));
+            prop_assert_eq!(tally.count, records.count());
+
+            let tally = tally_chunked(r#"!"ab" & "c""#, Bounds::Size(size), Tally::default(), &text, chunk_size);
+            let records = text
+                .chunks(size)
+                .filter(|r| !r.windows(2).any(|w| w == b"ab") && r.contains(&b'c'));
+            prop_assert_eq!(tally.count, records.count());
+        }
+    }
+}
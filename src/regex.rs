@@ -0,0 +1,143 @@
+use crate::matcher::{Match, Matcher};
+use regex_automata::dfa::regex::Regex as Dfa;
+use regex_automata::util::syntax;
+use regex_automata::Input;
+use std::sync::Arc;
+
+/// Matches a regular expression with a fully compiled DFA, searching forwards for where the
+/// earliest match ends and then backwards for where it starts.
+///
+/// Every match has to be non-empty and of bounded length, so that the counter knows how far back
+/// a match spanning chunks can start, and look-around such as `^` and `\b` isn't supported, since
+/// a chunk doesn't know what came before it.
+#[derive(Clone)]
+pub struct Regex {
+    pattern: String,
+    dfa: Arc<Dfa>,
+    max_len: usize,
+}
+
+impl Regex {
+    pub fn new(pattern: &str) -> Result<Self, String> {
+        let hir = syntax::parse(pattern).map_err(|e| e.to_string())?;
+        let props = hir.properties();
+        if !props.look_set().is_empty() {
+            return Err("look-around such as ^, $ and \\b isn't supported".to_string());
+        }
+        if props.minimum_len() == Some(0) {
+            return Err("the pattern must not match the empty string".to_string());
+        }
+        let Some(max_len) = props.maximum_len() else {
+            return Err("the pattern's matches must be of bounded length".to_string());
+        };
+        let dfa = Dfa::new(pattern).map_err(|e| e.to_string())?;
+        Ok(Regex {
+            pattern: pattern.to_string(),
+            dfa: Arc::new(dfa),
+            max_len,
+        })
+    }
+
+    pub fn pattern(&self) -> &str {
+        &self.pattern
+    }
+}
+
+impl PartialEq for Regex {
+    fn eq(&self, other: &Self) -> bool {
+        self.pattern == other.pattern
+    }
+}
+
+impl Matcher for Regex {
+    fn find_all(&self, haystack: &[u8], f: &mut dyn FnMut(Match) -> bool) {
+        let mut from = 0;
+        while from < haystack.len() {
+            let input = Input::new(haystack).range(from..).earliest(true);
+            let found = self
+                .dfa
+                .try_search(&input)
+                .expect("the DFA has no quit bytes");
+            let Some(found) = found else {
+                return;
+            };
+            let m = Match {
+                start: found.start(),
+                end: found.end(),
+                pattern: 0,
+            };
+            if !f(m) {
+                return;
+            }
+            from = m.end;
+        }
+    }
+
+    fn max_len(&self) -> usize {
+        self.max_len
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Counter, Literals};
+
+    use proptest::string::bytes_regex;
+    use proptest::{prop_assert_eq, proptest};
+
+    fn ends(matcher: &impl Matcher, haystack: &[u8]) -> Vec<usize> {
+        let mut ends = Vec::new();
+        matcher.find_all(haystack, &mut |m| {
+            ends.push(m.end);
+            true
+        });
+        ends
+    }
+
+    #[test]
+    fn test_regex() {
+        let regex = Regex::new("ERR(OR)?|FATAL").unwrap();
+        // The earliest end wins, so ERROR is matched as ERR.
+        assert_eq!(ends(&regex, b"ERROR, FATAL"), [3, 12]);
+        assert!(Regex::new("a*").is_err());
+        assert!(Regex::new("ab+").is_err());
+        assert!(Regex::new("^ab").is_err());
+        assert!(Regex::new("(").is_err());
+    }
+
+    proptest! {
+        #[test]
+        fn test_literals(
+            needles in proptest::collection::vec("[ab]{1,6}", 1..6),
+            haystack in bytes_regex("((?s-u:[abc]{0,200}))").unwrap(),
+        ) {
+            // An alternation of literals ends its matches where the literals do.
+            let regex = Regex::new(&needles.join("|")).unwrap();
+            prop_assert_eq!(ends(&regex, &haystack), ends(&Literals::new(&needles), &haystack));
+        }
+
+        #[test]
+        fn test_chunked(
+            chunk_size in 1..20_usize,
+            pattern in proptest::sample::select(vec!["a|b", "ab|ba", "a[bc]a", "b{2,3}", "ab?c"]),
+            haystack in bytes_regex("((?s-u:[abc]{0,200}))").unwrap(),
+            split in 0..200_usize,
+        ) {
+            let regex = Regex::new(pattern).unwrap();
+            let expected = ends(&regex, &haystack).len();
+
+            let mut counter = Counter::with_matcher(regex.clone());
+            haystack.chunks(chunk_size).for_each(|chunk| counter.write(chunk));
+            prop_assert_eq!(counter.count(), expected);
+
+            let split = split.min(haystack.len());
+            let mut left = Counter::mergeable(regex.clone());
+            left.write(&haystack[..split]);
+            let mut right = Counter::mergeable(regex);
+            right.write(&haystack[split..]);
+            left.merge(right);
+            prop_assert_eq!(left.count(), expected);
+        }
+    }
+}
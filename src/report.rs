@@ -0,0 +1,263 @@
+use crate::json;
+use crate::msgpack;
+use clap::ValueEnum;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum Format {
+    // The total, with the count of each pattern first if there are several.
+    Text,
+    // The same as text, with its columns lined up.
+    Table,
+    // A JSON object with the count in each file and the total, which --baseline can read back.
+    Json,
+    // A JSON object for each event as the run goes on, one per line.
+    Jsonl,
+    // The same as json, in MessagePack, which is more compact.
+    Msgpack,
+}
+
+// What --format jsonl reports, as soon as it happens.
+pub enum Event<'a> {
+    Start { file: &'a [u8] },
+    Finish { file: &'a [u8], count: usize },
+    Error { file: &'a [u8], message: &'a str },
+    Total { pattern: &'a [u8], count: usize },
+}
+
+impl Event<'_> {
+    // Writes the event as a line of JSON, and flushes it so that it's seen right away.
+    pub fn write(&self, out: &mut impl Write) -> io::Result<()> {
+        match self {
+            Event::Start { file } => {
+                out.write_all(b"{\"event\":\"start\",\"file\":")?;
+                json::write_string(out, file)?;
+                writeln!(out, "}}")?;
+            }
+            Event::Finish { file, count } => {
+                out.write_all(b"{\"event\":\"finish\",\"file\":")?;
+                json::write_string(out, file)?;
+                writeln!(out, ",\"count\":{}}}", count)?;
+            }
+            Event::Error { file, message } => {
+                out.write_all(b"{\"event\":\"error\",\"file\":")?;
+                json::write_string(out, file)?;
+                out.write_all(b",\"message\":")?;
+                json::write_string(out, message.as_bytes())?;
+                writeln!(out, "}}")?;
+            }
+            Event::Total { pattern, count } => {
+                out.write_all(b"{\"event\":\"total\",\"pattern\":")?;
+                json::write_string(out, pattern)?;
+                writeln!(out, ",\"count\":{}}}", count)?;
+            }
+        }
+        out.flush()
+    }
+}
+
+// The count of the pattern in each file, as printed by --format json.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Report {
+    pub files: Vec<(Vec<u8>, usize)>,
+}
+
+impl Report {
+    pub fn total(&self) -> usize {
+        self.files.iter().map(|(_, count)| count).sum()
+    }
+
+    pub fn read(path: &Path) -> Result<Report, String> {
+        Report::read_with_pattern(path).map(|(report, _)| report)
+    }
+
+    // Reads a report, and the pattern it counts.
+    pub fn read_with_pattern(path: &Path) -> Result<(Report, Vec<u8>), String> {
+        let doc =
+            fs::read(path).map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+        let not_report = || format!("{} is not a report from --format json", path.display());
+        let report = Report::parse(&doc).ok_or_else(not_report)?;
+        let pattern = json::string(&doc, ".pattern").ok_or_else(not_report)?;
+        Ok((report, pattern))
+    }
+
+    // Adds the counts in `other` to these, file by file. Files that are only in `other` come
+    // after the rest.
+    pub fn merge(&mut self, other: &Report) {
+        let mut index: HashMap<Vec<u8>, usize> = self
+            .files
+            .iter()
+            .enumerate()
+            .map(|(i, (path, _))| (path.clone(), i))
+            .collect();
+        for (path, count) in &other.files {
+            match index.get(path) {
+                Some(&i) => self.files[i].1 += count,
+                None => {
+                    index.insert(path.clone(), self.files.len());
+                    self.files.push((path.clone(), *count));
+                }
+            }
+        }
+    }
+
+    fn parse(doc: &[u8]) -> Option<Report> {
+        let files = json::members(doc, ".files")?
+            .into_iter()
+            .map(|(path, count)| Some((path, count.as_u64()?.try_into().ok()?)))
+            .collect::<Option<_>>()?;
+        Some(Report { files })
+    }
+
+    // Writes the report as a single line of JSON.
+    pub fn write_json(&self, out: &mut impl Write, pattern: &[u8]) -> io::Result<()> {
+        out.write_all(b"{\"pattern\":")?;
+        json::write_string(out, pattern)?;
+        out.write_all(b",\"files\":{")?;
+        for (i, (path, count)) in self.files.iter().enumerate() {
+            if i > 0 {
+                out.write_all(b",")?;
+            }
+            json::write_string(out, path)?;
+            write!(out, ":{}", count)?;
+        }
+        writeln!(out, "}},\"total\":{}}}", self.total())
+    }
+
+    // Writes the report as a MessagePack map with the same fields as the JSON.
+    pub fn write_msgpack(&self, out: &mut impl Write, pattern: &[u8]) -> io::Result<()> {
+        msgpack::write_map_len(out, 3)?;
+        msgpack::write_str(out, b"pattern")?;
+        msgpack::write_str(out, pattern)?;
+        msgpack::write_str(out, b"files")?;
+        msgpack::write_map_len(out, self.files.len())?;
+        for (path, count) in &self.files {
+            msgpack::write_str(out, path)?;
+            msgpack::write_uint(out, *count as u64)?;
+        }
+        msgpack::write_str(out, b"total")?;
+        msgpack::write_uint(out, self.total() as u64)
+    }
+
+    // Prints "old<TAB>new<TAB>file" for each file whose count differs from `baseline`, with "-"
+    // for files that are only in one of them, and then the totals. Returns whether any count
+    // went up.
+    pub fn print_diff(&self, out: &mut impl Write, baseline: &Report) -> io::Result<bool> {
+        let mut files: BTreeMap<&[u8], (Option<usize>, Option<usize>)> = BTreeMap::new();
+        for (path, count) in &baseline.files {
+            files.entry(path).or_default().0 = Some(*count);
+        }
+        for (path, count) in &self.files {
+            files.entry(path).or_default().1 = Some(*count);
+        }
+
+        let show = |count: Option<usize>| count.map_or("-".to_string(), |c| c.to_string());
+        let mut regressed = false;
+        for (path, (old, new)) in files {
+            if old == new {
+                continue;
+            }
+            regressed |= new.unwrap_or(0) > old.unwrap_or(0);
+            write!(out, "{}\t{}\t", show(old), show(new))?;
+            out.write_all(path)?;
+            writeln!(out)?;
+        }
+        writeln!(out, "{}\t{}\ttotal", baseline.total(), self.total())?;
+        Ok(regressed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(files: &[(&str, usize)]) -> Report {
+        Report {
+            files: files
+                .iter()
+                .map(|&(p, c)| (p.as_bytes().to_vec(), c))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_json() {
+        let current = report(&[("a.rs", 2), ("dir/\"b\".rs", 0)]);
+        let mut out = Vec::new();
+        current.write_json(&mut out, b"old_api(").unwrap();
+        assert_eq!(
+            String::from_utf8(out.clone()).unwrap(),
+            "{\"pattern\":\"old_api(\",\"files\":{\"a.rs\":2,\"dir/\\\"b\\\".rs\":0},\"total\":2}\n"
+        );
+        assert_eq!(Report::parse(&out), Some(current.clone()));
+
+        let mut out = Vec::new();
+        current.write_msgpack(&mut out, b"x").unwrap();
+        assert_eq!(
+            out,
+            b"\x83\xa7pattern\xa1x\xa5files\x82\xa4a.rs\x02\xaadir/\"b\".rs\x00\xa5total\x02"
+        );
+        assert_eq!(Report::parse(b"{\"files\":{\"a\":-1}}"), None);
+    }
+
+    #[test]
+    fn test_events() {
+        let mut out = Vec::new();
+        Event::Start { file: b"a.log" }.write(&mut out).unwrap();
+        Event::Finish {
+            file: b"a.log",
+            count: 3,
+        }
+        .write(&mut out)
+        .unwrap();
+        Event::Error {
+            file: b"b.log",
+            message: "b.log: Permission denied",
+        }
+        .write(&mut out)
+        .unwrap();
+        Event::Total {
+            pattern: b"ERROR",
+            count: 3,
+        }
+        .write(&mut out)
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "\
+{\"event\":\"start\",\"file\":\"a.log\"}
+{\"event\":\"finish\",\"file\":\"a.log\",\"count\":3}
+{\"event\":\"error\",\"file\":\"b.log\",\"message\":\"b.log: Permission denied\"}
+{\"event\":\"total\",\"pattern\":\"ERROR\",\"count\":3}
+"
+        );
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut merged = report(&[("a.log", 2), ("b.log", 1)]);
+        merged.merge(&report(&[("b.log", 3), ("c.log", 0)]));
+        assert_eq!(merged, report(&[("a.log", 2), ("b.log", 4), ("c.log", 0)]));
+    }
+
+    #[test]
+    fn test_diff() {
+        let baseline = report(&[("a.rs", 2), ("b.rs", 1), ("c.rs", 4)]);
+
+        let mut out = Vec::new();
+        let current = report(&[("a.rs", 1), ("b.rs", 1), ("d.rs", 0)]);
+        assert!(!current.print_diff(&mut out, &baseline).unwrap());
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "2\t1\ta.rs\n4\t-\tc.rs\n-\t0\td.rs\n7\t2\ttotal\n"
+        );
+
+        let mut out = Vec::new();
+        let current = report(&[("a.rs", 2), ("b.rs", 1), ("c.rs", 4), ("d.rs", 1)]);
+        assert!(current.print_diff(&mut out, &baseline).unwrap());
+        assert_eq!(String::from_utf8(out).unwrap(), "-\t1\td.rs\n7\t8\ttotal\n");
+    }
+}
@@ -0,0 +1,385 @@
+use freq::Matcher;
+use std::io::{self, Write};
+
+// Keeps a uniformly random sample of the matches in a stream, or the first few, with the bytes
+// around them.
+//
+// This searches the stream itself, alongside the counter, since the counter doesn't keep track
+// of where its matches are. Matches are chosen the same way, so the sample is drawn from exactly
+// the matches that are counted.
+pub struct Sampler<M> {
+    matcher: M,
+
+    // How many matches to keep, and how many bytes of context to keep on each side.
+    size: usize,
+    context: usize,
+
+    // Whether to keep the first matches instead of random ones.
+    first: bool,
+
+    rng: fastrand::Rng,
+
+    // The number of matches seen so far.
+    seen: usize,
+
+    samples: Vec<Sample>,
+
+    // The number of the current input.
+    input: usize,
+
+    // The end of the current input, from `context` bytes before where the next match might
+    // start, and where that is in the input.
+    window: Vec<u8>,
+    window_start: usize,
+    resume: usize,
+}
+
+pub struct Sample {
+    // Which input the match is in, and where.
+    pub input: usize,
+    pub offset: usize,
+
+    // The match with up to `context` bytes before and after it, and one more on each side if
+    // there is one, to tell whether the context was cut short.
+    text: Vec<u8>,
+    before: usize,
+    len: usize,
+    context: usize,
+
+    // The number of bytes of context after the match that we are still waiting for.
+    missing: usize,
+}
+
+impl<M: Matcher> Sampler<M> {
+    pub fn new(matcher: M, size: usize, context: usize) -> Self {
+        Sampler {
+            matcher,
+            size,
+            // One byte more than is shown, to tell whether there is more.
+            context: context + 1,
+            first: false,
+            rng: fastrand::Rng::new(),
+            seen: 0,
+            samples: Vec::with_capacity(size),
+            input: 0,
+            window: Vec::new(),
+            window_start: 0,
+            resume: 0,
+        }
+    }
+
+    // Keeps the first matches instead of a random sample.
+    pub fn keep_first(mut self) -> Self {
+        self.first = true;
+        self
+    }
+
+    pub fn write(&mut self, buf: &[u8]) {
+        self.window.extend(buf);
+        let from = self.resume - self.window_start;
+        let mut last_end = from;
+        let mut matches = Vec::new();
+        self.matcher.find_all(&self.window[from..], &mut |m| {
+            matches.push((from + m.start, from + m.end));
+            last_end = from + m.end;
+            true
+        });
+        for (start, end) in matches {
+            self.offer(start, end);
+        }
+
+        // Fill in the context after earlier samples from this input.
+        let window_end = self.window_start + self.window.len();
+        for sample in &mut self.samples {
+            if sample.input == self.input && sample.missing > 0 {
+                let end = sample.offset + sample.len + sample.context_after();
+                let more = sample.missing.min(window_end - end);
+                let from = end - self.window_start;
+                sample.text.extend(&self.window[from..from + more]);
+                sample.missing -= more;
+            }
+        }
+
+        // Find where the next match could start, as the counter does, and forget everything
+        // before the context we need for it.
+        let n = self.matcher.max_len();
+        let l = self.window.len().saturating_sub(n - 1).max(last_end);
+        let next = (l..self.window.len())
+            .find(|&i| self.matcher.could_start(&self.window[i..]))
+            .unwrap_or(self.window.len());
+        self.resume = self.window_start + next;
+        let keep = next.saturating_sub(self.context);
+        self.window.drain(..keep);
+        self.window_start += keep;
+    }
+
+    // Ends the current input.
+    pub fn finish(&mut self) {
+        self.input += 1;
+        self.window.clear();
+        self.window_start = 0;
+        self.resume = 0;
+    }
+
+    // Returns the samples in the order they occur.
+    pub fn into_samples(mut self) -> Vec<Sample> {
+        self.samples.sort_by_key(|s| (s.input, s.offset));
+        self.samples
+    }
+
+    // Considers the match at `start..end` of the window for the sample.
+    fn offer(&mut self, start: usize, end: usize) {
+        self.seen += 1;
+        let slot = if self.samples.len() < self.size {
+            self.samples.len()
+        } else if self.first {
+            return;
+        } else {
+            // Replacing a random sample with probability size / seen keeps every match seen so
+            // far equally likely to be in the sample.
+            let i = self.rng.usize(..self.seen);
+            if i >= self.size {
+                return;
+            }
+            i
+        };
+
+        let from = start.saturating_sub(self.context);
+        let sample = Sample {
+            input: self.input,
+            offset: self.window_start + start,
+            text: self.window[from..end].to_vec(),
+            before: start - from,
+            len: end - start,
+            context: self.context - 1,
+            missing: self.context,
+        };
+        if slot == self.samples.len() {
+            self.samples.push(sample);
+        } else {
+            self.samples[slot] = sample;
+        }
+    }
+}
+
+impl Sample {
+    // The number of bytes of context after the match that we have so far.
+    fn context_after(&self) -> usize {
+        self.text.len() - self.before - self.len
+    }
+
+    pub fn before(&self) -> &[u8] {
+        &self.text[self.before.saturating_sub(self.context)..self.before]
+    }
+
+    pub fn matched(&self) -> &[u8] {
+        &self.text[self.before..self.before + self.len]
+    }
+
+    pub fn after(&self) -> &[u8] {
+        let start = self.before + self.len;
+        &self.text[start..start + self.context_after().min(self.context)]
+    }
+
+    // Whether there is more of the input before or after the context shown.
+    pub fn cut_before(&self) -> bool {
+        self.before > self.context
+    }
+
+    pub fn cut_after(&self) -> bool {
+        self.context_after() > self.context
+    }
+
+    // Writes the context before the match, the match and the context after it, separated by
+    // tabs. With `lines`, the context is cut down to that many whole lines on each side besides
+    // the match's own. Context that was cut short is marked with "…".
+    pub fn write(&self, out: &mut impl Write, lines: Option<usize>) -> io::Result<()> {
+        let (mut before, mut cut_before) = (self.before(), self.cut_before());
+        let (mut after, mut cut_after) = (self.after(), self.cut_after());
+        if let Some(n) = lines {
+            if let Some(i) = memchr::memrchr_iter(b'\n', before).nth(n) {
+                before = &before[i + 1..];
+                cut_before = false;
+            }
+            if let Some(i) = memchr::memchr_iter(b'\n', after).nth(n) {
+                after = &after[..i];
+                cut_after = false;
+            }
+        }
+
+        if cut_before {
+            out.write_all("…".as_bytes())?;
+        }
+        write_escaped(out, before)?;
+        out.write_all(b"\t")?;
+        write_escaped(out, self.matched())?;
+        out.write_all(b"\t")?;
+        write_escaped(out, after)?;
+        if cut_after {
+            out.write_all("…".as_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+// How much context to show around a match.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Context {
+    Bytes(usize),
+    Lines(usize),
+}
+
+// The most bytes of context kept on each side of a match when showing lines of context, since
+// lines can be arbitrarily long.
+const MAX_LINE_CONTEXT: usize = 4096;
+
+impl Context {
+    // Parses a number of bytes, like "40", or of lines, like "2l".
+    pub fn parse(s: &str) -> Result<Context, String> {
+        let (digits, lines) = match s.strip_suffix(['l', 'L']) {
+            Some(digits) => (digits, true),
+            None => (s, false),
+        };
+        let n = digits.parse::<usize>().map_err(|_| {
+            format!(
+                "invalid context '{}'; use a number of bytes, or lines like 2l",
+                s
+            )
+        })?;
+        Ok(if lines {
+            Context::Lines(n)
+        } else {
+            Context::Bytes(n)
+        })
+    }
+
+    // How many bytes to keep on each side of a match.
+    pub fn bytes(self) -> usize {
+        match self {
+            Context::Bytes(n) => n,
+            Context::Lines(_) => MAX_LINE_CONTEXT,
+        }
+    }
+
+    pub fn lines(self) -> Option<usize> {
+        match self {
+            Context::Bytes(_) => None,
+            Context::Lines(n) => Some(n),
+        }
+    }
+}
+
+// Writes bytes so that they stay on one line: control characters, backslashes and invalid UTF-8
+// are escaped.
+pub fn write_escaped(out: &mut impl Write, bytes: &[u8]) -> io::Result<()> {
+    for chunk in bytes.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            match c {
+                '\t' => out.write_all(b"\\t")?,
+                '\n' => out.write_all(b"\\n")?,
+                '\r' => out.write_all(b"\\r")?,
+                '\\' => out.write_all(b"\\\\")?,
+                c if c.is_control() => write!(out, "\\x{:02x}", c as u32)?,
+                c => write!(out, "{}", c)?,
+            }
+        }
+        for b in chunk.invalid() {
+            write!(out, "\\x{:02x}", b)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use freq::Literal;
+    use proptest::{prop_assert_eq, proptest};
+
+    fn sample(text: &[u8], chunk_size: usize, size: usize) -> Vec<(usize, String)> {
+        let mut sampler = Sampler::new(Literal::new(b"aba"), size, 2);
+        for input in text.split(|&b| b == b'|') {
+            input.chunks(chunk_size).for_each(|c| sampler.write(c));
+            sampler.finish();
+        }
+        sampler
+            .into_samples()
+            .into_iter()
+            .map(|s| {
+                let text = [s.before(), b"[", s.matched(), b"]", s.after()].concat();
+                (s.offset, String::from_utf8(text).unwrap())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_write_escaped() {
+        let mut out = Vec::new();
+        write_escaped(&mut out, b"a\tb\n\\\x01\xff\xc3\xa9").unwrap();
+        assert_eq!(out, "a\\tb\\n\\\\\\x01\\xffé".as_bytes());
+    }
+
+    // Shows the first matches of "b" with the given context.
+    fn show(text: &[u8], size: usize, context: Context) -> Vec<String> {
+        let mut sampler = Sampler::new(Literal::new(b"b"), size, context.bytes()).keep_first();
+        text.chunks(3).for_each(|c| sampler.write(c));
+        sampler.finish();
+        sampler
+            .into_samples()
+            .into_iter()
+            .map(|s| {
+                let mut out = Vec::new();
+                s.write(&mut out, context.lines()).unwrap();
+                String::from_utf8(out).unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_show() {
+        let text = b"xxxb\0b\nline\nabc\nxb";
+        assert_eq!(
+            show(text, 2, Context::Bytes(2)),
+            ["…xx\tb\t\\x00b…", "…b\\x00\tb\t\\nl…"]
+        );
+        assert_eq!(
+            show(text, 5, Context::Lines(1)),
+            [
+                "xxx\tb\t\\x00b\\nline",
+                "xxxb\\x00\tb\t\\nline",
+                "line\\na\tb\tc\\nxb",
+                "abc\\nx\tb\t",
+            ]
+        );
+        assert_eq!(Context::parse("40"), Ok(Context::Bytes(40)));
+        assert_eq!(Context::parse("2l"), Ok(Context::Lines(2)));
+        assert!(Context::parse("x").is_err());
+    }
+
+    proptest! {
+        #[test]
+        fn test_context(chunk_size in 1..10_usize) {
+            // With room for every match, the sample is all of them.
+            prop_assert_eq!(
+                sample(b"xxabababaxabax|abaab", chunk_size, 10),
+                [
+                    (2, "xx[aba]ba".to_string()),
+                    (6, "ab[aba]xa".to_string()),
+                    (10, "ax[aba]x".to_string()),
+                    (0, "[aba]ab".to_string()),
+                ]
+            );
+        }
+
+        #[test]
+        fn test_size(chunk_size in 1..10_usize, size in 0..5_usize) {
+            let samples = sample(b"abaxabaxabaxabaxaba", chunk_size, size);
+            prop_assert_eq!(samples.len(), size);
+            for (offset, text) in samples {
+                prop_assert_eq!(offset % 4, 0);
+                prop_assert_eq!(text.matches("[aba]").count(), 1);
+            }
+        }
+    }
+}
@@ -0,0 +1,275 @@
+// Finding the sections of executables, as --sections does, so that only the bytes of some of
+// them are counted, such as the strings in .rodata, rather than those of the code and symbols
+// too. ELF and PE files are read, from their section headers.
+use crate::split::ByteRange;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+// Section headers beyond these are taken to be a corrupt file rather than read.
+const MAX_SECTIONS: usize = 1 << 16;
+
+// An ELF section that takes no room in the file, such as .bss.
+const SHT_NOBITS: u32 = 8;
+
+// Returns the ranges of the file at `path` of the sections named in `names`, in the order they
+// are in the file. Sections that take no room in the file, or aren't in it, have none.
+pub fn ranges(path: &Path, names: &[String]) -> io::Result<Vec<ByteRange>> {
+    let mut f = File::open(path)?;
+    let sections = read_sections(&mut f)?;
+    let mut ranges: Vec<ByteRange> = sections
+        .into_iter()
+        .filter(|(name, range)| range.start < range.end && names.iter().any(|n| n == name))
+        .map(|(_, range)| range)
+        .collect();
+    ranges.sort_by_key(|r| r.start);
+    Ok(ranges)
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+fn read_at(f: &mut (impl Read + Seek), offset: u64, len: usize) -> io::Result<Vec<u8>> {
+    f.seek(SeekFrom::Start(offset))?;
+    let mut buf = Vec::new();
+    f.take(len as u64).read_to_end(&mut buf)?;
+    if buf.len() < len {
+        return Err(invalid("a header runs past the end of the file"));
+    }
+    Ok(buf)
+}
+
+// Returns the name and range of each section of an ELF or PE file.
+fn read_sections(f: &mut (impl Read + Seek)) -> io::Result<Vec<(String, ByteRange)>> {
+    let mut magic = [0; 4];
+    f.read_exact(&mut magic)
+        .map_err(|_| invalid("not an ELF or PE file"))?;
+    match magic {
+        [0x7f, b'E', b'L', b'F'] => elf_sections(f),
+        [b'M', b'Z', ..] => pe_sections(f),
+        _ => Err(invalid("not an ELF or PE file")),
+    }
+}
+
+// Reads integers of a header in the byte order of its file.
+struct Fields<'a> {
+    bytes: &'a [u8],
+    big_endian: bool,
+}
+
+impl Fields<'_> {
+    fn int(&self, offset: usize, len: usize) -> u64 {
+        let bytes = &self.bytes[offset..offset + len];
+        let fold = |n: u64, &b: &u8| n << 8 | b as u64;
+        if self.big_endian {
+            bytes.iter().fold(0, fold)
+        } else {
+            bytes.iter().rev().fold(0, fold)
+        }
+    }
+
+    fn u16(&self, offset: usize) -> u64 {
+        self.int(offset, 2)
+    }
+
+    fn u32(&self, offset: usize) -> u64 {
+        self.int(offset, 4)
+    }
+
+    fn u64(&self, offset: usize) -> u64 {
+        self.int(offset, 8)
+    }
+}
+
+fn elf_sections(f: &mut (impl Read + Seek)) -> io::Result<Vec<(String, ByteRange)>> {
+    let header = read_at(f, 0, 64)
+        .or_else(|_| read_at(f, 0, 52))
+        .map_err(|_| invalid("the ELF header is cut short"))?;
+    let wide = match header[4] {
+        1 => false,
+        2 => true,
+        _ => return Err(invalid("unknown ELF class")),
+    };
+    let h = Fields {
+        bytes: &header,
+        big_endian: header[5] == 2,
+    };
+    if wide && header.len() < 64 {
+        return Err(invalid("the ELF header is cut short"));
+    }
+    let (shoff, shentsize, shnum, shstrndx) = if wide {
+        (h.u64(0x28), h.u16(0x3a), h.u16(0x3c), h.u16(0x3e))
+    } else {
+        (h.u32(0x20), h.u16(0x2e), h.u16(0x30), h.u16(0x32))
+    };
+    let (shentsize, shnum) = (shentsize as usize, shnum as usize);
+    if shoff == 0 || shnum == 0 {
+        return Ok(Vec::new());
+    }
+    if shnum > MAX_SECTIONS || shentsize < if wide { 64 } else { 40 } {
+        return Err(invalid("the ELF section headers are corrupt"));
+    }
+    let table = read_at(f, shoff, shentsize * shnum)?;
+    // The name offset, type, file offset and size of each section.
+    let sections: Vec<_> = table
+        .chunks(shentsize)
+        .map(|entry| {
+            let s = Fields {
+                bytes: entry,
+                big_endian: h.big_endian,
+            };
+            if wide {
+                (s.u32(0), s.u32(4), s.u64(0x18), s.u64(0x20))
+            } else {
+                (s.u32(0), s.u32(4), s.u32(0x10), s.u32(0x14))
+            }
+        })
+        .collect();
+    let Some(&(_, _, names_offset, names_size)) = sections.get(shstrndx as usize) else {
+        return Err(invalid("the ELF section names are missing"));
+    };
+    if names_size > 1 << 24 {
+        return Err(invalid("the ELF section names are corrupt"));
+    }
+    let names = read_at(f, names_offset, names_size as usize)?;
+    Ok(sections
+        .into_iter()
+        .filter(|&(_, kind, _, _)| kind != SHT_NOBITS as u64)
+        .map(|(name, _, offset, size)| {
+            let name = names.get(name as usize..).unwrap_or_default();
+            let name = &name[..memchr::memchr(0, name).unwrap_or(name.len())];
+            let range = ByteRange {
+                start: offset,
+                end: offset.saturating_add(size),
+            };
+            (String::from_utf8_lossy(name).into_owned(), range)
+        })
+        .collect())
+}
+
+fn pe_sections(f: &mut (impl Read + Seek)) -> io::Result<Vec<(String, ByteRange)>> {
+    let dos = read_at(f, 0, 64).map_err(|_| invalid("the DOS header is cut short"))?;
+    let lfanew = Fields {
+        bytes: &dos,
+        big_endian: false,
+    }
+    .u32(0x3c);
+    let header = read_at(f, lfanew, 24).map_err(|_| invalid("not a PE file"))?;
+    if header[..4] != *b"PE\0\0" {
+        return Err(invalid("not a PE file"));
+    }
+    let h = Fields {
+        bytes: &header,
+        big_endian: false,
+    };
+    let (count, optional) = (h.u16(6) as usize, h.u16(20));
+    let table = read_at(f, lfanew + 24 + optional, count * 40)?;
+    Ok(table
+        .chunks(40)
+        .map(|entry| {
+            let s = Fields {
+                bytes: entry,
+                big_endian: false,
+            };
+            // Names are padded with NULs, and those longer than 8 bytes, which only object
+            // files have, are cut short.
+            let name = &entry[..memchr::memchr(0, &entry[..8]).unwrap_or(8)];
+            let (virtual_size, raw_size, offset) = (s.u32(8), s.u32(16), s.u32(20));
+            // The raw data is padded to the file alignment, past the size of the section.
+            let size = match virtual_size {
+                0 => raw_size,
+                v => v.min(raw_size),
+            };
+            let range = ByteRange {
+                start: offset,
+                end: offset + size,
+            };
+            (String::from_utf8_lossy(name).into_owned(), range)
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn put(bytes: &mut Vec<u8>, offset: usize, value: u64, len: usize) {
+        if bytes.len() < offset + len {
+            bytes.resize(offset + len, 0);
+        }
+        bytes[offset..offset + len].copy_from_slice(&value.to_le_bytes()[..len]);
+    }
+
+    fn names(sections: Vec<(String, ByteRange)>) -> Vec<(String, u64, u64)> {
+        sections
+            .into_iter()
+            .map(|(name, r)| (name, r.start, r.end))
+            .collect()
+    }
+
+    #[test]
+    fn test_elf() {
+        // A header, section names at 0x40, and four section headers at 0x80: the null section,
+        // .rodata, .bss, which has no bytes in the file, and the names.
+        let mut elf = b"\x7fELF\x02\x01\x01".to_vec();
+        put(&mut elf, 0x28, 0x80, 8);
+        put(&mut elf, 0x3a, 64, 2);
+        put(&mut elf, 0x3c, 4, 2);
+        put(&mut elf, 0x3e, 3, 2);
+        elf.resize(0x40, 0);
+        elf.extend(b"\0.rodata\0.bss\0.shstrtab\0");
+        elf.resize(0x80 + 4 * 64, 0);
+        for (i, name, kind, offset, size) in [
+            (1, 1, 1, 0x200, 0x10),
+            (2, 9, SHT_NOBITS, 0x210, 0x100),
+            (3, 14, 3, 0x40, 24),
+        ] {
+            let entry = 0x80 + i * 64;
+            put(&mut elf, entry, name, 4);
+            put(&mut elf, entry + 4, kind as u64, 4);
+            put(&mut elf, entry + 0x18, offset, 8);
+            put(&mut elf, entry + 0x20, size, 8);
+        }
+        assert_eq!(
+            names(read_sections(&mut Cursor::new(&elf)).unwrap()),
+            [
+                ("".to_string(), 0, 0),
+                (".rodata".to_string(), 0x200, 0x210),
+                (".shstrtab".to_string(), 0x40, 0x58),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_pe() {
+        let mut pe = b"MZ".to_vec();
+        put(&mut pe, 0x3c, 0x40, 4);
+        pe.resize(0x40, 0);
+        pe.extend(b"PE\0\0");
+        put(&mut pe, 0x46, 2, 2);
+        // An optional header of 16 bytes, then the section table.
+        put(&mut pe, 0x54, 16, 2);
+        let table = 0x40 + 24 + 16;
+        for (i, name, virtual_size, raw_size, offset) in [
+            (0, &b".text\0\0\0"[..], 0x30, 0x200, 0x400),
+            (1, b".rdata\0\0", 0, 0x200, 0x600),
+        ] {
+            let entry = table + i * 40;
+            pe.resize(entry + 40, 0);
+            pe[entry..entry + 8].copy_from_slice(name);
+            put(&mut pe, entry + 8, virtual_size, 4);
+            put(&mut pe, entry + 16, raw_size, 4);
+            put(&mut pe, entry + 20, offset, 4);
+        }
+        assert_eq!(
+            names(read_sections(&mut Cursor::new(&pe)).unwrap()),
+            [
+                (".text".to_string(), 0x400, 0x430),
+                (".rdata".to_string(), 0x600, 0x800),
+            ]
+        );
+        assert!(read_sections(&mut Cursor::new(b"#!/bin/sh\n")).is_err());
+    }
+}
@@ -0,0 +1,313 @@
+use clap::ValueEnum;
+use memchr::memchr;
+
+#[derive(Clone, Copy, PartialEq, Debug, ValueEnum)]
+pub enum Format {
+    /// Records start with a ">" header line, followed by any number of sequence lines.
+    Fasta,
+    /// Records are four lines: "@" header, sequence, "+" separator, and qualities.
+    Fastq,
+    /// Every line is a sequence.
+    Plain,
+}
+
+// Returns the reverse complement of a DNA or RNA sequence, which may use IUPAC ambiguity
+// codes, or None if it contains anything else.
+pub fn reverse_complement(seq: &[u8]) -> Option<Vec<u8>> {
+    seq.iter()
+        .rev()
+        .map(|&b| {
+            let c = match b.to_ascii_uppercase() {
+                b'A' => b'T',
+                b'C' => b'G',
+                b'G' => b'C',
+                b'T' | b'U' => b'A',
+                b'R' => b'Y',
+                b'Y' => b'R',
+                b'K' => b'M',
+                b'M' => b'K',
+                b'B' => b'V',
+                b'V' => b'B',
+                b'D' => b'H',
+                b'H' => b'D',
+                c @ (b'S' | b'W' | b'N') => c,
+                _ => return None,
+            };
+            Some(if b.is_ascii_lowercase() {
+                c.to_ascii_lowercase()
+            } else {
+                c
+            })
+        })
+        .collect()
+}
+
+// Receives the parts of sequence records as they are parsed.
+pub trait SeqSink {
+    // Called with consecutive pieces of each record's header line, without its leading '>' or
+    // '@' (FASTA and FASTQ only).
+    fn header(&mut self, header: &[u8]) {
+        let _ = header;
+    }
+
+    // Called with consecutive pieces of the current record's sequence. Line breaks are removed.
+    fn sequence(&mut self, seq: &[u8]);
+
+    // Called with consecutive pieces of the current record's quality string (FASTQ only).
+    fn quality(&mut self, qual: &[u8]) {
+        let _ = qual;
+    }
+
+    // Called after the last piece of each record.
+    fn end_record(&mut self);
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Line {
+    Header,
+    Sequence,
+    Plus,
+    Quality,
+}
+
+// Splits a stream of FASTA, FASTQ or plain sequence data into records, chunk by chunk.
+pub struct SeqParser {
+    // The format, once known. If not given, it is detected from the first byte.
+    format: Option<Format>,
+
+    // The kind of the current line, once we have seen its first byte.
+    line: Option<Line>,
+
+    // The number of non-empty lines of the current FASTQ record we have finished.
+    fastq_lines: usize,
+
+    // Whether we have sent anything for the current record.
+    in_record: bool,
+
+    // Whether the last chunk ended with a '\r' that we held back, in case it is part of "\r\n".
+    pending_cr: bool,
+
+    // Whether the next byte is the first of a header line.
+    header_start: bool,
+}
+
+impl SeqParser {
+    pub fn new(format: Option<Format>) -> Self {
+        SeqParser {
+            format,
+            line: None,
+            fastq_lines: 0,
+            in_record: false,
+            pending_cr: false,
+            header_start: false,
+        }
+    }
+
+    pub fn write(&mut self, mut buf: &[u8], sink: &mut impl SeqSink) {
+        if self.pending_cr && !buf.is_empty() {
+            self.pending_cr = false;
+            if buf[0] != b'\n' {
+                self.content(b"\r", sink);
+            }
+        }
+
+        while !buf.is_empty() {
+            if self.line.is_none() {
+                if buf[0] == b'\n' && self.skips_empty_lines() {
+                    buf = &buf[1..];
+                    continue;
+                }
+                self.start_line(buf[0], sink);
+            }
+
+            match memchr(b'\n', buf) {
+                Some(i) => {
+                    let line = &buf[..i];
+                    self.content(line.strip_suffix(b"\r").unwrap_or(line), sink);
+                    self.end_line(sink);
+                    buf = &buf[i + 1..];
+                }
+                None => {
+                    match buf.strip_suffix(b"\r") {
+                        Some(line) => {
+                            self.content(line, sink);
+                            self.pending_cr = true;
+                        }
+                        None => self.content(buf, sink),
+                    }
+                    break;
+                }
+            }
+        }
+    }
+
+    // Ends the current input.
+    pub fn finish(&mut self, sink: &mut impl SeqSink) {
+        if self.pending_cr {
+            self.content(b"\r", sink);
+            self.pending_cr = false;
+        }
+        if self.in_record {
+            sink.end_record();
+        }
+        self.line = None;
+        self.fastq_lines = 0;
+        self.in_record = false;
+    }
+
+    fn skips_empty_lines(&self) -> bool {
+        self.format == Some(Format::Fastq) && self.fastq_lines == 0
+    }
+
+    fn start_line(&mut self, first: u8, sink: &mut impl SeqSink) {
+        let format = *self.format.get_or_insert(match first {
+            b'>' => Format::Fasta,
+            b'@' => Format::Fastq,
+            _ => Format::Plain,
+        });
+
+        let line = match format {
+            Format::Fasta if first == b'>' => {
+                if self.in_record {
+                    sink.end_record();
+                    self.in_record = false;
+                }
+                Line::Header
+            }
+            Format::Fasta | Format::Plain => Line::Sequence,
+            Format::Fastq => match self.fastq_lines {
+                0 => Line::Header,
+                1 => Line::Sequence,
+                2 => Line::Plus,
+                _ => Line::Quality,
+            },
+        };
+        if line == Line::Sequence {
+            self.in_record = true;
+        }
+        self.header_start = line == Line::Header;
+        self.line = Some(line);
+    }
+
+    fn content(&mut self, content: &[u8], sink: &mut impl SeqSink) {
+        match self.line {
+            Some(Line::Sequence) if !content.is_empty() => sink.sequence(content),
+            Some(Line::Quality) if !content.is_empty() => sink.quality(content),
+            Some(Line::Header) if !content.is_empty() => {
+                if std::mem::take(&mut self.header_start) {
+                    sink.header(&content[1..]);
+                } else {
+                    sink.header(content);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn end_line(&mut self, sink: &mut impl SeqSink) {
+        match self.format {
+            Some(Format::Plain) => {
+                sink.end_record();
+                self.in_record = false;
+            }
+            Some(Format::Fastq) => {
+                self.fastq_lines += 1;
+                if self.fastq_lines == 4 {
+                    sink.end_record();
+                    self.in_record = false;
+                    self.fastq_lines = 0;
+                }
+            }
+            _ => {}
+        }
+        self.line = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use proptest::{prop_assert_eq, proptest};
+
+    #[derive(Default, Debug, PartialEq)]
+    struct Records {
+        done: Vec<(Vec<u8>, Vec<u8>)>,
+        headers: Vec<u8>,
+        seq: Vec<u8>,
+        qual: Vec<u8>,
+    }
+
+    impl SeqSink for Records {
+        fn header(&mut self, header: &[u8]) {
+            self.headers.extend(header);
+        }
+
+        fn sequence(&mut self, seq: &[u8]) {
+            self.seq.extend(seq);
+        }
+
+        fn quality(&mut self, qual: &[u8]) {
+            self.qual.extend(qual);
+        }
+
+        fn end_record(&mut self) {
+            let seq = std::mem::take(&mut self.seq);
+            let qual = std::mem::take(&mut self.qual);
+            self.done.push((seq, qual));
+        }
+    }
+
+    fn parse(text: &[u8], chunk_size: usize) -> (Vec<(String, String)>, String) {
+        let mut parser = SeqParser::new(None);
+        let mut records = Records::default();
+        text.chunks(chunk_size)
+            .for_each(|chunk| parser.write(chunk, &mut records));
+        parser.finish(&mut records);
+        let headers = String::from_utf8(records.headers).unwrap();
+        let records = records
+            .done
+            .into_iter()
+            .map(|(s, q)| (String::from_utf8(s).unwrap(), String::from_utf8(q).unwrap()))
+            .collect();
+        (records, headers)
+    }
+
+    fn owned(v: &[(&str, &str)], headers: &str) -> (Vec<(String, String)>, String) {
+        let records = v
+            .iter()
+            .map(|&(s, q)| (s.to_string(), q.to_string()))
+            .collect();
+        (records, headers.to_string())
+    }
+
+    #[test]
+    fn test_reverse_complement() {
+        assert_eq!(reverse_complement(b"AACgtN"), Some(b"NacGTT".to_vec()));
+        assert_eq!(reverse_complement(b"ACGU"), Some(b"ACGT".to_vec()));
+        assert_eq!(reverse_complement(b"ACGX"), None);
+    }
+
+    proptest! {
+        #[test]
+        fn test_formats(chunk_size in 1..20_usize) {
+            let fasta = b">one\r\nACGT\r\nTT\r\n>two desc\nGG\n\n>empty\n>three\nA";
+            prop_assert_eq!(
+                parse(fasta, chunk_size),
+                owned(&[("ACGTTT", ""), ("GG", ""), ("A", "")], "onetwo descemptythree")
+            );
+
+            let fastq = b"@r1\nACGT\n+\n!!II\n\n@r2\nGG\n+r2\nII\n";
+            prop_assert_eq!(
+                parse(fastq, chunk_size),
+                owned(&[("ACGT", "!!II"), ("GG", "II")], "r1r2")
+            );
+
+            let plain = b"ACGT\nGG\r\nT";
+            prop_assert_eq!(
+                parse(plain, chunk_size),
+                owned(&[("ACGT", ""), ("GG", ""), ("T", "")], "")
+            );
+        }
+    }
+}
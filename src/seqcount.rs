@@ -0,0 +1,191 @@
+use crate::color;
+use crate::input::{for_each_chunk, Input};
+use crate::seq::{Format, SeqParser, SeqSink};
+use freq::{Counter, Matcher};
+use std::io::Write;
+
+// Counts matches in the sequences of FASTA, FASTQ or plain sequence data, ignoring headers and
+// qualities. Returns the total for each pattern, and prints each record's count to
+// `per_record` if given. With `min_qual`, matches that overlap a base whose FASTQ quality is
+// below that Phred score are not counted.
+pub fn count<M: Matcher>(
+    matcher: M,
+    inputs: Vec<Input>,
+    format: Format,
+    buffer_size: usize,
+    min_qual: Option<u8>,
+    per_record: Option<&mut dyn Write>,
+) -> Vec<usize> {
+    let mut records = RecordCounter {
+        totals: vec![0; matcher.patterns()],
+        masked: vec![0; matcher.patterns()],
+        counter: Counter::with_matcher(matcher),
+        min_qual,
+        seq: Vec::new(),
+        qual: Vec::new(),
+        header: Vec::new(),
+        index: 0,
+        per_record,
+    };
+    let mut parser = SeqParser::new(Some(format));
+    for f in inputs {
+        for_each_chunk(f, buffer_size, |chunk| parser.write(chunk, &mut records));
+        parser.finish(&mut records);
+    }
+    records.totals
+}
+
+struct RecordCounter<'a, M> {
+    // Counts the current record. Matches never span records.
+    counter: Counter<M>,
+
+    // The Phred score a base needs for matches over it to count. When set, we have to wait for a
+    // record's qualities before counting, so its sequence and qualities are buffered instead,
+    // and its counts go in `masked`.
+    min_qual: Option<u8>,
+    seq: Vec<u8>,
+    qual: Vec<u8>,
+    masked: Vec<usize>,
+
+    // The current record's header.
+    header: Vec<u8>,
+
+    // How many records we have seen.
+    index: usize,
+
+    totals: Vec<usize>,
+
+    per_record: Option<&'a mut dyn Write>,
+}
+
+impl<M: Matcher> SeqSink for RecordCounter<'_, M> {
+    fn header(&mut self, header: &[u8]) {
+        self.header.extend(header);
+    }
+
+    fn sequence(&mut self, seq: &[u8]) {
+        match self.min_qual {
+            Some(_) => self.seq.extend(seq),
+            None => self.counter.write(seq),
+        }
+    }
+
+    fn quality(&mut self, qual: &[u8]) {
+        if self.min_qual.is_some() {
+            self.qual.extend(qual);
+        }
+    }
+
+    fn end_record(&mut self) {
+        let counts = match self.min_qual {
+            Some(min_qual) => {
+                self.count_masked(min_qual);
+                &self.masked
+            }
+            None => self.counter.counts(),
+        };
+        let count: usize = counts.iter().sum();
+        for (total, c) in self.totals.iter_mut().zip(counts) {
+            *total += c;
+        }
+        self.index += 1;
+
+        if let Some(out) = &mut self.per_record {
+            // Records are named by the first word of their header, or their position if they
+            // have none.
+            let name = self
+                .header
+                .split(|b| b.is_ascii_whitespace())
+                .next()
+                .unwrap_or_default();
+            let res = if name.is_empty() {
+                writeln!(out, "{}\t{}", color::count(count), self.index)
+            } else {
+                write!(out, "{}\t", color::count(count))
+                    .and_then(|_| out.write_all(name))
+                    .and_then(|_| out.write_all(b"\n"))
+            };
+            res.expect("failed to write output");
+        }
+
+        self.counter.reset();
+        self.header.clear();
+    }
+}
+
+impl<M: Matcher> RecordCounter<'_, M> {
+    // Counts the matches in the buffered record that only cover bases of at least `min_qual`.
+    // Bases without a quality, in a truncated record, count as low quality.
+    fn count_masked(&mut self, min_qual: u8) {
+        self.masked.fill(0);
+        let matcher = self.counter.matcher();
+        let seq = std::mem::take(&mut self.seq);
+        let passes = |i: usize| {
+            self.qual
+                .get(i)
+                .is_some_and(|&q| q.saturating_sub(b'!') >= min_qual)
+        };
+
+        // Search each stretch of passing bases on its own, so no match can cover a failing one.
+        let mut start = 0;
+        while start < seq.len() {
+            if !passes(start) {
+                start += 1;
+                continue;
+            }
+            let mut end = start + 1;
+            while end < seq.len() && passes(end) {
+                end += 1;
+            }
+            matcher.find_all(&seq[start..end], &mut |m| {
+                self.masked[m.pattern] += 1;
+                true
+            });
+            start = end;
+        }
+
+        self.seq = seq;
+        self.seq.clear();
+        self.qual.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Cursor;
+
+    #[test]
+    fn test_fasta() {
+        let fasta = b">r1 desc\nACGTAC\nGTAC\n>r2 ACGT\nTTTT\n>ACGT\n";
+        let mut out = Vec::new();
+        let totals = count(
+            freq::Literal::new(b"ACGT"),
+            vec![Box::new(Cursor::new(fasta))],
+            Format::Fasta,
+            3,
+            None,
+            Some(&mut out),
+        );
+        assert_eq!(totals, [2]);
+        assert_eq!(out, b"2\tr1\n0\tr2\n");
+    }
+
+    #[test]
+    fn test_min_qual() {
+        // Phred scores: '!' is 0, '+' is 10, '5' is 20.
+        let fastq = b"@r1\nACGTACGTACGT\n+\n55555+555555\n@r2\nACGTACGT\n+\n5555555\n";
+        let mut out = Vec::new();
+        let totals = count(
+            freq::Literal::new(b"ACGT"),
+            vec![Box::new(Cursor::new(fastq))],
+            Format::Fastq,
+            5,
+            Some(20),
+            Some(&mut out),
+        );
+        assert_eq!(totals, [3]);
+        assert_eq!(out, b"2\tr1\n1\tr2\n");
+    }
+}
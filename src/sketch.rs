@@ -0,0 +1,214 @@
+use std::borrow::Borrow;
+use std::cmp::Reverse;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BinaryHeap, HashMap};
+use std::hash::{Hash, Hasher};
+
+// The number of rows in the sketch. Each estimate is within the error bound with probability
+// 1 - e^-DEPTH, about 98%.
+const DEPTH: usize = 4;
+
+// Finds the most frequent keys of a stream in bounded memory, with a count-min sketch that
+// estimates the count of every key, and the `k` keys with the highest estimates so far.
+//
+// Estimates are never too low, and are too high by at most `error_bound()` with high
+// probability. A key that becomes frequent late may have been evicted earlier, but its estimate
+// includes the occurrences from before that.
+pub struct HeavyHitters<K> {
+    // DEPTH rows of `width` counters.
+    counters: Vec<usize>,
+    width: usize,
+
+    // The sum of all counts.
+    total: usize,
+
+    k: usize,
+
+    // The current top keys and their estimates.
+    top: HashMap<K, usize>,
+
+    // The top keys, least frequent first. Estimates only grow, so an entry may be lower than the
+    // key's current estimate in `top`, in which case it is refreshed when it reaches the front.
+    heap: BinaryHeap<Reverse<(usize, K)>>,
+}
+
+impl<K: Hash + Eq + Ord + Clone> HeavyHitters<K> {
+    // Makes a sketch whose counters take about `memory` bytes, keeping the top `k` keys.
+    pub fn new(memory: usize, k: usize) -> Self {
+        let width = (memory / (DEPTH * std::mem::size_of::<usize>())).max(1);
+        HeavyHitters {
+            counters: vec![0; DEPTH * width],
+            width,
+            total: 0,
+            k,
+            top: HashMap::new(),
+            heap: BinaryHeap::new(),
+        }
+    }
+
+    pub fn add_count<Q>(&mut self, key: &Q, count: usize)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        self.total += count;
+
+        // Conservative update: only raise the counters that would otherwise underestimate the
+        // key, which keeps the other keys that share them more accurate.
+        let cells = self.cells(key);
+        let estimate = cells.iter().map(|&i| self.counters[i]).min().unwrap() + count;
+        for i in cells {
+            self.counters[i] = self.counters[i].max(estimate);
+        }
+
+        if let Some(c) = self.top.get_mut(key) {
+            *c = estimate;
+        } else if self.top.len() < self.k {
+            self.insert(key.to_owned(), estimate);
+        } else {
+            // Replace the least frequent top key, if this one is now more frequent.
+            while let Some(Reverse((count, _))) = self.heap.peek() {
+                if estimate <= *count {
+                    return;
+                }
+                let Reverse((count, min)) = self.heap.pop().unwrap();
+                let current = *self.top.get::<K>(&min).unwrap();
+                if current == count {
+                    self.top.remove::<K>(&min);
+                    self.insert(key.to_owned(), estimate);
+                    return;
+                }
+                self.heap.push(Reverse((current, min)));
+            }
+        }
+    }
+
+    // How much higher than the true count an estimate may be.
+    pub fn error_bound(&self) -> usize {
+        (std::f64::consts::E * self.total as f64 / self.width as f64).ceil() as usize
+    }
+
+    // The sum of all counts added, including those of keys that aren't kept.
+    pub fn total(&self) -> usize {
+        self.total
+    }
+
+    pub fn into_entries(self) -> impl Iterator<Item = (K, usize)> {
+        self.top.into_iter()
+    }
+
+    fn insert(&mut self, key: K, estimate: usize) {
+        self.heap.push(Reverse((estimate, key.clone())));
+        self.top.insert(key, estimate);
+    }
+
+    // Returns the index of the key's counter in each row.
+    fn cells<Q: Hash + ?Sized>(&self, key: &Q) -> [usize; DEPTH] {
+        // Rows use hashes h1 + i * h2, which are as good as independent hashes for this.
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        let hash = hasher.finish();
+        let (h1, h2) = (hash as u32 as usize, (hash >> 32) as usize | 1);
+        std::array::from_fn(|i| i * self.width + h1.wrapping_add(i * h2) % self.width)
+    }
+}
+
+// Estimates the number of distinct keys in a stream, to within about 0.8%, in 16 KB.
+pub struct HyperLogLog {
+    // For each group of hashes, the most leading zeros (plus one) seen in the rest of a hash.
+    registers: Vec<u8>,
+}
+
+// The number of bits of the hash that pick a register.
+const PRECISION: u32 = 14;
+
+impl HyperLogLog {
+    pub fn new() -> Self {
+        HyperLogLog {
+            registers: vec![0; 1 << PRECISION],
+        }
+    }
+
+    pub fn add<Q: Hash + ?Sized>(&mut self, key: &Q) {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        let hash = hasher.finish();
+        let register = (hash >> (64 - PRECISION)) as usize;
+        let rank = ((hash << PRECISION) | 1 << (PRECISION - 1)).leading_zeros() + 1;
+        self.registers[register] = self.registers[register].max(rank as u8);
+    }
+
+    // Combines the keys of another sketch into this one.
+    #[cfg_attr(target_os = "wasi", allow(dead_code))]
+    pub fn merge(&mut self, other: &HyperLogLog) {
+        for (r, &o) in self.registers.iter_mut().zip(&other.registers) {
+            *r = (*r).max(o);
+        }
+    }
+
+    pub fn estimate(&self) -> usize {
+        let m = self.registers.len() as f64;
+        let alpha = 0.7213 / (1.0 + 1.079 / m);
+        let sum: f64 = self.registers.iter().map(|&r| 2f64.powi(-(r as i32))).sum();
+        let estimate = alpha * m * m / sum;
+
+        // For small counts, the number of empty registers is a better guide.
+        let zeros = self.registers.iter().filter(|&&r| r == 0).count();
+        if estimate <= 2.5 * m && zeros > 0 {
+            (m * (m / zeros as f64).ln()).round() as usize
+        } else {
+            estimate.round() as usize
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_heavy_hitters() {
+        // Keys 0..10 are heavy, and 10..100_000 occur once each.
+        let mut sketch = HeavyHitters::new(64 * 1024, 10);
+        let mut exact = HashMap::new();
+        for i in 0..100_000_u64 {
+            let key = if i % 5 == 0 { i % 50 / 5 } else { i + 10 };
+            sketch.add_count(&key, 1);
+            *exact.entry(key).or_insert(0) += 1;
+        }
+
+        let bound = sketch.error_bound();
+        let mut top: Vec<_> = sketch.into_entries().collect();
+        top.sort();
+        assert_eq!(top.len(), 10);
+        for (i, &(key, estimate)) in top.iter().enumerate() {
+            assert_eq!(key, i as u64);
+            assert!(estimate >= exact[&key]);
+            assert!(estimate <= exact[&key] + bound);
+        }
+    }
+
+    #[test]
+    fn test_hyperloglog() {
+        for n in [0, 1, 100, 10_000, 1_000_000] {
+            // Split the keys over two sketches, with some in both.
+            let mut a = HyperLogLog::new();
+            let mut b = HyperLogLog::new();
+            for i in 0..n {
+                a.add(&i);
+                if i % 3 == 0 {
+                    a.add(&i);
+                    b.add(&i);
+                }
+            }
+            a.merge(&b);
+            let error = (a.estimate() as f64 - n as f64).abs();
+            assert!(
+                error <= n as f64 * 0.03,
+                "{} estimated as {}",
+                n,
+                a.estimate()
+            );
+        }
+    }
+}
@@ -0,0 +1,133 @@
+use clap::Args;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+
+#[derive(Args)]
+pub struct SplitArgs {
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = clap::value_parser!(u32).range(1..),
+        help = "How many parts to split the file into. There are fewer if its lines are too long for this many."
+    )]
+    parts: u32,
+
+    #[arg(help = "The file to split.")]
+    input: PathBuf,
+}
+
+pub fn run(args: SplitArgs, out: &mut impl Write) {
+    let plan = File::open(&args.input).and_then(|mut f| {
+        let size = f.metadata()?.len();
+        boundaries(&mut f, size, args.parts as u64)
+    });
+    let plan = plan.unwrap_or_else(|e| {
+        eprintln!("freq: {}: {}", args.input.display(), e);
+        std::process::exit(2);
+    });
+    for range in plan.windows(2) {
+        write!(out, "{}-{}\t", range[0], range[1]).expect("failed to write output");
+        out.write_all(args.input.as_os_str().as_encoded_bytes())
+            .and_then(|_| writeln!(out))
+            .expect("failed to write output");
+    }
+}
+
+// Returns where the parts of a file of `size` bytes start, and then `size`. Each part but the
+// first starts a line, so that no match of a pattern without a newline spans two parts.
+fn boundaries(f: &mut (impl Read + Seek), size: u64, parts: u64) -> io::Result<Vec<u64>> {
+    let mut starts = vec![0];
+    let mut buf = vec![0; 64 * 1024];
+    for i in 1..parts {
+        let target = (size as u128 * i as u128 / parts as u128) as u64;
+        let prev = *starts.last().unwrap();
+        if target <= prev {
+            continue;
+        }
+        // The next line starts after the first newline at or after target - 1.
+        let mut pos = target - 1;
+        f.seek(SeekFrom::Start(pos))?;
+        let start = loop {
+            let n = f.read(&mut buf)?;
+            if n == 0 {
+                break size;
+            }
+            if let Some(j) = memchr::memchr(b'\n', &buf[..n]) {
+                break pos + j as u64 + 1;
+            }
+            pos += n as u64;
+        };
+        if start >= size {
+            break;
+        }
+        starts.push(start);
+    }
+    starts.push(size);
+    Ok(starts)
+}
+
+// A range of bytes, from --range START-END, such as one line of the plan from freq split.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ByteRange {
+    pub start: u64,
+    // Exclusive.
+    pub end: u64,
+}
+
+impl ByteRange {
+    pub fn parse(s: &str) -> Result<ByteRange, String> {
+        let parse = |n: &str| {
+            n.parse::<u64>()
+                .map_err(|_| format!("expected a byte offset, not '{}'", n))
+        };
+        let (start, end) = s
+            .split_once('-')
+            .ok_or("expected START-END, as printed by freq split")?;
+        let (start, end) = (parse(start)?, parse(end)?);
+        if start > end {
+            return Err(format!("the range ends before it starts at {}", start));
+        }
+        Ok(ByteRange { start, end })
+    }
+
+    pub fn len(&self) -> u64 {
+        self.end - self.start
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_boundaries() {
+        let text = b"aaaa\nbb\ncccccccc\nd\n";
+        let split = |parts| boundaries(&mut Cursor::new(text), text.len() as u64, parts).unwrap();
+        assert_eq!(split(1), [0, 19]);
+        assert_eq!(split(2), [0, 17, 19]);
+        assert_eq!(split(4), [0, 5, 17, 19]);
+        // A long line makes for fewer parts.
+        assert_eq!(split(10), [0, 5, 8, 17, 19]);
+        // Every part but the first starts a line.
+        for parts in 1..30 {
+            let starts = split(parts);
+            assert!(starts.windows(2).all(|w| w[0] < w[1]));
+            assert!(starts[1..starts.len() - 1]
+                .iter()
+                .all(|&s| text[s as usize - 1] == b'\n'));
+        }
+    }
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(
+            ByteRange::parse("5-17"),
+            Ok(ByteRange { start: 5, end: 17 })
+        );
+        assert!(ByteRange::parse("17-5").is_err());
+        assert!(ByteRange::parse("5").is_err());
+        assert!(ByteRange::parse("-5").is_err());
+    }
+}
@@ -0,0 +1,157 @@
+use crate::bytes::ByteHistogram;
+use crate::color;
+use crate::input::{for_each_chunk, open_inputs};
+use crate::words::WordCounter;
+use clap::Args;
+use memchr::memchr_iter;
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+#[derive(Args)]
+pub struct StatsArgs {
+    #[arg(help = "The files to read, with - for stdin. If not provided, stdin is used.")]
+    input: Vec<PathBuf>,
+}
+
+pub fn run(args: StatsArgs, buffer_size: usize, out: &mut impl Write) {
+    let mut stats = Stats::default();
+    for f in open_inputs(&args.input) {
+        for_each_chunk(f, buffer_size, |chunk| stats.write(chunk));
+        stats.finish();
+    }
+
+    stats.print(out).expect("failed to write output");
+}
+
+// Statistics about a corpus, gathered in one pass.
+#[derive(Default)]
+pub struct Stats {
+    histogram: ByteHistogram,
+    words: WordCounter,
+
+    // Lines that don't end in a newline, at the end of an input.
+    unterminated_lines: u64,
+    crlf: u64,
+
+    // The last byte of the current input so far, if there is one.
+    last: Option<u8>,
+}
+
+impl Stats {
+    pub fn write(&mut self, buf: &[u8]) {
+        let Some(&last) = buf.last() else {
+            return;
+        };
+        self.histogram.write(buf);
+        self.words.write(buf);
+        for i in memchr_iter(b'\n', buf) {
+            let before = if i == 0 { self.last } else { Some(buf[i - 1]) };
+            if before == Some(b'\r') {
+                self.crlf += 1;
+            }
+        }
+        self.last = Some(last);
+    }
+
+    // Ends the current input.
+    pub fn finish(&mut self) {
+        if self.last.is_some_and(|b| b != b'\n') {
+            self.unterminated_lines += 1;
+        }
+        self.last = None;
+        self.words.finish();
+    }
+
+    // Prints "value<TAB>statistic" lines.
+    pub fn print(&self, out: &mut impl Write) -> io::Result<()> {
+        let counts = self.histogram.counts();
+        let bytes: u64 = counts.iter().sum();
+        let lf = counts[b'\n' as usize];
+        let lines = lf + self.unterminated_lines;
+        let words = self.words.table();
+
+        // Line lengths don't include the line endings.
+        let line_bytes = bytes - lf - self.crlf;
+        let mean_line_length = if lines == 0 {
+            0.0
+        } else {
+            line_bytes as f64 / lines as f64
+        };
+
+        writeln!(out, "{}\tbytes", color::count(bytes))?;
+        writeln!(out, "{}\tlines", color::count(lines))?;
+        writeln!(out, "{}\twords", color::count(words.total()))?;
+        writeln!(out, "{}\tdistinct words", color::count(words.distinct()))?;
+        writeln!(out, "{:.2}\tmean line length", mean_line_length)?;
+        writeln!(out, "{:.4}\tbyte entropy", entropy(&counts))?;
+        writeln!(out, "{}\tLF line endings", color::count(lf - self.crlf))?;
+        writeln!(out, "{}\tCRLF line endings", color::count(self.crlf))?;
+        Ok(())
+    }
+}
+
+// The Shannon entropy of the byte distribution, in bits per byte, from 0 to 8.
+fn entropy(counts: &[u64; 256]) -> f64 {
+    let total: u64 = counts.iter().sum();
+    if total == 0 {
+        return 0.0;
+    }
+    let total = total as f64;
+    let entropy: f64 = counts
+        .iter()
+        .filter(|&&c| c > 0)
+        .map(|&c| {
+            let p = c as f64 / total;
+            -p * p.log2()
+        })
+        .sum();
+    // Avoid printing -0.0000 for a single byte value.
+    entropy.max(0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use proptest::string::bytes_regex;
+    use proptest::{prop_assert_eq, proptest};
+
+    fn stats(inputs: &[&[u8]], chunk_size: usize) -> String {
+        let mut stats = Stats::default();
+        for input in inputs {
+            input.chunks(chunk_size).for_each(|c| stats.write(c));
+            stats.finish();
+        }
+        let mut out = Vec::new();
+        stats.print(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_stats() {
+        assert_eq!(
+            stats(&[b"the cat\r\nthe dog\n", b"a b"], 100),
+            "\
+20\tbytes
+3\tlines
+6\twords
+5\tdistinct words
+5.67\tmean line length
+3.4464\tbyte entropy
+1\tLF line endings
+1\tCRLF line endings
+"
+        );
+        assert_eq!(entropy(&[0; 256]), 0.0);
+    }
+
+    proptest! {
+        #[test]
+        fn test_chunked(
+            chunk_size in 1..20_usize,
+            text in bytes_regex("((?s-u:[a \\r\\n]{0,200}))").unwrap(),
+        ) {
+            prop_assert_eq!(stats(&[&text], chunk_size), stats(&[&text], text.len().max(1)));
+        }
+    }
+}
@@ -0,0 +1,254 @@
+// Listening for syslog messages, as --syslog-listen does, over UDP, where each datagram is a
+// message, and over TCP, where messages are framed as RFC 6587 describes: each either starts
+// with its length and a space, or ends with a newline.
+use crate::interval::Intervals;
+use freq::Matcher;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, UdpSocket};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use tracing::{debug, info};
+
+// The longest message read, as the largest UDP datagram. Longer ones are cut off here.
+const MAX_MESSAGE: usize = 65535;
+
+// Listens on `addr` over both UDP and TCP, on threads of their own, and returns the messages
+// they receive.
+pub fn listen(addr: SocketAddr) -> io::Result<Receiver<Vec<u8>>> {
+    let udp = UdpSocket::bind(addr)?;
+    let tcp = TcpListener::bind(addr)?;
+    info!("listening for syslog messages on {}", addr);
+    let (s, r) = mpsc::channel();
+
+    let datagrams = s.clone();
+    std::thread::spawn(move || {
+        let mut buf = vec![0; MAX_MESSAGE];
+        loop {
+            match udp.recv(&mut buf) {
+                Ok(n) => {
+                    if datagrams.send(buf[..n].to_vec()).is_err() {
+                        return;
+                    }
+                }
+                Err(e) => info!("failed to receive a datagram: {}", e),
+            }
+        }
+    });
+    std::thread::spawn(move || {
+        for stream in tcp.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let s = s.clone();
+                    std::thread::spawn(move || receive(stream, s));
+                }
+                Err(e) => info!("failed to accept a connection: {}", e),
+            }
+        }
+    });
+    Ok(r)
+}
+
+// Reads the messages of a TCP connection until it is closed.
+fn receive(mut stream: TcpStream, s: Sender<Vec<u8>>) {
+    let peer = stream
+        .peer_addr()
+        .map(|a| a.to_string())
+        .unwrap_or_default();
+    debug!("receiving syslog messages from {}", peer);
+    let mut framer = Framer::default();
+    let mut buf = vec![0; 64 * 1024];
+    let mut send = |message: &[u8]| {
+        let _ = s.send(message.to_vec());
+    };
+    loop {
+        match stream.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => framer.write(&buf[..n], &mut send),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => {
+                info!("failed to read from {}: {}", peer, e);
+                break;
+            }
+        }
+    }
+    framer.finish(&mut send);
+    debug!("{} closed the connection", peer);
+}
+
+// Counts matches in each message, printing the count of each interval, until freq is stopped.
+pub fn run<M: Matcher>(
+    messages: Receiver<Vec<u8>>,
+    mut intervals: Intervals<M>,
+    out: &mut impl Write,
+) -> ! {
+    loop {
+        match messages.recv_timeout(intervals.until_due()) {
+            Ok(message) => intervals.message(&message),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => unreachable!("the listeners never stop"),
+        }
+        if intervals.until_due().is_zero() {
+            intervals.emit(out).expect("failed to write output");
+        }
+    }
+}
+
+// Splits a stream of syslog messages into messages, whichever framing each is sent with.
+#[derive(Default)]
+struct Framer {
+    frame: Frame,
+    // The part of the current message received so far.
+    message: Vec<u8>,
+}
+
+#[derive(Default)]
+enum Frame {
+    // Between messages.
+    #[default]
+    Start,
+    // Reading the length of an octet-counted message, whose digits so far are in `message`.
+    Length,
+    // Reading an octet-counted message, with this many bytes of it left.
+    Counted(usize),
+    // Reading a message that ends with a newline.
+    Line,
+}
+
+impl Framer {
+    fn write(&mut self, mut data: &[u8], f: &mut impl FnMut(&[u8])) {
+        while let Some(&b) = data.first() {
+            match self.frame {
+                Frame::Start => {
+                    self.frame = match b {
+                        b'1'..=b'9' => Frame::Length,
+                        // Empty lines aren't messages.
+                        b'\n' | b'\r' => {
+                            data = &data[1..];
+                            Frame::Start
+                        }
+                        _ => Frame::Line,
+                    };
+                }
+                Frame::Length => match b {
+                    b'0'..=b'9' if self.message.len() < 9 => {
+                        self.message.push(b);
+                        data = &data[1..];
+                    }
+                    b' ' => {
+                        let len = std::str::from_utf8(&self.message)
+                            .ok()
+                            .and_then(|s| s.parse().ok())
+                            .unwrap_or(0);
+                        self.message.clear();
+                        self.frame = Frame::Counted(len);
+                        data = &data[1..];
+                    }
+                    // Not a length after all, but the start of a message that ends with a
+                    // newline.
+                    _ => self.frame = Frame::Line,
+                },
+                Frame::Counted(left) => {
+                    let n = left.min(data.len());
+                    self.push(&data[..n]);
+                    data = &data[n..];
+                    self.frame = Frame::Counted(left - n);
+                    if left == n {
+                        self.end(f);
+                    }
+                }
+                Frame::Line => match memchr::memchr(b'\n', data) {
+                    Some(i) => {
+                        let line = &data[..i];
+                        self.push(line.strip_suffix(b"\r").unwrap_or(line));
+                        data = &data[i + 1..];
+                        self.end(f);
+                    }
+                    None => {
+                        self.push(data);
+                        data = &[];
+                    }
+                },
+            }
+        }
+    }
+
+    // Ends the stream. A message that ends with a newline may be sent without one last, but an
+    // octet-counted message that is cut short is dropped.
+    fn finish(&mut self, f: &mut impl FnMut(&[u8])) {
+        match self.frame {
+            Frame::Length | Frame::Line if !self.message.is_empty() => self.end(f),
+            _ => {}
+        }
+        self.message.clear();
+        self.frame = Frame::Start;
+    }
+
+    fn push(&mut self, data: &[u8]) {
+        let room = MAX_MESSAGE.saturating_sub(self.message.len());
+        self.message
+            .extend_from_slice(&data[..data.len().min(room)]);
+    }
+
+    fn end(&mut self, f: &mut impl FnMut(&[u8])) {
+        f(&self.message);
+        self.message.clear();
+        self.frame = Frame::Start;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn frame(chunks: &[&[u8]]) -> Vec<Vec<u8>> {
+        let mut framer = Framer::default();
+        let mut messages = Vec::new();
+        let mut add = |message: &[u8]| messages.push(message.to_vec());
+        for chunk in chunks {
+            framer.write(chunk, &mut add);
+        }
+        framer.finish(&mut add);
+        messages
+    }
+
+    #[test]
+    fn test_framer() {
+        let stream: &[u8] = b"<34>Oct 11 22:14:15 host su: ERROR\r\n\n13 <13>1 - - a\nb<14>last";
+        assert_eq!(
+            frame(&[stream]),
+            [
+                &b"<34>Oct 11 22:14:15 host su: ERROR"[..],
+                b"<13>1 - - a\nb",
+                b"<14>last"
+            ]
+        );
+        // Digits that aren't followed by a space start a message of their own.
+        assert_eq!(frame(&[b"123abc\n"]), [b"123abc"]);
+        // An octet-counted message that is cut short is dropped.
+        assert_eq!(frame(&[b"10 <13>1"]), Vec::<Vec<u8>>::new());
+    }
+
+    proptest! {
+        #[test]
+        fn test_chunked(
+            chunk_size in 1..20_usize,
+            messages in proptest::collection::vec(
+                ("<[0-9]{1,3}>[a-z \\n]{0,30}", proptest::bool::ANY),
+                0..10,
+            ),
+        ) {
+            // Messages with a newline in them can only be sent octet-counted.
+            let mut stream = Vec::new();
+            for (message, counted) in &messages {
+                if *counted || message.contains('\n') {
+                    stream.extend(format!("{} {}", message.len(), message).bytes());
+                } else {
+                    stream.extend(format!("{}\n", message).bytes());
+                }
+            }
+            let chunks: Vec<_> = stream.chunks(chunk_size).collect();
+            let expected: Vec<_> = messages.iter().map(|(m, _)| m.as_bytes().to_vec()).collect();
+            prop_assert_eq!(frame(&chunks), expected);
+        }
+    }
+}
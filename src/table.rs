@@ -0,0 +1,539 @@
+use crate::color;
+use crate::memory;
+use crate::normalize::{NormalizeArgs, Normalizer};
+use crate::sketch::{HeavyHitters, HyperLogLog};
+use clap::{Args, ValueEnum};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::io::{self, Write};
+
+#[derive(Args)]
+pub struct TableArgs {
+    #[arg(short = 'n', long, help = "Only print the N most frequent entries.")]
+    pub top: Option<usize>,
+
+    #[arg(
+        short = 'm',
+        long,
+        default_value = "1",
+        help = "Only print entries that occur at least this many times."
+    )]
+    pub min_count: usize,
+
+    #[arg(
+        long,
+        help = "Estimate the counts in bounded memory (see --memory), keeping only the most frequent entries (--top, or 100)."
+    )]
+    pub approx: bool,
+
+    #[arg(
+        long,
+        default_value = "64M",
+        value_parser = parse_size,
+        requires = "approx",
+        help = "The memory to use for estimating counts with --approx. Accepts K, M and G suffixes."
+    )]
+    pub memory: usize,
+
+    #[arg(
+        long,
+        visible_alias = "distinct-only",
+        conflicts_with_all = ["approx", "top"],
+        help = "Only print the number of distinct entries. It is estimated in a few KB of memory, typically to within 1%, unless --exact is given."
+    )]
+    pub distinct: bool,
+
+    #[arg(
+        long,
+        requires = "distinct",
+        help = "Count distinct entries exactly, remembering each one."
+    )]
+    pub exact: bool,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value = "count",
+        help = "How to order the entries. --top still picks the most frequent ones."
+    )]
+    pub sort: SortOrder,
+
+    #[arg(long, help = "Print the entries in the opposite order.")]
+    pub reverse: bool,
+
+    #[arg(
+        long,
+        conflicts_with = "distinct",
+        help = "Also print each entry's share of the total count, as a percentage."
+    )]
+    pub percent: bool,
+
+    #[arg(
+        long,
+        conflicts_with = "distinct",
+        help = "Finish with an \"(other)\" entry counting everything left out by --top and --min-count."
+    )]
+    pub other: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum SortOrder {
+    /// Most frequent first, with ties broken by key.
+    Count,
+    /// By key, byte by byte.
+    Key,
+    /// By the number at the start of the key, so that "9" comes before "10". Keys that don't
+    /// start with a number come last, by key.
+    NumericKey,
+}
+
+// A key of a table.
+pub trait TableKey: Ord {
+    // The number the key starts with, if any, for --sort numeric-key.
+    fn leading_number(&self) -> Option<f64>;
+}
+
+impl TableKey for Vec<u8> {
+    fn leading_number(&self) -> Option<f64> {
+        leading_number(self)
+    }
+}
+
+// Parses a number like "42", "-7" or "3.14" from the start of `key`, after any spaces.
+fn leading_number(key: &[u8]) -> Option<f64> {
+    let key = &key[key.iter().take_while(|&&b| b == b' ').count()..];
+    let sign = usize::from(key.first() == Some(&b'-'));
+    let digits = |from: usize| {
+        from + key[from..]
+            .iter()
+            .take_while(|b| b.is_ascii_digit())
+            .count()
+    };
+    let mut end = digits(sign);
+    if end == sign {
+        return None;
+    }
+    if key.get(end) == Some(&b'.') && key.get(end + 1).is_some_and(u8::is_ascii_digit) {
+        end = digits(end + 1);
+    }
+    std::str::from_utf8(&key[..end]).ok()?.parse().ok()
+}
+
+fn compare_numeric<K: TableKey>(k1: &K, k2: &K) -> Ordering {
+    match (k1.leading_number(), k2.leading_number()) {
+        (Some(n1), Some(n2)) => n1.total_cmp(&n2).then_with(|| k1.cmp(k2)),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => k1.cmp(k2),
+    }
+}
+
+// The key of the --other entry.
+const OTHER_KEY: &[u8] = b"(other)";
+
+// The number of entries --approx keeps if --top isn't given.
+const DEFAULT_APPROX_TOP: usize = 100;
+
+impl TableArgs {
+    // Makes a sketch for --approx, with `memory` split over `shards` sketches.
+    pub fn sketch<K: Hash + Eq + Ord + Clone>(&self, shards: usize) -> Option<HeavyHitters<K>> {
+        self.approx
+            .then(|| HeavyHitters::new(self.memory / shards, self.approx_top()))
+    }
+
+    // The number of entries a sketch keeps.
+    pub fn approx_top(&self) -> usize {
+        self.top.unwrap_or(DEFAULT_APPROX_TOP)
+    }
+
+    // Makes a sketch for --distinct, unless it should be exact.
+    pub fn distinct_sketch(&self) -> Option<HyperLogLog> {
+        (self.distinct && !self.exact).then(HyperLogLog::new)
+    }
+}
+
+// Parses a byte count like "512", "64K", "64M" or "1G".
+pub fn parse_size(s: &str) -> Result<usize, String> {
+    let (digits, unit) = match s.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => s.split_at(i),
+        None => (s, ""),
+    };
+    let shift = match unit {
+        "" | "B" => 0,
+        "K" | "k" | "KB" | "KiB" => 10,
+        "M" | "m" | "MB" | "MiB" => 20,
+        "G" | "g" | "GB" | "GiB" => 30,
+        _ => return Err(format!("unknown size suffix '{}'", unit)),
+    };
+    digits
+        .parse::<usize>()
+        .ok()
+        .and_then(|n| n.checked_mul(1 << shift))
+        .ok_or_else(|| format!("invalid size '{}'", s))
+}
+
+// Reports the error bound of approximate counts.
+pub fn report_error_bound(bound: usize) {
+    eprintln!(
+        "freq: counts are estimates, each at most {} too high with 98% confidence",
+        bound
+    );
+}
+
+// Counts how often each distinct key occurs.
+#[derive(Default)]
+pub struct FreqTable {
+    counts: HashMap<Vec<u8>, usize>,
+
+    // With --approx, keys are counted here instead.
+    sketch: Option<Box<HeavyHitters<Vec<u8>>>>,
+
+    // With --distinct, keys are only counted here, as they are never printed.
+    distinct: Option<Box<HyperLogLog>>,
+
+    // Rewrites keys before they are counted, for --fold-case and --normalize.
+    normalizer: Option<Box<Normalizer>>,
+
+    // Whether keys may contain newlines and tabs, which are escaped when they're printed.
+    escaped: bool,
+
+    // With --max-memory, how much `counts` can take before it's turned into a sketch, and how
+    // much it takes so far, roughly.
+    max_bytes: Option<usize>,
+    bytes: usize,
+
+    // What to turn `counts` into: a sketch keeping this many entries, or with --distinct, an
+    // estimate of the number of entries.
+    approx_top: usize,
+    distinct_only: bool,
+}
+
+// Roughly what each entry of a table takes besides its key: the key's Vec and allocation, the
+// count, and the hash table's spare room.
+const ENTRY_BYTES: usize = 64;
+
+impl FreqTable {
+    pub fn new(args: &TableArgs) -> Self {
+        let sketch = args.sketch(1).map(Box::new);
+        let distinct = args.distinct_sketch().map(Box::new);
+        // Only exact counts can outgrow --max-memory.
+        let max_bytes = memory::table_limit().filter(|_| sketch.is_none() && distinct.is_none());
+        FreqTable {
+            counts: HashMap::new(),
+            sketch,
+            distinct,
+            normalizer: None,
+            escaped: false,
+            max_bytes,
+            bytes: 0,
+            approx_top: args.approx_top(),
+            distinct_only: args.distinct,
+        }
+    }
+
+    // Normalizes keys as the arguments ask.
+    pub fn normalized(mut self, args: &NormalizeArgs) -> Self {
+        self.normalizer = Normalizer::new(args).map(Box::new);
+        self
+    }
+
+    // Escapes backslashes, newlines, carriage returns and tabs in the printed keys if `escaped`,
+    // so that each entry stays on one line and the key in one column.
+    pub fn escaped(mut self, escaped: bool) -> Self {
+        self.escaped = escaped;
+        self
+    }
+
+    pub fn add(&mut self, key: &[u8]) {
+        self.add_count(key, 1);
+    }
+
+    pub fn add_count(&mut self, key: &[u8], count: usize) {
+        let key = match &mut self.normalizer {
+            Some(normalizer) => normalizer.apply(key),
+            None => key,
+        };
+        if let Some(distinct) = &mut self.distinct {
+            distinct.add(key);
+            return;
+        }
+        if let Some(sketch) = &mut self.sketch {
+            sketch.add_count(key, count);
+            return;
+        }
+
+        // Look up by slice first so that we only allocate for new keys.
+        match self.counts.get_mut(key) {
+            Some(c) => *c += count,
+            None => {
+                self.counts.insert(key.to_vec(), count);
+                self.bytes += ENTRY_BYTES + key.len();
+                if self.max_bytes.is_some_and(|max| self.bytes > max) {
+                    self.approximate();
+                }
+            }
+        }
+    }
+
+    // Turns the counts so far into a sketch, so that the table stops growing.
+    fn approximate(&mut self) {
+        let memory = self.max_bytes.take().unwrap_or_default();
+        let counts = std::mem::take(&mut self.counts);
+        self.bytes = 0;
+        if self.distinct_only {
+            memory::warn_table_outgrown("the number of distinct entries is estimated");
+            let mut distinct = HyperLogLog::new();
+            for key in counts.keys() {
+                distinct.add(key);
+            }
+            self.distinct = Some(Box::new(distinct));
+        } else {
+            memory::warn_table_outgrown(
+                "only the most frequent entries are kept, with estimated counts",
+            );
+            // The sketch takes half, leaving room for the entries it keeps.
+            let mut sketch = HeavyHitters::new(memory / 2, self.approx_top);
+            for (key, count) in counts {
+                sketch.add_count(&key[..], count);
+            }
+            self.sketch = Some(Box::new(sketch));
+        }
+    }
+
+    // The number of distinct keys, estimated with --distinct unless --exact is given. Not known
+    // with --approx.
+    pub fn distinct(&self) -> usize {
+        match &self.distinct {
+            Some(distinct) => distinct.estimate(),
+            None => self.counts.len(),
+        }
+    }
+
+    // The sum of all counts. Only exact without --approx and --distinct.
+    pub fn total(&self) -> usize {
+        self.counts.values().sum()
+    }
+
+    #[cfg(test)]
+    pub fn counts(&self) -> &HashMap<Vec<u8>, usize> {
+        &self.counts
+    }
+
+    // Prints the table. See `print_entries`.
+    pub fn print<W: Write>(self, args: &TableArgs, out: &mut W) -> io::Result<()> {
+        if let Some(distinct) = self.distinct {
+            return writeln!(out, "{}", distinct.estimate());
+        }
+
+        let escaped = self.escaped;
+        let write_key = |out: &mut W, key: &Vec<u8>| {
+            if escaped {
+                write_escaped(out, key)
+            } else {
+                out.write_all(key)
+            }
+        };
+        match self.sketch {
+            Some(sketch) => {
+                report_error_bound(sketch.error_bound());
+                let total = sketch.total();
+                print_entries(sketch.into_entries(), Some(total), args, out, write_key)
+            }
+            None => print_entries(self.counts, None, args, out, write_key),
+        }
+    }
+}
+
+// Prints "count<TAB>key" lines, in the order --sort asks for. With --percent,
+// each count is followed by its share of the total, and with --other, a last line counts the
+// entries that weren't printed. With --distinct, prints only the number of entries.
+//
+// `total` is the sum of all counts, if `entries` doesn't include them all, as with --approx.
+pub fn print_entries<K: TableKey, W: Write>(
+    entries: impl IntoIterator<Item = (K, usize)>,
+    total: Option<usize>,
+    args: &TableArgs,
+    out: &mut W,
+    mut write_key: impl FnMut(&mut W, &K) -> io::Result<()>,
+) -> io::Result<()> {
+    let mut sum = 0;
+    let mut entries: Vec<_> = entries
+        .into_iter()
+        .inspect(|&(_, c)| sum += c)
+        .filter(|&(_, c)| c >= args.min_count)
+        .collect();
+    if args.distinct {
+        return writeln!(out, "{}", entries.len());
+    }
+    entries.sort_unstable_by(|(k1, c1), (k2, c2)| c2.cmp(c1).then_with(|| k1.cmp(k2)));
+    entries.truncate(args.top.unwrap_or(usize::MAX));
+    match args.sort {
+        SortOrder::Count => {}
+        SortOrder::Key => entries.sort_unstable_by(|(k1, _), (k2, _)| k1.cmp(k2)),
+        SortOrder::NumericKey => {
+            entries.sort_unstable_by(|(k1, _), (k2, _)| compare_numeric(k1, k2))
+        }
+    }
+    if args.reverse {
+        entries.reverse();
+    }
+
+    let total = total.unwrap_or(sum);
+    let write_count = |out: &mut W, count: usize| {
+        write!(out, "{}\t", color::count(count))?;
+        if args.percent {
+            write!(out, "{:.2}%\t", percent(count, total))?;
+        }
+        Ok::<_, io::Error>(())
+    };
+    // Estimates can add up to more than the total, so the rest is at least 0.
+    let mut other = total;
+    for (key, count) in entries {
+        other = other.saturating_sub(count);
+        write_count(out, count)?;
+        write_key(out, &key)?;
+        out.write_all(b"\n")?;
+    }
+    if args.other && other > 0 {
+        write_count(out, other)?;
+        out.write_all(OTHER_KEY)?;
+        out.write_all(b"\n")?;
+    }
+    Ok(())
+}
+
+// Writes `key` with backslashes, newlines, carriage returns and tabs escaped as in Rust strings.
+fn write_escaped(out: &mut impl Write, key: &[u8]) -> io::Result<()> {
+    let mut start = 0;
+    for (i, &b) in key.iter().enumerate() {
+        let escape: &[u8] = match b {
+            b'\\' => b"\\\\",
+            b'\n' => b"\\n",
+            b'\r' => b"\\r",
+            b'\t' => b"\\t",
+            _ => continue,
+        };
+        out.write_all(&key[start..i])?;
+        out.write_all(escape)?;
+        start = i + 1;
+    }
+    out.write_all(&key[start..])
+}
+
+fn percent(count: usize, total: usize) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        count as f64 * 100.0 / total as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("512"), Ok(512));
+        assert_eq!(parse_size("64K"), Ok(64 << 10));
+        assert_eq!(parse_size("64M"), Ok(64 << 20));
+        assert_eq!(parse_size("2GiB"), Ok(2 << 30));
+        assert!(parse_size("64X").is_err());
+        assert!(parse_size("M").is_err());
+    }
+
+    #[test]
+    fn test_leading_number() {
+        assert_eq!(leading_number(b"443"), Some(443.0));
+        assert_eq!(leading_number(b" -1.5KB"), Some(-1.5));
+        assert_eq!(leading_number(b"8080/tcp"), Some(8080.0));
+        assert_eq!(leading_number(b"3."), Some(3.0));
+        assert_eq!(leading_number(b"-"), None);
+        assert_eq!(leading_number(b"v2"), None);
+    }
+
+    #[test]
+    fn test_write_escaped() {
+        let mut out = Vec::new();
+        write_escaped(&mut out, b"a\tb\r\nc\\d").unwrap();
+        assert_eq!(out, b"a\\tb\\r\\nc\\\\d");
+    }
+
+    #[test]
+    fn test_print_entries() {
+        let print = |top, min_count, total| {
+            let args = TableArgs {
+                top,
+                min_count,
+                approx: false,
+                memory: 0,
+                distinct: false,
+                exact: false,
+                sort: SortOrder::Count,
+                reverse: false,
+                percent: true,
+                other: true,
+            };
+            let entries =
+                [("a", 5), ("b", 3), ("c", 1), ("d", 1)].map(|(k, c)| (k.as_bytes().to_vec(), c));
+            let mut out = Vec::new();
+            print_entries(entries, total, &args, &mut out, |out, key| {
+                out.write_all(key)
+            })
+            .unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        assert_eq!(
+            print(None, 1, None),
+            "5\t50.00%\ta\n3\t30.00%\tb\n1\t10.00%\tc\n1\t10.00%\td\n"
+        );
+        assert_eq!(
+            print(Some(1), 1, None),
+            "5\t50.00%\ta\n5\t50.00%\t(other)\n"
+        );
+        assert_eq!(
+            print(None, 2, Some(20)),
+            "5\t25.00%\ta\n3\t15.00%\tb\n12\t60.00%\t(other)\n"
+        );
+    }
+
+    #[test]
+    fn test_outgrow_max_memory() {
+        let mut args = TableArgs {
+            top: Some(1),
+            min_count: 1,
+            approx: false,
+            memory: 0,
+            distinct: false,
+            exact: false,
+            sort: SortOrder::Count,
+            reverse: false,
+            percent: false,
+            other: false,
+        };
+        let fill = |args: &TableArgs| {
+            let mut table = FreqTable::new(args);
+            table.max_bytes = Some(10 * ENTRY_BYTES);
+            for i in 0..100 {
+                table.add(i.to_string().as_bytes());
+                table.add(b"x");
+            }
+            table
+        };
+
+        // The counts are turned into a sketch, which still finds the most frequent entry.
+        let table = fill(&args);
+        assert!(table.counts.is_empty());
+        let mut out = Vec::new();
+        table.print(&args, &mut out).unwrap();
+        assert_eq!(out, b"100\tx\n");
+
+        args.top = None;
+        args.distinct = true;
+        args.exact = true;
+        let table = fill(&args);
+        assert!(table.counts.is_empty() && table.distinct.is_some());
+    }
+}
@@ -0,0 +1,493 @@
+use crate::color;
+use crate::input::{for_each_chunk, Input};
+use freq::Matcher;
+use memchr::memchr_iter;
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// Counts matches per time bucket, using the timestamp on each line. Lines without one, such as
+// the rest of a stack trace, belong to the last timestamp before them. Matches are only found
+// within lines.
+//
+// Prints "count<TAB>bucket" for each bucket that has any timestamped lines, in order, to `out`,
+// and returns the total for each pattern.
+pub fn count<M: Matcher>(
+    matcher: M,
+    inputs: Vec<Input>,
+    buffer_size: usize,
+    bucket: i64,
+    format: Option<TimeFormat>,
+    out: &mut impl Write,
+) -> io::Result<Vec<usize>> {
+    let mut lines = BucketCounter {
+        totals: vec![0; matcher.patterns()],
+        matcher,
+        bucket,
+        format,
+        year: current_year(),
+        buckets: BTreeMap::new(),
+        current: None,
+        undated: 0,
+        carry: Vec::new(),
+    };
+    for f in inputs {
+        for_each_chunk(f, buffer_size, |chunk| lines.write(chunk));
+        lines.finish();
+    }
+
+    if lines.undated > 0 {
+        eprintln!(
+            "freq: {} matches are before the first timestamp of their file, and not in any bucket",
+            lines.undated
+        );
+    }
+    for (&start, &count) in &lines.buckets {
+        writeln!(
+            out,
+            "{}\t{}",
+            color::count(count),
+            DateTime::from_seconds(start)
+        )?;
+    }
+    Ok(lines.totals)
+}
+
+struct BucketCounter<M> {
+    matcher: M,
+
+    // The length of a bucket, in seconds.
+    bucket: i64,
+
+    // The format of the timestamps, once it is known.
+    format: Option<TimeFormat>,
+
+    // The year to assume for timestamps that don't have one.
+    year: i64,
+
+    // The number of matches in each bucket, by its start.
+    buckets: BTreeMap<i64, usize>,
+
+    // The start of the bucket of the current input's last timestamp.
+    current: Option<i64>,
+
+    // The number of matches in lines before the first timestamp of an input.
+    undated: usize,
+
+    totals: Vec<usize>,
+
+    // The start of a line that continues into the next chunk.
+    carry: Vec<u8>,
+}
+
+impl<M: Matcher> BucketCounter<M> {
+    fn write(&mut self, buf: &[u8]) {
+        let mut start = 0;
+        for end in memchr_iter(b'\n', buf) {
+            if self.carry.is_empty() {
+                self.line(&buf[start..end]);
+            } else {
+                let mut line = std::mem::take(&mut self.carry);
+                line.extend(&buf[start..end]);
+                self.line(&line);
+                line.clear();
+                self.carry = line;
+            }
+            start = end + 1;
+        }
+        self.carry.extend(&buf[start..]);
+    }
+
+    // Ends the current input.
+    fn finish(&mut self) {
+        if !self.carry.is_empty() {
+            let line = std::mem::take(&mut self.carry);
+            self.line(&line);
+        }
+        self.current = None;
+    }
+
+    fn line(&mut self, line: &[u8]) {
+        if self.format.is_none() {
+            self.format = TimeFormat::detect(line);
+        }
+        if let Some(time) = self.format.as_ref().and_then(|f| f.find(line, self.year)) {
+            let start = time.seconds().div_euclid(self.bucket) * self.bucket;
+            self.buckets.entry(start).or_insert(0);
+            self.current = Some(start);
+        }
+
+        let mut matches = 0;
+        let totals = &mut self.totals;
+        self.matcher.find_all(line, &mut |m| {
+            totals[m.pattern] += 1;
+            matches += 1;
+            true
+        });
+        if matches == 0 {
+            return;
+        }
+        match self.current {
+            Some(start) => *self.buckets.entry(start).or_insert(0) += matches,
+            None => self.undated += matches,
+        }
+    }
+}
+
+// Parses a bucket length like "30s", "5m", "1h" or "1d" into seconds.
+pub fn parse_duration(s: &str) -> Result<i64, String> {
+    let (digits, unit) = s.split_at(s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len()));
+    let unit = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => return Err(format!("unknown unit '{}', expected s, m, h or d", unit)),
+    };
+    digits
+        .parse::<i64>()
+        .ok()
+        .filter(|&n| n > 0)
+        .and_then(|n| n.checked_mul(unit))
+        .ok_or_else(|| format!("invalid duration '{}'", s))
+}
+
+// Parses a timeout such as "500ms" or "30s", which unlike a duration can be under a second.
+pub fn parse_timeout(s: &str) -> Result<Duration, String> {
+    match s.strip_suffix("ms") {
+        Some(millis) => millis
+            .parse::<u64>()
+            .ok()
+            .filter(|&n| n > 0 && millis.bytes().all(|b| b.is_ascii_digit()))
+            .map(Duration::from_millis)
+            .ok_or_else(|| format!("invalid duration '{}'", s)),
+        None => parse_duration(s)
+            .map(|seconds| Duration::from_secs(seconds as u64))
+            .map_err(|e| e.replace("s, m, h or d", "ms, s, m, h or d")),
+    }
+}
+
+// Parses a whole date, or date and time, such as "2024-05-01" or "2024-05-01 12:00:00", into
+// seconds since the Unix epoch, taking it to be UTC.
+pub fn parse_timestamp(s: &str) -> Option<i64> {
+    [
+        ("%Y-%m-%d %H:%M:%S", 19),
+        ("%Y-%m-%dT%H:%M:%S", 19),
+        ("%Y-%m-%d", 10),
+    ]
+    .iter()
+    .filter(|&&(_, len)| s.len() == len)
+    .find_map(|&(format, _)| {
+        let format = TimeFormat::parse(format).expect("the formats are valid");
+        format.parse_at(s.as_bytes(), 1970)
+    })
+    .map(|t| t.seconds())
+}
+
+// Shows `seconds` since the Unix epoch as the start of a bucket is shown, in UTC.
+#[cfg(any(feature = "kafka", not(target_os = "wasi")))]
+pub fn timestamp(seconds: i64) -> impl std::fmt::Display {
+    DateTime::from_seconds(seconds)
+}
+
+// A strftime-style timestamp format. Only the fields freq needs to place a line in time are
+// supported: %Y, %m, %b, %d, %e, %H, %M, %S and %%. Time zones are ignored.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TimeFormat {
+    items: Vec<Item>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Item {
+    Year,
+    Month,
+    MonthName,
+    Day,
+    // A day of the month that may be padded with a space instead of a 0.
+    SpaceDay,
+    Hour,
+    Minute,
+    Second,
+    Literal(u8),
+}
+
+const MONTHS: [&[u8]; 12] = [
+    b"Jan", b"Feb", b"Mar", b"Apr", b"May", b"Jun", b"Jul", b"Aug", b"Sep", b"Oct", b"Nov", b"Dec",
+];
+
+// The formats tried when --time-format isn't given, in order: ISO 8601, with a "T" or a space,
+// Apache and nginx access logs, nginx error logs, and syslog.
+const COMMON_FORMATS: [&str; 5] = [
+    "%Y-%m-%dT%H:%M:%S",
+    "%Y-%m-%d %H:%M:%S",
+    "%d/%b/%Y:%H:%M:%S",
+    "%Y/%m/%d %H:%M:%S",
+    "%b %e %H:%M:%S",
+];
+
+impl TimeFormat {
+    pub fn parse(format: &str) -> Result<TimeFormat, String> {
+        let mut items = Vec::new();
+        let mut bytes = format.bytes();
+        while let Some(b) = bytes.next() {
+            if b != b'%' {
+                items.push(Item::Literal(b));
+                continue;
+            }
+            items.push(match bytes.next() {
+                Some(b'Y') => Item::Year,
+                Some(b'm') => Item::Month,
+                Some(b'b') => Item::MonthName,
+                Some(b'd') => Item::Day,
+                Some(b'e') => Item::SpaceDay,
+                Some(b'H') => Item::Hour,
+                Some(b'M') => Item::Minute,
+                Some(b'S') => Item::Second,
+                Some(b'%') => Item::Literal(b'%'),
+                Some(c) => return Err(format!("unsupported field '%{}'", c as char)),
+                None => return Err("format ends with '%'".to_string()),
+            });
+        }
+        let has = |item| items.contains(&item);
+        if !has(Item::Day) && !has(Item::SpaceDay) {
+            return Err("format needs a day (%d or %e)".to_string());
+        }
+        if !has(Item::Month) && !has(Item::MonthName) {
+            return Err("format needs a month (%m or %b)".to_string());
+        }
+        Ok(TimeFormat { items })
+    }
+
+    // Returns the first of the common formats that finds a timestamp in `line`.
+    fn detect(line: &[u8]) -> Option<TimeFormat> {
+        COMMON_FORMATS
+            .iter()
+            .map(|f| TimeFormat::parse(f).expect("common formats are valid"))
+            .find(|f| f.find(line, 1970).is_some())
+    }
+
+    // Finds the first timestamp in `line`, taking it to be in `year` if the format has no year.
+    fn find(&self, line: &[u8], year: i64) -> Option<DateTime> {
+        (0..line.len()).find_map(|i| self.parse_at(&line[i..], year))
+    }
+
+    fn parse_at(&self, mut s: &[u8], year: i64) -> Option<DateTime> {
+        let mut t = DateTime {
+            year,
+            month: 1,
+            day: 1,
+            hour: 0,
+            minute: 0,
+            second: 0,
+        };
+        for &item in &self.items {
+            let (value, rest) = match item {
+                Item::Literal(b) => (0, s.strip_prefix(&[b])?),
+                Item::MonthName => {
+                    let i = MONTHS.iter().position(|m| s.starts_with(m))?;
+                    (i as i64 + 1, &s[3..])
+                }
+                Item::SpaceDay => match s.strip_prefix(b" ") {
+                    Some(rest) => number(rest, 1)?,
+                    None => number(s, 2)?,
+                },
+                Item::Year => number(s, 4)?,
+                _ => number(s, 2)?,
+            };
+            match item {
+                Item::Year => t.year = value,
+                Item::Month | Item::MonthName => t.month = value,
+                Item::Day | Item::SpaceDay => t.day = value,
+                Item::Hour => t.hour = value,
+                Item::Minute => t.minute = value,
+                Item::Second => t.second = value,
+                Item::Literal(_) => {}
+            }
+            s = rest;
+        }
+        t.is_valid().then_some(t)
+    }
+}
+
+// Parses exactly `digits` digits from the start of `s`.
+fn number(s: &[u8], digits: usize) -> Option<(i64, &[u8])> {
+    let (n, rest) = s.split_at_checked(digits)?;
+    if !n.iter().all(u8::is_ascii_digit) {
+        return None;
+    }
+    let value = n.iter().fold(0, |v, &d| v * 10 + (d - b'0') as i64);
+    Some((value, rest))
+}
+
+// A date and time, without a time zone.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct DateTime {
+    year: i64,
+    month: i64,
+    day: i64,
+    hour: i64,
+    minute: i64,
+    second: i64,
+}
+
+impl DateTime {
+    fn is_valid(&self) -> bool {
+        (1..=12).contains(&self.month)
+            && (1..=31).contains(&self.day)
+            && (0..24).contains(&self.hour)
+            && (0..60).contains(&self.minute)
+            // Allow for leap seconds.
+            && (0..=60).contains(&self.second)
+    }
+
+    // Seconds since the Unix epoch, taking the time to be UTC.
+    fn seconds(&self) -> i64 {
+        let days = days_from_civil(self.year, self.month, self.day);
+        ((days * 24 + self.hour) * 60 + self.minute) * 60 + self.second
+    }
+
+    fn from_seconds(seconds: i64) -> DateTime {
+        let (days, rest) = (seconds.div_euclid(86400), seconds.rem_euclid(86400));
+        let (year, month, day) = civil_from_days(days);
+        DateTime {
+            year,
+            month,
+            day,
+            hour: rest / 3600,
+            minute: rest / 60 % 60,
+            second: rest % 60,
+        }
+    }
+}
+
+impl std::fmt::Display for DateTime {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
+
+// The number of days from 1970-01-01 to a date in the proleptic Gregorian calendar, from
+// http://howardhinnant.github.io/date_algorithms.html.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+// The inverse of `days_from_civil`.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+fn current_year() -> i64 {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64);
+    DateTime::from_seconds(seconds).year
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use freq::Literal;
+    use proptest::{prop_assert_eq, proptest};
+
+    fn find(format: &str, line: &str) -> Option<String> {
+        let format = TimeFormat::parse(format).unwrap();
+        format.find(line.as_bytes(), 2024).map(|t| t.to_string())
+    }
+
+    #[test]
+    fn test_time_format() {
+        assert_eq!(
+            find("%Y-%m-%dT%H:%M:%S", "[2024-03-05T14:07:09.123Z] ERROR"),
+            Some("2024-03-05T14:07:09".to_string())
+        );
+        assert_eq!(
+            find(
+                "%d/%b/%Y:%H:%M:%S",
+                "1.2.3.4 - - [05/Mar/2024:14:07:09 +0000] \"GET /\""
+            ),
+            Some("2024-03-05T14:07:09".to_string())
+        );
+        assert_eq!(
+            find("%b %e %H:%M:%S", "Mar  5 14:07:09 host sshd[1]: error"),
+            Some("2024-03-05T14:07:09".to_string())
+        );
+        assert_eq!(find("%Y-%m-%d %H:%M:%S", "2024-13-05 14:07:09"), None);
+        assert!(TimeFormat::parse("%H:%M").is_err());
+        assert!(TimeFormat::parse("%Y-%m-%d %Q").is_err());
+
+        assert_eq!(
+            TimeFormat::detect(b"Mar  5 14:07:09 host"),
+            TimeFormat::parse("%b %e %H:%M:%S").ok()
+        );
+        assert_eq!(TimeFormat::detect(b"no time here"), None);
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("30s"), Ok(30));
+        assert_eq!(parse_duration("5m"), Ok(300));
+        assert_eq!(parse_duration("1d"), Ok(86400));
+        assert!(parse_duration("0m").is_err());
+        assert!(parse_duration("5").is_err());
+        assert!(parse_duration("m").is_err());
+        assert_eq!(parse_timeout("250ms"), Ok(Duration::from_millis(250)));
+        assert_eq!(parse_timeout("2m"), Ok(Duration::from_secs(120)));
+        assert!(parse_timeout("0ms").is_err());
+        assert!(parse_timeout("+5ms").is_err());
+    }
+
+    #[test]
+    fn test_parse_timestamp() {
+        assert_eq!(parse_timestamp("1970-01-02"), Some(86400));
+        assert_eq!(parse_timestamp("2024-05-01 12:00:30"), Some(1714564830));
+        assert_eq!(parse_timestamp("2024-05-01T12:00:30"), Some(1714564830));
+        assert_eq!(parse_timestamp("2024-05-01 12:00"), None);
+        assert_eq!(parse_timestamp("2024-13-01"), None);
+    }
+
+    #[test]
+    fn test_buckets() {
+        let log = "\
+2024-03-05 14:07:09 ERROR a
+  at ERROR b
+2024-03-05 14:08:59 INFO
+2024-03-05 14:11:00 ERROR ERROR
+";
+        let mut out = Vec::new();
+        let inputs: Vec<Input> = vec![Box::new(log.as_bytes()), Box::new(&b"ERROR\n"[..])];
+        let totals = count(Literal::new(b"ERROR"), inputs, 7, 300, None, &mut out).unwrap();
+        assert_eq!(totals, [5]);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "2\t2024-03-05T14:05:00\n2\t2024-03-05T14:10:00\n"
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn test_civil(days in -1_000_000..1_000_000_i64) {
+            let (y, m, d) = civil_from_days(days);
+            prop_assert_eq!(days_from_civil(y, m, d), days);
+        }
+    }
+}
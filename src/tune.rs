@@ -0,0 +1,133 @@
+use crate::memory;
+use std::sync::Mutex;
+use std::time::Duration;
+use tracing::{debug, info};
+
+// The buffer sizes --auto-tune tries. Pipes only hand over 64K at a time, while NFS and other
+// network filesystems can take far larger reads than local disks to keep busy.
+const CANDIDATES: [usize; 5] = [64 << 10, 256 << 10, 1 << 20, 4 << 20, 16 << 20];
+
+// How much each candidate reads before it is judged. Tuning is done once all have read this
+// much, across inputs if they're short.
+pub const TRIAL_BYTES: usize = 64 << 20;
+
+// How tuning is going, if --auto-tune was given.
+static TUNING: Mutex<Option<Tuning>> = Mutex::new(None);
+
+struct Tuning {
+    trials: Vec<Trial>,
+    // The size that read and counted fastest, once all have been tried.
+    chosen: Option<usize>,
+}
+
+struct Trial {
+    size: usize,
+    bytes: usize,
+    // Reading and counting those bytes.
+    time: Duration,
+}
+
+pub fn enable() {
+    let max = memory::max_buffer_size().unwrap_or(usize::MAX);
+    *TUNING.lock().unwrap() = Some(Tuning::new(max));
+}
+
+// The buffer size to try next, or None if tuning is done or wasn't asked for.
+pub fn next_trial() -> Option<usize> {
+    TUNING.lock().unwrap().as_ref()?.next_trial()
+}
+
+// Records that reading and counting `bytes` with a buffer of `size` took `time`, and chooses
+// the fastest size once every one has been tried.
+pub fn record(size: usize, bytes: usize, time: Duration) {
+    if let Some(tuning) = TUNING.lock().unwrap().as_mut() {
+        tuning.record(size, bytes, time);
+    }
+}
+
+// The buffer size tuning chose, if it's done.
+pub fn chosen() -> Option<usize> {
+    TUNING.lock().unwrap().as_ref()?.chosen
+}
+
+impl Tuning {
+    // Tries the candidates up to `max` bytes.
+    fn new(max: usize) -> Self {
+        let mut sizes: Vec<usize> = CANDIDATES.iter().map(|&size| size.min(max)).collect();
+        sizes.dedup();
+        let trials = sizes
+            .into_iter()
+            .map(|size| Trial {
+                size,
+                bytes: 0,
+                time: Duration::ZERO,
+            })
+            .collect();
+        Tuning {
+            trials,
+            chosen: None,
+        }
+    }
+
+    fn next_trial(&self) -> Option<usize> {
+        let trial = self.trials.iter().find(|t| t.bytes < TRIAL_BYTES)?;
+        Some(trial.size)
+    }
+
+    fn record(&mut self, size: usize, bytes: usize, time: Duration) {
+        let Some(trial) = self.trials.iter_mut().find(|t| t.size == size) else {
+            return;
+        };
+        trial.bytes += bytes;
+        trial.time += time;
+        if trial.bytes < TRIAL_BYTES {
+            return;
+        }
+        debug!(
+            "auto-tune: buffers of {} bytes went at {:.1} MB/s",
+            size,
+            trial.rate() / 1e6
+        );
+        if self.next_trial().is_none() {
+            let best = self
+                .trials
+                .iter()
+                .max_by(|a, b| a.rate().total_cmp(&b.rate()));
+            self.chosen = best.map(|t| t.size);
+            info!(
+                "auto-tune: reading in buffers of {} bytes",
+                self.chosen.unwrap_or_default()
+            );
+        }
+    }
+}
+
+impl Trial {
+    // Bytes per second.
+    fn rate(&self) -> f64 {
+        self.bytes as f64 / self.time.as_secs_f64().max(f64::MIN_POSITIVE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tuning() {
+        let mut tuning = Tuning::new(1 << 20);
+        assert_eq!(tuning.trials.len(), 3);
+
+        // A short input only goes part of the way.
+        tuning.record(64 << 10, TRIAL_BYTES / 2, Duration::from_millis(50));
+        assert_eq!(tuning.next_trial(), Some(64 << 10));
+        tuning.record(64 << 10, TRIAL_BYTES / 2, Duration::from_millis(50));
+        assert_eq!(tuning.next_trial(), Some(256 << 10));
+
+        tuning.record(256 << 10, TRIAL_BYTES, Duration::from_millis(40));
+        assert_eq!(tuning.chosen, None);
+        tuning.record(1 << 20, TRIAL_BYTES, Duration::from_millis(60));
+        assert_eq!(tuning.next_trial(), None);
+        assert_eq!(tuning.chosen, Some(256 << 10));
+    }
+}
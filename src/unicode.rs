@@ -0,0 +1,63 @@
+// Approximate Unicode properties, built from what the standard library provides plus a few
+// code point ranges.
+
+pub fn is_combining_mark(c: char) -> bool {
+    matches!(c,
+        '\u{0300}'..='\u{036F}'
+        | '\u{0483}'..='\u{0489}'
+        | '\u{0591}'..='\u{05BD}'
+        | '\u{064B}'..='\u{065F}'
+        | '\u{0900}'..='\u{0903}'
+        | '\u{093A}'..='\u{094F}'
+        | '\u{0E31}' | '\u{0E34}'..='\u{0E3A}' | '\u{0E47}'..='\u{0E4E}'
+        | '\u{1AB0}'..='\u{1AFF}'
+        | '\u{1DC0}'..='\u{1DFF}'
+        | '\u{20D0}'..='\u{20FF}'
+        | '\u{3099}'..='\u{309A}'
+        | '\u{FE20}'..='\u{FE2F}')
+}
+
+// Invisible formatting characters, such as zero width spaces and joiners.
+pub fn is_format(c: char) -> bool {
+    matches!(c,
+        '\u{00AD}'
+        | '\u{200B}'..='\u{200F}'
+        | '\u{202A}'..='\u{202E}'
+        | '\u{2060}'..='\u{2064}'
+        | '\u{FEFF}')
+}
+
+fn is_punctuation(c: char) -> bool {
+    if c.is_ascii() {
+        return c.is_ascii_punctuation() && !"$+<=>^`|~".contains(c);
+    }
+    matches!(c,
+        '\u{00A1}' | '\u{00A7}' | '\u{00AB}' | '\u{00B6}' | '\u{00B7}' | '\u{00BB}' | '\u{00BF}'
+        | '\u{2010}'..='\u{2027}'
+        | '\u{2030}'..='\u{205E}'
+        | '\u{3001}'..='\u{3003}'
+        | '\u{3008}'..='\u{3011}'
+        | '\u{3014}'..='\u{301F}'
+        | '\u{FF01}'..='\u{FF0F}'
+        | '\u{FF1A}'..='\u{FF20}')
+}
+
+// The major general category of `c`: Letter, Mark, Number, Punctuation, Symbol, Separator or
+// Other.
+pub fn category(c: char) -> &'static str {
+    if is_combining_mark(c) {
+        "Mark"
+    } else if c.is_alphabetic() {
+        "Letter"
+    } else if c.is_numeric() {
+        "Number"
+    } else if c.is_control() || is_format(c) || matches!(c, '\u{E000}'..='\u{F8FF}') {
+        "Other"
+    } else if c.is_whitespace() {
+        "Separator"
+    } else if is_punctuation(c) {
+        "Punctuation"
+    } else {
+        "Symbol"
+    }
+}
@@ -0,0 +1,126 @@
+use crate::glob::Glob;
+use crate::input::{long_path, report};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+use tracing::debug;
+
+// The names of directories not to search, from --exclude-dir.
+static EXCLUDED: OnceLock<Vec<Glob>> = OnceLock::new();
+
+// Whether to stay on the file system of each directory given, with --one-file-system.
+static ONE_FILE_SYSTEM: AtomicBool = AtomicBool::new(false);
+
+pub fn exclude_dirs(globs: Vec<Glob>) {
+    let _ = EXCLUDED.set(globs);
+}
+
+pub fn set_one_file_system() {
+    ONE_FILE_SYSTEM.store(true, Ordering::Relaxed);
+}
+
+// Returns the files under each of `paths`, in order, with paths that aren't directories, such as
+// "-" for stdin, as they are. Directories that can't be read are reported and skipped.
+pub fn files(paths: &[PathBuf]) -> Vec<PathBuf> {
+    let excluded = EXCLUDED.get().map_or(&[][..], Vec::as_slice);
+    search(paths, excluded, ONE_FILE_SYSTEM.load(Ordering::Relaxed))
+}
+
+// Like files, but leaves out the directories found in `paths` whose names match one of
+// `excluded`, and with `one_file_system`, those on another file system than the directory in
+// `paths` they were found in, such as /proc under /, without reading them. The directories in
+// `paths` themselves are always searched.
+fn search(paths: &[PathBuf], excluded: &[Glob], one_file_system: bool) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    // Each path, with the device of the directory given that it was found in, if it was.
+    // Reversed, so that they come off the stack in order.
+    let mut stack: Vec<(PathBuf, Option<u64>)> =
+        paths.iter().rev().map(|p| (p.clone(), None)).collect();
+    while let Some((path, root)) = stack.pop() {
+        let meta = match fs::metadata(&path) {
+            Ok(meta) if meta.is_dir() => meta,
+            _ => {
+                files.push(path);
+                continue;
+            }
+        };
+        let device = device(&meta);
+        if let Some(root) = root {
+            let crossed = one_file_system && device != root;
+            if crossed || is_excluded(&path, excluded) {
+                debug!("not searching {}", path.display());
+                continue;
+            }
+        }
+        let entries = fs::read_dir(long_path(&path)).and_then(|dir| {
+            dir.map(|entry| Ok(entry?.path()))
+                .collect::<io::Result<Vec<PathBuf>>>()
+        });
+        let mut entries = match entries {
+            Ok(entries) => entries,
+            Err(e) => {
+                report(format!("{}: {}", path.display(), e));
+                continue;
+            }
+        };
+        entries.sort_unstable_by(|a, b| b.cmp(a));
+        let root = root.unwrap_or(device);
+        stack.extend(entries.into_iter().map(|p| (p, Some(root))));
+    }
+    files
+}
+
+// The device of the file system a file is on.
+#[cfg(unix)]
+fn device(meta: &fs::Metadata) -> u64 {
+    std::os::unix::fs::MetadataExt::dev(meta)
+}
+
+// Elsewhere, file systems can't be told apart, so mount points are searched.
+#[cfg(not(unix))]
+fn device(_: &fs::Metadata) -> u64 {
+    0
+}
+
+fn is_excluded(dir: &Path, excluded: &[Glob]) -> bool {
+    let Some(name) = dir.file_name() else {
+        return false;
+    };
+    let name = name.to_string_lossy();
+    excluded.iter().any(|glob| glob.matches(&name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_files() {
+        let dir = std::env::temp_dir().join(format!("freq-walk-{}", std::process::id()));
+        fs::create_dir_all(dir.join("b")).unwrap();
+        for name in ["c", "b/x", "a"] {
+            fs::write(dir.join(name), "").unwrap();
+        }
+        assert_eq!(
+            files(std::slice::from_ref(&dir)),
+            [dir.join("a"), dir.join("b/x"), dir.join("c")]
+        );
+        let paths = [dir.join("b"), PathBuf::from("-"), dir.join("a")];
+        assert_eq!(
+            files(&paths),
+            [dir.join("b/x"), PathBuf::from("-"), dir.join("a")]
+        );
+        let excluded = [Glob::parse("[b-z]").unwrap()];
+        assert_eq!(
+            search(std::slice::from_ref(&dir), &excluded, true),
+            [dir.join("a"), dir.join("c")]
+        );
+        assert_eq!(
+            search(&[dir.join("b")], &excluded, false),
+            [dir.join("b/x")]
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,112 @@
+use crate::color;
+use std::io::{self, Write};
+
+// The lines, words and bytes read, as wc counts them, for those of --lines, --words and --bytes
+// that were given.
+pub struct Counts {
+    lines: Option<u64>,
+    words: Option<u64>,
+    bytes: Option<u64>,
+
+    // Whether the last byte read was part of a word.
+    in_word: bool,
+}
+
+impl Counts {
+    pub fn new(lines: bool, words: bool, bytes: bool) -> Self {
+        Counts {
+            lines: lines.then_some(0),
+            words: words.then_some(0),
+            bytes: bytes.then_some(0),
+            in_word: false,
+        }
+    }
+
+    pub fn write(&mut self, chunk: &[u8]) {
+        if let Some(lines) = &mut self.lines {
+            *lines += bytecount::count(chunk, b'\n') as u64;
+        }
+        if let Some(words) = &mut self.words {
+            // A word starts at each byte that isn't whitespace after one that is.
+            for &b in chunk {
+                let in_word = !is_space(b);
+                *words += u64::from(in_word && !self.in_word);
+                self.in_word = in_word;
+            }
+        }
+        if let Some(bytes) = &mut self.bytes {
+            *bytes += chunk.len() as u64;
+        }
+    }
+
+    // Ends the current input. Words don't continue from one input into the next.
+    pub fn finish(&mut self) {
+        self.in_word = false;
+    }
+
+    // Prints "count<TAB>lines", "count<TAB>words" and "count<TAB>bytes", in wc's order, for those
+    // that were counted.
+    pub fn print(&self, out: &mut impl Write) -> io::Result<()> {
+        for (count, name) in [
+            (self.lines, "lines"),
+            (self.words, "words"),
+            (self.bytes, "bytes"),
+        ] {
+            if let Some(count) = count {
+                writeln!(out, "{}\t{}", color::count(count), name)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// The bytes that separate words, as isspace in the C locale.
+fn is_space(b: u8) -> bool {
+    matches!(b, b' ' | b'\t' | b'\n' | b'\x0b' | b'\x0c' | b'\r')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use proptest::string::bytes_regex;
+    use proptest::{prop_assert_eq, proptest};
+
+    fn print(counts: &Counts) -> String {
+        let mut out = Vec::new();
+        counts.print(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_counts() {
+        let mut counts = Counts::new(true, true, true);
+        counts.write(b"one two\n\tthr");
+        counts.write(b"ee\x0bfour\r\nfive");
+        counts.finish();
+        counts.write(b"six\n");
+        counts.finish();
+        assert_eq!(print(&counts), "3\tlines\n6\twords\n29\tbytes\n");
+
+        let mut counts = Counts::new(false, true, false);
+        counts.write(b"  a b  ");
+        assert_eq!(print(&counts), "2\twords\n");
+    }
+
+    proptest! {
+        #[test]
+        fn test_chunked(
+            chunk_size in 1..20_usize,
+            text in bytes_regex("((?s-u:[a \\n]{0,200}))").unwrap(),
+        ) {
+            let mut counts = Counts::new(true, true, true);
+            text.chunks(chunk_size).for_each(|chunk| counts.write(chunk));
+            let words = text.split(|&b| is_space(b)).filter(|w| !w.is_empty()).count();
+            let lines = text.iter().filter(|&&b| b == b'\n').count();
+            prop_assert_eq!(
+                print(&counts),
+                format!("{}\tlines\n{}\twords\n{}\tbytes\n", lines, words, text.len())
+            );
+        }
+    }
+}
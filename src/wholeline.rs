@@ -0,0 +1,110 @@
+use memchr::memchr_iter;
+
+// Counts the lines that are exactly the pattern, as grep -x does. Lines are compared whole, so a
+// line that merely contains the pattern doesn't count, and a \r before the newline is part of the
+// line.
+pub struct WholeLineCounter {
+    pattern: Vec<u8>,
+    count: usize,
+
+    // The start of a line that continues into the next chunk, while it can still be the pattern.
+    carry: Vec<u8>,
+
+    // Whether the line that continues is already longer than the pattern.
+    too_long: bool,
+}
+
+impl WholeLineCounter {
+    pub fn new(pattern: &[u8]) -> Self {
+        WholeLineCounter {
+            pattern: pattern.to_vec(),
+            count: 0,
+            carry: Vec::new(),
+            too_long: false,
+        }
+    }
+
+    pub fn write(&mut self, buf: &[u8]) {
+        let mut start = 0;
+        for end in memchr_iter(b'\n', buf) {
+            let line = &buf[start..end];
+            if self.is_pattern(line) {
+                self.count += 1;
+            }
+            self.carry.clear();
+            self.too_long = false;
+            start = end + 1;
+        }
+        let rest = &buf[start..];
+        if self.too_long || self.carry.len() + rest.len() > self.pattern.len() {
+            self.carry.clear();
+            self.too_long = true;
+        } else {
+            self.carry.extend_from_slice(rest);
+        }
+    }
+
+    // Counts the last line of the current input, if it has no trailing newline.
+    pub fn finish(&mut self) {
+        if !self.carry.is_empty() && self.carry == self.pattern {
+            self.count += 1;
+        }
+        self.carry.clear();
+        self.too_long = false;
+    }
+
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    // Whether the line that ends with `end` is the pattern.
+    fn is_pattern(&self, end: &[u8]) -> bool {
+        let carried = self.carry.len();
+        !self.too_long
+            && carried + end.len() == self.pattern.len()
+            && self.pattern[..carried] == self.carry
+            && self.pattern[carried..] == *end
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use proptest::string::bytes_regex;
+    use proptest::{prop_assert_eq, proptest};
+
+    fn count_chunked(pattern: &[u8], text: &[u8], chunk_size: usize) -> usize {
+        let mut counter = WholeLineCounter::new(pattern);
+        text.chunks(chunk_size)
+            .for_each(|chunk| counter.write(chunk));
+        counter.finish();
+        counter.count()
+    }
+
+    #[test]
+    fn test_whole_lines() {
+        let text = b"ok\nnot ok\nok\r\nok\nok";
+        assert_eq!(count_chunked(b"ok", text, 100), 3);
+        assert_eq!(count_chunked(b"ok\r", text, 2), 1);
+        assert_eq!(count_chunked(b"not ok", text, 1), 1);
+        assert_eq!(count_chunked(b"o", text, 3), 0);
+    }
+
+    proptest! {
+        #[test]
+        fn test_chunked(
+            chunk_size in 1..20_usize,
+            pattern in bytes_regex("((?s-u:[ab]{1,3}))").unwrap(),
+            text in bytes_regex("((?s-u:[ab\\n]{0,200}))").unwrap(),
+        ) {
+            let expected = text
+                .strip_suffix(b"\n")
+                .unwrap_or(&text)
+                .split(|&b| b == b'\n')
+                .filter(|line| *line == pattern)
+                .count();
+            prop_assert_eq!(count_chunked(&pattern, &text, chunk_size), expected);
+        }
+    }
+}
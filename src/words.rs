@@ -0,0 +1,360 @@
+use crate::input::{for_each_chunk, open_inputs};
+use crate::normalize::NormalizeArgs;
+use crate::table::{FreqTable, TableArgs};
+use crate::unicode::is_combining_mark;
+use clap::Args;
+use std::collections::HashSet;
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+
+#[derive(Args)]
+pub struct WordsArgs {
+    #[arg(help = "The files to read, with - for stdin. If not provided, stdin is used.")]
+    input: Vec<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Only treat ASCII letters, digits and underscores as word characters. Faster, and does not require UTF-8 input."
+    )]
+    ascii: bool,
+
+    #[arg(
+        long,
+        help = "Count words in lowercase, so that \"The\" and \"the\" are the same word."
+    )]
+    lowercase: bool,
+
+    #[arg(
+        long,
+        value_name = "N",
+        default_value = "1",
+        help = "Skip words shorter than N characters."
+    )]
+    min_length: usize,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Skip the words listed in FILE, separated by whitespace. Matching ignores case. Can be given more than once."
+    )]
+    stopwords: Vec<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Skip common English words such as \"the\", \"a\" and \"of\"."
+    )]
+    english_stopwords: bool,
+
+    #[command(flatten)]
+    normalize: NormalizeArgs,
+
+    #[command(flatten)]
+    table: TableArgs,
+}
+
+pub fn run(args: WordsArgs, buffer_size: usize, out: &mut impl Write) {
+    let mut filter = WordFilter {
+        lowercase: args.lowercase,
+        min_length: args.min_length,
+        ..WordFilter::default()
+    };
+    if args.english_stopwords {
+        filter.add_stopwords(ENGLISH_STOPWORDS.split_whitespace().map(str::as_bytes));
+    }
+    for path in &args.stopwords {
+        let list = fs::read(path).unwrap_or_else(|e| {
+            eprintln!("freq: {}: {}", path.display(), e);
+            std::process::exit(2);
+        });
+        filter.add_stopwords(list.split(|b| b.is_ascii_whitespace()));
+    }
+
+    let mut words = WordCounter::new(
+        FreqTable::new(&args.table).normalized(&args.normalize),
+        filter,
+        args.ascii,
+    );
+    for f in open_inputs(&args.input) {
+        for_each_chunk(f, buffer_size, |chunk| words.write(chunk));
+        words.finish();
+    }
+
+    words
+        .table
+        .print(&args.table, out)
+        .expect("failed to write output");
+}
+
+#[derive(Default)]
+pub struct WordCounter {
+    table: FreqTable,
+    filter: WordFilter,
+
+    ascii: bool,
+
+    // The end of the input so far, from its last separator on. Words never contain separators,
+    // so everything before it can be split into words without waiting for more.
+    carry: Vec<u8>,
+}
+
+impl WordCounter {
+    pub fn new(table: FreqTable, filter: WordFilter, ascii: bool) -> Self {
+        WordCounter {
+            table,
+            filter,
+            ascii,
+            carry: Vec::new(),
+        }
+    }
+
+    pub fn write(&mut self, buf: &[u8]) {
+        let Some(last) = buf.iter().rposition(|&b| is_separator(b)) else {
+            self.carry.extend(buf);
+            return;
+        };
+
+        let mut rest = &buf[..last];
+        if !self.carry.is_empty() {
+            // Finish off the word that was cut off at the end of the last chunk.
+            let first = rest.iter().position(|&b| is_separator(b)).unwrap_or(last);
+            self.carry.extend(&rest[..first]);
+            let (table, filter) = (&mut self.table, &mut self.filter);
+            for_each_word(&self.carry, self.ascii, |w| filter.add(table, w));
+            self.carry.clear();
+            rest = &rest[first..];
+        }
+
+        let (table, filter) = (&mut self.table, &mut self.filter);
+        for_each_word(rest, self.ascii, |w| filter.add(table, w));
+        self.carry.extend(&buf[last..]);
+    }
+
+    pub fn table(&self) -> &FreqTable {
+        &self.table
+    }
+
+    // Counts the last word of the current input.
+    pub fn finish(&mut self) {
+        let (table, filter) = (&mut self.table, &mut self.filter);
+        for_each_word(&self.carry, self.ascii, |w| filter.add(table, w));
+        self.carry.clear();
+    }
+}
+
+// A common list of English stopwords, in the same format as a --stopwords file.
+const ENGLISH_STOPWORDS: &str = "\
+    a about above after again against all am an and any are as at be because been before being \
+    below between both but by can could did do does doing don't down during each few for from \
+    further had has have having he her here hers herself him himself his how i if in into is isn't \
+    it it's its itself just me more most my myself no nor not now of off on once only or other our \
+    ours ourselves out over own same she should so some such than that the their theirs them \
+    themselves then there these they this those through to too under until up very was we were \
+    what when where which while who whom why will with would you your yours yourself yourselves";
+
+// Decides which words are counted, and as what.
+#[derive(Default)]
+pub struct WordFilter {
+    lowercase: bool,
+    min_length: usize,
+
+    // In lowercase.
+    stopwords: HashSet<Vec<u8>>,
+
+    // For lowercasing words into, kept to avoid reallocating it.
+    lower: Vec<u8>,
+}
+
+impl WordFilter {
+    fn add_stopwords<'a>(&mut self, words: impl IntoIterator<Item = &'a [u8]>) {
+        for word in words.into_iter().filter(|w| !w.is_empty()) {
+            lowercase_into(word, &mut self.lower);
+            self.stopwords.insert(self.lower.clone());
+        }
+    }
+
+    // Counts `word` in `table`, unless it is filtered out.
+    fn add(&mut self, table: &mut FreqTable, word: &[u8]) {
+        if self.min_length > 1 && char_len(word) < self.min_length {
+            return;
+        }
+        if !self.lowercase && self.stopwords.is_empty() {
+            table.add(word);
+            return;
+        }
+
+        lowercase_into(word, &mut self.lower);
+        if !self.stopwords.contains(&self.lower) {
+            table.add(if self.lowercase { &self.lower } else { word });
+        }
+    }
+}
+
+fn char_len(word: &[u8]) -> usize {
+    if word.is_ascii() {
+        word.len()
+    } else {
+        String::from_utf8_lossy(word).chars().count()
+    }
+}
+
+fn lowercase_into(word: &[u8], out: &mut Vec<u8>) {
+    out.clear();
+    if word.is_ascii() {
+        out.extend(word.iter().map(u8::to_ascii_lowercase));
+    } else {
+        let mut buf = [0; 4];
+        for c in String::from_utf8_lossy(word)
+            .chars()
+            .flat_map(char::to_lowercase)
+        {
+            out.extend(c.encode_utf8(&mut buf).as_bytes());
+        }
+    }
+}
+
+// Calls `emit` on each word in `text`.
+//
+// This approximates Unicode word segmentation: a word is a run of letters, digits, underscores
+// and combining marks, which may contain apostrophes ("don't"). Invalid UTF-8 separates words.
+fn for_each_word(text: &[u8], ascii: bool, mut emit: impl FnMut(&[u8])) {
+    if ascii {
+        let chars = text.iter().map(|&b| b as char).enumerate();
+        words_in(text, chars, is_ascii_word_char, &mut emit);
+    } else {
+        for chunk in text.utf8_chunks() {
+            let valid = chunk.valid();
+            words_in(
+                valid.as_bytes(),
+                valid.char_indices(),
+                is_word_char,
+                &mut emit,
+            );
+        }
+    }
+}
+
+// Finds the words in `text`, given its characters and their offsets.
+fn words_in(
+    text: &[u8],
+    chars: impl Iterator<Item = (usize, char)>,
+    is_word_char: fn(char) -> bool,
+    emit: &mut impl FnMut(&[u8]),
+) {
+    let mut start = None;
+    let mut end = 0;
+    let mut chars = chars.peekable();
+    while let Some((i, c)) = chars.next() {
+        if is_word_char(c) {
+            start.get_or_insert(i);
+            end = i + c.len_utf8();
+        } else if start.is_some()
+            && (c == '\'' || c == '\u{2019}')
+            && chars.peek().is_some_and(|&(_, next)| is_word_char(next))
+        {
+            // An apostrophe inside a word.
+        } else if let Some(s) = start.take() {
+            emit(&text[s..end]);
+        }
+    }
+    if let Some(s) = start {
+        emit(&text[s..end]);
+    }
+}
+
+// Whether `b` ends any word it follows: an ASCII byte that isn't a word character or an apostrophe,
+// which can be inside a word. It can't be part of a multi-byte UTF-8 character either, so the
+// input can be split at it.
+fn is_separator(b: u8) -> bool {
+    b.is_ascii() && !is_ascii_word_char(b as char) && b != b'\''
+}
+
+fn is_ascii_word_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || is_combining_mark(c)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use proptest::string::bytes_regex;
+    use proptest::{prop_assert_eq, proptest};
+
+    fn words(text: &str, ascii: bool) -> Vec<String> {
+        let mut v = Vec::new();
+        for_each_word(text.as_bytes(), ascii, |w| {
+            v.push(String::from_utf8(w.to_vec()).unwrap())
+        });
+        v
+    }
+
+    #[test]
+    fn test_words() {
+        assert_eq!(
+            words("Don't panic, it's 42 o'clock_now! 'quoted'", false),
+            ["Don't", "panic", "it's", "42", "o'clock_now", "quoted"]
+        );
+        assert_eq!(words("naïve café übung", false), ["naïve", "café", "übung"]);
+        assert_eq!(words("cafe\u{301} ok", false), ["cafe\u{301}", "ok"]);
+        assert_eq!(words("naïve café", true), ["na", "ve", "caf"]);
+    }
+
+    #[test]
+    fn test_filter() {
+        let mut filter = WordFilter {
+            min_length: 2,
+            ..WordFilter::default()
+        };
+        filter.add_stopwords([&b"The"[..], b"\xc3\x84rger"]);
+        let mut table = FreqTable::default();
+        for_each_word("The cat, the ärger, a cat, Cat".as_bytes(), false, |w| {
+            filter.add(&mut table, w)
+        });
+        assert_eq!(table.counts().len(), 2);
+        assert_eq!(table.counts()[&b"cat"[..]], 2);
+        assert_eq!(table.counts()[&b"Cat"[..]], 1);
+
+        filter.lowercase = true;
+        let mut table = FreqTable::default();
+        for_each_word("The CAT É é".as_bytes(), false, |w| {
+            filter.add(&mut table, w)
+        });
+        assert_eq!(table.counts().len(), 1);
+        assert_eq!(table.counts()[&b"cat"[..]], 1);
+    }
+
+    #[test]
+    fn test_carry_split_at_punctuation() {
+        // Text without whitespace is still split into words as it arrives.
+        let mut counter = WordCounter::default();
+        for _ in 0..1000 {
+            counter.write(b"one,two;three.");
+        }
+        assert!(counter.carry.len() <= 1);
+        counter.finish();
+        assert_eq!(counter.table.counts()[&b"three"[..]], 1000);
+    }
+
+    proptest! {
+        #[test]
+        fn test_chunked(
+            chunk_size in 1..20_usize,
+            text in bytes_regex("((?s-u:[a b',\\n\\xc3\\xa9\\xff]{0,200}))").unwrap(),
+            ascii in proptest::bool::ANY,
+        ) {
+            let mut whole = WordCounter::new(FreqTable::default(), WordFilter::default(), ascii);
+            whole.write(&text);
+            whole.finish();
+
+            let mut chunked = WordCounter::new(FreqTable::default(), WordFilter::default(), ascii);
+            text.chunks(chunk_size).for_each(|chunk| chunked.write(chunk));
+            chunked.finish();
+
+            prop_assert_eq!(whole.table.counts(), chunked.table.counts());
+        }
+    }
+}